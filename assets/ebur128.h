@@ -59,7 +59,9 @@ enum channel {
   EBUR128_Tp000,          /**< itu T+000 */
   EBUR128_Bp000,          /**< itu B+000 */
   EBUR128_Bp045,          /**< itu B+045 */
-  EBUR128_Bm045           /**< itu B-045 */
+  EBUR128_Bm045,          /**< itu B-045 */
+  EBUR128_MID,            /**< mid channel of a mid/side signal */
+  EBUR128_SIDE            /**< side channel of a mid/side signal */
 };
 
 /** \enum error
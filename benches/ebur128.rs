@@ -46,13 +46,14 @@ fn get_results(ebu: &EbuR128, mode: Mode) {
     if mode.contains(Mode::I) {
         black_box(ebu.loudness_global().unwrap());
     }
-    if mode.contains(Mode::M) {
+    // Only the peaks are tracked with SAMPLE_PEAK or TRUE_PEAK alone
+    if mode != Mode::SAMPLE_PEAK && mode != Mode::TRUE_PEAK {
         black_box(ebu.loudness_momentary().unwrap());
+        black_box(ebu.loudness_window(1).unwrap());
     }
     if mode.contains(Mode::S) {
         black_box(ebu.loudness_shortterm().unwrap());
     }
-    black_box(ebu.loudness_window(1).unwrap());
 
     if mode.contains(Mode::LRA) {
         black_box(ebu.loudness_range().unwrap());
@@ -340,5 +341,126 @@ pub fn criterion_benchmark(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, criterion_benchmark);
+pub fn criterion_benchmark_polling(c: &mut Criterion) {
+    // 10 minutes of history, and new frames in chunks of 10ms
+    let mut data = vec![0f32; 48_000 * 2];
+    for (i, frame) in data.chunks_exact_mut(2).enumerate() {
+        let s = f32::sin(2.0 * std::f32::consts::PI * 440.0 * i as f32 / 48_000.0) * 0.5;
+        frame.fill(s);
+    }
+
+    for (name, mode, sorted) in [
+        ("I", Mode::I, false),
+        ("I sorted", Mode::I, true),
+        ("I histogram", Mode::I | Mode::HISTOGRAM, false),
+    ] {
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu.set_sorted_integrated(sorted);
+        ebu.set_max_history(10 * 60 * 1000).unwrap();
+        for _ in 0..10 * 60 {
+            ebu.add_frames_f32(&data).unwrap();
+        }
+
+        let mut group = c.benchmark_group(format!("ebur128 polling: 48kHz 2ch {name}"));
+        group.bench_function("Rust", |b| {
+            b.iter(|| {
+                for chunk in data.chunks_exact(480 * 2).take(10) {
+                    ebu.add_frames_f32(chunk).unwrap();
+                    black_box(ebu.loudness_global().unwrap());
+                }
+            })
+        });
+        group.finish();
+    }
+}
+
+pub fn criterion_benchmark_true_peak_threshold(c: &mut Criterion) {
+    // 5s of a sine whose level varies between -20 dBFS and -1 dBFS, so that it is only above
+    // the threshold a small part of the time
+    let mut data = vec![0f32; 48_000 * 5 * 2];
+    for (i, frame) in data.chunks_exact_mut(2).enumerate() {
+        let t = i as f32 / 48_000.0;
+        let level_db = -10.5 + 9.5 * f32::sin(2.0 * std::f32::consts::PI * 0.5 * t);
+        let s = f32::sin(2.0 * std::f32::consts::PI * 440.0 * t) * f32::powf(10.0, level_db / 20.0);
+        frame.fill(s);
+    }
+
+    let mut group = c.benchmark_group("ebur128 true peak threshold: 48kHz 2ch");
+    for (name, threshold) in [("none", None), ("-3 dBFS", Some(-3.0))] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+                ebu.set_true_peak_threshold(threshold).unwrap();
+                ebu.add_frames_f32(&data).unwrap();
+                black_box(ebu.true_peak(0).unwrap());
+            })
+        });
+    }
+    group.finish();
+}
+
+pub fn criterion_benchmark_mono(c: &mut Criterion) {
+    // Mono is very common, e.g. for speech and podcasts
+    let data = (0..48_000 * 5)
+        .map(|i| f32::sin(2.0 * std::f32::consts::PI * 440.0 * i as f32 / 48_000.0))
+        .collect::<Vec<_>>();
+
+    for (name, mode) in [("I", Mode::I), ("I | TRUE_PEAK", Mode::I | Mode::TRUE_PEAK)] {
+        let mut group = c.benchmark_group(format!("ebur128 process: 48kHz f32 1ch {name}"));
+
+        #[cfg(feature = "c-tests")]
+        {
+            let mode_c = if mode.contains(Mode::TRUE_PEAK) {
+                ModeC::I | ModeC::TRUE_PEAK
+            } else {
+                ModeC::I
+            };
+            group.bench_function("C", |b| {
+                b.iter(|| {
+                    let mut ebu =
+                        EbuR128C::new(black_box(1), black_box(48_000), black_box(mode_c)).unwrap();
+                    ebu.add_frames_f32(&data).unwrap();
+
+                    black_box(ebu.loudness_global().unwrap());
+                })
+            });
+        }
+        group.bench_function("Rust", |b| {
+            b.iter(|| {
+                let mut ebu =
+                    EbuR128::new(black_box(1), black_box(48_000), black_box(mode)).unwrap();
+                ebu.add_frames_f32(&data).unwrap();
+
+                black_box(ebu.loudness_global().unwrap());
+            })
+        });
+
+        group.finish();
+    }
+}
+
+pub fn criterion_benchmark_create_batch(c: &mut Criterion) {
+    // Like a library scan with one instance per track
+    let mut group = c.benchmark_group("ebur128 create batch: 10000x 48kHz 2ch");
+    for (name, mode) in [("I", Mode::I), ("TRUE_PEAK", Mode::TRUE_PEAK)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for _ in 0..10_000 {
+                    let ebu = EbuR128::new(black_box(2), black_box(48_000), mode).unwrap();
+                    drop(black_box(ebu));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    criterion_benchmark,
+    criterion_benchmark_polling,
+    criterion_benchmark_true_peak_threshold,
+    criterion_benchmark_create_batch,
+    criterion_benchmark_mono
+);
 criterion_main!(benches);
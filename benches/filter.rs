@@ -298,6 +298,40 @@ pub fn criterion_benchmark(c: &mut Criterion) {
 
         group.finish();
     }
+
+    // Sample peak and true peak are calculated in a single pass over the samples, which should be
+    // cheaper than calculating both separately.
+    let channel_map = [ebur128::Channel::Left; 2];
+    let mut data_out = vec![0.0f64; 19200 * 2];
+    let mut data = vec![0.0f32; 19200 * 2];
+    let mut accumulator = 0.0;
+    let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+    for out in data.chunks_exact_mut(2) {
+        let val = f32::sin(accumulator);
+        out[0] = val;
+        out[1] = val;
+        accumulator += step;
+    }
+
+    let mut group = c.benchmark_group("filter process: 48kHz 2ch f32 peaks");
+    for (name, sample_peak, true_peak) in &[
+        ("sample peak", true, false),
+        ("true peak", false, true),
+        ("sample + true peak", true, true),
+    ] {
+        let mut f = filter::Filter::new(48_000, 2, *sample_peak, *true_peak);
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                f.process(
+                    black_box(ebur128::Interleaved::new(&data, 2).unwrap()),
+                    black_box(&mut data_out),
+                    black_box(0),
+                    black_box(&channel_map),
+                );
+            })
+        });
+    }
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -137,6 +137,28 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         }
         group.finish();
     }
+
+    // One short-term block per second for 24 hours, comparing sorting the energies on every call
+    // with keeping them sorted while adding.
+    let mut group = c.benchmark_group("history loudness range: 24h Queue");
+    for (sorted, name) in &[(false, "sort"), (true, "sorted")] {
+        let mut hist = if *sorted {
+            history::History::new_sorted(false, 100_000)
+        } else {
+            history::History::new(false, 100_000)
+        };
+
+        for e in black_box(&energies[..86_400]) {
+            hist.add(*e);
+        }
+
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                black_box(hist.loudness_range());
+            })
+        });
+    }
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,112 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::fs;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+/// Common sample rates of music and video.
+const RATES: [u32; 6] = [44_100, 48_000, 88_200, 96_000, 176_400, 192_000];
+
+/// Same as `calc_biquads()` in `src/filter.rs`.
+#[allow(non_snake_case)]
+fn calc_biquads(rate: f64) -> [[f64; 3]; 4] {
+    let f0 = 1681.974450955533;
+    let G = 3.999843853973347;
+    let Q = 0.7071752369554196;
+
+    let K = f64::tan(std::f64::consts::PI * f0 / rate);
+    let Vh = f64::powf(10.0, G / 20.0);
+    let Vb = f64::powf(Vh, 0.4996667741545416);
+
+    let mut pb = [0.0, 0.0, 0.0];
+    let mut pa = [1.0, 0.0, 0.0];
+    let rb = [1.0, -2.0, 1.0];
+    let mut ra = [1.0, 0.0, 0.0];
+
+    let a0 = 1.0 + K / Q + K * K;
+    pb[0] = (Vh + Vb * K / Q + K * K) / a0;
+    pb[1] = 2.0 * (K * K - Vh) / a0;
+    pb[2] = (Vh - Vb * K / Q + K * K) / a0;
+    pa[1] = 2.0 * (K * K - 1.0) / a0;
+    pa[2] = (1.0 - K / Q + K * K) / a0;
+
+    let f0 = 38.13547087602444;
+    let Q = 0.5003270373238773;
+    let K = f64::tan(std::f64::consts::PI * f0 / rate);
+
+    ra[1] = 2.0 * (K * K - 1.0) / (1.0 + K / Q + K * K);
+    ra[2] = (1.0 - K / Q + K * K) / (1.0 + K / Q + K * K);
+
+    [pb, pa, rb, ra]
+}
+
+fn main() {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("src");
+    path.push("biquads.rs");
+
+    let mut contents = Vec::new();
+
+    let biquads = RATES.map(|rate| calc_biquads(rate as f64));
+
+    write!(
+        &mut contents,
+        "\
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the \"Software\"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED \"AS IS\", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// DO NOT EDIT: This file is autogenerated by `examples/generate_biquads.rs`
+
+/// Sample rates with precomputed biquads.
+pub static RATES: [u32; {len}] = {rates:?};
+
+/// Biquads for each of the sample rates in `RATES`: pre-filter numerator and denominator, and
+/// RLB filter numerator and denominator.
+pub static BIQUADS: [[[f64; 3]; 4]; {len}] = {biquads:#.prec$?};
+",
+        len = RATES.len(),
+        rates = RATES,
+        biquads = biquads,
+        prec = 36,
+    )
+    .expect("Failed to format file contents");
+
+    fs::write(path, contents).expect("Failed to write file");
+}
@@ -0,0 +1,102 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use std::f64::consts::PI;
+use std::fs;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+/// Upsampling factors of the true peak interpolators.
+const FACTORS: [usize; 2] = [2, 4];
+/// Number of taps of the interpolation filter.
+const TAPS: usize = 48;
+
+/// Same as `coefficients()` in `src/interp.rs`.
+fn coefficients(factor: usize, taps: usize) -> Vec<f32> {
+    (0..taps)
+        .map(|j| {
+            let j = j as f64;
+            let window = taps as f64;
+            let w = 0.5 * (1.0 - f64::cos(2.0 * PI * j / window));
+
+            let m = j - window / 2.0;
+            let c = if m.abs() > 0.000001 {
+                w * f64::sin(m * PI / factor as f64) / (m * PI / factor as f64)
+            } else {
+                w
+            };
+
+            c as f32
+        })
+        .collect()
+}
+
+fn main() {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("src");
+    path.push("interp_coefficients.rs");
+
+    let mut contents = Vec::new();
+
+    let coefficients = FACTORS.map(|factor| coefficients(factor, TAPS));
+
+    write!(
+        &mut contents,
+        "\
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the \"Software\"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED \"AS IS\", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// DO NOT EDIT: This file is autogenerated by `examples/generate_interp_coefficients.rs`
+
+/// Upsampling factors with precomputed interpolator coefficients.
+pub static FACTORS: [usize; {len}] = {factors:?};
+
+/// Coefficients of the interpolation filter with {taps} taps for each of the factors in
+/// `FACTORS`.
+pub static COEFFICIENTS: [[f32; {taps}]; {len}] = {coefficients:#?};
+",
+        len = FACTORS.len(),
+        taps = TAPS,
+        factors = FACTORS,
+        coefficients = coefficients,
+    )
+    .expect("Failed to format file contents");
+
+    fs::write(path, contents).expect("Failed to write file");
+}
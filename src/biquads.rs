@@ -0,0 +1,162 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// DO NOT EDIT: This file is autogenerated by `examples/generate_biquads.rs`
+
+/// Sample rates with precomputed biquads.
+pub static RATES: [u32; 6] = [44100, 48000, 88200, 96000, 176400, 192000];
+
+/// Biquads for each of the sample rates in `RATES`: pre-filter numerator and denominator, and
+/// RLB filter numerator and denominator.
+pub static BIQUADS: [[[f64; 3]; 4]; 6] = [
+    [
+        [
+            1.530841230050347778757213745848275721,
+            -2.650979995154729706996477034408599138,
+            1.169079079921587105417302154819481075,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -1.663655113256020445788863071356900036,
+            0.712595428073225400922297012584749609,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -2.000000000000000000000000000000000000,
+            1.000000000000000000000000000000000000,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -1.989169673629795909164386102929711342,
+            0.989199035787039271383491723099723458,
+        ],
+    ],
+    [
+        [
+            1.535124859586970202940392482560127974,
+            -2.691696189406380668174278980586677790,
+            1.198392810852850098868316308653447777,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -1.690659293182410261024983810784760863,
+            0.732480774215850116704018546442966908,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -2.000000000000000000000000000000000000,
+            1.000000000000000000000000000000000000,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -1.990047454833979712063296574342530221,
+            0.990072250366209938299277837359113619,
+        ],
+    ],
+    [
+        [
+            1.557515375579653804649638004775624722,
+            -2.905627079926345057714343056431971490,
+            1.361333977472212408699192565109115094,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -1.830919987962332129427522886544466019,
+            0.844142261087852729950498087418964133,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -2.000000000000000000000000000000000000,
+            1.000000000000000000000000000000000000,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -1.994577515450344451508613019541371614,
+            0.994584875878054885944834495603572577,
+        ],
+    ],
+    [
+        [
+            1.559714228975796590503932748106308281,
+            -2.926741578251082387396309059113264084,
+            1.378261202315818723107554433227051049,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -1.844609469890108544021245506883133203,
+            0.855843322930641248191818704071920365,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -2.000000000000000000000000000000000000,
+            1.000000000000000000000000000000000000,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -1.995017544724715596871078560070600361,
+            0.995023759040923327923167107655899599,
+        ],
+    ],
+    [
+        [
+            1.571115317741846206800460095109883696,
+            -3.036544502404652678961838319082744420,
+            1.468871611989762238081880241225007921,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -1.915329316833051631618900501052848995,
+            0.918771744160007508561704980820650235,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -2.000000000000000000000000000000000000,
+            1.000000000000000000000000000000000000,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -1.997286922423813892990551721595693380,
+            0.997288765026068224628374991880264133,
+        ],
+    ],
+    [
+        [
+            1.572227215091279051151218482118565589,
+            -3.047283051561550770713893143692985177,
+            1.477971340979609360388735694868955761,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -1.922202230607488626645817930693738163,
+            0.925117735116825934404971576441312209,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -2.000000000000000000000000000000000000,
+            1.000000000000000000000000000000000000,
+        ],
+        [
+            1.000000000000000000000000000000000000,
+            -1.997507222840700036314842691353987902,
+            0.997508778355509662105760071426630020,
+        ],
+    ],
+];
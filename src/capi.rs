@@ -61,11 +61,7 @@ pub unsafe extern "C" fn ebur128_destroy(state: *mut *mut State) {
 
 impl From<ebur128::Error> for i32 {
     fn from(v: ebur128::Error) -> i32 {
-        match v {
-            ebur128::Error::NoMem => 1,
-            ebur128::Error::InvalidMode => 2,
-            ebur128::Error::InvalidChannelIndex => 3,
-        }
+        ebur128::ErrorCode::from(v) as i32
     }
 }
 
@@ -97,7 +93,7 @@ pub unsafe extern "C" fn ebur128_change_parameters(
     let s = &mut *state;
 
     if s.channels == channels && s.samplerate == samplerate {
-        return 4; // EBUR128_ERROR_NO_CHANGE
+        return ebur128::ErrorCode::NoChange as i32;
     }
 
     let e = &mut *s.internal;
@@ -124,8 +120,11 @@ pub unsafe extern "C" fn ebur128_set_max_window(
     let s = &mut *state;
     let e = &mut *s.internal;
 
-    if e.max_window() == window as usize {
-        return 4; // EBUR128_ERROR_NO_CHANGE
+    // libebur128 silently uses the minimum window required by the mode
+    let window = std::cmp::max(window as usize, e.required_window());
+
+    if e.max_window() == window {
+        return ebur128::ErrorCode::NoChange as i32;
     }
 
     match e.set_max_window(window as u32) {
@@ -146,8 +145,11 @@ pub unsafe extern "C" fn ebur128_set_max_history(
     let s = &mut *state;
     let e = &mut *s.internal;
 
-    if e.max_history() == history as usize {
-        return 4; // EBUR128_ERROR_NO_CHANGE
+    // libebur128 silently uses the minimum history required by the mode
+    let history = std::cmp::max(history as usize, e.required_history());
+
+    if e.max_history() == history {
+        return ebur128::ErrorCode::NoChange as i32;
     }
 
     match e.set_max_history(history as u32) {
@@ -0,0 +1,173 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use crate::{Channel, EbuR128, Error, Mode};
+
+/// Channel map of a standard loudspeaker layout together with the resulting BS.1770 channel
+/// gains.
+///
+/// ITU BS.1770 weights the surround channels with a gain of about +1.5 dB and excludes the LFE
+/// channel from the measurement. The presets map the channels of the common layouts in their
+/// usual order, so that these weights are applied correctly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelProfile {
+    channel_map: Vec<Channel>,
+}
+
+impl ChannelProfile {
+    /// Create a profile with a custom channel map.
+    pub fn new(channel_map: &[Channel]) -> Self {
+        ChannelProfile {
+            channel_map: channel_map.to_vec(),
+        }
+    }
+
+    /// Mono: C.
+    pub fn mono() -> Self {
+        Self::new(&[Channel::Center])
+    }
+
+    /// Stereo: L, R.
+    pub fn stereo() -> Self {
+        Self::new(&[Channel::Left, Channel::Right])
+    }
+
+    /// 5.1 surround in SMPTE order: L, R, C, LFE, Ls, Rs.
+    pub fn surround_5_1() -> Self {
+        Self::new(&[
+            Channel::Left,
+            Channel::Right,
+            Channel::Center,
+            Channel::Unused,
+            Channel::LeftSurround,
+            Channel::RightSurround,
+        ])
+    }
+
+    /// 7.1 surround in SMPTE order: L, R, C, LFE, Lss, Rss, Lrs, Rrs.
+    ///
+    /// The side surround channels at ±90° get the surround gain, while the rear surround
+    /// channels at ±135° are not weighted according to BS.1770.
+    pub fn surround_7_1() -> Self {
+        Self::new(&[
+            Channel::Left,
+            Channel::Right,
+            Channel::Center,
+            Channel::Unused,
+            Channel::Mp090,
+            Channel::Mm090,
+            Channel::Mp135,
+            Channel::Mm135,
+        ])
+    }
+
+    /// Get the number of channels.
+    pub fn channels(&self) -> u32 {
+        self.channel_map.len() as u32
+    }
+
+    /// Get the channel map.
+    pub fn channel_map(&self) -> &[Channel] {
+        &self.channel_map
+    }
+
+    /// Get the gain in dB that is applied to each channel before summing the channels, or `None`
+    /// for channels that are excluded from the measurement.
+    pub fn gains_db(&self) -> Vec<Option<f64>> {
+        self.channel_map
+            .iter()
+            .map(|&channel| {
+                if channel == Channel::Unused {
+                    None
+                } else {
+                    let weight = crate::filter::channel_weight(channel).unwrap_or(1.0);
+                    Some(10.0 * f64::log10(weight))
+                }
+            })
+            .collect()
+    }
+}
+
+impl EbuR128 {
+    /// Create a new instance with the number of channels and the channel map of `profile`.
+    pub fn with_channel_profile(
+        profile: &ChannelProfile,
+        rate: u32,
+        mode: Mode,
+    ) -> Result<Self, Error> {
+        let mut ebu = EbuR128::new(profile.channels(), rate, mode)?;
+        ebu.set_channel_map(profile.channel_map())?;
+
+        Ok(ebu)
+    }
+
+    /// Set the channel map of `profile`.
+    ///
+    /// Returns `Error::InvalidChannelIndex` if the number of channels differs.
+    pub fn set_channel_profile(&mut self, profile: &ChannelProfile) -> Result<(), Error> {
+        self.set_channel_map(profile.channel_map())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    #[test]
+    fn gains() {
+        assert_eq!(ChannelProfile::stereo().gains_db(), vec![Some(0.0); 2]);
+
+        let gains = ChannelProfile::surround_5_1().gains_db();
+        assert_eq!(gains[..4], [Some(0.0), Some(0.0), Some(0.0), None]);
+        for gain in &gains[4..] {
+            assert_float_eq!(gain.unwrap(), 1.49, abs <= 0.01);
+        }
+
+        let gains = ChannelProfile::surround_7_1().gains_db();
+        assert_eq!(gains.len(), 8);
+        assert_eq!(gains[3], None);
+        assert_float_eq!(gains[4].unwrap(), 1.49, abs <= 0.01);
+        assert_float_eq!(gains[5].unwrap(), 1.49, abs <= 0.01);
+        assert_eq!(gains[6..], [Some(0.0), Some(0.0)]);
+
+        assert_eq!(
+            ChannelProfile::new(&[Channel::DualMono]).gains_db(),
+            vec![Some(10.0 * f64::log10(2.0))]
+        );
+    }
+
+    #[test]
+    fn with_channel_profile() {
+        let profile = ChannelProfile::surround_7_1();
+        let mut ebu = EbuR128::with_channel_profile(&profile, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.channels(), 8);
+        assert_eq!(ebu.channel_map(), profile.channel_map());
+
+        ebu.set_channel_profile(&ChannelProfile::new(&[Channel::Unused; 8]))
+            .unwrap();
+        assert_eq!(ebu.channel_map(), &[Channel::Unused; 8]);
+        assert_eq!(
+            ebu.set_channel_profile(&ChannelProfile::surround_5_1()),
+            Err(Error::InvalidChannelIndex)
+        );
+    }
+}
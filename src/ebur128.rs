@@ -28,7 +28,10 @@ use std::error;
 use std::fmt;
 
 /// Error values for [`EbuR128`](struct.EbuR128.html) functions.
+///
+/// New variants can be added in minor releases, so matching on this requires a wildcard arm.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Error {
     /// Not enough memory
     NoMem,
@@ -36,6 +39,18 @@ pub enum Error {
     InvalidMode,
     /// Invalid channel index passed
     InvalidChannelIndex,
+    /// Sample rate not supported, e.g. because the filter would be unstable
+    UnsupportedRate,
+    /// Buffer doesn't contain a whole number of frames
+    BufferSizeMismatch,
+    /// Adding the frames would exceed the maximum duration
+    MaxDurationExceeded,
+    /// Histories of different kinds can't be combined without loss
+    MixedHistoryTypes,
+    /// Duration of the added frames doesn't match the expected duration
+    DurationMismatch,
+    /// The thread that analyzes the frames panicked
+    AnalysisPanicked,
 }
 
 impl error::Error for Error {}
@@ -46,6 +61,59 @@ impl fmt::Display for Error {
             Error::NoMem => write!(f, "NoMem"),
             Error::InvalidMode => write!(f, "Invalid Mode"),
             Error::InvalidChannelIndex => write!(f, "Invalid Channel Index"),
+            Error::UnsupportedRate => write!(f, "Unsupported Rate"),
+            Error::BufferSizeMismatch => write!(f, "Buffer Size Mismatch"),
+            Error::MaxDurationExceeded => write!(f, "Max Duration Exceeded"),
+            Error::MixedHistoryTypes => write!(f, "Mixed History Types"),
+            Error::DurationMismatch => write!(f, "Duration Mismatch"),
+            Error::AnalysisPanicked => write!(f, "Analysis Panicked"),
+        }
+    }
+}
+
+/// Numeric error codes as used by the C API of libebur128.
+///
+/// The values correspond to the `EBUR128_SUCCESS` and `EBUR128_ERROR_*` constants of
+/// libebur128 and can be used when exposing a C API on top of this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ErrorCode {
+    /// `EBUR128_SUCCESS`
+    Success = 0,
+    /// `EBUR128_ERROR_NOMEM`
+    NoMem = 1,
+    /// `EBUR128_ERROR_INVALID_MODE`
+    InvalidMode = 2,
+    /// `EBUR128_ERROR_INVALID_CHANNEL_INDEX`
+    InvalidChannelIndex = 3,
+    /// `EBUR128_ERROR_NO_CHANGE`
+    NoChange = 4,
+}
+
+impl From<Error> for ErrorCode {
+    /// Map errors to the error codes libebur128 returns in the same situation.
+    ///
+    /// `Error::UnsupportedRate` maps to `ErrorCode::NoMem` because libebur128 returns
+    /// `EBUR128_ERROR_NOMEM` for unsupported sample rates. `Error::BufferSizeMismatch` can't
+    /// happen with libebur128, which takes the number of frames, and also maps to
+    /// `ErrorCode::NoMem` like in earlier versions of this crate. `Error::MaxDurationExceeded`
+    /// is a resource limit of this crate only and maps to `ErrorCode::NoMem` as well.
+    /// `Error::MixedHistoryTypes` maps to `ErrorCode::InvalidMode`, which libebur128 returns when
+    /// combining histograms and queues. `Error::DurationMismatch` usually means that the frames
+    /// were added at an unsupported rate and maps to `ErrorCode::NoMem` like
+    /// `Error::UnsupportedRate`. `Error::AnalysisPanicked` has no equivalent in libebur128 and
+    /// maps to `ErrorCode::NoMem` as well.
+    fn from(v: Error) -> ErrorCode {
+        match v {
+            Error::NoMem => ErrorCode::NoMem,
+            Error::InvalidMode => ErrorCode::InvalidMode,
+            Error::InvalidChannelIndex => ErrorCode::InvalidChannelIndex,
+            Error::UnsupportedRate => ErrorCode::NoMem,
+            Error::BufferSizeMismatch => ErrorCode::NoMem,
+            Error::MaxDurationExceeded => ErrorCode::NoMem,
+            Error::MixedHistoryTypes => ErrorCode::InvalidMode,
+            Error::DurationMismatch => ErrorCode::NoMem,
+            Error::AnalysisPanicked => ErrorCode::NoMem,
         }
     }
 }
@@ -55,6 +123,12 @@ bitflags! {
     ///
     /// Use these values in [`EbuR128::new`](struct.EbuR128.html#method.new). Try to use the lowest
     /// possible modes that suit your needs, as performance will be better.
+    ///
+    /// Each mode includes the modes it depends on, e.g. `LRA` includes `S`, which includes `M`.
+    /// Any combination of these values is valid, as long as it contains at least one mode besides
+    /// `HISTOGRAM`. Modes with only some bits of a value, e.g. created with
+    /// [`Mode::from_bits_truncate`](#method.from_bits_truncate), are invalid. See
+    /// [`Mode::is_valid`](#method.is_valid).
     pub struct Mode: u8 {
         /// can call [`EbuR128::loudness_momentary`](struct.EbuR128.html#method.loudness_momentary)
         const M = 0b00000001;
@@ -64,23 +138,122 @@ bitflags! {
         /// [`EbuR128::relative_threshold`](struct.EbuR128.html#method.relative_threshold)
         const I = 0b00000100 | Mode::M.bits;
         /// can call [`EbuR128::loudness_range`](struct.EbuR128.html#method.loudness_range)
+        ///
+        /// Without `HISTOGRAM`, the short-term energies are kept in a queue that grows as needed
+        /// by doubling its capacity, so streams of unknown length can be measured without
+        /// configuring the maximum history. This needs 16 bytes per second of audio, e.g. about
+        /// 1.4MB for 24 hours. With `HISTOGRAM` the memory usage is constant.
         const LRA = 0b00001000 | Mode::S.bits;
         /// can call [`EbuR128::sample_peak`](struct.EbuR128.html#method.sample_peak)
+        ///
+        /// If this is the only mode (besides `HISTOGRAM`), no filtering is happening and only the
+        /// sample peak is tracked. Loudness queries return `Error::InvalidMode` in that case.
         const SAMPLE_PEAK = 0b00010000 | Mode::M.bits;
         /// can call [`EbuR128::true_peak`](struct.EbuR128.html#method.true_peak)
+        ///
+        /// Like for `SAMPLE_PEAK`, only the peaks are tracked if this is the only mode (besides
+        /// `HISTOGRAM`) and no loudness history is allocated.
         const TRUE_PEAK = 0b00110001;
         /// uses histogram algorithm to calculate loudness
         const HISTOGRAM = 0b01000000;
     }
 }
 
+/// Names of the individual modes, as used by [`Mode::from_str_list`] and [`Mode::to_str_list`].
+const MODE_NAMES: [(&str, Mode); 7] = [
+    ("M", Mode::M),
+    ("S", Mode::S),
+    ("I", Mode::I),
+    ("LRA", Mode::LRA),
+    ("SAMPLE_PEAK", Mode::SAMPLE_PEAK),
+    ("TRUE_PEAK", Mode::TRUE_PEAK),
+    ("HISTOGRAM", Mode::HISTOGRAM),
+];
+
+impl Mode {
+    /// Check if this is a valid combination of modes.
+    ///
+    /// This is the case if it contains at least one mode besides `HISTOGRAM`, and all modes it
+    /// partially contains are contained completely. [`EbuR128::new`](struct.EbuR128.html#method.new)
+    /// returns `Error::InvalidMode` for invalid combinations.
+    pub fn is_valid(&self) -> bool {
+        // Pairs of modes and the modes they directly depend on
+        const DEPENDENCIES: [(Mode, Mode); 5] = [
+            (Mode::S, Mode::M),
+            (Mode::I, Mode::M),
+            (Mode::LRA, Mode::S),
+            (Mode::SAMPLE_PEAK, Mode::M),
+            (Mode::TRUE_PEAK, Mode::SAMPLE_PEAK),
+        ];
+
+        self.contains(Mode::M)
+            && DEPENDENCIES.iter().all(|&(mode, dependency)| {
+                !self.intersects(mode - dependency) || self.contains(mode)
+            })
+    }
+
+    /// Parse a list of mode names, e.g. `["I", "LRA", "true_peak"]`, into the combined mode.
+    ///
+    /// See [`Mode::from_str`](#method.from_str) for the accepted names.
+    pub fn from_str_list<S: AsRef<str>>(names: &[S]) -> Result<Mode, Error> {
+        names.iter().try_fold(Mode::empty(), |mode, name| {
+            Ok(mode | name.as_ref().parse::<Mode>()?)
+        })
+    }
+
+    /// Get the names of the modes that make up this mode.
+    ///
+    /// Modes that are implied by another contained mode are not listed, e.g. `Mode::LRA` results
+    /// in only `["LRA"]` and not also `["M", "S"]`.
+    pub fn to_str_list(&self) -> Vec<&'static str> {
+        MODE_NAMES
+            .iter()
+            .filter(|(_, mode)| self.contains(*mode))
+            .filter(|(_, mode)| {
+                !MODE_NAMES.iter().any(|(_, other)| {
+                    *other != *mode && self.contains(*other) && other.contains(*mode)
+                })
+            })
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+impl std::str::FromStr for Mode {
+    type Err = Error;
+
+    /// Parse the name of a single mode.
+    ///
+    /// The names are the ones of the constants, e.g. `"TRUE_PEAK"`. Case is ignored and `-` can be
+    /// used instead of `_`. Returns `Error::InvalidMode` for unknown names.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().replace('-', "_");
+
+        MODE_NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&s))
+            .map(|(_, mode)| *mode)
+            .ok_or(Error::InvalidMode)
+    }
+}
+
+impl fmt::Display for Mode {
+    /// Formats the mode as the names of [`Mode::to_str_list`] separated by `|`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_str_list().join(" | "))
+    }
+}
+
 /// Channel position.
 ///
 /// Use these values when setting the channel map with
 /// [`EbuR128::set_channel`](struct.EbuR128.html#method.set_channel).
 /// See definitions in ITU R-REC-BS 1770-4 and ITU R-REC-BS 2051-2.
+///
+/// New variants can be added in minor releases, so matching on this requires a wildcard arm.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
+#[non_exhaustive]
 pub enum Channel {
     /// unused channel (for example LFE channel)
     Unused,
@@ -146,9 +319,219 @@ pub enum Channel {
     Bp045,
     /// ITU B-045
     Bm045,
+    /// Mid channel of a mid/side signal
+    ///
+    /// Mid and side channels are weighted like the left and right channels. For a mid/side
+    /// signal with `M = (L + R) / √2` and `S = (L - R) / √2` this gives the same loudness as
+    /// measuring the corresponding left/right signal.
+    ///
+    /// With the common `M = (L + R) / 2` and `S = (L - R) / 2` convention, the loudness is
+    /// 3.01 dB lower than the one of the left/right signal.
+    Mid,
+    /// Side channel of a mid/side signal
+    ///
+    /// See [`Channel::Mid`] for the weighting.
+    Side,
+}
+
+/// Stages of the K-weighting filter that are applied before measuring the loudness.
+///
+/// Anything but `Full` deviates from ITU BS.1770 and is only useful for understanding the effect
+/// of each stage of the filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KWeighting {
+    /// Pre-filter (high shelving filter) followed by the RLB (high-pass) filter, as specified
+    Full,
+    /// Only the pre-filter (high shelving filter)
+    PreFilterOnly,
+    /// Only the RLB (high-pass) filter
+    RlbOnly,
+}
+
+impl Default for KWeighting {
+    fn default() -> Self {
+        KWeighting::Full
+    }
+}
+
+/// Handling of gaps between the frames passed to
+/// [`EbuR128::add_frames_at_f32`](struct.EbuR128.html#method.add_frames_at_f32).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapPolicy {
+    /// Fill the gap with silence.
+    ///
+    /// The time base stays continuous, so the gap counts towards the measured duration and the
+    /// loudness of the blocks that overlap it is lowered accordingly, as if the lost frames had
+    /// been silent. Gaps longer than the maximum set with
+    /// [`EbuR128::set_max_silence_gap`](struct.EbuR128.html#method.set_max_silence_gap) are
+    /// handled like with `ResetFilter` instead.
+    InsertSilence,
+    /// Reset the filter state and continue with the frames after the gap as a new island.
+    ///
+    /// The gap is skipped and does not count towards the measured duration, but the filter does
+    /// not ring across the discontinuity. The loudness history and the peaks are kept, and the
+    /// block that is currently being filled continues with the frames after the gap.
+    ResetFilter,
+}
+
+impl Default for GapPolicy {
+    fn default() -> Self {
+        GapPolicy::InsertSilence
+    }
+}
+
+/// Kind of loudness measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoudnessKind {
+    /// Momentary loudness, see [`EbuR128::loudness_momentary`](struct.EbuR128.html#method.loudness_momentary)
+    Momentary,
+    /// Short-term loudness, see [`EbuR128::loudness_shortterm`](struct.EbuR128.html#method.loudness_shortterm)
+    ShortTerm,
+    /// Global integrated loudness, see [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global)
+    Integrated,
+}
+
+/// Crossing of the threshold of an alarm set with
+/// [`EbuR128::set_threshold_alarm`](struct.EbuR128.html#method.set_threshold_alarm).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThresholdCrossing {
+    /// Kind of loudness that crossed the threshold.
+    pub kind: LoudnessKind,
+    /// Number of frames added since the instance was created or reset, see
+    /// [`EbuR128::frames_added`](struct.EbuR128.html#method.frames_added).
+    pub position: u64,
+    /// Loudness in LUFS after the crossing.
+    pub loudness: f64,
+    /// `true` if the loudness rose above the threshold, `false` if it fell below it again.
+    pub above: bool,
+}
+
+/// Callback for [`EbuR128::set_threshold_alarm`](struct.EbuR128.html#method.set_threshold_alarm).
+pub type ThresholdAlarmCallback = dyn FnMut(&ThresholdCrossing) + Send + Sync;
+
+/// Hysteresis of threshold alarms in LU.
+pub const THRESHOLD_ALARM_HYSTERESIS: f64 = 1.0;
+
+struct ThresholdAlarm {
+    kind: LoudnessKind,
+    threshold: f64,
+    /// Whether the loudness is currently above the threshold.
+    above: bool,
+    callback: Box<ThresholdAlarmCallback>,
+}
+
+/// Histories with the other backend than the configured one for validating `Mode::HISTOGRAM`,
+/// configured with [`EbuR128::set_dual_history`](struct.EbuR128.html#method.set_dual_history).
+#[cfg(feature = "dual-history")]
+#[derive(Debug)]
+struct DualHistory {
+    /// Histogram if the configured backend is a queue and vice versa, if `Mode::I` is set.
+    block_energy_history: Option<crate::history::History>,
+    /// Histogram if the configured backend is a queue and vice versa, if `Mode::LRA` is set.
+    short_term_block_energy_history: Option<crate::history::History>,
+}
+
+/// Summed mono measurement configured with
+/// [`EbuR128::set_mono_sum`](struct.EbuR128.html#method.set_mono_sum).
+struct MonoSum {
+    /// Input channels that are summed.
+    channels: Box<[u32]>,
+    /// Analyzer of the summed mono channel.
+    analyzer: Box<EbuR128>,
+    /// Samples of the summed mono channel.
+    samples: Vec<f64>,
+}
+
+impl MonoSum {
+    /// Average the configured channels of `src` into `samples`.
+    fn sum<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(&mut self, src: &S) {
+        let gain = 1.0 / self.channels.len() as f64;
+
+        self.samples.clear();
+        self.samples.resize(src.frames(), 0.0);
+        for &c in self.channels.iter() {
+            src.foreach_sample_zipped(c as usize, self.samples.iter_mut(), |src, dest| {
+                *dest += (*src).to_sample::<f64>() * gain;
+            });
+        }
+    }
+
+    /// Add the summed frames of `src` to the analyzer.
+    fn add<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: &S,
+        prefiltered: bool,
+    ) -> Result<(), Error> {
+        self.sum(src);
+        if prefiltered {
+            self.analyzer.add_filtered_frames_f64(&self.samples)
+        } else {
+            self.analyzer.add_frames_f64(&self.samples)
+        }
+    }
+}
+
+/// Integrated loudness of fixed-duration segments configured with
+/// [`EbuR128::set_segment_duration`](struct.EbuR128.html#method.set_segment_duration).
+#[derive(Debug)]
+struct Segments {
+    /// Duration of each segment in seconds.
+    duration: f64,
+    /// Duration of each segment in frames.
+    frames: u64,
+    /// Position of the end of the current segment.
+    end: u64,
+    /// Energies of the gating blocks completely inside the current segment.
+    blocks: crate::history::History,
+    /// Integrated loudness of each completed segment.
+    series: Vec<f64>,
+}
+
+impl Segments {
+    /// Start the segments at `position` with the given duration in frames.
+    fn restart(&mut self, position: u64, frames: u64) {
+        self.frames = frames;
+        self.end = position + frames;
+        self.blocks.reset();
+    }
+
+    /// Complete all segments that end before `position`.
+    fn complete_before(&mut self, position: u64) {
+        while self.end < position {
+            self.series.push(self.blocks.gated_loudness());
+            self.blocks.reset();
+            self.end += self.frames;
+        }
+    }
+
+    /// Add the energy of a gating block of `block_frames` that ends at `position`.
+    fn add_block(&mut self, position: u64, block_frames: usize, energy: f64) {
+        self.complete_before(position);
+        if position >= self.end - self.frames + block_frames as u64 {
+            self.blocks.add(energy);
+        }
+    }
+}
+
+/// Named region of the stream added with
+/// [`EbuR128::add_region`](struct.EbuR128.html#method.add_region).
+#[derive(Debug)]
+struct Region {
+    /// Name of the region.
+    name: String,
+    /// Position of the first frame of the region.
+    start: u64,
+    /// Position after the last frame of the region.
+    end: u64,
+    /// Energies of the gating blocks whose center is inside the region.
+    blocks: crate::history::History,
 }
 
 /// EBU R128 loudness analyzer.
+///
+/// Samples of all supported types are converted to the same internal representation, with integer
+/// samples scaled to the range `[-1.0, 1.0)`. The different `add_frames_*` functions can thus be
+/// mixed across calls, e.g. when the sample type of a source changes mid-stream.
 pub struct EbuR128 {
     /// The current mode.
     mode: Mode,
@@ -161,6 +544,8 @@ pub struct EbuR128 {
     audio_data: Box<[f64]>,
     /// Current index for audio_data.
     audio_data_index: usize,
+    /// Number of frames in the audio buffer since it was cleared, up to its size.
+    window_frames: usize,
 
     /// How many frames are needed for a gating block. Will correspond to 400ms
     /// of audio at initialization, and 100ms after the first block (75% overlap
@@ -176,11 +561,11 @@ pub struct EbuR128 {
     /// Filter.
     filter: crate::filter::Filter,
 
-    /// Block energy history.
-    block_energy_history: crate::history::History,
+    /// Block energy history, only with `Mode::I`.
+    block_energy_history: Option<crate::history::History>,
 
-    /// Short term block energy history.
-    short_term_block_energy_history: crate::history::History,
+    /// Short term block energy history, only with `Mode::LRA`.
+    short_term_block_energy_history: Option<crate::history::History>,
     short_term_frame_counter: usize,
 
     /// Maximum sample peak, one per channel.
@@ -188,20 +573,137 @@ pub struct EbuR128 {
 
     /// Maximum true peak, one per channel.
     true_peak: Box<[f64]>,
+    /// Events where the true peak exceeded the ceiling, one per channel.
+    true_peak_over_counts: Box<[u64]>,
 
     /// The maximum window duration in ms.
     window: usize,
     history: usize,
+    /// The short-term window duration in ms.
+    shortterm_window: usize,
+    /// Whether the short-term window is centered on the current position.
+    shortterm_centered: bool,
+    /// The maximum true peak window duration in ms.
+    true_peak_window: usize,
+    /// The sample peak in dBFS below which the true peak is not calculated.
+    true_peak_threshold: Option<f64>,
+    /// The ceiling in dBTP above which true peak events are counted.
+    true_peak_over_ceiling: Option<f64>,
+    /// The applied stages of the K-weighting filter.
+    kweighting: KWeighting,
+    /// The cutoff frequency in Hz of the high-pass filter before the K-weighting filter.
+    input_highpass: Option<f64>,
+    /// The number of histogram bins.
+    histogram_bins: usize,
+    /// Whether the sums of the block energies compensate rounding errors.
+    stable_accumulation: bool,
+    /// Whether the energies of the integrated loudness history are kept sorted.
+    sorted_integrated: bool,
+
+    /// Whether incomplete trailing frames are buffered.
+    allow_partial_frames: bool,
+    /// Samples of an incomplete trailing frame.
+    partial_frame: Vec<f64>,
+    /// Whether added frames are excluded from the measurement.
+    paused: bool,
+    /// Whether `flush()` adds the trailing partial gating block to the integrated loudness.
+    finalize_partial: bool,
+    /// Whether the trailing partial gating block was already added by `flush()`.
+    partial_block_flushed: bool,
+
+    /// Resampler for the input frames if resampling is enabled.
+    #[cfg(feature = "resampling")]
+    resampler: Option<crate::resampler::Resampler>,
+    /// Histories with the other backend, if enabled.
+    #[cfg(feature = "dual-history")]
+    dual_history: Option<DualHistory>,
+
+    /// Decay of the displayed peak in dB per second.
+    peak_decay: f64,
+    /// Decaying peak for display, one per channel.
+    displayed_peak: Box<[f64]>,
+
+    /// Custom gating predicate for the integrated loudness.
+    gating_predicate: Option<Box<GatingPredicate>>,
+
+    /// Number of frames added since the last reset.
+    frames_added: u64,
+    /// Maximum duration in seconds of the added frames.
+    max_duration: Option<f64>,
+    /// Callback for the momentary loudness of each completed block.
+    momentary_callback: Option<Box<MomentaryCallback>>,
+    /// Callback for the metered values of each completed block.
+    block_log_callback: Option<Box<BlockLogCallback>>,
+    /// Alarm for crossing a loudness threshold.
+    threshold_alarm: Option<ThresholdAlarm>,
+    /// Summed mono measurement.
+    mono_sum: Option<MonoSum>,
+    /// Weighted energies of each channel in each gating block, if enabled.
+    channel_block_energies: Option<std::collections::VecDeque<f64>>,
+    /// Sum of the samples of each channel for the DC offset, if enabled.
+    dc_sums: Option<Box<[f64]>>,
+    /// Number of frames in `dc_sums`.
+    dc_frames: u64,
+    /// Maximum and minimum sample value of each channel, if enabled.
+    signed_peaks: Option<Box<[(f64, f64)]>>,
+    /// Integrated loudness of fixed-duration segments, if enabled.
+    segments: Option<Segments>,
+    /// Maximum true peak of each gating block, if enabled.
+    block_true_peaks: Option<Vec<f32>>,
+    /// Gain in dB and as linear factor that is applied to all added samples, if any.
+    pregain: Option<(f64, f64)>,
+    /// Named regions whose integrated loudness is measured.
+    regions: Vec<Region>,
+    /// Handling of gaps between timestamped frames.
+    gap_policy: GapPolicy,
+    /// Maximum gap in seconds between timestamped frames that is filled with silence.
+    max_silence_gap: f64,
+    /// Timestamp of the frame expected next by `add_frames_at_f32`, if any were added yet.
+    next_timestamp: Option<u64>,
+}
+
+/// Gating predicate for [`EbuR128::set_gating_predicate`](struct.EbuR128.html#method.set_gating_predicate).
+///
+/// This is a `Fn` and not a `FnMut` because it is called from
+/// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global), which only borrows
+/// the instance immutably.
+pub type GatingPredicate = dyn Fn(f64) -> bool + Send + Sync;
+
+/// Callback for [`EbuR128::set_momentary_callback`](struct.EbuR128.html#method.set_momentary_callback).
+pub type MomentaryCallback = dyn FnMut(u64, f64) + Send + Sync;
+
+/// Metered values at the end of a completed block, passed to the callback set with
+/// [`EbuR128::set_block_log_callback`](struct.EbuR128.html#method.set_block_log_callback).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockLog {
+    /// Number of frames added since the instance was created or reset at the end of the block,
+    /// see [`EbuR128::frames_added`](struct.EbuR128.html#method.frames_added). Dividing it by the
+    /// sample rate gives the timestamp in seconds.
+    pub position: u64,
+    /// Momentary loudness in LUFS.
+    pub momentary: f64,
+    /// Short-term loudness in LUFS if `Mode::S` is set.
+    pub shortterm: Option<f64>,
+    /// Global integrated loudness in LUFS of all blocks so far if `Mode::I` is set.
+    pub integrated: Option<f64>,
+    /// Maximum true peak of all channels so far if `Mode::TRUE_PEAK` is set, see
+    /// [`EbuR128::true_peak`](struct.EbuR128.html#method.true_peak).
+    pub true_peak: Option<f64>,
 }
 
+/// Callback for [`EbuR128::set_block_log_callback`](struct.EbuR128.html#method.set_block_log_callback).
+pub type BlockLogCallback = dyn FnMut(&BlockLog) + Send + Sync;
+
 impl fmt::Debug for EbuR128 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("EbuR128")
+        let mut debug = f.debug_struct("EbuR128");
+        debug
             .field("mode", &self.mode)
             .field("rate", &self.rate)
             .field("channels", &self.channels)
             // Not audio data
             .field("audio_data_index", &self.audio_data_index)
+            .field("window_frames", &self.window_frames)
             .field("needed_frames", &self.needed_frames)
             .field("channel_map", &self.channel_map)
             .field("samples_in_100ms", &self.samples_in_100ms)
@@ -214,9 +716,64 @@ impl fmt::Debug for EbuR128 {
             .field("short_term_frame_counter", &self.short_term_frame_counter)
             .field("sample_peak", &self.sample_peak)
             .field("true_peak", &self.true_peak)
+            .field("true_peak_over_counts", &self.true_peak_over_counts)
             .field("window", &self.window)
             .field("history", &self.history)
-            .finish()
+            .field("shortterm_window", &self.shortterm_window)
+            .field("shortterm_centered", &self.shortterm_centered)
+            .field("true_peak_window", &self.true_peak_window)
+            .field("true_peak_threshold", &self.true_peak_threshold)
+            .field("true_peak_over_ceiling", &self.true_peak_over_ceiling)
+            .field("kweighting", &self.kweighting)
+            .field("input_highpass", &self.input_highpass)
+            .field("histogram_bins", &self.histogram_bins)
+            .field("stable_accumulation", &self.stable_accumulation)
+            .field("sorted_integrated", &self.sorted_integrated)
+            .field("peak_decay", &self.peak_decay)
+            .field("displayed_peak", &self.displayed_peak)
+            .field("gating_predicate", &self.gating_predicate.is_some())
+            .field("frames_added", &self.frames_added)
+            .field("max_duration", &self.max_duration)
+            .field("momentary_callback", &self.momentary_callback.is_some())
+            .field("block_log_callback", &self.block_log_callback.is_some())
+            .field(
+                "threshold_alarm",
+                &self
+                    .threshold_alarm
+                    .as_ref()
+                    .map(|alarm| (alarm.kind, alarm.threshold, alarm.above)),
+            )
+            .field(
+                "mono_sum",
+                &self
+                    .mono_sum
+                    .as_ref()
+                    .map(|mono_sum| (&mono_sum.channels, &mono_sum.analyzer)),
+            )
+            .field("channel_block_energies", &self.channel_block_energies)
+            .field("dc_sums", &self.dc_sums)
+            .field("dc_frames", &self.dc_frames)
+            .field("signed_peaks", &self.signed_peaks)
+            .field("segments", &self.segments)
+            .field(
+                "block_true_peaks",
+                &self.block_true_peaks.as_ref().map(|peaks| peaks.len()),
+            )
+            .field("pregain", &self.pregain)
+            .field("regions", &self.regions)
+            .field("gap_policy", &self.gap_policy)
+            .field("max_silence_gap", &self.max_silence_gap)
+            .field("next_timestamp", &self.next_timestamp)
+            .field("allow_partial_frames", &self.allow_partial_frames)
+            .field("partial_frame", &self.partial_frame)
+            .field("paused", &self.paused)
+            .field("finalize_partial", &self.finalize_partial)
+            .field("partial_block_flushed", &self.partial_block_flushed);
+        #[cfg(feature = "resampling")]
+        debug.field("resampler", &self.resampler);
+        #[cfg(feature = "dual-history")]
+        debug.field("dual_history", &self.dual_history);
+        debug.finish()
     }
 }
 
@@ -256,23 +813,43 @@ pub(crate) fn default_channel_map(channels: u32) -> Vec<Channel> {
 }
 
 const MAX_RATE: u32 = 2822400;
-const MAX_CHANNELS: u32 = 64;
+
+/// Maximum number of channels of an [`EbuR128`](struct.EbuR128.html) instance.
+///
+/// This bounds the memory that is allocated per channel, e.g. for channel counts parsed from
+/// untrusted files.
+pub const MAX_CHANNELS: u32 = 64;
+
+/// Number of samples of the stack buffers that frames are converted in before processing them,
+/// e.g. for [`EbuR128::add_frames_from`](struct.EbuR128.html#method.add_frames_from). This holds
+/// at least 64 frames of [`MAX_CHANNELS`] channels.
+const CHUNK_SAMPLES: usize = 4096;
 
 impl EbuR128 {
-    /// Allocate audio data buffer used by the filter and check if we can allocate enough memory
-    /// for it.
-    fn allocate_audio_data(channels: u32, rate: u32, window: usize) -> Result<Box<[f64]>, Error> {
+    /// Number of frames of audio data needed for a window of `window` ms.
+    fn audio_data_frames(rate: u32, window: usize) -> Result<usize, Error> {
         let samples_in_100ms = (rate as usize + 5) / 10;
 
         let mut audio_data_frames = (rate as usize).checked_mul(window).ok_or(Error::NoMem)? / 1000;
-        if audio_data_frames % samples_in_100ms != 0 {
+        let remainder = audio_data_frames
+            .checked_rem(samples_in_100ms)
+            .ok_or(Error::NoMem)?;
+        if remainder != 0 {
             // round up to multiple of samples_in_100ms
             audio_data_frames = audio_data_frames
                 .checked_add(samples_in_100ms)
                 .ok_or(Error::NoMem)?
-                - (audio_data_frames % samples_in_100ms);
+                - remainder;
         }
 
+        Ok(audio_data_frames)
+    }
+
+    /// Allocate audio data buffer used by the filter and check if we can allocate enough memory
+    /// for it.
+    fn allocate_audio_data(channels: u32, rate: u32, window: usize) -> Result<Box<[f64]>, Error> {
+        let audio_data_frames = Self::audio_data_frames(rate, window)?;
+
         let audio_data = vec![
             0.0;
             audio_data_frames
@@ -284,39 +861,136 @@ impl EbuR128 {
         Ok(audio_data)
     }
 
+    /// Estimate the memory in bytes used by an instance with the given configuration.
+    ///
+    /// This includes the instance itself, the audio data of the maximum window, the filter and
+    /// peak states of all channels and the loudness histories after measuring `max_history_s`
+    /// seconds of audio with that maximum history, see
+    /// [`EbuR128::set_max_history`](struct.EbuR128.html#method.set_max_history). `Mode::HISTOGRAM`
+    /// is set or cleared according to `use_histogram`.
+    ///
+    /// With `use_histogram` each history needs a constant 8KB for its histogram, independent of
+    /// `max_history_s`. Otherwise the histories are queues that need 8 bytes per 100ms of audio
+    /// for `Mode::I` and 16 bytes per 3s for `Mode::LRA`, with an initial capacity of 5000
+    /// entries each. Growing queues double their capacity when full, so the actual usage can be
+    /// higher unless [`EbuR128::reserve_duration`](struct.EbuR128.html#method.reserve_duration)
+    /// was called.
+    pub fn estimated_history_bytes(
+        channels: u32,
+        rate: u32,
+        mode: Mode,
+        use_histogram: bool,
+        max_history_s: f64,
+    ) -> usize {
+        let window = if mode.contains(Mode::S) { 3000 } else { 400 };
+        let audio_data_frames =
+            Self::audio_data_frames(rate, Self::audio_data_window(mode, window)).unwrap_or(0);
+
+        let channels_usize = channels as usize;
+        let mut size = std::mem::size_of::<Self>()
+            .saturating_add(audio_data_frames.saturating_mul(channels_usize) * 8)
+            // Sample peak, true peak, true peak events and displayed peak, and the channel map
+            .saturating_add(channels_usize * (4 * 8 + std::mem::size_of::<Channel>()))
+            .saturating_add(crate::filter::Filter::heap_size(
+                rate,
+                channels,
+                mode.contains(Mode::TRUE_PEAK),
+            ));
+
+        let history_ms = f64::min(max_history_s * 1000.0, usize::MAX as f64) as usize;
+        if mode.contains(Mode::I) {
+            size = size.saturating_add(crate::history::History::heap_size(
+                use_histogram,
+                false,
+                history_ms / 100,
+            ));
+        }
+        if mode.contains(Mode::LRA) {
+            size = size.saturating_add(crate::history::History::heap_size(
+                use_histogram,
+                true,
+                history_ms / 3000,
+            ));
+        }
+
+        size
+    }
+
+    /// Whether only the sample and/or true peak is tracked, without any filtering.
+    fn is_peak_only(mode: Mode) -> bool {
+        let mode = mode & !Mode::HISTOGRAM;
+        mode == Mode::SAMPLE_PEAK || mode == Mode::TRUE_PEAK
+    }
+
+    /// Window for which audio data has to be kept, which is none if only the peaks are tracked.
+    fn audio_data_window(mode: Mode, window: usize) -> usize {
+        if Self::is_peak_only(mode) {
+            0
+        } else {
+            window
+        }
+    }
+
     /// Create a new instance with the given configuration.
+    ///
+    /// The sample rate must be the actual sample rate of the added frames. Adding frames at a
+    /// different rate, e.g. at 96kHz to an instance created for 48kHz, doesn't fail but gives
+    /// wrong loudness values because the filters and the durations of all windows are based on
+    /// the configured rate. [`EbuR128::check_duration`](struct.EbuR128.html#method.check_duration)
+    /// allows to detect such a mismatch if the duration of the audio is known.
+    ///
+    /// Returns `Error::InvalidChannelIndex` if there are more than
+    /// [`MAX_CHANNELS`](constant.MAX_CHANNELS.html) channels.
     pub fn new(channels: u32, rate: u32, mode: Mode) -> Result<Self, Error> {
-        if channels == 0 || channels > MAX_CHANNELS {
+        if channels == 0 {
             return Err(Error::NoMem);
         }
 
+        if channels > MAX_CHANNELS {
+            return Err(Error::InvalidChannelIndex);
+        }
+
         if !(16..=MAX_RATE).contains(&rate) {
             return Err(Error::NoMem);
         }
 
+        if !crate::filter::is_stable(rate) {
+            return Err(Error::UnsupportedRate);
+        }
+
+        if !mode.is_valid() {
+            return Err(Error::InvalidMode);
+        }
+
         let sample_peak = vec![0.0; channels as usize];
         let true_peak = vec![0.0; channels as usize];
 
         let history = usize::MAX;
         let samples_in_100ms = (rate as usize + 5) / 10;
 
-        let window = if mode.contains(Mode::S) {
-            3000
-        } else if mode.contains(Mode::M) {
-            400
-        } else {
-            return Err(Error::InvalidMode);
-        };
+        let window = if mode.contains(Mode::S) { 3000 } else { 400 };
 
-        let audio_data = Self::allocate_audio_data(channels, rate, window)?;
+        let audio_data =
+            Self::allocate_audio_data(channels, rate, Self::audio_data_window(mode, window))?;
         // start at the beginning of the buffer
         let audio_data_index = 0;
 
-        let block_energy_history =
-            crate::history::History::new(mode.contains(Mode::HISTOGRAM), history / 100);
+        // No need to allocate histories that are never filled
+        let use_histogram = mode.contains(Mode::HISTOGRAM);
+        let block_energy_history = if mode.contains(Mode::I) {
+            Some(crate::history::History::new(use_histogram, history / 100))
+        } else {
+            None
+        };
 
-        let short_term_block_energy_history =
-            crate::history::History::new(mode.contains(Mode::HISTOGRAM), history / 3000);
+        let short_term_block_energy_history = if mode.contains(Mode::LRA) {
+            Some(crate::history::History::new_sorted(
+                use_histogram,
+                history / 3000,
+            ))
+        } else {
+            None
+        };
         let short_term_frame_counter = 0;
 
         let filter = crate::filter::Filter::new(
@@ -337,6 +1011,7 @@ impl EbuR128 {
             channels,
             audio_data,
             audio_data_index,
+            window_frames: 0,
             needed_frames,
             channel_map: channel_map.into_boxed_slice(),
             samples_in_100ms,
@@ -346,74 +1021,452 @@ impl EbuR128 {
             short_term_frame_counter,
             sample_peak: sample_peak.into_boxed_slice(),
             true_peak: true_peak.into_boxed_slice(),
+            true_peak_over_counts: vec![0; channels as usize].into_boxed_slice(),
             window,
             history,
+            shortterm_window: 3000,
+            shortterm_centered: false,
+            true_peak_window: 0,
+            true_peak_threshold: None,
+            true_peak_over_ceiling: None,
+            kweighting: KWeighting::Full,
+            input_highpass: None,
+            histogram_bins: crate::history::DEFAULT_HISTOGRAM_BINS,
+            stable_accumulation: false,
+            sorted_integrated: false,
+            allow_partial_frames: false,
+            partial_frame: Vec::new(),
+            paused: false,
+            finalize_partial: false,
+            partial_block_flushed: false,
+            #[cfg(feature = "resampling")]
+            resampler: None,
+            #[cfg(feature = "dual-history")]
+            dual_history: None,
+            peak_decay: 0.0,
+            displayed_peak: vec![0.0; channels as usize].into_boxed_slice(),
+            gating_predicate: None,
+            frames_added: 0,
+            max_duration: None,
+            momentary_callback: None,
+            block_log_callback: None,
+            threshold_alarm: None,
+            mono_sum: None,
+            channel_block_energies: None,
+            dc_sums: None,
+            dc_frames: 0,
+            signed_peaks: None,
+            segments: None,
+            block_true_peaks: None,
+            pregain: None,
+            regions: Vec::new(),
+            gap_policy: GapPolicy::default(),
+            max_silence_gap: 10.0,
+            next_timestamp: None,
         })
     }
 
+    /// Create a new instance with an explicit choice of the loudness history.
+    ///
+    /// This is the same as [`EbuR128::new`](struct.EbuR128.html#method.new) with `Mode::HISTOGRAM`
+    /// set or cleared according to `use_histogram`.
+    ///
+    /// With a histogram, the history used for `Mode::I` and `Mode::LRA` has a constant size of
+    /// about 8KB each but the measured loudness is quantized to 0.1 LU. Otherwise the history is
+    /// a queue of all measured blocks, which is exact but grows with the measured duration up to
+    /// the maximum history, see [`EbuR128::set_max_history`](struct.EbuR128.html#method.set_max_history).
+    /// [`EbuR128::estimated_history_bytes`](struct.EbuR128.html#method.estimated_history_bytes)
+    /// allows to estimate the memory usage before creating an instance.
+    pub fn with_histogram(
+        channels: u32,
+        rate: u32,
+        mode: Mode,
+        use_histogram: bool,
+    ) -> Result<Self, Error> {
+        let mode = if use_histogram {
+            mode | Mode::HISTOGRAM
+        } else {
+            mode & !Mode::HISTOGRAM
+        };
+
+        Self::new(channels, rate, mode)
+    }
+
+    /// Check if the BS.1770 filter is stable at the given sample rate.
+    ///
+    /// The filter coefficients are calculated for each sample rate, which results in an unstable
+    /// filter for some very low sample rates (e.g. 3000 Hz). Creating an instance with such a
+    /// sample rate fails with `Error::UnsupportedRate`.
+    pub fn check_filter_stability(rate: u32) -> bool {
+        crate::filter::is_stable(rate)
+    }
+
     /// Get the configured mode.
     pub fn mode(&self) -> Mode {
         self.mode
     }
 
+    /// Add modes to the configured mode while measuring.
+    ///
+    /// This allocates what the additional modes need, e.g. the history for `Mode::LRA` or the
+    /// interpolator for `Mode::TRUE_PEAK`, and keeps all measurements of the already configured
+    /// modes. The added measurements only reflect the frames that are added after upgrading,
+    /// e.g. [`EbuR128::loudness_range`](struct.EbuR128.html#method.loudness_range) only
+    /// considers short-term blocks that are completed afterwards.
+    ///
+    /// If the added modes need a larger audio buffer than the maximum window, e.g. when adding
+    /// `Mode::S` to `Mode::M`, the buffer is reallocated like with
+    /// [`EbuR128::set_max_window`](struct.EbuR128.html#method.set_max_window) and the current
+    /// unfinished gating block is lost. The same happens when upgrading an instance that only
+    /// measures peaks.
+    ///
+    /// Returns `Error::InvalidMode` if the resulting mode is invalid, if `Mode::HISTOGRAM` is
+    /// added as the history of the already configured modes can't be changed, or if `Mode::LRA`
+    /// is added with a maximum history shorter than the short-term window.
+    pub fn upgrade_mode(&mut self, additional: Mode) -> Result<(), Error> {
+        let mode = self.mode | additional;
+        if !mode.is_valid()
+            || (additional.contains(Mode::HISTOGRAM) && !self.mode.contains(Mode::HISTOGRAM))
+        {
+            return Err(Error::InvalidMode);
+        }
+
+        if mode == self.mode {
+            return Ok(());
+        }
+
+        let added = mode - self.mode;
+        if added.contains(Mode::LRA & !Mode::S) && self.history < self.shortterm_window {
+            return Err(Error::InvalidMode);
+        }
+
+        let was_peak_only = Self::is_peak_only(self.mode);
+        let window = if mode.contains(Mode::S) {
+            usize::max(self.window, self.shortterm_window)
+        } else {
+            usize::max(self.window, 400)
+        };
+        if was_peak_only != Self::is_peak_only(mode) || window != self.window {
+            self.audio_data = Self::allocate_audio_data(
+                self.channels,
+                self.rate,
+                Self::audio_data_window(mode, window),
+            )?;
+            self.window = window;
+
+            // the first block needs 400ms of audio data
+            self.needed_frames = self.samples_in_100ms * 4;
+            // start at the beginning of the buffer
+            self.audio_data_index = 0;
+            self.window_frames = 0;
+        }
+
+        if let Some(ref mut mono_sum) = self.mono_sum {
+            mono_sum
+                .analyzer
+                .upgrade_mode(additional & (Mode::I | Mode::LRA))?;
+        }
+
+        let use_histogram = mode.contains(Mode::HISTOGRAM);
+        if added.contains(Mode::I & !Mode::M) {
+            let mut history = crate::history::History::new(use_histogram, self.history / 100);
+            history.set_histogram_bins(self.histogram_bins);
+            history.set_stable_accumulation(self.stable_accumulation);
+            history.set_sorted(self.sorted_integrated);
+            self.block_energy_history = Some(history);
+        }
+        if added.contains(Mode::LRA & !Mode::S) {
+            let mut history =
+                crate::history::History::new_sorted(use_histogram, self.history / 3000);
+            history.set_histogram_bins(self.histogram_bins);
+            history.set_stable_accumulation(self.stable_accumulation);
+            self.short_term_block_energy_history = Some(history);
+            // the first short-term block needs a whole short-term window of audio data
+            self.short_term_frame_counter = 0;
+        }
+
+        self.filter.enable_peaks(
+            self.rate,
+            mode.contains(Mode::SAMPLE_PEAK),
+            mode.contains(Mode::TRUE_PEAK),
+        );
+
+        self.mode = mode;
+
+        Ok(())
+    }
+
+    /// Create a new instance that resamples all frames from `rate` to `target_rate` before
+    /// measuring.
+    ///
+    /// This allows measuring audio at sample rates that are not supported by the filter, see
+    /// [`EbuR128::check_filter_stability`](struct.EbuR128.html#method.check_filter_stability), or
+    /// whose actual rate differs from the declared one. The frames are resampled by linear
+    /// interpolation, which attenuates high frequencies by `sinc²(f / rate)`, e.g. by about
+    /// 0.37 dB at 5 kHz and 1.5 dB at 10 kHz for 44.1 kHz input. When downsampling, the frames are
+    /// low-pass filtered at 40% of `target_rate` first to prevent aliasing, which removes all
+    /// content above e.g. 19.2 kHz for a `target_rate` of 48 kHz. The resulting loudness is thus
+    /// slightly lower for content with much high-frequency energy, and sample and true peaks are
+    /// measured on the resampled frames.
+    ///
+    /// [`EbuR128::rate`](struct.EbuR128.html#method.rate) returns `target_rate`, and
+    /// [`EbuR128::change_parameters`](struct.EbuR128.html#method.change_parameters) changes the
+    /// input rate while keeping `target_rate`.
+    ///
+    /// This is only available with the `resampling` feature.
+    #[cfg(feature = "resampling")]
+    pub fn with_resampling(
+        channels: u32,
+        rate: u32,
+        mode: Mode,
+        target_rate: u32,
+    ) -> Result<Self, Error> {
+        if !(1..=MAX_RATE).contains(&rate) {
+            return Err(Error::NoMem);
+        }
+
+        let mut ebu = Self::new(channels, target_rate, mode)?;
+        ebu.resampler = Some(crate::resampler::Resampler::new(
+            channels,
+            rate,
+            target_rate,
+        ));
+
+        Ok(ebu)
+    }
+
     /// Get the configured number of channels.
     pub fn channels(&self) -> u32 {
         self.channels
     }
 
+    /// Sample rate of the added frames, which is the rate before resampling with
+    /// [`EbuR128::with_resampling`](struct.EbuR128.html#method.with_resampling).
+    fn input_rate(&self) -> u32 {
+        #[cfg(feature = "resampling")]
+        if let Some(ref resampler) = self.resampler {
+            return resampler.input_rate();
+        }
+
+        self.rate
+    }
+
     /// Get the configured sample rate.
+    ///
+    /// Wrappers can compare this to the sample rate of the audio they add to detect a mismatch,
+    /// which is not detected by the instance itself and results in wrong loudness values.
     pub fn rate(&self) -> u32 {
         self.rate
     }
 
+    /// Check that the duration of the added frames at the configured sample rate matches the
+    /// known duration of the audio in seconds, e.g. from the container.
+    ///
+    /// This detects frames that are added at a different sample rate than the configured one, a
+    /// common integration mistake that silently gives wrong loudness values. The durations match
+    /// if they differ by at most 1% or 100ms, so that the mismatch of e.g. 44.1kHz and 48kHz is
+    /// still detected. All added frames count, including frames added while paused. For
+    /// development builds, the check can be turned into an assertion:
+    ///
+    /// ```
+    /// # use ebur128::{EbuR128, Mode};
+    /// let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+    /// ebu.add_frames_f32(&vec![0.0; 48_000]).unwrap();
+    /// debug_assert!(ebu.check_duration(1.0).is_ok());
+    /// assert!(ebu.check_duration(0.5).is_err());
+    /// ```
+    ///
+    /// Returns `Error::DurationMismatch` if the durations don't match.
+    pub fn check_duration(&self, expected_seconds: f64) -> Result<(), Error> {
+        let seconds = self.frames_to_seconds(self.frames_added);
+        let tolerance = f64::max(0.01 * expected_seconds, 0.1);
+
+        let difference = f64::abs(seconds - expected_seconds);
+        if difference.is_nan() || difference > tolerance {
+            trace!(
+                "Added {} seconds at {}Hz but expected {} seconds",
+                seconds,
+                self.rate,
+                expected_seconds
+            );
+            return Err(Error::DurationMismatch);
+        }
+
+        Ok(())
+    }
+
+    /// Get the number of frames between two gating blocks.
+    ///
+    /// This is the hop size of 100ms for the current sample rate (rounded). Gating blocks are
+    /// completed after every `block_size_frames()` frames, besides the first one that needs four
+    /// times as many frames. Adding frames in chunks of a multiple of this size keeps the chunks
+    /// aligned to the gating blocks.
+    pub fn block_size_frames(&self) -> usize {
+        self.samples_in_100ms
+    }
+
+    /// Convert a number of frames at the configured sample rate to seconds.
+    pub fn frames_to_seconds(&self, frames: u64) -> f64 {
+        frames as f64 / self.rate as f64
+    }
+
+    /// Convert seconds to the nearest number of frames at the configured sample rate.
+    ///
+    /// Negative and `NaN` durations result in zero frames.
+    pub fn seconds_to_frames(&self, seconds: f64) -> u64 {
+        (seconds * self.rate as f64).round() as u64
+    }
+
     /// Get the configured channel types.
     pub fn channel_map(&self) -> &[Channel] {
         &self.channel_map
     }
 
-    /// Get the configured maximum window duration in ms.
-    pub fn max_window(&self) -> usize {
-        self.window
+    /// Get the configured stages of the K-weighting filter.
+    pub fn kweighting(&self) -> KWeighting {
+        self.kweighting
     }
 
-    /// Get the configured maximum history in ms.
-    pub fn max_history(&self) -> usize {
-        self.history
+    /// Set the stages of the K-weighting filter.
+    ///
+    /// The default is `KWeighting::Full`, anything else deviates from ITU BS.1770 and is only
+    /// useful for understanding the effect of each stage of the filter. Note that this resets the
+    /// filter state, so it should be set before adding any frames.
+    pub fn set_kweighting(&mut self, kweighting: KWeighting) {
+        if kweighting == self.kweighting {
+            return;
+        }
+
+        self.kweighting = kweighting;
+        self.filter.set_kweighting(self.rate, kweighting);
     }
 
-    /// Set channel type.
+    /// Get the cutoff frequency in Hz of the input high-pass filter, see
+    /// [`EbuR128::set_input_highpass`](struct.EbuR128.html#method.set_input_highpass).
+    pub fn input_highpass(&self) -> Option<f64> {
+        self.input_highpass
+    }
+
+    /// Apply a second order Butterworth high-pass filter with the given cutoff frequency in Hz to
+    /// the input before the K-weighting filter, or remove it with `None`.
     ///
-    /// The default is:
+    /// This deviates from ITU BS.1770 and is only meant for previewing how a rumble filter would
+    /// change the loudness without rendering the filtered audio. The default is no filter. The
+    /// sample and true peaks are still measured on the unfiltered input. Note that this resets the
+    /// state of the high-pass filter, so it should be set before adding any frames.
     ///
-    /// * 0 \-> `Left`
-    /// * 1 \-> `Right`
-    /// * 2 \-> `Center`
-    /// * 3 \-> `Unused`
-    /// * 4 \-> `LeftSurround`
-    /// * 5 \-> `RightSurround`
-    /// * _ \-> `Unused`
-    pub fn set_channel(&mut self, channel_number: u32, value: Channel) -> Result<(), Error> {
-        if channel_number >= self.channels {
-            return Err(Error::InvalidChannelIndex);
+    /// Returns `Error::InvalidMode` if the cutoff frequency is not between 0 Hz and half the
+    /// sample rate. Changing the sample rate later fails with `Error::UnsupportedRate` if the
+    /// cutoff frequency is not below half the new sample rate.
+    pub fn set_input_highpass(&mut self, cutoff_hz: Option<f64>) -> Result<(), Error> {
+        if let Some(cutoff_hz) = cutoff_hz {
+            if !(cutoff_hz > 0.0 && cutoff_hz < self.rate as f64 / 2.0) {
+                return Err(Error::InvalidMode);
+            }
         }
 
-        if value == Channel::DualMono && (self.channels != 1 || channel_number != 0) {
-            return Err(Error::InvalidChannelIndex);
+        if let Some(ref mut mono_sum) = self.mono_sum {
+            mono_sum.analyzer.set_input_highpass(cutoff_hz)?;
         }
 
-        self.channel_map[channel_number as usize] = value;
+        self.input_highpass = cutoff_hz;
+        self.filter.set_highpass(self.rate, cutoff_hz);
+
         Ok(())
     }
 
-    /// Set channel types.
-    ///
-    /// The default is:
-    ///
-    /// * 0 \-> `Left`
-    /// * 1 \-> `Right`
-    /// * 2 \-> `Center`
-    /// * 3 \-> `Unused`
-    /// * 4 \-> `LeftSurround`
+    /// Get the configured maximum window duration in ms.
+    pub fn max_window(&self) -> usize {
+        self.window
+    }
+
+    /// Get the configured maximum history in ms.
+    pub fn max_history(&self) -> usize {
+        self.history
+    }
+
+    /// Get the configured short-term window duration in ms.
+    pub fn shortterm_window(&self) -> usize {
+        self.shortterm_window
+    }
+
+    /// Get the lowest loudness in LUFS that contributes to the integrated loudness and the
+    /// loudness range, i.e. the absolute gate of -70 LUFS.
+    ///
+    /// Blocks below it are not kept in the histories, and it is the lower bound of the histogram
+    /// bins with `Mode::HISTOGRAM`. The momentary and short-term loudness can be lower, but any
+    /// value below it, including negative infinity for digital silence, means that there is no
+    /// measurable signal. E.g. user-entered loudness targets can be clamped to this.
+    ///
+    /// There is no upper bound in general. A full-scale signal in a single channel is around 0
+    /// LUFS, depending on its frequency, and each additional channel adds its share, e.g. up to
+    /// about +3 dB for stereo. With `Mode::HISTOGRAM` the histogram bins end at +30 LUFS and
+    /// louder blocks are counted in the highest bin, so the integrated loudness and loudness
+    /// range are only precise up to +30 LUFS.
+    pub fn min_loudness_lufs() -> f64 {
+        -70.0
+    }
+
+    /// Get the minimum maximum window duration in ms that is required by the configured mode.
+    ///
+    /// This is the short-term window for `Mode::S` and 400ms for `Mode::M`.
+    pub fn required_window(&self) -> usize {
+        if self.mode.contains(Mode::S) {
+            self.shortterm_window
+        } else if self.mode.contains(Mode::M) {
+            400
+        } else {
+            0
+        }
+    }
+
+    /// Get the minimum maximum history in ms that is required by the configured mode.
+    ///
+    /// `Mode::LRA` needs to keep at least one short-term block and thus requires a history of the
+    /// short-term window. `Mode::I` needs to keep at least one 400ms gating block.
+    pub fn required_history(&self) -> usize {
+        if self.mode.contains(Mode::LRA) {
+            self.shortterm_window
+        } else if self.mode.contains(Mode::I) {
+            400
+        } else {
+            0
+        }
+    }
+
+    /// Set channel type.
+    ///
+    /// The default is:
+    ///
+    /// * 0 \-> `Left`
+    /// * 1 \-> `Right`
+    /// * 2 \-> `Center`
+    /// * 3 \-> `Unused`
+    /// * 4 \-> `LeftSurround`
+    /// * 5 \-> `RightSurround`
+    /// * _ \-> `Unused`
+    pub fn set_channel(&mut self, channel_number: u32, value: Channel) -> Result<(), Error> {
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        if value == Channel::DualMono && (self.channels != 1 || channel_number != 0) {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        self.channel_map[channel_number as usize] = value;
+        Ok(())
+    }
+
+    /// Set channel types.
+    ///
+    /// The default is:
+    ///
+    /// * 0 \-> `Left`
+    /// * 1 \-> `Right`
+    /// * 2 \-> `Center`
+    /// * 3 \-> `Unused`
+    /// * 4 \-> `LeftSurround`
     /// * 5 \-> `RightSurround`
     /// * _ \-> `Unused`
     pub fn set_channel_map(&mut self, channel_map: &[Channel]) -> Result<(), Error> {
@@ -435,31 +1488,93 @@ impl EbuR128 {
     ///
     /// Note that the channel map will be reset when setting a different number of channels. The
     /// current unfinished block will be lost.
+    ///
+    /// Returns `Error::InvalidChannelIndex` if there are more than
+    /// [`MAX_CHANNELS`](constant.MAX_CHANNELS.html) channels.
     pub fn change_parameters(&mut self, channels: u32, rate: u32) -> Result<(), Error> {
-        if channels == 0 || channels > MAX_CHANNELS {
+        if channels == 0 {
             return Err(Error::NoMem);
         }
 
+        if channels > MAX_CHANNELS {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        #[cfg(feature = "resampling")]
+        if let Some(target_rate) = self.resampler.as_ref().map(|r| r.output_rate()) {
+            if !(1..=MAX_RATE).contains(&rate) {
+                return Err(Error::NoMem);
+            }
+
+            self.resampler = Some(crate::resampler::Resampler::new(
+                channels,
+                rate,
+                target_rate,
+            ));
+            return self.change_internal_parameters(channels, target_rate);
+        }
+
+        self.change_internal_parameters(channels, rate)
+    }
+
+    /// Change the number of channels and sample rate of the measurement after resampling.
+    fn change_internal_parameters(&mut self, channels: u32, rate: u32) -> Result<(), Error> {
         if !(16..=MAX_RATE).contains(&rate) {
             return Err(Error::NoMem);
         }
 
+        if !crate::filter::is_stable(rate) {
+            return Err(Error::UnsupportedRate);
+        }
+
+        if let Some(cutoff_hz) = self.input_highpass {
+            if cutoff_hz >= rate as f64 / 2.0 {
+                return Err(Error::UnsupportedRate);
+            }
+        }
+
         if self.rate == rate && self.channels == channels {
             return Ok(());
         }
 
-        self.audio_data = Self::allocate_audio_data(channels, rate, self.window)?;
+        self.partial_frame.clear();
+        self.audio_data = Self::allocate_audio_data(
+            channels,
+            rate,
+            Self::audio_data_window(self.mode, self.window),
+        )?;
 
         if self.channels != channels {
             self.channels = channels;
             self.channel_map = default_channel_map(channels).into_boxed_slice();
+            self.mono_sum = None;
+            if let Some(ref mut channel_block_energies) = self.channel_block_energies {
+                channel_block_energies.clear();
+            }
+            if self.dc_sums.is_some() {
+                self.dc_sums = Some(vec![0.0; channels as usize].into_boxed_slice());
+                self.dc_frames = 0;
+            }
+            if self.signed_peaks.is_some() {
+                self.signed_peaks = Some(vec![(0.0, 0.0); channels as usize].into_boxed_slice());
+            }
             self.sample_peak = vec![0.0; channels as usize].into_boxed_slice();
             self.true_peak = vec![0.0; channels as usize].into_boxed_slice();
+            self.true_peak_over_counts = vec![0; channels as usize].into_boxed_slice();
+            self.displayed_peak = vec![0.0; channels as usize].into_boxed_slice();
         }
 
         if self.rate != rate {
             self.rate = rate;
             self.samples_in_100ms = (rate as usize + 5) / 10;
+            self.next_timestamp = None;
+            if let Some(ref mut mono_sum) = self.mono_sum {
+                mono_sum.analyzer.change_parameters(1, rate)?;
+            }
+            if let Some(ref mut segments) = self.segments {
+                let frames = Self::segment_frames(segments.duration, rate)?;
+                segments.restart(self.frames_added, frames);
+            }
         }
 
         self.filter = crate::filter::Filter::new(
@@ -468,11 +1583,22 @@ impl EbuR128 {
             self.mode.contains(Mode::SAMPLE_PEAK),
             self.mode.contains(Mode::TRUE_PEAK),
         );
+        self.filter
+            .set_true_peak_window(Self::true_peak_window_frames(rate, self.true_peak_window));
+        self.filter
+            .set_true_peak_threshold(self.true_peak_threshold.map(Self::dbfs_to_amplitude));
+        self.filter
+            .set_true_peak_over_ceiling(self.true_peak_over_ceiling.map(Self::dbfs_to_amplitude));
+        if self.kweighting != KWeighting::Full {
+            self.filter.set_kweighting(rate, self.kweighting);
+        }
+        self.filter.set_highpass(rate, self.input_highpass);
 
         // the first block needs 400ms of audio data
         self.needed_frames = self.samples_in_100ms * 4;
         // start at the beginning of the buffer
         self.audio_data_index = 0;
+        self.window_frames = 0;
         // reset short term frame counter
         self.short_term_frame_counter = 0;
 
@@ -484,32 +1610,110 @@ impl EbuR128 {
     /// Set the maximum duration in ms that will be used for
     /// [`EbuR128::loudness_window`](struct.EbuR128.html#method.loudness_window). Note that this
     /// destroys the current content of the audio buffer.
+    ///
+    /// Returns `Error::InvalidMode` if the window is smaller than what is required by the
+    /// configured mode, see [`EbuR128::required_window`](struct.EbuR128.html#method.required_window).
     pub fn set_max_window(&mut self, window: u32) -> Result<(), Error> {
-        let window = if self.mode.contains(Mode::S) {
-            std::cmp::max(window, 3000)
-        } else if self.mode.contains(Mode::M) {
-            std::cmp::max(window, 400)
-        } else {
-            window
-        };
+        if (window as usize) < self.required_window() {
+            return Err(Error::InvalidMode);
+        }
 
         if window as usize == self.window {
             return Ok(());
         }
 
-        self.audio_data = Self::allocate_audio_data(self.channels, self.rate, window as usize)?;
+        self.audio_data = Self::allocate_audio_data(
+            self.channels,
+            self.rate,
+            Self::audio_data_window(self.mode, window as usize),
+        )?;
         self.window = window as usize;
 
         // the first block needs 400ms of audio data
         self.needed_frames = self.samples_in_100ms * 4;
         // start at the beginning of the buffer
         self.audio_data_index = 0;
+        self.window_frames = 0;
+        // reset short term frame counter
+        self.short_term_frame_counter = 0;
+
+        Ok(())
+    }
+
+    /// Set the short-term window duration.
+    ///
+    /// Set the duration in ms of the window used for
+    /// [`EbuR128::loudness_shortterm`](struct.EbuR128.html#method.loudness_shortterm) and the
+    /// short-term blocks of [`EbuR128::loudness_range`](struct.EbuR128.html#method.loudness_range).
+    /// The window must be a non-zero multiple of 100ms and must not be larger than the maximum
+    /// window, see [`EbuR128::set_max_window`](struct.EbuR128.html#method.set_max_window). With
+    /// `Mode::LRA` it must also not be larger than the maximum history, see
+    /// [`EbuR128::set_max_history`](struct.EbuR128.html#method.set_max_history).
+    ///
+    /// Default is 3000ms. Any other value deviates from EBU R128 and is only useful for
+    /// experimentation or alternative standards.
+    pub fn set_shortterm_window(&mut self, window: u32) -> Result<(), Error> {
+        let window = window as usize;
+        if window == 0 || window % 100 != 0 || window > self.window {
+            return Err(Error::InvalidMode);
+        }
+
+        if self.mode.contains(Mode::LRA) && window > self.history {
+            return Err(Error::InvalidMode);
+        }
+
+        if window == self.shortterm_window {
+            return Ok(());
+        }
+
+        self.shortterm_window = window;
         // reset short term frame counter
         self.short_term_frame_counter = 0;
 
         Ok(())
     }
 
+    /// Get whether the short-term window is centered on the current position.
+    pub fn shortterm_centered(&self) -> bool {
+        self.shortterm_centered
+    }
+
+    /// Set whether the short-term window is centered on the current position.
+    ///
+    /// By default the short-term loudness is the loudness of the last 3s of added audio, i.e. of
+    /// a window trailing the current position, so transients only show up with a delay. If the
+    /// window is centered, the current position is considered to be half a window behind the
+    /// last added frame and the caller is expected to add the audio up to that look-ahead. The
+    /// short-term loudness then covers the audio from half a window before to half a window after
+    /// the current position.
+    ///
+    /// The measured values are the same in both cases, only the position they belong to differs
+    /// by [`EbuR128::shortterm_latency`](struct.EbuR128.html#method.shortterm_latency). For
+    /// offline analysis the look-ahead is free, while for real-time use it adds a latency of half
+    /// the short-term window, i.e. 1.5s by default.
+    pub fn set_shortterm_centered(&mut self, centered: bool) {
+        self.shortterm_centered = centered;
+    }
+
+    /// Get the latency of the short-term loudness in frames.
+    ///
+    /// This is the look-ahead in frames that has to be added beyond the current position before
+    /// [`EbuR128::loudness_shortterm`](struct.EbuR128.html#method.loudness_shortterm) belongs to
+    /// it: half the short-term window if the window is centered, see
+    /// [`EbuR128::set_shortterm_centered`](struct.EbuR128.html#method.set_shortterm_centered),
+    /// and 0 otherwise.
+    pub fn shortterm_latency(&self) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::S) {
+            return Err(Error::InvalidMode);
+        }
+
+        if !self.shortterm_centered {
+            return Ok(0.0);
+        }
+
+        Ok(self.shortterm_frames() as f64 / 2.0)
+    }
+
     /// Set the maximum history.
     ///
     /// Set the maximum history in ms that will be stored for loudness integration. More history
@@ -519,16 +1723,16 @@ impl EbuR128 {
     /// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global) when
     /// `Mode::HISTOGRAM` is not set.
     ///
-    /// Default is `ULONG_MAX` (at least ~50 days). Minimum is 3000ms for `Mode::LRA` and 400ms
-    /// for `Mode::M`.
+    /// Default is `ULONG_MAX` (at least ~50 days), i.e. the history grows as needed, see
+    /// [`EbuR128::reserve_duration`](struct.EbuR128.html#method.reserve_duration) for avoiding
+    /// reallocations if the duration is known. Returns `Error::InvalidMode` if the history is
+    /// smaller than what is required by the configured mode, i.e. the short-term window (3000ms by
+    /// default) for `Mode::LRA` and 400ms for `Mode::I`, see
+    /// [`EbuR128::required_history`](struct.EbuR128.html#method.required_history).
     pub fn set_max_history(&mut self, history: u32) -> Result<(), Error> {
-        let history = if self.mode.contains(Mode::S) {
-            std::cmp::max(history, 3000)
-        } else if self.mode.contains(Mode::M) {
-            std::cmp::max(history, 400)
-        } else {
-            history
-        };
+        if (history as usize) < self.required_history() {
+            return Err(Error::InvalidMode);
+        }
 
         if self.history == history as usize {
             return Ok(());
@@ -536,1867 +1740,7637 @@ impl EbuR128 {
 
         self.history = history as usize;
 
-        self.block_energy_history.set_max_size(self.history / 100);
-        self.short_term_block_energy_history
-            .set_max_size(self.history / 3000);
+        if let Some(ref mut block_energy_history) = self.block_energy_history {
+            block_energy_history.set_max_size(self.history / 100);
+        }
+        if let Some(ref mut short_term_block_energy_history) = self.short_term_block_energy_history
+        {
+            short_term_block_energy_history.set_max_size(self.history / 3000);
+        }
+        self.truncate_channel_block_energies();
+        #[cfg(feature = "dual-history")]
+        if let Some(ref mut dual_history) = self.dual_history {
+            if let Some(ref mut history) = dual_history.block_energy_history {
+                history.set_max_size(self.history / 100);
+            }
+            if let Some(ref mut history) = dual_history.short_term_block_energy_history {
+                history.set_max_size(self.history / 3000);
+            }
+        }
 
         Ok(())
     }
 
-    /// Resets the current state.
-    pub fn reset(&mut self) {
-        self.audio_data.fill(0.0);
-
-        // the first block needs 400ms of audio data
-        self.needed_frames = self.samples_in_100ms * 4;
-        // start at the beginning of the buffer
-        self.audio_data_index = 0;
-        // reset short term frame counter
-        self.short_term_frame_counter = 0;
-
-        self.true_peak.fill(0.0);
-        self.sample_peak.fill(0.0);
-
-        self.filter.reset();
-        self.block_energy_history.reset();
-        self.short_term_block_energy_history.reset();
+    /// Get the configured number of histogram bins used with `Mode::HISTOGRAM`.
+    pub fn histogram_bins(&self) -> usize {
+        self.histogram_bins
     }
 
-    /// Process frames. This is the generic variant of the different public add_frames() functions
-    /// that are defined below.
-    fn add_frames<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
-        &mut self,
-        mut src: S,
-    ) -> Result<(), Error> {
-        if src.frames() == 0 {
-            return Ok(());
+    /// Set the number of histogram bins used with `Mode::HISTOGRAM`.
+    ///
+    /// The histogram bins cover the loudness range from -70 LUFS to +30 LUFS, so the default of
+    /// 1000 bins gives a resolution of 0.1 LU. More bins give a higher precision of the integrated
+    /// loudness and loudness range for the cost of memory and speed: each of the two histograms
+    /// needs 8 bytes per bin, and the bins need another 16 bytes per bin unless the default is
+    /// used. E.g. 10000 bins give a resolution of 0.01 LU with about 400kB of memory.
+    ///
+    /// Note that this destroys the current content of the histograms. Returns
+    /// `Error::InvalidMode` if `Mode::HISTOGRAM` is not set or `bins` is zero.
+    pub fn set_histogram_bins(&mut self, bins: u32) -> Result<(), Error> {
+        if !self.mode.contains(Mode::HISTOGRAM) || bins == 0 {
+            return Err(Error::InvalidMode);
         }
 
-        if self.channels == 0 {
-            return Err(Error::NoMem);
+        if self.histogram_bins == bins as usize {
+            return Ok(());
         }
 
-        self.filter.reset_peaks();
+        self.histogram_bins = bins as usize;
 
-        while src.frames() > 0 {
-            let num_frames = src.frames();
+        if let Some(ref mut block_energy_history) = self.block_energy_history {
+            block_energy_history.set_histogram_bins(self.histogram_bins);
+        }
+        if let Some(ref mut short_term_block_energy_history) = self.short_term_block_energy_history
+        {
+            short_term_block_energy_history.set_histogram_bins(self.histogram_bins);
+        }
 
-            if num_frames >= self.needed_frames {
-                let (current, next) = src.split_at(self.needed_frames);
+        Ok(())
+    }
 
-                self.filter.process(
-                    current,
-                    &mut self.audio_data,
-                    self.audio_data_index,
-                    &self.channel_map,
-                );
+    /// Get the compression of the t-digest used for the loudness range, if any, see
+    /// [`EbuR128::set_loudness_range_digest`](struct.EbuR128.html#method.set_loudness_range_digest).
+    pub fn loudness_range_digest(&self) -> Option<u32> {
+        self.short_term_block_energy_history
+            .as_ref()
+            .and_then(|h| h.digest_compression())
+    }
 
-                src = next;
-                self.audio_data_index += self.needed_frames;
+    /// Approximate the distribution of the short-term loudness for
+    /// [`EbuR128::loudness_range`](struct.EbuR128.html#method.loudness_range) with a t-digest of
+    /// the given compression instead of keeping all short-term blocks, or go back to the default
+    /// history with `None`.
+    ///
+    /// The t-digest keeps up to `compression` centroids plus a buffer of `5 * compression`
+    /// blocks, i.e. 144 bytes per unit of compression, independent of the duration of the audio
+    /// and of the maximum history. Its percentiles are interpolated, so the loudness range is only
+    /// approximated: more compression gives a higher accuracy. Only the centroids in the middle
+    /// of the distribution summarize many blocks, and for a day of short-term blocks with a
+    /// loudness evenly distributed over 40 LU a compression of 500 gives a loudness range within
+    /// 0.1 LU of the exact one, while the exact history needs about 1.4MB.
+    ///
+    /// The t-digest replaces the history of `Mode::HISTOGRAM` and is not limited by the maximum
+    /// history, see [`EbuR128::set_max_history`](struct.EbuR128.html#method.set_max_history).
+    /// The short-term blocks measured so far are discarded when changing the history. Instances
+    /// with a t-digest can only be combined in
+    /// [`EbuR128::loudness_range_multiple`](struct.EbuR128.html#method.loudness_range_multiple)
+    /// with other instances with a t-digest, or with a loss of accuracy in
+    /// [`EbuR128::loudness_range_multiple_mixed`](struct.EbuR128.html#method.loudness_range_multiple_mixed).
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::LRA` is not set or the compression is 0.
+    pub fn set_loudness_range_digest(&mut self, compression: Option<u32>) -> Result<(), Error> {
+        if !self.mode.contains(Mode::LRA) || compression == Some(0) {
+            return Err(Error::InvalidMode);
+        }
 
-                if self.mode.contains(Mode::I) {
-                    let energy = crate::filter::Filter::calc_gating_block(
-                        self.samples_in_100ms * 4,
-                        &self.audio_data,
-                        self.audio_data_index,
-                        &self.channel_map,
-                    );
-                    self.block_energy_history.add(energy);
-                }
+        if self.loudness_range_digest() == compression {
+            return Ok(());
+        }
 
-                if self.mode.contains(Mode::LRA) {
-                    self.short_term_frame_counter += self.needed_frames;
-                    if self.short_term_frame_counter == self.samples_in_100ms * 30 {
-                        let energy = self.energy_shortterm()?;
-                        self.short_term_block_energy_history.add(energy);
-                        self.short_term_frame_counter = self.samples_in_100ms * 20;
-                    }
-                }
+        let mut history = match compression {
+            Some(compression) => crate::history::History::new_digest(compression),
+            None => {
+                let mut history = crate::history::History::new_sorted(
+                    self.mode.contains(Mode::HISTOGRAM),
+                    self.history / 3000,
+                );
+                history.set_histogram_bins(self.histogram_bins);
+                history
+            }
+        };
+        history.set_stable_accumulation(self.stable_accumulation);
+        self.short_term_block_energy_history = Some(history);
 
-                if self.audio_data_index == self.audio_data.len() / self.channels as usize {
-                    self.audio_data_index = 0;
-                }
+        Ok(())
+    }
 
-                // 100ms are needed for all blocks besides the first one
-                self.needed_frames = self.samples_in_100ms;
-            } else {
-                let (current, next) = src.split_at(num_frames);
+    /// Get whether the sums of the block energies compensate rounding errors, see
+    /// [`EbuR128::set_stable_accumulation`](struct.EbuR128.html#method.set_stable_accumulation).
+    pub fn stable_accumulation(&self) -> bool {
+        self.stable_accumulation
+    }
 
-                self.filter.process(
-                    current,
-                    &mut self.audio_data,
-                    self.audio_data_index,
-                    &self.channel_map,
-                );
+    /// Sum the energies of the gating blocks with compensation of the rounding errors.
+    ///
+    /// By default the energies are summed naively like libebur128 does, so rounding errors
+    /// accumulate over the millions of blocks of a stream of many hours. E.g. the integrated
+    /// loudness of a constant signal then drifts slightly away from the loudness of a single
+    /// block. With stable accumulation the sums stay within a rounding error of the exact sum
+    /// independent of the duration, for a small cost when calculating the loudness. The results
+    /// differ slightly from the ones of libebur128 then.
+    ///
+    /// This applies to the integrated loudness, the loudness range and all values derived from
+    /// them, and keeps the blocks measured so far.
+    pub fn set_stable_accumulation(&mut self, enabled: bool) {
+        if self.stable_accumulation == enabled {
+            return;
+        }
 
-                self.audio_data_index += num_frames;
-                if self.mode.contains(Mode::LRA) {
-                    self.short_term_frame_counter += num_frames;
-                }
+        self.stable_accumulation = enabled;
 
-                src = next;
-                self.needed_frames -= num_frames;
-            }
+        if let Some(ref mut block_energy_history) = self.block_energy_history {
+            block_energy_history.set_stable_accumulation(enabled);
         }
-
-        let prev_sample_peak = self.filter.sample_peak();
-        for (sample_peak, prev_sample_peak) in
-            Iterator::zip(self.sample_peak.iter_mut(), prev_sample_peak.iter())
+        if let Some(ref mut short_term_block_energy_history) = self.short_term_block_energy_history
         {
-            if *prev_sample_peak > *sample_peak {
-                *sample_peak = *prev_sample_peak;
-            }
+            short_term_block_energy_history.set_stable_accumulation(enabled);
         }
-
-        let prev_true_peak = self.filter.true_peak();
-        for (true_peak, prev_true_peak) in
-            Iterator::zip(self.true_peak.iter_mut(), prev_true_peak.iter())
-        {
-            if *prev_true_peak > *true_peak {
-                *true_peak = *prev_true_peak;
+        #[cfg(feature = "dual-history")]
+        if let Some(ref mut dual_history) = self.dual_history {
+            if let Some(ref mut history) = dual_history.block_energy_history {
+                history.set_stable_accumulation(enabled);
+            }
+            if let Some(ref mut history) = dual_history.short_term_block_energy_history {
+                history.set_stable_accumulation(enabled);
             }
         }
-
-        Ok(())
-    }
-
-    fn seed_frames<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(&mut self, src: S) {
-        self.filter.seed(src, &self.channel_map);
+        if let Some(ref mut segments) = self.segments {
+            segments.blocks.set_stable_accumulation(enabled);
+        }
+        for region in self.regions.iter_mut() {
+            region.blocks.set_stable_accumulation(enabled);
+        }
+        if let Some(ref mut mono_sum) = self.mono_sum {
+            mono_sum.analyzer.set_stable_accumulation(enabled);
+        }
     }
 
-    /// Add interleaved frames to be processed.
-    pub fn add_frames_i16(&mut self, frames: &[i16]) -> Result<(), Error> {
-        self.add_frames(crate::Interleaved::new(frames, self.channels as usize)?)
+    /// Get whether the energies of the integrated loudness are kept sorted, see
+    /// [`EbuR128::set_sorted_integrated`](struct.EbuR128.html#method.set_sorted_integrated).
+    pub fn sorted_integrated(&self) -> bool {
+        self.sorted_integrated
     }
 
-    /// Add interleaved frames to be processed.
-    pub fn add_frames_i32(&mut self, frames: &[i32]) -> Result<(), Error> {
-        self.add_frames(crate::Interleaved::new(frames, self.channels as usize)?)
-    }
+    /// Keep the energies of the gating blocks of the integrated loudness sorted.
+    ///
+    /// By default [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global) sums
+    /// the energies of all gating blocks on every call, which is O(n) in the number of blocks.
+    /// With sorted energies it is O(log n), which is worth it if the integrated loudness is
+    /// polled after every few buffers of a long stream, for the cost of about eight times the
+    /// memory per gating block. The energies are summed in a different order then, so the
+    /// results can differ from the default ones in the last digits.
+    ///
+    /// This has no effect with `Mode::HISTOGRAM`, whose cost only depends on the number of bins,
+    /// and keeps the blocks measured so far.
+    pub fn set_sorted_integrated(&mut self, enabled: bool) {
+        self.sorted_integrated = enabled;
 
-    /// Add interleaved frames to be processed.
-    pub fn add_frames_f32(&mut self, frames: &[f32]) -> Result<(), Error> {
-        self.add_frames(crate::Interleaved::new(frames, self.channels as usize)?)
+        if let Some(ref mut block_energy_history) = self.block_energy_history {
+            block_energy_history.set_sorted(enabled);
+        }
     }
 
-    /// Add interleaved frames to be processed.
-    pub fn add_frames_f64(&mut self, frames: &[f64]) -> Result<(), Error> {
-        self.add_frames(crate::Interleaved::new(frames, self.channels as usize)?)
-    }
-
-    /// Add planar frames to be processed.
-    pub fn add_frames_planar_i16(&mut self, frames: &[&[i16]]) -> Result<(), Error> {
-        self.add_frames(crate::Planar::new(frames)?)
-    }
-
-    /// Add planar frames to be processed.
-    pub fn add_frames_planar_i32(&mut self, frames: &[&[i32]]) -> Result<(), Error> {
-        self.add_frames(crate::Planar::new(frames)?)
-    }
+    /// Reserve history for the given duration.
+    ///
+    /// Pre-allocates the history used for
+    /// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global) and
+    /// [`EbuR128::loudness_range`](struct.EbuR128.html#method.loudness_range) so that it can hold
+    /// the blocks of `seconds` of audio, limited by the maximum history. Adding frames up to that
+    /// duration will then not cause any reallocations.
+    ///
+    /// This does not change the maximum history and is a no-op if `Mode::HISTOGRAM` is set.
+    /// Returns `Error::NoMem` if the memory for the duration can't be allocated, e.g. for a huge
+    /// duration with the default unlimited maximum history.
+    pub fn reserve_duration(&mut self, seconds: f64) -> Result<(), Error> {
+        if seconds.is_nan() || seconds <= 0.0 {
+            return Ok(());
+        }
 
-    /// Add planar frames to be processed.
-    pub fn add_frames_planar_f32(&mut self, frames: &[&[f32]]) -> Result<(), Error> {
-        self.add_frames(crate::Planar::new(frames)?)
-    }
+        let ms = f64::min(seconds * 1000.0, usize::MAX as f64) as usize;
 
-    /// Add planar frames to be processed.
-    pub fn add_frames_planar_f64(&mut self, frames: &[&[f64]]) -> Result<(), Error> {
-        self.add_frames(crate::Planar::new(frames)?)
-    }
+        if let Some(ref mut block_energy_history) = self.block_energy_history {
+            block_energy_history.reserve(ms / 100)?;
+        }
+        if let Some(ref mut short_term_block_energy_history) = self.short_term_block_energy_history
+        {
+            short_term_block_energy_history.reserve(ms / 1000)?;
+        }
 
-    /// Add interleaved frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_i16(&mut self, frames: &[i16]) -> Result<(), Error> {
-        self.seed_frames(crate::Interleaved::new(frames, self.channels as usize)?);
         Ok(())
     }
 
-    /// Add interleaved frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_i32(&mut self, frames: &[i32]) -> Result<(), Error> {
-        self.seed_frames(crate::Interleaved::new(frames, self.channels as usize)?);
-        Ok(())
+    /// Set a custom gating predicate for the integrated loudness.
+    ///
+    /// The predicate is called with the energy of each gating block and decides whether the block
+    /// is considered for [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global).
+    /// The integrated loudness is then the loudness of the mean energy of all these blocks. Blocks
+    /// below the absolute gate of -70 LUFS are never stored and thus never passed to the
+    /// predicate. With `Mode::HISTOGRAM` the predicate is called with the energy at the center of
+    /// each histogram bin.
+    ///
+    /// Passing `None` restores the default, which is the standard two-stage gating with the
+    /// absolute gate of -70 LUFS and the relative gate of -10 LU. Any other predicate deviates
+    /// from EBU R128 and is only useful for experimentation.
+    ///
+    /// The predicate can't be a `FnMut` as `loudness_global()` only takes `&self` and can be
+    /// called from multiple threads at once. It is also called again for all blocks on every call
+    /// of `loudness_global()` and not in the order the blocks were measured, so any state of a
+    /// predicate should only depend on the energies. Stateful predicates, e.g. counters, can keep
+    /// their state in atomics or a `Mutex`.
+    ///
+    /// [`EbuR128::loudness_global_multiple`](struct.EbuR128.html#method.loudness_global_multiple)
+    /// always uses the standard gating.
+    pub fn set_gating_predicate(&mut self, predicate: Option<Box<GatingPredicate>>) {
+        self.gating_predicate = predicate;
     }
 
-    /// Add interleaved frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_f32(&mut self, frames: &[f32]) -> Result<(), Error> {
-        self.seed_frames(crate::Interleaved::new(frames, self.channels as usize)?);
-        Ok(())
-    }
+    /// Set a callback for the momentary loudness of each completed block.
+    ///
+    /// The callback is called from the `add_frames_*` functions every 100ms of added audio, once
+    /// the first 400ms are complete, with the number of frames added since the instance was
+    /// created or reset and the momentary loudness in LUFS of the 400ms ending at that frame.
+    /// This allows to associate the momentary loudness with positions on the timeline of the
+    /// audio, e.g. for inserting loudness metadata into a stream. With resampling, see
+    /// [`EbuR128::with_resampling`](struct.EbuR128.html#method.with_resampling), the frames are
+    /// counted at the resampled rate.
+    ///
+    /// Not available if only peaks are measured, in which case `Error::InvalidMode` is returned.
+    /// Passing `None` removes the callback.
+    pub fn set_momentary_callback(
+        &mut self,
+        callback: Option<Box<MomentaryCallback>>,
+    ) -> Result<(), Error> {
+        if Self::is_peak_only(self.mode) {
+            return Err(Error::InvalidMode);
+        }
 
-    /// Add interleaved frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_f64(&mut self, frames: &[f64]) -> Result<(), Error> {
-        self.seed_frames(crate::Interleaved::new(frames, self.channels as usize)?);
+        self.momentary_callback = callback;
         Ok(())
     }
 
-    /// Add planar frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_planar_i16(&mut self, frames: &[&[i16]]) -> Result<(), Error> {
-        self.seed_frames(crate::Planar::new(frames)?);
-        Ok(())
-    }
+    /// Set a callback for all standard metered values of each completed block.
+    ///
+    /// The callback is called from the `add_frames_*` functions every 100ms of added audio, once
+    /// the first 400ms are complete, like the callback set with
+    /// [`EbuR128::set_momentary_callback`](struct.EbuR128.html#method.set_momentary_callback).
+    /// It gets the momentary loudness, and depending on the mode the short-term loudness, the
+    /// integrated loudness and the maximum true peak so far, which allows to write a loudness log
+    /// file without polling.
+    ///
+    /// This is called in the hot path of adding frames, so the callback itself should be cheap.
+    /// Additionally, the integrated loudness has to be calculated for each block. With
+    /// `Mode::HISTOGRAM` this takes constant time, but otherwise it takes time proportional to
+    /// the number of measured blocks, which makes long measurements quadratic in their duration.
+    /// For logging long streams, `Mode::HISTOGRAM` should be used.
+    ///
+    /// Not available if only peaks are measured, in which case `Error::InvalidMode` is returned.
+    /// Passing `None` removes the callback.
+    pub fn set_block_log_callback(
+        &mut self,
+        callback: Option<Box<BlockLogCallback>>,
+    ) -> Result<(), Error> {
+        if Self::is_peak_only(self.mode) {
+            return Err(Error::InvalidMode);
+        }
 
-    /// Add planar frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_planar_i32(&mut self, frames: &[&[i32]]) -> Result<(), Error> {
-        self.seed_frames(crate::Planar::new(frames)?);
+        self.block_log_callback = callback;
         Ok(())
     }
 
-    /// Add planar frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_planar_f32(&mut self, frames: &[&[f32]]) -> Result<(), Error> {
-        self.seed_frames(crate::Planar::new(frames)?);
-        Ok(())
-    }
+    /// Metered values for the block log callback at the end of the block at `position`.
+    fn block_log(&self, position: u64) -> Result<BlockLog, Error> {
+        let true_peak = if self.mode.contains(Mode::TRUE_PEAK) {
+            // The peaks of the current call to `add_frames_*` are only merged at its end, and
+            // those of paused frames not at all
+            let (current_true_peak, current_sample_peak): (&[f64], &[f64]) = if self.paused {
+                (&[], &[])
+            } else {
+                (self.filter.true_peak(), self.filter.sample_peak())
+            };
+
+            Some(
+                Iterator::chain(self.true_peak.iter(), self.sample_peak.iter())
+                    .chain(current_true_peak.iter())
+                    .chain(current_sample_peak.iter())
+                    .fold(0.0, |max, peak| f64::max(max, *peak)),
+            )
+        } else {
+            None
+        };
 
-    /// Add planar frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_planar_f64(&mut self, frames: &[&[f64]]) -> Result<(), Error> {
-        self.seed_frames(crate::Planar::new(frames)?);
-        Ok(())
+        Ok(BlockLog {
+            position,
+            momentary: self.loudness_momentary()?,
+            shortterm: if self.mode.contains(Mode::S) {
+                Some(self.loudness_shortterm()?)
+            } else {
+                None
+            },
+            integrated: if self.mode.contains(Mode::I) {
+                Some(self.loudness_global()?)
+            } else {
+                None
+            },
+            true_peak,
+        })
     }
 
-    /// Get global integrated loudness in LUFS.
-    pub fn loudness_global(&self) -> Result<f64, Error> {
-        if !self.mode.contains(Mode::I) {
+    /// Set an alarm for the loudness crossing a threshold.
+    ///
+    /// After each completed block, i.e. every 100ms of added audio once the first 400ms are
+    /// complete, the loudness of the given kind is compared against `threshold_lufs` and
+    /// `callback` is called from the `add_frames_*` functions when the loudness rises above the
+    /// threshold. Once above, it is called again when the loudness falls below the threshold
+    /// minus a hysteresis of [`THRESHOLD_ALARM_HYSTERESIS`](constant.THRESHOLD_ALARM_HYSTERESIS.html)
+    /// LU, which avoids repeated alarms for loudness around the threshold.
+    ///
+    /// This replaces any previously set alarm. Passing `None` as callback removes the alarm.
+    /// Returns `Error::InvalidMode` if the mode does not allow measuring the given kind of
+    /// loudness or the threshold is not finite.
+    pub fn set_threshold_alarm(
+        &mut self,
+        kind: LoudnessKind,
+        threshold_lufs: f64,
+        callback: Option<Box<ThresholdAlarmCallback>>,
+    ) -> Result<(), Error> {
+        let callback = match callback {
+            Some(callback) => callback,
+            None => {
+                self.threshold_alarm = None;
+                return Ok(());
+            }
+        };
+
+        if !threshold_lufs.is_finite() {
             return Err(Error::InvalidMode);
         }
+        self.loudness(kind)?;
 
-        Ok(self.block_energy_history.gated_loudness())
+        self.threshold_alarm = Some(ThresholdAlarm {
+            kind,
+            threshold: threshold_lufs,
+            above: false,
+            callback,
+        });
+
+        Ok(())
     }
 
-    /// Get global integrated loudness in LUFS across multiple instances.
-    ///
-    /// This can be used to allow parallel iteration of long signals, assuming some care is taken:
-    ///  1. Divide input-signal up in "chunks" of even 100ms samples. Make chunks overlap by 400ms, for example (0-10s, 9.6-20s, 19.6-30s, ...)
-    ///  2. The first chunk is processed as normal. Then in parallel, for each remaining chunk, create a new instance of `EbuR128`, and in parallel:
-    ///     1. Feed the first 100ms of the chunk (these are samples overlapping with last chunk) through `seed_frames_*` function. This is sufficient to make filter-states in each instance what they would have been if a single analyzer would have reached this point.
-    ///     2. Process the remaining samples of each chunk through the analyzer
-    ///  3. Call [`EbuR128::loudness_global_multiple`] over all the chunks to get the global loudness
-    // FIXME: Should maybe be IntoIterator? Maybe AsRef<Self>?
-    pub fn loudness_global_multiple<'a>(
-        iter: impl Iterator<Item = &'a Self>,
-    ) -> Result<f64, Error> {
-        use smallvec::SmallVec;
+    /// Call the threshold alarm if the loudness crossed its threshold.
+    fn check_threshold_alarm(&mut self, position: u64) -> Result<(), Error> {
+        let (kind, threshold, above) = match self.threshold_alarm {
+            Some(ref alarm) => (alarm.kind, alarm.threshold, alarm.above),
+            None => return Ok(()),
+        };
 
-        let h = iter
-            .map(|e| {
-                if !e.mode.contains(Mode::I) {
-                    Err(Error::InvalidMode)
-                } else {
-                    Ok(&e.block_energy_history)
-                }
-            })
-            .collect::<Result<SmallVec<[_; 16]>, _>>()?;
+        let loudness = self.loudness(kind)?;
+        let crossed = if above {
+            loudness < threshold - THRESHOLD_ALARM_HYSTERESIS
+        } else {
+            loudness > threshold
+        };
 
-        Ok(crate::history::History::gated_loudness_multiple(&h))
+        if crossed {
+            if let Some(ref mut alarm) = self.threshold_alarm {
+                alarm.above = !above;
+                (alarm.callback)(&ThresholdCrossing {
+                    kind,
+                    position,
+                    loudness,
+                    above: !above,
+                });
+            }
+        }
+
+        Ok(())
     }
 
-    fn energy_in_interval(&self, interval_frames: usize) -> Result<f64, Error> {
-        if interval_frames > self.audio_data.len() / self.channels as usize {
+    /// Measure the average of the given input channels as an additional mono channel.
+    ///
+    /// The frames of the given channels are averaged, i.e. summed with a gain of `1 / n` for `n`
+    /// channels, and measured by a separate mono analyzer with its own K-weighting filter that
+    /// is available via [`EbuR128::mono_sum`](struct.EbuR128.html#method.mono_sum). The gain
+    /// keeps the sum from reading hot: identical content on all summed channels measures the same
+    /// loudness as a single one of them, while a plain sum would read `20 * log10(n)` dB louder.
+    /// Uncorrelated content measures about `10 * log10(n)` dB quieter than a single channel.
+    ///
+    /// The mono analyzer measures the loudness of this instance's mode, and is created with its
+    /// current K-weighting, maximum window and history, short-term window and histogram bins.
+    /// Later changes of this configuration are not applied to it. Pausing, resetting and changing
+    /// the sample rate apply to it, while changing the number of channels removes it.
+    ///
+    /// This replaces any previously configured mono sum and starts measuring from the next added
+    /// frames. Passing `None` removes the mono sum. Returns `Error::InvalidChannelIndex` if no or
+    /// invalid channels are given, and `Error::InvalidMode` if only peaks are tracked.
+    pub fn set_mono_sum(&mut self, channels: Option<&[u32]>) -> Result<(), Error> {
+        let channels = match channels {
+            Some(channels) => channels,
+            None => {
+                self.mono_sum = None;
+                return Ok(());
+            }
+        };
+
+        if channels.is_empty() || channels.iter().any(|&c| c >= self.channels) {
+            return Err(Error::InvalidChannelIndex);
+        }
+        if Self::is_peak_only(self.mode) {
             return Err(Error::InvalidMode);
         }
 
-        Ok(crate::filter::Filter::calc_gating_block(
-            interval_frames,
-            &self.audio_data,
-            self.audio_data_index,
-            &self.channel_map,
-        ))
-    }
+        let mut analyzer = EbuR128::new(
+            1,
+            self.rate,
+            self.mode & (Mode::I | Mode::LRA | Mode::HISTOGRAM),
+        )?;
+        analyzer.set_kweighting(self.kweighting);
+        analyzer.set_input_highpass(self.input_highpass)?;
+        analyzer.set_stable_accumulation(self.stable_accumulation);
+        analyzer.set_max_window(self.window as u32)?;
+        analyzer.set_max_history(self.history as u32)?;
+        if self.mode.contains(Mode::S) {
+            analyzer.set_shortterm_window(self.shortterm_window as u32)?;
+        }
+        if self.mode.contains(Mode::HISTOGRAM) {
+            analyzer.set_histogram_bins(self.histogram_bins as u32)?;
+        }
+        analyzer.set_paused(self.paused);
+        analyzer.set_finalize_partial(self.finalize_partial)?;
 
-    /// Get momentary loudness (last 400ms) in LUFS.
-    pub fn loudness_momentary(&self) -> Result<f64, Error> {
-        let energy = self.energy_in_interval(self.samples_in_100ms * 4)?;
+        self.mono_sum = Some(MonoSum {
+            channels: channels.into(),
+            analyzer: Box::new(analyzer),
+            samples: Vec::new(),
+        });
 
-        if energy <= 0.0 {
-            return Ok(-f64::INFINITY);
-        }
+        Ok(())
+    }
 
-        Ok(energy_to_loudness(energy))
+    /// Get the analyzer of the mono sum configured with
+    /// [`EbuR128::set_mono_sum`](struct.EbuR128.html#method.set_mono_sum), if any.
+    pub fn mono_sum(&self) -> Option<&EbuR128> {
+        self.mono_sum.as_ref().map(|mono_sum| &*mono_sum.analyzer)
     }
 
-    fn energy_shortterm(&self) -> Result<f64, Error> {
-        self.energy_in_interval(self.samples_in_100ms * 30)
+    /// Get whether the energies of each channel are kept for
+    /// [`EbuR128::loudness_of_channels`](struct.EbuR128.html#method.loudness_of_channels) and
+    /// [`EbuR128::recompute_integrated`](struct.EbuR128.html#method.recompute_integrated).
+    pub fn channel_energies(&self) -> bool {
+        self.channel_block_energies.is_some()
     }
 
-    /// Get short-term loudness (last 3s) in LUFS.
-    pub fn loudness_shortterm(&self) -> Result<f64, Error> {
-        let energy = self.energy_shortterm()?;
+    /// Enable or disable keeping the energies of each channel for
+    /// [`EbuR128::loudness_of_channels`](struct.EbuR128.html#method.loudness_of_channels) and
+    /// [`EbuR128::recompute_integrated`](struct.EbuR128.html#method.recompute_integrated).
+    ///
+    /// The energies are kept without the channel weights, which are only applied according to the
+    /// channel map when they are used. Channels that are `Channel::Unused` are not filtered, and
+    /// their energy is kept as zero.
+    ///
+    /// This needs 8 bytes per channel for each 100ms of audio, up to the maximum history, see
+    /// [`EbuR128::set_max_history`](struct.EbuR128.html#method.set_max_history). Only frames
+    /// added after enabling are considered, and disabling discards the kept energies.
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::I` is not set.
+    pub fn set_channel_energies(&mut self, enabled: bool) -> Result<(), Error> {
+        if !self.mode.contains(Mode::I) {
+            return Err(Error::InvalidMode);
+        }
 
-        if energy <= 0.0 {
-            return Ok(-f64::INFINITY);
+        if !enabled {
+            self.channel_block_energies = None;
+        } else if self.channel_block_energies.is_none() {
+            self.channel_block_energies = Some(std::collections::VecDeque::new());
         }
 
-        Ok(energy_to_loudness(energy))
+        Ok(())
     }
 
-    /// Get loudness of the specified window in LUFS.
-    ///
-    /// window must not be larger than the current window. The current window can be changed by
-    /// calling [`EbuR128::set_max_window`](struct.EbuR128.html#method.set_max_window).
-    pub fn loudness_window(&self, window: u32) -> Result<f64, Error> {
-        let interval_frames = (self.rate as usize)
-            .checked_mul(window as usize)
-            .ok_or(Error::InvalidMode)?
-            / 1000;
-        let energy = self.energy_in_interval(interval_frames)?;
-
-        if energy <= 0.0 {
-            return Ok(-f64::INFINITY);
+    /// Drop the energies of the oldest blocks that exceed the maximum history.
+    fn truncate_channel_block_energies(&mut self) {
+        if let Some(ref mut channel_block_energies) = self.channel_block_energies {
+            let max = (self.history / 100).saturating_mul(self.channels as usize);
+            while channel_block_energies.len() > max {
+                channel_block_energies.pop_front();
+            }
         }
+    }
 
-        Ok(energy_to_loudness(energy))
+    /// Get the number of frames added since the instance was created or reset.
+    ///
+    /// Frames passed to the `seed_frames_*` functions are not counted.
+    pub fn frames_added(&self) -> u64 {
+        self.frames_added
     }
 
-    /// Get loudness range (LRA) of programme in LU.
+    /// Get the maximum duration in seconds, see
+    /// [`EbuR128::set_max_duration`](struct.EbuR128.html#method.set_max_duration).
+    pub fn max_duration(&self) -> Option<f64> {
+        self.max_duration
+    }
+
+    /// Limit the duration in seconds of the frames that can be added, or remove the limit with
+    /// `None`.
     ///
-    /// Calculates loudness range according to EBU 3342.
-    pub fn loudness_range(&self) -> Result<f64, Error> {
-        if !self.mode.contains(Mode::LRA) {
-            return Err(Error::InvalidMode);
+    /// A call to one of the `add_frames_*` functions that would add frames beyond the maximum
+    /// duration returns `Error::MaxDurationExceeded` without processing any of its frames. The
+    /// duration counts all frames since the instance was created or reset, see
+    /// [`EbuR128::frames_added`](struct.EbuR128.html#method.frames_added), so lowering the
+    /// maximum below the current duration makes all further calls fail until the next reset.
+    ///
+    /// This protects services that analyze untrusted input of unknown length. With a queue
+    /// history and a large maximum history, see
+    /// [`EbuR128::set_max_history`](struct.EbuR128.html#method.set_max_history), the memory
+    /// grows with the duration of the input. With `Mode::HISTOGRAM` the memory of the history is
+    /// bounded by the number of bins anyway, and the maximum duration only limits the processing
+    /// time.
+    ///
+    /// Returns `Error::InvalidMode` if the duration is negative or not a number.
+    pub fn set_max_duration(&mut self, seconds: Option<f64>) -> Result<(), Error> {
+        if let Some(seconds) = seconds {
+            if seconds.is_nan() || seconds < 0.0 {
+                return Err(Error::InvalidMode);
+            }
         }
 
-        Ok(self.short_term_block_energy_history.loudness_range())
+        self.max_duration = seconds;
+        Ok(())
     }
 
-    /// Get loudness range (LRA) of programme in LU across multiple instances.
+    /// Get how much of the window or history of the given kind of loudness is filled, between
+    /// 0.0 and 1.0.
     ///
-    /// Calculates loudness range according to EBU 3342.
-    // FIXME: Should maybe be IntoIterator? Maybe AsRef<Self>?
-    pub fn loudness_range_multiple<'a>(
-        iter: impl IntoIterator<Item = &'a Self>,
-    ) -> Result<f64, Error> {
-        use smallvec::SmallVec;
-
-        let h = iter
-            .into_iter()
-            .map(|e| {
-                if !e.mode.contains(Mode::LRA) {
-                    Err(Error::InvalidMode)
-                } else {
-                    Ok(&e.short_term_block_energy_history)
+    /// This is the fraction of the 400ms block for the momentary loudness and of the short-term
+    /// window for the short-term loudness that is filled with frames since the instance was
+    /// created or reset, or the audio buffer was cleared by changing its parameters. The
+    /// integrated loudness is available once the first gating block is complete, so its fill is
+    /// that of the first 400ms block. This is useful e.g. for showing the warm-up of a meter.
+    ///
+    /// Returns `Error::InvalidMode` if the loudness of the given kind is not measured by the
+    /// configured mode.
+    pub fn window_fill(&self, kind: LoudnessKind) -> Result<f64, Error> {
+        let frames = match kind {
+            LoudnessKind::Momentary => self.samples_in_100ms * 4,
+            LoudnessKind::ShortTerm if self.mode.contains(Mode::S) => self.shortterm_frames(),
+            LoudnessKind::Integrated if self.mode.contains(Mode::I) => {
+                if self
+                    .block_energy_history
+                    .as_ref()
+                    .map_or(false, |h| !h.is_empty())
+                {
+                    return Ok(1.0);
                 }
-            })
-            .collect::<Result<SmallVec<[_; 16]>, _>>()?;
+                self.samples_in_100ms * 4
+            }
+            _ => return Err(Error::InvalidMode),
+        };
 
-        crate::history::History::loudness_range_multiple(&h)
+        Ok(f64::min(self.window_frames as f64 / frames as f64, 1.0))
     }
 
-    /// Get maximum sample peak from all frames that have been processed.
+    /// Resets only the peaks, like the peak reset button of a hardware meter.
     ///
-    /// The equation to convert to dBFS is: 20 * log10(out)
-    pub fn sample_peak(&self, channel_number: u32) -> Result<f64, Error> {
-        if !self.mode.contains(Mode::SAMPLE_PEAK) {
-            return Err(Error::InvalidMode);
-        }
+    /// This clears the maximum sample and true peaks, the peaks of the last call to
+    /// `add_frames_*`, the displayed peak and the true peak window, while the loudness
+    /// measurements and the filter state are left untouched. The true peak interpolator keeps its
+    /// history, so that the true peak of the next added frames is calculated without any
+    /// additional latency.
+    pub fn reset_peaks(&mut self) {
+        self.true_peak.fill(0.0);
+        self.true_peak_over_counts.fill(0);
+        self.sample_peak.fill(0.0);
+        self.displayed_peak.fill(0.0);
 
-        if channel_number >= self.channels {
-            return Err(Error::InvalidChannelIndex);
+        if let Some(ref mut signed_peaks) = self.signed_peaks {
+            signed_peaks.fill((0.0, 0.0));
         }
 
-        Ok(self.sample_peak[channel_number as usize])
+        self.filter.reset_peaks();
+        self.filter.reset_true_peak_window();
     }
 
-    /// Get maximum sample peak from the last call to
-    /// [`EbuR128::add_frames`](struct.EbuR128.html#method.add_frames_i16).
+    /// Resets only the loudness range, like the LRA reset button of a hardware meter.
     ///
-    /// The equation to convert to dBFS is: 20 * log10(out)
-    pub fn prev_sample_peak(&self, channel_number: u32) -> Result<f64, Error> {
-        if !self.mode.contains(Mode::SAMPLE_PEAK) {
-            return Err(Error::InvalidMode);
+    /// This clears the history of short-term loudness values that the loudness range is
+    /// calculated from, including the one kept for
+    /// [`EbuR128::set_dual_history`](struct.EbuR128.html#method.set_dual_history), while the
+    /// integrated loudness, the peaks and the filter state are left untouched. The integrated
+    /// loudness uses a separate history of the 400ms blocks, so it is not affected. As the
+    /// short-term loudness is measured over a sliding window, the first values after the reset
+    /// still include the frames up to 3s before it.
+    pub fn reset_loudness_range(&mut self) {
+        if let Some(ref mut short_term_block_energy_history) = self.short_term_block_energy_history
+        {
+            short_term_block_energy_history.reset();
+        }
+        #[cfg(feature = "dual-history")]
+        if let Some(ref mut dual_history) = self.dual_history {
+            if let Some(ref mut history) = dual_history.short_term_block_energy_history {
+                history.reset();
+            }
         }
+        if let Some(ref mut mono_sum) = self.mono_sum {
+            mono_sum.analyzer.reset_loudness_range();
+        }
+    }
 
-        if channel_number >= self.channels {
-            return Err(Error::InvalidChannelIndex);
+    /// Resets the current state.
+    pub fn reset(&mut self) {
+        self.audio_data.fill(0.0);
+        self.frames_added = 0;
+        self.next_timestamp = None;
+        self.partial_block_flushed = false;
+        if let Some(ref mut alarm) = self.threshold_alarm {
+            alarm.above = false;
         }
 
-        Ok(self.filter.sample_peak()[channel_number as usize])
-    }
+        // the first block needs 400ms of audio data
+        self.needed_frames = self.samples_in_100ms * 4;
+        // start at the beginning of the buffer
+        self.audio_data_index = 0;
+        self.window_frames = 0;
+        // reset short term frame counter
+        self.short_term_frame_counter = 0;
 
-    /// Get maximum true peak from all frames that have been processed.
-    ///
-    /// Uses an implementation defined algorithm to calculate the true peak. Do not try to compare
-    /// resulting values across different versions of the library, as the algorithm may change.
-    ///
-    /// The current implementation uses a custom polyphase FIR interpolator to calculate true peak.
-    /// Will oversample 4x for sample rates < 96000 Hz, 2x for sample rates < 192000 Hz and leave
-    /// the signal unchanged for 192000 Hz.
-    ///
-    /// The equation to convert to dBTP is: 20 * log10(out)
-    pub fn true_peak(&self, channel_number: u32) -> Result<f64, Error> {
-        if !self.mode.contains(Mode::TRUE_PEAK) {
-            return Err(Error::InvalidMode);
-        }
+        self.true_peak.fill(0.0);
+        self.true_peak_over_counts.fill(0);
+        self.sample_peak.fill(0.0);
+        self.displayed_peak.fill(0.0);
 
-        if channel_number >= self.channels {
-            return Err(Error::InvalidChannelIndex);
+        self.filter.reset();
+        self.partial_frame.clear();
+        if let Some(ref mut mono_sum) = self.mono_sum {
+            mono_sum.analyzer.reset();
         }
-
-        if self.sample_peak[channel_number as usize] > self.true_peak[channel_number as usize] {
-            Ok(self.sample_peak[channel_number as usize])
-        } else {
-            Ok(self.true_peak[channel_number as usize])
+        if let Some(ref mut channel_block_energies) = self.channel_block_energies {
+            channel_block_energies.clear();
+        }
+        if let Some(ref mut dc_sums) = self.dc_sums {
+            dc_sums.fill(0.0);
+        }
+        self.dc_frames = 0;
+        if let Some(ref mut signed_peaks) = self.signed_peaks {
+            signed_peaks.fill((0.0, 0.0));
+        }
+        if let Some(ref mut segments) = self.segments {
+            segments.series.clear();
+            segments.restart(0, segments.frames);
+        }
+        if let Some(ref mut block_true_peaks) = self.block_true_peaks {
+            block_true_peaks.clear();
+        }
+        for region in self.regions.iter_mut() {
+            region.blocks.reset();
+        }
+        #[cfg(feature = "resampling")]
+        if let Some(ref mut resampler) = self.resampler {
+            resampler.reset();
+        }
+        if let Some(ref mut block_energy_history) = self.block_energy_history {
+            block_energy_history.reset();
+        }
+        if let Some(ref mut short_term_block_energy_history) = self.short_term_block_energy_history
+        {
+            short_term_block_energy_history.reset();
+        }
+        #[cfg(feature = "dual-history")]
+        if let Some(ref mut dual_history) = self.dual_history {
+            for history in Iterator::chain(
+                dual_history.block_energy_history.iter_mut(),
+                dual_history.short_term_block_energy_history.iter_mut(),
+            ) {
+                history.reset();
+            }
         }
     }
 
-    /// Get maximum true peak from the last call to
-    /// [`EbuR128::add_frames`](struct.EbuR128.html#method.add_frames_i16).
-    ///
-    /// Uses an implementation defined algorithm to calculate the true peak. Do not try to compare
-    /// resulting values across different versions of the library, as the algorithm may change.
-    ///
-    /// The current implementation uses a custom polyphase FIR interpolator to calculate true peak.
-    /// Will oversample 4x for sample rates < 96000 Hz, 2x for sample rates < 192000 Hz and leave
-    /// the signal unchanged for 192000 Hz.
-    ///
-    /// The equation to convert to dBTP is: 20 * log10(out)
-    pub fn prev_true_peak(&self, channel_number: u32) -> Result<f64, Error> {
-        if !self.mode.contains(Mode::TRUE_PEAK) {
-            return Err(Error::InvalidMode);
+    /// Process frames. This is the generic variant of the different public add_frames() functions
+    /// that are defined below.
+    fn add_frames<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+    ) -> Result<(), Error> {
+        self.add_frames_impl(src, false)
+    }
+
+    /// Process frames, which are already K-weighted if `prefiltered` is set.
+    fn add_frames_impl<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        prefiltered: bool,
+    ) -> Result<(), Error> {
+        self.add_frame_parts(std::iter::once(src), prefiltered)
+    }
+
+    /// Process multiple parts of frames one after another as if they were a single buffer, e.g.
+    /// the two halves of a ring buffer.
+    fn add_frame_parts<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        parts: impl IntoIterator<Item = S>,
+        prefiltered: bool,
+    ) -> Result<(), Error> {
+        if self.channels == 0 {
+            return Err(Error::NoMem);
         }
 
-        if channel_number >= self.channels {
-            return Err(Error::InvalidChannelIndex);
+        // At most three parts, see `add_frames_circular_f32`
+        let parts = parts
+            .into_iter()
+            .filter(|src| src.frames() > 0)
+            .collect::<smallvec::SmallVec<[S; 3]>>();
+        if parts.is_empty() {
+            return Ok(());
         }
 
-        let sample_peak = self.filter.sample_peak();
-        let true_peak = self.filter.true_peak();
+        self.check_max_duration(parts.iter().map(|src| src.frames() as u64).sum::<u64>())?;
 
-        if sample_peak[channel_number as usize] > true_peak[channel_number as usize] {
-            Ok(sample_peak[channel_number as usize])
-        } else {
-            Ok(true_peak[channel_number as usize])
+        self.filter.reset_peaks();
+        let frames_added = self.frames_added;
+
+        for src in parts {
+            self.process_part(src, prefiltered)?;
         }
+
+        self.finish_frames((self.frames_added - frames_added) as usize);
+
+        Ok(())
     }
 
-    /// Get relative threshold in LUFS.
-    pub fn relative_threshold(&self) -> Result<f64, Error> {
-        if !self.mode.contains(Mode::I) {
-            return Err(Error::InvalidMode);
+    /// Process the frames of `src` after applying the pregain and resampling them, if enabled.
+    fn process_part<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        prefiltered: bool,
+    ) -> Result<(), Error> {
+        if let Some((_, gain)) = self.pregain {
+            let channels = self.channels as usize;
+            let mut buffer = [0.0; CHUNK_SAMPLES];
+            let mut src = src;
+            while src.frames() > 0 {
+                let chunk_frames = usize::min(CHUNK_SAMPLES / channels, src.frames());
+                let (chunk, rest) = src.split_at(chunk_frames);
+                src = rest;
+
+                let scaled = &mut buffer[..chunk.frames() * channels];
+                Self::scale_frames(&chunk, gain, scaled);
+                self.process_resampled(crate::Interleaved::new(scaled, channels)?, prefiltered)?;
+            }
+
+            return Ok(());
         }
 
-        Ok(self.block_energy_history.relative_threshold())
+        self.process_resampled(src, prefiltered)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    #[cfg(feature = "c-tests")]
-    use crate::tests::Signal;
-    use float_eq::assert_float_eq;
-    #[cfg(feature = "c-tests")]
-    use quickcheck_macros::quickcheck;
+    /// Process the frames of `src` after resampling them, if enabled.
+    fn process_resampled<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        prefiltered: bool,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "resampling")]
+        if let Some(mut resampler) = self.resampler.take() {
+            let res = crate::Interleaved::new(resampler.process(&src), self.channels as usize)
+                .and_then(|src| self.process_frames(src, prefiltered));
+            self.resampler = Some(resampler);
+            return res;
+        }
 
-    fn f64_max(mut values: impl Iterator<Item = f64>) -> Option<f64> {
-        let mut v = values.next()?;
-        for candidate in values {
-            if candidate > v {
-                v = candidate
+        self.process_frames(src, prefiltered)
+    }
+
+    /// Fail with `Error::MaxDurationExceeded` if adding `frames` more frames would exceed the
+    /// maximum duration. With resampling, `frames` is the number of frames before resampling.
+    fn check_max_duration(&self, frames: u64) -> Result<(), Error> {
+        #[cfg(feature = "resampling")]
+        let frames = match self.resampler {
+            Some(ref resampler) if self.max_duration.is_some() => {
+                resampler.output_frames(frames as usize) as u64
+            }
+            _ => frames,
+        };
+
+        if let Some(max_duration) = self.max_duration {
+            if (self.frames_added + frames) as f64 > max_duration * self.rate as f64 {
+                return Err(Error::MaxDurationExceeded);
             }
         }
-        Some(v)
+
+        Ok(())
     }
 
-    #[test]
-    fn sine_stereo_i16() {
-        let mut data = vec![0i16; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator) * (i16::MAX - 1) as f32;
-            out[0] = val as i16;
-            out[1] = val as i16;
-            accumulator += step;
+    /// Process the frames of `src`, which are already K-weighted if `prefiltered` is set.
+    ///
+    /// All frames of a call to one of the `add_frames_*` functions can be passed in multiple
+    /// parts. The peaks of the filter must be reset before the first part, and
+    /// [`EbuR128::finish_frames`] must be called after the last part.
+    fn process_frames<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        mut src: S,
+        prefiltered: bool,
+    ) -> Result<(), Error> {
+        self.frames_added += src.frames() as u64;
+        if src.frames() > 0 {
+            self.partial_block_flushed = false;
         }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
-        ebu.add_frames_i16(&data).unwrap();
+        if let Some(ref mut mono_sum) = self.mono_sum {
+            mono_sum.add(&src, prefiltered)?;
+        }
 
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.6500000000000054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6820309226891973,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6834583474398446,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.875007988101488,
-            abs <= 0.000001
-        );
-        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
+        if let Some(ref mut dc_sums) = self.dc_sums {
+            if !prefiltered && !self.paused {
+                for (c, dc_sum) in dc_sums.iter_mut().enumerate() {
+                    src.foreach_sample(c, |sample| *dc_sum += sample.to_sample::<f64>());
+                }
+                self.dc_frames += src.frames() as u64;
+            }
+        }
 
-        assert_float_eq!(
-            ebu.sample_peak(0).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.sample_peak(1).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_sample_peak(0).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_sample_peak(1).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
-        );
+        if let Some(ref mut signed_peaks) = self.signed_peaks {
+            if !prefiltered && !self.paused {
+                for (c, (max, min)) in signed_peaks.iter_mut().enumerate() {
+                    src.foreach_sample(c, |sample| {
+                        let v = sample.to_sample::<f64>();
+                        if v > *max {
+                            *max = v;
+                        } else if v < *min {
+                            *min = v;
+                        }
+                    });
+                }
+            }
+        }
 
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
-        );
+        if Self::is_peak_only(self.mode) {
+            // Only the peaks are tracked, nothing to filter
+            self.filter.update_peaks(src);
+            return Ok(());
+        }
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.650000000000006,
-            abs <= 0.000001
-        );
+        while src.frames() > 0 {
+            let num_frames = src.frames();
 
-        ebu.reset();
+            if num_frames >= self.needed_frames {
+                let (current, next) = src.split_at(self.needed_frames);
 
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -f64::INFINITY,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -f64::INFINITY,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -f64::INFINITY,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -f64::INFINITY,
-            abs <= 0.000001
-        );
-        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
+                if prefiltered {
+                    crate::filter::Filter::copy(
+                        current,
+                        &mut self.audio_data,
+                        self.audio_data_index,
+                        &self.channel_map,
+                    );
+                } else {
+                    self.filter.process(
+                        current,
+                        &mut self.audio_data,
+                        self.audio_data_index,
+                        &self.channel_map,
+                    );
+                }
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 0.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 0.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 0.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 0.0, abs <= 0.000001);
+                src = next;
+                self.audio_data_index += self.needed_frames;
+                self.window_frames += self.needed_frames;
 
-        assert_float_eq!(ebu.true_peak(0).unwrap(), 0.0, abs <= 0.000001);
-        assert_float_eq!(ebu.true_peak(1).unwrap(), 0.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_true_peak(0).unwrap(), 0.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_true_peak(1).unwrap(), 0.0, abs <= 0.000001);
+                if let Some(ref mut block_energy_history) = self.block_energy_history {
+                    let energy = crate::filter::Filter::calc_gating_block(
+                        self.samples_in_100ms * 4,
+                        &self.audio_data,
+                        self.audio_data_index,
+                        &self.channel_map,
+                    );
+                    trace!("Finished gating block with energy {}", energy);
+                    if let Some(ref mut segments) = self.segments {
+                        let position = self.frames_added - src.frames() as u64;
+                        if self.paused {
+                            segments.complete_before(position);
+                        } else {
+                            segments.add_block(position, self.samples_in_100ms * 4, energy);
+                        }
+                    }
+                    if !self.paused {
+                        let center = (self.frames_added - src.frames() as u64)
+                            .saturating_sub(self.samples_in_100ms as u64 * 2);
+                        for region in self.regions.iter_mut() {
+                            if region.start <= center && center < region.end {
+                                region.blocks.add(energy);
+                            }
+                        }
+
+                        block_energy_history.add(energy);
+                        #[cfg(feature = "dual-history")]
+                        if let Some(DualHistory {
+                            block_energy_history: Some(ref mut history),
+                            ..
+                        }) = self.dual_history
+                        {
+                            history.add(energy);
+                        }
+                    }
+                }
 
-        assert_float_eq!(ebu.relative_threshold().unwrap(), -70.0, abs <= 0.000001);
-    }
+                if let Some(ref mut channel_block_energies) = self.channel_block_energies {
+                    if !self.paused {
+                        crate::filter::Filter::calc_channel_gating_blocks(
+                            self.samples_in_100ms * 4,
+                            &self.audio_data,
+                            self.audio_data_index,
+                            &self.channel_map,
+                            channel_block_energies,
+                        );
+                        self.truncate_channel_block_energies();
+                    }
+                }
+
+                if let Some(ref mut block_true_peaks) = self.block_true_peaks {
+                    if !self.paused {
+                        let block_frames = self.samples_in_100ms * 4;
+                        let filter = &self.filter;
+                        let peak = (0..self.channels as usize)
+                            .filter_map(|c| filter.true_peak_window(c, block_frames))
+                            .fold(0.0, f64::max);
+                        block_true_peaks.push(peak as f32);
+                    }
+                }
+
+                // Position of the end of this block
+                let position = self.frames_added - src.frames() as u64;
+                if self.momentary_callback.is_some() {
+                    let loudness = self.loudness_momentary()?;
+                    if let Some(ref mut momentary_callback) = self.momentary_callback {
+                        momentary_callback(position, loudness);
+                    }
+                }
+                if self.block_log_callback.is_some() {
+                    let log = self.block_log(position)?;
+                    if let Some(ref mut block_log_callback) = self.block_log_callback {
+                        block_log_callback(&log);
+                    }
+                }
+                self.check_threshold_alarm(position)?;
+
+                if self.mode.contains(Mode::LRA) {
+                    self.short_term_frame_counter += self.needed_frames;
+                    let shortterm_frames = self.shortterm_frames();
+                    if self.short_term_frame_counter == shortterm_frames {
+                        let energy = self.energy_shortterm()?;
+                        trace!("Finished short-term block with energy {}", energy);
+                        if let Some(ref mut short_term_block_energy_history) =
+                            self.short_term_block_energy_history
+                        {
+                            if !self.paused {
+                                short_term_block_energy_history.add(energy);
+                                #[cfg(feature = "dual-history")]
+                                if let Some(DualHistory {
+                                    short_term_block_energy_history: Some(ref mut history),
+                                    ..
+                                }) = self.dual_history
+                                {
+                                    history.add(energy);
+                                }
+                            }
+                        }
+                        // Short-term blocks are taken every second
+                        self.short_term_frame_counter =
+                            shortterm_frames.saturating_sub(self.samples_in_100ms * 10);
+                    }
+                }
+
+                let audio_data_frames = self.audio_data.len() / self.channels as usize;
+                if self.audio_data_index == audio_data_frames {
+                    self.audio_data_index = 0;
+                }
+                self.window_frames = usize::min(self.window_frames, audio_data_frames);
+
+                // 100ms are needed for all blocks besides the first one
+                self.needed_frames = self.samples_in_100ms;
+            } else {
+                let (current, next) = src.split_at(num_frames);
+
+                if prefiltered {
+                    crate::filter::Filter::copy(
+                        current,
+                        &mut self.audio_data,
+                        self.audio_data_index,
+                        &self.channel_map,
+                    );
+                } else {
+                    self.filter.process(
+                        current,
+                        &mut self.audio_data,
+                        self.audio_data_index,
+                        &self.channel_map,
+                    );
+                }
+
+                self.audio_data_index += num_frames;
+                self.window_frames += num_frames;
+                if self.mode.contains(Mode::LRA) {
+                    self.short_term_frame_counter += num_frames;
+                }
+
+                src = next;
+                self.needed_frames -= num_frames;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Update the peaks and segments after processing all `frames` frames of a call to one of the
+    /// `add_frames_*` functions.
+    fn finish_frames(&mut self, frames: usize) {
+        if let Some(ref mut segments) = self.segments {
+            // Segments that end with the last added frame are complete as well
+            segments.complete_before(self.frames_added + 1);
+        }
+
+        if self.paused {
+            self.update_displayed_peak(frames);
+            return;
+        }
+
+        let prev_sample_peak = self.filter.sample_peak();
+        for (sample_peak, prev_sample_peak) in
+            Iterator::zip(self.sample_peak.iter_mut(), prev_sample_peak.iter())
+        {
+            if *prev_sample_peak > *sample_peak {
+                *sample_peak = *prev_sample_peak;
+            }
+        }
+
+        let prev_true_peak = self.filter.true_peak();
+        for (true_peak, prev_true_peak) in
+            Iterator::zip(self.true_peak.iter_mut(), prev_true_peak.iter())
+        {
+            if *prev_true_peak > *true_peak {
+                *true_peak = *prev_true_peak;
+            }
+        }
+
+        if let Some(prev_counts) = self.filter.true_peak_over_counts() {
+            for (count, prev_count) in
+                Iterator::zip(self.true_peak_over_counts.iter_mut(), prev_counts.iter())
+            {
+                *count += *prev_count;
+            }
+        }
+
+        self.update_displayed_peak(frames);
+    }
+
+    /// Write the frames of `src` multiplied by `gain` to the interleaved `dst`, which must have
+    /// the same size.
+    fn scale_frames<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        src: &S,
+        gain: f64,
+        dst: &mut [f64],
+    ) {
+        let channels = src.channels();
+        debug_assert_eq!(dst.len(), src.frames() * channels);
+
+        for c in 0..channels {
+            let mut index = c;
+            src.foreach_sample(c, |sample| {
+                dst[index] = sample.to_sample::<f64>() * gain;
+                index += channels;
+            });
+        }
+    }
+
+    /// Let the displayed peak decay for the duration of `frames` and update it with the peaks of
+    /// the last call to `add_frames()`.
+    fn update_displayed_peak(&mut self, frames: usize) {
+        if !self.mode.contains(Mode::SAMPLE_PEAK) {
+            return;
+        }
+
+        let decay_db = self.peak_decay * frames as f64 / self.rate as f64;
+        let decay_factor = f64::powf(10.0, -decay_db / 20.0);
+        let calculate_true_peak = self.mode.contains(Mode::TRUE_PEAK);
+
+        for (c, displayed_peak) in self.displayed_peak.iter_mut().enumerate() {
+            let mut peak = self.filter.sample_peak()[c];
+            if calculate_true_peak {
+                peak = f64::max(peak, self.filter.true_peak()[c]);
+            }
+
+            *displayed_peak = f64::max(*displayed_peak * decay_factor, peak);
+        }
+    }
+
+    /// Get the configured decay of the displayed peak in dB per second.
+    pub fn peak_decay(&self) -> f64 {
+        self.peak_decay
+    }
+
+    /// Set the decay of the displayed peak in dB per second.
+    ///
+    /// The default is 0, i.e. the displayed peak is held at the maximum. The decay is applied for
+    /// the duration of each call to [`EbuR128::add_frames`](struct.EbuR128.html#method.add_frames_i16)
+    /// before updating the displayed peak with the peak of the added frames. Returns
+    /// `Error::InvalidMode` for negative or non-finite values.
+    pub fn set_peak_decay(&mut self, db_per_second: f64) -> Result<(), Error> {
+        if !db_per_second.is_finite() || db_per_second < 0.0 {
+            return Err(Error::InvalidMode);
+        }
+
+        self.peak_decay = db_per_second;
+        Ok(())
+    }
+
+    /// Get the decaying peak of the given channel for display, e.g. in a peak meter.
+    ///
+    /// This is the true peak if `Mode::TRUE_PEAK` is set and otherwise the sample peak, with the
+    /// decay configured by [`EbuR128::set_peak_decay`](struct.EbuR128.html#method.set_peak_decay)
+    /// applied. [`EbuR128::true_peak`](struct.EbuR128.html#method.true_peak) and
+    /// [`EbuR128::sample_peak`](struct.EbuR128.html#method.sample_peak) still return the maximum.
+    pub fn displayed_peak(&self, channel_number: u32) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::SAMPLE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        Ok(self.displayed_peak[channel_number as usize])
+    }
+
+    fn seed_frames<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(&mut self, src: S) {
+        if let Some((db, gain)) = self.pregain.take() {
+            let channels = self.channels as usize;
+            let mut buffer = [0.0; CHUNK_SAMPLES];
+            let mut src = src;
+            while src.frames() > 0 {
+                let chunk_frames = usize::min(CHUNK_SAMPLES / channels, src.frames());
+                let (chunk, rest) = src.split_at(chunk_frames);
+                src = rest;
+
+                let scaled = &mut buffer[..chunk.frames() * channels];
+                Self::scale_frames(&chunk, gain, scaled);
+                self.seed_frames(
+                    crate::Interleaved::new(&*scaled, channels)
+                        .expect("scaled frames must contain full frames"),
+                );
+            }
+            self.pregain = Some((db, gain));
+            return;
+        }
+
+        #[cfg(feature = "resampling")]
+        if let Some(ref mut resampler) = self.resampler {
+            let src = crate::Interleaved::new(resampler.process(&src), self.channels as usize)
+                .expect("resampler output must contain full frames");
+            if let Some(ref mut mono_sum) = self.mono_sum {
+                mono_sum.sum(&src);
+                mono_sum
+                    .analyzer
+                    .seed_frames_f64(&mono_sum.samples)
+                    .unwrap();
+            }
+            self.filter.seed(src, &self.channel_map);
+            return;
+        }
+
+        if let Some(ref mut mono_sum) = self.mono_sum {
+            mono_sum.sum(&src);
+            mono_sum
+                .analyzer
+                .seed_frames_f64(&mono_sum.samples)
+                .unwrap();
+        }
+        self.filter.seed(src, &self.channel_map);
+    }
+
+    /// Get the gain in dB that is applied to all added samples, see
+    /// [`EbuR128::set_measurement_pregain`](struct.EbuR128.html#method.set_measurement_pregain).
+    pub fn measurement_pregain(&self) -> f64 {
+        self.pregain.map_or(0.0, |(db, _)| db)
+    }
+
+    /// Apply a gain in dB to all samples that are added from now on, before filtering and peak
+    /// detection.
+    ///
+    /// All measurements are as if the input was scaled by the gain, which allows to preview the
+    /// outcome of a normalization without scaling the audio and adding it again. This includes
+    /// the sample and true peaks, so e.g.
+    /// [`EbuR128::true_peak`](struct.EbuR128.html#method.true_peak) shows whether the gain would
+    /// clip. Measurements of frames that were added before are not changed.
+    ///
+    /// The samples are scaled in chunks into a buffer on the stack, which costs a multiplication
+    /// and a copy per sample but no allocations. 0 dB, the default, disables the gain. Returns
+    /// `Error::InvalidMode` if the gain is not finite.
+    pub fn set_measurement_pregain(&mut self, db: f64) -> Result<(), Error> {
+        if !db.is_finite() {
+            return Err(Error::InvalidMode);
+        }
+
+        self.pregain = if db == 0.0 {
+            None
+        } else {
+            Some((db, f64::powf(10.0, db / 20.0)))
+        };
+
+        Ok(())
+    }
+
+    /// Get whether added frames are excluded from the measurement.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pause or resume the measurement.
+    ///
+    /// While paused, added frames are still filtered so that the measurement continues seamlessly
+    /// when resuming, but they don't contribute to the global integrated loudness, the loudness
+    /// range, or the sample and true peaks. This is useful for excluding e.g. commercial breaks
+    /// from the measurement of a live stream. The momentary and short-term loudness, the peaks
+    /// of the last call to `add_frames_*` and the displayed peak still reflect the added frames.
+    ///
+    /// Gating blocks that are completed while paused are dropped, even if they partially
+    /// consist of frames added before pausing or after resuming.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        if let Some(ref mut mono_sum) = self.mono_sum {
+            mono_sum.analyzer.set_paused(paused);
+        }
+    }
+
+    /// Get whether [`EbuR128::flush`](struct.EbuR128.html#method.flush) adds the trailing partial
+    /// gating block to the integrated loudness.
+    pub fn finalize_partial(&self) -> bool {
+        self.finalize_partial
+    }
+
+    /// Set whether [`EbuR128::flush`](struct.EbuR128.html#method.flush) adds the trailing partial
+    /// gating block to the integrated loudness.
+    ///
+    /// This deviates from ITU-R BS.1770, which only considers complete 400ms gating blocks, and
+    /// is disabled by default. It allows measuring the integrated loudness of audio shorter than
+    /// 400ms, which is otherwise negative infinity, and counts the frames after the last
+    /// complete gating block.
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::I` is not set.
+    pub fn set_finalize_partial(&mut self, finalize_partial: bool) -> Result<(), Error> {
+        if !self.mode.contains(Mode::I) {
+            return Err(Error::InvalidMode);
+        }
+
+        self.finalize_partial = finalize_partial;
+        if let Some(ref mut mono_sum) = self.mono_sum {
+            mono_sum.analyzer.set_finalize_partial(finalize_partial)?;
+        }
+
+        Ok(())
+    }
+
+    /// Finalize the measurement of the frames added so far.
+    ///
+    /// If enabled with
+    /// [`EbuR128::set_finalize_partial`](struct.EbuR128.html#method.set_finalize_partial) and
+    /// frames were added after the last complete gating block, this adds a gating block that
+    /// ends with the last added frame to the integrated loudness. The block has the standard
+    /// duration of 400ms, overlapping the previous blocks, or consists of all added frames if
+    /// there are fewer. It is added only once, even if this is called multiple times, and not
+    /// while paused. Frames added afterwards are measured as before, so the frames of the
+    /// partial block are counted again by the next complete gating blocks.
+    ///
+    /// Without enabling this, or if there is no partial gating block, this does nothing.
+    pub fn flush(&mut self) {
+        if let Some(ref mut mono_sum) = self.mono_sum {
+            mono_sum.analyzer.flush();
+        }
+
+        if !self.finalize_partial || self.partial_block_flushed || self.paused {
+            return;
+        }
+
+        // The first block needs 400ms and all following blocks 100ms of audio data
+        let first_block = self.window_frames < self.samples_in_100ms * 4;
+        if self.window_frames == 0 || (!first_block && self.needed_frames == self.samples_in_100ms)
+        {
+            return;
+        }
+
+        let frames = usize::min(self.window_frames, self.samples_in_100ms * 4);
+        if let Some(ref mut block_energy_history) = self.block_energy_history {
+            let energy = crate::filter::Filter::calc_gating_block(
+                frames,
+                &self.audio_data,
+                self.audio_data_index,
+                &self.channel_map,
+            );
+            trace!("Flushed partial gating block with energy {}", energy);
+            block_energy_history.add(energy);
+            #[cfg(feature = "dual-history")]
+            if let Some(DualHistory {
+                block_energy_history: Some(ref mut history),
+                ..
+            }) = self.dual_history
+            {
+                history.add(energy);
+            }
+        }
+
+        if let Some(ref mut channel_block_energies) = self.channel_block_energies {
+            crate::filter::Filter::calc_channel_gating_blocks(
+                frames,
+                &self.audio_data,
+                self.audio_data_index,
+                &self.channel_map,
+                channel_block_energies,
+            );
+            self.truncate_channel_block_energies();
+        }
+
+        self.partial_block_flushed = true;
+    }
+
+    /// Get whether incomplete trailing frames are buffered.
+    pub fn allow_partial_frames(&self) -> bool {
+        self.allow_partial_frames
+    }
+
+    /// Set whether incomplete trailing frames are buffered.
+    ///
+    /// By default, the interleaved `add_frames_*` functions return `Error::BufferSizeMismatch`
+    /// if the number of samples is not a multiple of the number of channels. If partial frames
+    /// are allowed, the samples of an incomplete trailing frame are buffered instead and
+    /// prepended to the samples of the next call. This is useful for inputs that are not aligned
+    /// to frame boundaries, e.g. byte streams.
+    ///
+    /// Disabling partial frames drops any buffered samples.
+    pub fn set_allow_partial_frames(&mut self, allow_partial_frames: bool) {
+        self.allow_partial_frames = allow_partial_frames;
+        if !allow_partial_frames {
+            self.partial_frame.clear();
+        }
+    }
+
+    /// Add interleaved frames, buffering an incomplete trailing frame if partial frames are
+    /// allowed.
+    fn add_interleaved_frames<T: Sample>(&mut self, frames: &[T]) -> Result<(), Error> {
+        let channels = self.channels as usize;
+        if !self.allow_partial_frames {
+            return self.add_frames(crate::Interleaved::new(frames, channels)?);
+        }
+
+        if self.partial_frame.is_empty() {
+            let complete = frames.len() - frames.len() % channels;
+            self.add_frames(crate::Interleaved::new(&frames[..complete], channels)?)?;
+            self.partial_frame
+                .extend(frames[complete..].iter().map(|s| s.to_sample::<f64>()));
+
+            return Ok(());
+        }
+
+        // Complete the buffered frame with the first new samples
+        let missing = channels - self.partial_frame.len();
+        if frames.len() < missing {
+            self.partial_frame
+                .extend(frames.iter().map(|s| s.to_sample::<f64>()));
+
+            return Ok(());
+        }
+
+        // Process it together with all following complete frames as a single call
+        let (head, frames) = frames.split_at(missing);
+        let complete = frames.len() - frames.len() % channels;
+        self.check_max_duration((1 + complete / channels) as u64)?;
+
+        self.filter.reset_peaks();
+        let frames_added = self.frames_added;
+        self.process_partial_frame(head)?;
+        if complete > 0 {
+            self.process_part(
+                crate::Interleaved::new(&frames[..complete], channels)?,
+                false,
+            )?;
+        }
+        self.finish_frames((self.frames_added - frames_added) as usize);
+
+        self.partial_frame
+            .extend(frames[complete..].iter().map(|s| s.to_sample::<f64>()));
+
+        Ok(())
+    }
+
+    /// Complete the buffered partial frame with `samples` and process it.
+    ///
+    /// The buffered samples are kept if the completed frame fails to be processed.
+    fn process_partial_frame<T: Sample>(&mut self, samples: &[T]) -> Result<(), Error> {
+        let channels = self.channels as usize;
+        let buffered = self.partial_frame.len();
+        debug_assert_eq!(buffered + samples.len(), channels);
+
+        let mut frame = [0.0; MAX_CHANNELS as usize];
+        frame[..buffered].copy_from_slice(&self.partial_frame);
+        for (dst, sample) in Iterator::zip(frame[buffered..channels].iter_mut(), samples.iter()) {
+            *dst = sample.to_sample::<f64>();
+        }
+
+        self.process_part(
+            crate::Interleaved::new(&frame[..channels], channels)?,
+            false,
+        )?;
+        self.partial_frame.clear();
+
+        Ok(())
+    }
+
+    /// Add `samples` interleaved samples that `decode` writes in chunks into a buffer on the
+    /// stack, like a single call of one of the `add_frames_*` functions with all samples.
+    ///
+    /// `decode` is called in order with the index of the first sample of each chunk and the chunk
+    /// to fill. Incomplete frames are handled like by the `add_frames_*` functions, see
+    /// [`EbuR128::set_allow_partial_frames`](struct.EbuR128.html#method.set_allow_partial_frames).
+    pub(crate) fn add_decoded_frames<T: Sample>(
+        &mut self,
+        samples: usize,
+        mut decode: impl FnMut(usize, &mut [T]),
+    ) -> Result<(), Error> {
+        let channels = self.channels as usize;
+        if channels == 0 {
+            return Err(Error::NoMem);
+        }
+        if !self.allow_partial_frames && samples % channels != 0 {
+            return Err(Error::BufferSizeMismatch);
+        }
+
+        let mut buffer = [T::EQUILIBRIUM; CHUNK_SAMPLES];
+        let buffered = self.partial_frame.len();
+        let frames = (buffered + samples) / channels;
+        if frames == 0 {
+            decode(0, &mut buffer[..samples]);
+            self.partial_frame
+                .extend(buffer[..samples].iter().map(|s| s.to_sample::<f64>()));
+
+            return Ok(());
+        }
+
+        self.check_max_duration(frames as u64)?;
+
+        self.filter.reset_peaks();
+        let frames_added = self.frames_added;
+
+        let mut offset = 0;
+        if buffered > 0 {
+            offset = channels - buffered;
+            decode(0, &mut buffer[..offset]);
+            self.process_partial_frame(&buffer[..offset])?;
+        }
+
+        let complete = samples - (samples - offset) % channels;
+        let chunk_samples = CHUNK_SAMPLES / channels * channels;
+        while offset < complete {
+            let len = usize::min(chunk_samples, complete - offset);
+            decode(offset, &mut buffer[..len]);
+            self.process_part(crate::Interleaved::new(&buffer[..len], channels)?, false)?;
+            offset += len;
+        }
+
+        self.finish_frames((self.frames_added - frames_added) as usize);
+
+        if offset < samples {
+            decode(offset, &mut buffer[..samples - offset]);
+            self.partial_frame.extend(
+                buffer[..samples - offset]
+                    .iter()
+                    .map(|s| s.to_sample::<f64>()),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Add interleaved frames to be processed.
+    ///
+    /// See [`EbuR128::set_allow_partial_frames`](struct.EbuR128.html#method.set_allow_partial_frames)
+    /// for the handling of incomplete frames.
+    pub fn add_frames_i16(&mut self, frames: &[i16]) -> Result<(), Error> {
+        self.add_interleaved_frames(frames)
+    }
+
+    /// Add interleaved frames to be processed.
+    ///
+    /// The samples are scaled by 2^-31 into `f64`, which is exact, so the full precision of
+    /// 32-bit samples is preserved for the loudness and the sample peak. The true peak is
+    /// calculated with `f32` precision.
+    ///
+    /// See [`EbuR128::set_allow_partial_frames`](struct.EbuR128.html#method.set_allow_partial_frames)
+    /// for the handling of incomplete frames.
+    pub fn add_frames_i32(&mut self, frames: &[i32]) -> Result<(), Error> {
+        self.add_interleaved_frames(frames)
+    }
+
+    /// Add interleaved frames to be processed.
+    ///
+    /// See [`EbuR128::set_allow_partial_frames`](struct.EbuR128.html#method.set_allow_partial_frames)
+    /// for the handling of incomplete frames.
+    pub fn add_frames_f32(&mut self, frames: &[f32]) -> Result<(), Error> {
+        self.add_interleaved_frames(frames)
+    }
+
+    /// Add interleaved frames to be processed.
+    ///
+    /// See [`EbuR128::set_allow_partial_frames`](struct.EbuR128.html#method.set_allow_partial_frames)
+    /// for the handling of incomplete frames.
+    pub fn add_frames_f64(&mut self, frames: &[f64]) -> Result<(), Error> {
+        self.add_interleaved_frames(frames)
+    }
+
+    /// Add interleaved frames that start at the given timestamp to be processed.
+    ///
+    /// `timestamp_samples` is the position of the first frame in frames, e.g. derived from the
+    /// RTP timestamp of a packet. If it is after the end of the frames of the previous call, the
+    /// frames in between were lost and the gap is handled according to the
+    /// [`GapPolicy`](enum.GapPolicy.html) set with
+    /// [`EbuR128::set_gap_policy`](struct.EbuR128.html#method.set_gap_policy). Frames before the
+    /// end of the frames of the previous call, e.g. of duplicated packets, are dropped. The
+    /// timestamp of the first call after creating or resetting the instance or changing its
+    /// sample rate is taken as is.
+    ///
+    /// Returns `Error::BufferSizeMismatch` if `frames` doesn't contain a whole number of frames.
+    pub fn add_frames_at_f32(
+        &mut self,
+        frames: &[f32],
+        timestamp_samples: u64,
+    ) -> Result<(), Error> {
+        let channels = self.channels as usize;
+        if frames.len() % channels != 0 {
+            return Err(Error::BufferSizeMismatch);
+        }
+
+        let mut start = timestamp_samples;
+        let mut frames = frames;
+        if let Some(next_timestamp) = self.next_timestamp {
+            if start < next_timestamp {
+                let overlap =
+                    u64::min(next_timestamp - start, (frames.len() / channels) as u64) as usize;
+                frames = &frames[overlap * channels..];
+                start += overlap as u64;
+            }
+
+            if start > next_timestamp {
+                let mut gap = start - next_timestamp;
+                let max_gap = self.max_silence_gap * self.input_rate() as f64;
+                match self.gap_policy {
+                    GapPolicy::InsertSilence if gap as f64 <= max_gap => {
+                        let silence = [0.0; CHUNK_SAMPLES];
+                        let chunk_frames = (CHUNK_SAMPLES / channels) as u64;
+                        while gap > 0 {
+                            let len = u64::min(gap, chunk_frames) as usize;
+                            self.add_frames_f32(&silence[..len * channels])?;
+                            gap -= len as u64;
+                        }
+                    }
+                    GapPolicy::InsertSilence | GapPolicy::ResetFilter => self.filter.reset_state(),
+                }
+            }
+        }
+
+        self.add_frames_f32(frames)?;
+        self.next_timestamp = Some(start + (frames.len() / channels) as u64);
+
+        Ok(())
+    }
+
+    /// Get the handling of gaps between the frames passed to
+    /// [`EbuR128::add_frames_at_f32`](struct.EbuR128.html#method.add_frames_at_f32).
+    pub fn gap_policy(&self) -> GapPolicy {
+        self.gap_policy
+    }
+
+    /// Set the handling of gaps between the frames passed to
+    /// [`EbuR128::add_frames_at_f32`](struct.EbuR128.html#method.add_frames_at_f32).
+    ///
+    /// The default is [`GapPolicy::InsertSilence`](enum.GapPolicy.html#variant.InsertSilence).
+    pub fn set_gap_policy(&mut self, policy: GapPolicy) {
+        self.gap_policy = policy;
+    }
+
+    /// Get the maximum gap in seconds that is filled with silence, see
+    /// [`EbuR128::set_max_silence_gap`](struct.EbuR128.html#method.set_max_silence_gap).
+    pub fn max_silence_gap(&self) -> f64 {
+        self.max_silence_gap
+    }
+
+    /// Set the maximum gap in seconds between the frames passed to
+    /// [`EbuR128::add_frames_at_f32`](struct.EbuR128.html#method.add_frames_at_f32) that is
+    /// filled with silence with [`GapPolicy::InsertSilence`](enum.GapPolicy.html#variant.InsertSilence).
+    ///
+    /// The silence is processed like any other frames before the call returns, so a bogus
+    /// timestamp far in the future, e.g. after the stream was restarted or the RTP timestamps
+    /// wrapped around, would take very long. Longer gaps are handled like with
+    /// [`GapPolicy::ResetFilter`](enum.GapPolicy.html#variant.ResetFilter) instead. The default
+    /// is 10 seconds.
+    ///
+    /// Returns `Error::InvalidMode` if the gap is negative or not a number.
+    pub fn set_max_silence_gap(&mut self, seconds: f64) -> Result<(), Error> {
+        if seconds.is_nan() || seconds < 0.0 {
+            return Err(Error::InvalidMode);
+        }
+
+        self.max_silence_gap = seconds;
+
+        Ok(())
+    }
+
+    /// Add interleaved frames from a ring buffer to be processed.
+    ///
+    /// `first` and `second` are the two contiguous parts of the ring buffer's content, e.g. as
+    /// returned by [`VecDeque::as_slices`](std::collections::VecDeque::as_slices), and are
+    /// processed one after another as if they were a single buffer without copying them. A frame
+    /// can span both parts.
+    ///
+    /// Returns `Error::BufferSizeMismatch` if both parts together don't contain a whole number
+    /// of frames. Partial frames are not buffered, see
+    /// [`EbuR128::set_allow_partial_frames`](struct.EbuR128.html#method.set_allow_partial_frames).
+    pub fn add_frames_circular_f32(&mut self, first: &[f32], second: &[f32]) -> Result<(), Error> {
+        let channels = self.channels as usize;
+        if (first.len() + second.len()) % channels != 0 {
+            return Err(Error::BufferSizeMismatch);
+        }
+
+        // Samples of the frame that spans both parts, if any
+        let split = first.len() - first.len() % channels;
+        let missing = (channels - first.len() % channels) % channels;
+        let mut spanning = smallvec::SmallVec::<[f32; 8]>::new();
+        spanning.extend_from_slice(&first[split..]);
+        spanning.extend_from_slice(&second[..missing]);
+
+        self.add_frame_parts(
+            [
+                crate::Interleaved::new(&first[..split], channels)?,
+                crate::Interleaved::new(&spanning, channels)?,
+                crate::Interleaved::new(&second[missing..], channels)?,
+            ],
+            false,
+        )
+    }
+
+    /// Add interleaved frames that are already K-weighted to be processed.
+    ///
+    /// The frames must have been filtered with the two-stage K-weighting filter of ITU BS.1770:
+    /// a high shelving pre-filter with about +4 dB gain above 2 kHz, followed by the RLB high-pass
+    /// filter with a cutoff frequency of about 38 Hz. The filter coefficients for 48 kHz are given
+    /// in ITU BS.1770, and must be recalculated for other sample rates.
+    ///
+    /// The frames are not filtered again and directly used for the loudness measurements. Sample
+    /// and true peaks are not updated by these frames, as the peaks of the filtered signal are
+    /// meaningless. Returns `Error::InvalidMode` if only peaks are tracked.
+    pub fn add_filtered_frames_f64(&mut self, frames: &[f64]) -> Result<(), Error> {
+        if Self::is_peak_only(self.mode) {
+            return Err(Error::InvalidMode);
+        }
+
+        self.add_frames_impl(
+            crate::Interleaved::new(frames, self.channels as usize)?,
+            true,
+        )
+    }
+
+    /// Add planar frames to be processed.
+    pub fn add_frames_planar_i16(&mut self, frames: &[&[i16]]) -> Result<(), Error> {
+        self.add_frames(crate::Planar::new(frames)?)
+    }
+
+    /// Add planar frames to be processed.
+    pub fn add_frames_planar_i32(&mut self, frames: &[&[i32]]) -> Result<(), Error> {
+        self.add_frames(crate::Planar::new(frames)?)
+    }
+
+    /// Add planar frames to be processed.
+    pub fn add_frames_planar_f32(&mut self, frames: &[&[f32]]) -> Result<(), Error> {
+        self.add_frames(crate::Planar::new(frames)?)
+    }
+
+    /// Add planar frames to be processed.
+    pub fn add_frames_planar_f64(&mut self, frames: &[&[f64]]) -> Result<(), Error> {
+        self.add_frames(crate::Planar::new(frames)?)
+    }
+
+    /// Add frames from a [`FrameSource`](crate::FrameSource) to be processed.
+    ///
+    /// Fails with `Error::BufferSizeMismatch` if the number of channels of the source doesn't
+    /// match, and with `Error::UnsupportedRate` if its sample rate doesn't match. With resampling,
+    /// see [`EbuR128::with_resampling`](struct.EbuR128.html#method.with_resampling), this is the
+    /// sample rate before resampling.
+    ///
+    /// The samples are processed in chunks as they are yielded by the source, without collecting
+    /// them first. If the source ends with an incomplete frame, all frames before it are processed
+    /// and `Error::BufferSizeMismatch` is returned. With a maximum duration, see
+    /// [`EbuR128::set_max_duration`](struct.EbuR128.html#method.set_max_duration), the source is
+    /// rejected as a whole if the lower bound of its size hint exceeds the maximum duration, and
+    /// otherwise only stops being processed at the chunk that would exceed it.
+    pub fn add_frames_from<S: crate::FrameSource>(&mut self, src: S) -> Result<(), Error> {
+        if self.channels == 0 {
+            return Err(Error::NoMem);
+        }
+
+        if src
+            .channels()
+            .map_or(false, |channels| channels != self.channels)
+        {
+            return Err(Error::BufferSizeMismatch);
+        }
+
+        if src.rate().map_or(false, |rate| rate != self.input_rate()) {
+            return Err(Error::UnsupportedRate);
+        }
+
+        let channels = self.channels as usize;
+        let mut samples = src.into_samples();
+        self.check_max_duration((samples.size_hint().0 / channels) as u64)?;
+
+        let mut buffer = [0.0; CHUNK_SAMPLES];
+        let chunk_samples = CHUNK_SAMPLES / channels * channels;
+
+        self.filter.reset_peaks();
+        let frames_added = self.frames_added;
+
+        loop {
+            let mut len = 0;
+            for (dst, sample) in Iterator::zip(buffer[..chunk_samples].iter_mut(), &mut samples) {
+                *dst = sample;
+                len += 1;
+            }
+
+            let chunk_frames = len / channels;
+            if chunk_frames > 0 {
+                let res = self.check_max_duration(chunk_frames as u64).and_then(|()| {
+                    let src =
+                        crate::Interleaved::new(&buffer[..chunk_frames * channels], channels)?;
+                    self.process_part(src, false)
+                });
+                if let Err(err) = res {
+                    // The chunks before count towards the loudness, so their peaks count too
+                    self.finish_frames((self.frames_added - frames_added) as usize);
+                    return Err(err);
+                }
+            }
+
+            if len < chunk_samples {
+                self.finish_frames((self.frames_added - frames_added) as usize);
+                return if len % channels == 0 {
+                    Ok(())
+                } else {
+                    Err(Error::BufferSizeMismatch)
+                };
+            }
+        }
+    }
+
+    /// Add interleaved frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_i16(&mut self, frames: &[i16]) -> Result<(), Error> {
+        self.seed_frames(crate::Interleaved::new(frames, self.channels as usize)?);
+        Ok(())
+    }
+
+    /// Add interleaved frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_i32(&mut self, frames: &[i32]) -> Result<(), Error> {
+        self.seed_frames(crate::Interleaved::new(frames, self.channels as usize)?);
+        Ok(())
+    }
+
+    /// Add interleaved frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_f32(&mut self, frames: &[f32]) -> Result<(), Error> {
+        self.seed_frames(crate::Interleaved::new(frames, self.channels as usize)?);
+        Ok(())
+    }
+
+    /// Add interleaved frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_f64(&mut self, frames: &[f64]) -> Result<(), Error> {
+        self.seed_frames(crate::Interleaved::new(frames, self.channels as usize)?);
+        Ok(())
+    }
+
+    /// Add planar frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_planar_i16(&mut self, frames: &[&[i16]]) -> Result<(), Error> {
+        self.seed_frames(crate::Planar::new(frames)?);
+        Ok(())
+    }
+
+    /// Add planar frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_planar_i32(&mut self, frames: &[&[i32]]) -> Result<(), Error> {
+        self.seed_frames(crate::Planar::new(frames)?);
+        Ok(())
+    }
+
+    /// Add planar frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_planar_f32(&mut self, frames: &[&[f32]]) -> Result<(), Error> {
+        self.seed_frames(crate::Planar::new(frames)?);
+        Ok(())
+    }
+
+    /// Add planar frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_planar_f64(&mut self, frames: &[&[f64]]) -> Result<(), Error> {
+        self.seed_frames(crate::Planar::new(frames)?);
+        Ok(())
+    }
+
+    /// Get global integrated loudness in LUFS.
+    ///
+    /// This sums the energies of all gating blocks, which is O(n) in the number of blocks. For
+    /// polling it after every call to `add_frames_*` during a long stream, see
+    /// [`EbuR128::set_sorted_integrated`](struct.EbuR128.html#method.set_sorted_integrated).
+    /// With `Mode::HISTOGRAM` it only depends on the number of histogram bins. A custom gating
+    /// predicate, see
+    /// [`EbuR128::set_gating_predicate`](struct.EbuR128.html#method.set_gating_predicate), is
+    /// evaluated for all blocks on every call.
+    pub fn loudness_global(&self) -> Result<f64, Error> {
+        let block_energy_history = self
+            .block_energy_history
+            .as_ref()
+            .ok_or(Error::InvalidMode)?;
+
+        if let Some(ref predicate) = self.gating_predicate {
+            return Ok(block_energy_history.predicate_gated_loudness(&**predicate));
+        }
+
+        Ok(block_energy_history.gated_loudness())
+    }
+
+    /// Get global integrated loudness in LUFS with only the absolute gate applied.
+    ///
+    /// This is the loudness of the mean energy of all gating blocks above the absolute gate of
+    /// -70 LUFS, without the relative gate of -10 LU. The difference to
+    /// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global) shows how much
+    /// quiet passages are excluded by the relative gate. Returns negative infinity if there are
+    /// no such blocks.
+    ///
+    /// This ignores the predicate set via [`EbuR128::set_gating_predicate`].
+    pub fn loudness_global_ungated(&self) -> Result<f64, Error> {
+        let block_energy_history = self
+            .block_energy_history
+            .as_ref()
+            .ok_or(Error::InvalidMode)?;
+
+        Ok(block_energy_history.absolute_gated_loudness())
+    }
+
+    /// Get the energies of the gating blocks behind the global integrated loudness in the order
+    /// they were measured.
+    ///
+    /// These are the mean square energies of all 400ms gating blocks above the absolute gate of
+    /// -70 LUFS, before the relative gate is applied, as kept in the history of `Mode::I`. Only
+    /// the blocks within the maximum history are kept, see
+    /// [`EbuR128::set_max_history`](struct.EbuR128.html#method.set_max_history). The loudness of
+    /// a block in LUFS is `10 * log10(energy) - 0.691`.
+    ///
+    /// This is only available with the `block-energies` feature. Returns `Error::InvalidMode` if
+    /// `Mode::I` is not set or `Mode::HISTOGRAM` is set, as the histogram doesn't keep the
+    /// individual blocks and their order.
+    #[cfg(feature = "block-energies")]
+    pub fn gated_block_energies(&self) -> Result<Vec<f64>, Error> {
+        self.block_energy_history
+            .as_ref()
+            .and_then(|history| history.energies())
+            .map(|energies| energies.collect())
+            .ok_or(Error::InvalidMode)
+    }
+
+    /// Get the global integrated loudness in LUFS of only the given channels.
+    ///
+    /// The energies of the given channels are weighted according to the current channel map and
+    /// summed for each gating block, and the loudness is gated like
+    /// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global) does, e.g. for
+    /// measuring only the front channels of a surround mix. With all channels this is the global
+    /// integrated loudness, up to rounding errors. The predicate set via
+    /// [`EbuR128::set_gating_predicate`] is ignored.
+    ///
+    /// Returns `Error::InvalidMode` if keeping the energies of each channel is not enabled, see
+    /// [`EbuR128::set_channel_energies`](struct.EbuR128.html#method.set_channel_energies), and
+    /// `Error::InvalidChannelIndex` for invalid channels.
+    pub fn loudness_of_channels(&self, channels: &[u32]) -> Result<f64, Error> {
+        if channels.iter().any(|&c| c >= self.channels) {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        Ok(self.channel_block_history(channels)?.gated_loudness())
+    }
+
+    /// Recompute the global integrated loudness from the kept energies of each channel.
+    ///
+    /// This replaces the gating blocks of the global integrated loudness by the sum of the
+    /// energies of each channel, weighted according to the current channel map. After correcting
+    /// the channel map with [`EbuR128::set_channel_map`](struct.EbuR128.html#method.set_channel_map),
+    /// this gives the integrated loudness as if the corrected channel map had been set from the
+    /// beginning. Channels that were `Channel::Unused` when the frames were added can't be
+    /// measured afterwards, as their energy was not kept.
+    ///
+    /// Only gating blocks since enabling the energies of each channel are kept, see
+    /// [`EbuR128::set_channel_energies`](struct.EbuR128.html#method.set_channel_energies), and
+    /// the loudness range is not recomputed. Returns `Error::InvalidMode` if keeping the energies
+    /// of each channel is not enabled.
+    pub fn recompute_integrated(&mut self) -> Result<(), Error> {
+        let channels = (0..self.channels).collect::<Vec<_>>();
+        let mut history = self.channel_block_history(&channels)?;
+        history.set_max_size(self.history / 100);
+        history.set_sorted(self.sorted_integrated);
+
+        self.block_energy_history = Some(history);
+
+        Ok(())
+    }
+
+    /// Create a block energy history from the kept energies of the given channels, weighted
+    /// according to the current channel map.
+    fn channel_block_history(&self, channels: &[u32]) -> Result<crate::history::History, Error> {
+        let channel_block_energies = match self.channel_block_energies {
+            Some(ref channel_block_energies) => channel_block_energies,
+            None => return Err(Error::InvalidMode),
+        };
+
+        let use_histogram = self.mode.contains(Mode::HISTOGRAM);
+        let blocks = channel_block_energies.len() / self.channels as usize;
+        let mut history = crate::history::History::new(use_histogram, blocks);
+        if use_histogram {
+            history.set_histogram_bins(self.histogram_bins);
+        }
+        history.set_stable_accumulation(self.stable_accumulation);
+
+        for block in 0..blocks {
+            let offset = block * self.channels as usize;
+            let energy = channels
+                .iter()
+                .map(|&c| {
+                    let channel = self.channel_map[c as usize];
+                    if channel == Channel::Unused {
+                        return 0.0;
+                    }
+
+                    let weight = crate::filter::channel_weight(channel).unwrap_or(1.0);
+                    channel_block_energies[offset + c as usize] * weight
+                })
+                .sum();
+            history.add(energy);
+        }
+
+        Ok(history)
+    }
+
+    /// Get global integrated loudness in LKFS.
+    ///
+    /// LKFS is the unit used by ITU BS.1770 and ATSC A/85 and is identical to LUFS, so this
+    /// returns the same value as [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global).
+    #[inline]
+    pub fn loudness_global_lkfs(&self) -> Result<f64, Error> {
+        self.loudness_global()
+    }
+
+    /// Get momentary loudness (last 400ms) in LKFS.
+    ///
+    /// This returns the same value as
+    /// [`EbuR128::loudness_momentary`](struct.EbuR128.html#method.loudness_momentary), see
+    /// [`EbuR128::loudness_global_lkfs`](struct.EbuR128.html#method.loudness_global_lkfs).
+    #[inline]
+    pub fn loudness_momentary_lkfs(&self) -> Result<f64, Error> {
+        self.loudness_momentary()
+    }
+
+    /// Get short-term loudness (last 3s by default) in LKFS.
+    ///
+    /// This returns the same value as
+    /// [`EbuR128::loudness_shortterm`](struct.EbuR128.html#method.loudness_shortterm), see
+    /// [`EbuR128::loudness_global_lkfs`](struct.EbuR128.html#method.loudness_global_lkfs).
+    #[inline]
+    pub fn loudness_shortterm_lkfs(&self) -> Result<f64, Error> {
+        self.loudness_shortterm()
+    }
+
+    /// Get global integrated loudness in LU relative to the given target loudness in LUFS.
+    ///
+    /// This is the scale of EBU mode meters, where 0 LU corresponds to the target, e.g. -23 LUFS
+    /// for EBU R128. Positive values are louder than the target. Silence gives `-inf`.
+    #[inline]
+    pub fn loudness_global_relative_to(&self, target_lufs: f64) -> Result<f64, Error> {
+        Ok(self.loudness_global()? - target_lufs)
+    }
+
+    /// Get momentary loudness (last 400ms) in LU relative to the given target loudness in LUFS,
+    /// see [`EbuR128::loudness_global_relative_to`](struct.EbuR128.html#method.loudness_global_relative_to).
+    #[inline]
+    pub fn loudness_momentary_relative_to(&self, target_lufs: f64) -> Result<f64, Error> {
+        Ok(self.loudness_momentary()? - target_lufs)
+    }
+
+    /// Get short-term loudness (last 3s by default) in LU relative to the given target loudness
+    /// in LUFS, see
+    /// [`EbuR128::loudness_global_relative_to`](struct.EbuR128.html#method.loudness_global_relative_to).
+    #[inline]
+    pub fn loudness_shortterm_relative_to(&self, target_lufs: f64) -> Result<f64, Error> {
+        Ok(self.loudness_shortterm()? - target_lufs)
+    }
+
+    /// Get the gain step in dB that a live loudness leveler should apply to move towards the
+    /// target loudness in LUFS.
+    ///
+    /// This is the difference between the target and the short-term loudness, limited to
+    /// `max_step_db` in either direction so that the controller changes the gain smoothly, i.e.
+    /// a proportional controller with a gain of one and a rate limit. It is meant for an
+    /// instance that measures the output of the leveler: the step is applied on top of the
+    /// current gain on each update, and becomes zero once the output is at the target. The
+    /// short-term loudness is used because the momentary loudness fluctuates too much between
+    /// syllables or beats to follow it without pumping. Silence gives a step of zero, so that
+    /// the gain is not raised during pauses.
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::S` is not set or `max_step_db` is negative or NaN.
+    pub fn leveling_step(&self, target_lufs: f64, max_step_db: f64) -> Result<f64, Error> {
+        if max_step_db.is_nan() || max_step_db < 0.0 {
+            return Err(Error::InvalidMode);
+        }
+
+        let loudness = self.loudness_shortterm()?;
+        if !loudness.is_finite() {
+            return Ok(0.0);
+        }
+
+        Ok(f64::clamp(
+            target_lufs - loudness,
+            -max_step_db,
+            max_step_db,
+        ))
+    }
+
+    /// Get the standard deviation in LU of the loudness of the blocks that contribute to the
+    /// global integrated loudness, i.e. the blocks above the relative gate.
+    ///
+    /// A high value indicates dynamic content for which the integrated loudness is less
+    /// representative. Returns `NaN` if no block is above the gates.
+    ///
+    /// This ignores the predicate set via [`EbuR128::set_gating_predicate`].
+    pub fn integrated_stddev(&self) -> Result<f64, Error> {
+        let block_energy_history = self
+            .block_energy_history
+            .as_ref()
+            .ok_or(Error::InvalidMode)?;
+
+        Ok(block_energy_history.gated_loudness_stddev())
+    }
+
+    /// Get the loudness distribution of the gating blocks, e.g. for plotting it.
+    ///
+    /// The range from -70 LUFS to +30 LUFS is divided into `bins` equally sized bins and for each
+    /// bin a pair of the loudness at its center in LUFS and the number of gating blocks in it is
+    /// returned. Blocks louder than +30 LUFS are counted in the last bin. Like for the global
+    /// integrated loudness, blocks below the absolute gate of -70 LUFS are not included.
+    ///
+    /// With `Mode::HISTOGRAM` the blocks are binned by the center of the internal histogram bin
+    /// they fall into, so `bins` should not exceed
+    /// [`EbuR128::histogram_bins`](struct.EbuR128.html#method.histogram_bins).
+    pub fn loudness_histogram(&self, bins: usize) -> Result<Vec<(f64, u64)>, Error> {
+        let block_energy_history = self
+            .block_energy_history
+            .as_ref()
+            .ok_or(Error::InvalidMode)?;
+
+        Ok(block_energy_history.loudness_histogram(bins))
+    }
+
+    /// Get a rough estimate of the noise floor in LUFS.
+    ///
+    /// This is the loudness of the 5th percentile of all gating blocks above the absolute gate of
+    /// -70 LUFS, or the one of the quietest block if there are only a few blocks. Returns
+    /// negative infinity if there are no such blocks.
+    pub fn noise_floor_lufs(&self) -> Result<f64, Error> {
+        let block_energy_history = self
+            .block_energy_history
+            .as_ref()
+            .ok_or(Error::InvalidMode)?;
+
+        Ok(block_energy_history.percentile_loudness(0.05))
+    }
+
+    /// Loudness in LUFS of the loudest gating block in the history, i.e. the maximum momentary
+    /// loudness at the block boundaries.
+    pub(crate) fn max_block_loudness(&self) -> Result<f64, Error> {
+        let block_energy_history = self
+            .block_energy_history
+            .as_ref()
+            .ok_or(Error::InvalidMode)?;
+
+        Ok(block_energy_history.percentile_loudness(1.0))
+    }
+
+    /// Get the difference of the global integrated loudness to the one of `reference` in LU.
+    ///
+    /// This is the gain that has to be applied to the audio of `reference` to match the loudness
+    /// of this instance. Returns `None` if the loudness of either instance is not finite, e.g.
+    /// because only silence was processed so far.
+    pub fn loudness_delta(&self, reference: &Self) -> Result<Option<f64>, Error> {
+        let loudness = self.loudness_global()?;
+        let reference_loudness = reference.loudness_global()?;
+
+        if !loudness.is_finite() || !reference_loudness.is_finite() {
+            return Ok(None);
+        }
+
+        Ok(Some(loudness - reference_loudness))
+    }
+
+    /// Get global integrated loudness in LUFS across multiple instances.
+    ///
+    /// This can be used to allow parallel iteration of long signals, assuming some care is taken:
+    ///  1. Divide input-signal up in "chunks" of even 100ms samples. Make chunks overlap by 400ms, for example (0-10s, 9.6-20s, 19.6-30s, ...)
+    ///  2. The first chunk is processed as normal. Then in parallel, for each remaining chunk, create a new instance of `EbuR128`, and in parallel:
+    ///     1. Feed the first 100ms of the chunk (these are samples overlapping with last chunk) through `seed_frames_*` function. This is sufficient to make filter-states in each instance what they would have been if a single analyzer would have reached this point.
+    ///     2. Process the remaining samples of each chunk through the analyzer
+    ///  3. Call [`EbuR128::loudness_global_multiple`] over all the chunks to get the global loudness
+    // FIXME: Should maybe be IntoIterator? Maybe AsRef<Self>?
+    pub fn loudness_global_multiple<'a>(
+        iter: impl Iterator<Item = &'a Self>,
+    ) -> Result<f64, Error> {
+        use smallvec::SmallVec;
+
+        let h = iter
+            .map(|e| e.block_energy_history.as_ref().ok_or(Error::InvalidMode))
+            .collect::<Result<SmallVec<[_; 16]>, _>>()?;
+
+        Ok(crate::history::History::gated_loudness_multiple(&h))
+    }
+
+    fn energy_in_interval(&self, interval_frames: usize) -> Result<f64, Error> {
+        // Without any audio data, e.g. if only the peaks are tracked, there is no loudness at all
+        // and not even an empty window
+        if Self::is_peak_only(self.mode)
+            || self.audio_data.is_empty()
+            || interval_frames > self.audio_data.len() / self.channels as usize
+        {
+            return Err(Error::InvalidMode);
+        }
+
+        Ok(crate::filter::Filter::calc_gating_block(
+            interval_frames,
+            &self.audio_data,
+            self.audio_data_index,
+            &self.channel_map,
+        ))
+    }
+
+    /// Get the loudness of the given kind in LUFS.
+    fn loudness(&self, kind: LoudnessKind) -> Result<f64, Error> {
+        match kind {
+            LoudnessKind::Momentary => self.loudness_momentary(),
+            LoudnessKind::ShortTerm => self.loudness_shortterm(),
+            LoudnessKind::Integrated => self.loudness_global(),
+        }
+    }
+
+    /// Get momentary loudness (last 400ms) in LUFS.
+    pub fn loudness_momentary(&self) -> Result<f64, Error> {
+        let energy = self.energy_in_interval(self.samples_in_100ms * 4)?;
+
+        if energy <= 0.0 {
+            return Ok(-f64::INFINITY);
+        }
+
+        Ok(energy_to_loudness(energy))
+    }
+
+    fn shortterm_frames(&self) -> usize {
+        self.samples_in_100ms * (self.shortterm_window / 100)
+    }
+
+    fn energy_shortterm(&self) -> Result<f64, Error> {
+        self.energy_in_interval(self.shortterm_frames())
+    }
+
+    /// Get short-term loudness (last 3s by default) in LUFS.
+    ///
+    /// The window duration can be changed with
+    /// [`EbuR128::set_shortterm_window`](struct.EbuR128.html#method.set_shortterm_window) and
+    /// centered on a position before the last added frame with
+    /// [`EbuR128::set_shortterm_centered`](struct.EbuR128.html#method.set_shortterm_centered).
+    pub fn loudness_shortterm(&self) -> Result<f64, Error> {
+        let energy = self.energy_shortterm()?;
+
+        if energy <= 0.0 {
+            return Ok(-f64::INFINITY);
+        }
+
+        Ok(energy_to_loudness(energy))
+    }
+
+    /// Get loudness of the specified window in LUFS.
+    ///
+    /// window must not be larger than the current window. The current window can be changed by
+    /// calling [`EbuR128::set_max_window`](struct.EbuR128.html#method.set_max_window).
+    pub fn loudness_window(&self, window: u32) -> Result<f64, Error> {
+        let interval_frames = (self.rate as usize)
+            .checked_mul(window as usize)
+            .ok_or(Error::InvalidMode)?
+            / 1000;
+        let energy = self.energy_in_interval(interval_frames)?;
+
+        if energy <= 0.0 {
+            return Ok(-f64::INFINITY);
+        }
+
+        Ok(energy_to_loudness(energy))
+    }
+
+    /// Get loudness of multiple windows in LUFS.
+    ///
+    /// This gives the same results as calling
+    /// [`EbuR128::loudness_window`](struct.EbuR128.html#method.loudness_window) for each of the
+    /// windows but only sums up the overlapping parts of the windows once. All windows must not
+    /// be larger than the current window.
+    pub fn loudness_windows(&self, windows: &[u32]) -> Result<Vec<f64>, Error> {
+        if Self::is_peak_only(self.mode) || self.audio_data.is_empty() {
+            return Err(Error::InvalidMode);
+        }
+
+        let audio_data_frames = self.audio_data.len() / self.channels as usize;
+        let interval_frames = windows
+            .iter()
+            .map(|window| {
+                let interval_frames = (self.rate as usize)
+                    .checked_mul(*window as usize)
+                    .ok_or(Error::InvalidMode)?
+                    / 1000;
+                if interval_frames > audio_data_frames {
+                    return Err(Error::InvalidMode);
+                }
+
+                Ok(interval_frames)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let energies = crate::filter::Filter::calc_gating_blocks(
+            &interval_frames,
+            &self.audio_data,
+            self.audio_data_index,
+            &self.channel_map,
+        );
+
+        Ok(energies
+            .into_iter()
+            .map(|energy| {
+                if energy <= 0.0 {
+                    -f64::INFINITY
+                } else {
+                    energy_to_loudness(energy)
+                }
+            })
+            .collect())
+    }
+
+    /// Get loudness range (LRA) of programme in LU.
+    ///
+    /// Calculates loudness range according to EBU 3342.
+    pub fn loudness_range(&self) -> Result<f64, Error> {
+        let short_term_block_energy_history = self
+            .short_term_block_energy_history
+            .as_ref()
+            .ok_or(Error::InvalidMode)?;
+
+        Ok(short_term_block_energy_history.loudness_range())
+    }
+
+    /// Get loudness range (LRA) of programme in LU across multiple instances.
+    ///
+    /// Calculates loudness range according to EBU 3342.
+    ///
+    /// Returns `Error::MixedHistoryTypes` if the instances keep their histories differently, e.g.
+    /// some with `Mode::HISTOGRAM` and some without, see
+    /// [`EbuR128::loudness_range_multiple_mixed`](struct.EbuR128.html#method.loudness_range_multiple_mixed).
+    // FIXME: Should maybe be IntoIterator? Maybe AsRef<Self>?
+    pub fn loudness_range_multiple<'a>(
+        iter: impl IntoIterator<Item = &'a Self>,
+    ) -> Result<f64, Error> {
+        Self::loudness_range_multiple_mixed(iter, false)
+    }
+
+    /// Like [`EbuR128::loudness_range_multiple`](struct.EbuR128.html#method.loudness_range_multiple)
+    /// but with `allow_lossy_mix` instances that keep their histories differently can be combined
+    /// too.
+    ///
+    /// Histories can only be combined exactly if they are all queues, all histograms with the
+    /// same number of bins, or all t-digests. Otherwise, all blocks are added to a single
+    /// histogram with the finest resolution of the instances with `Mode::HISTOGRAM`, or 0.1 LU if
+    /// there are none. The loudness of each block is then only known within the resolution of
+    /// the histogram, which can change the loudness range by up to about that resolution, and the
+    /// t-digests only contribute the approximated distribution they keep.
+    ///
+    /// Returns `Error::MixedHistoryTypes` if the histories are of different kinds and
+    /// `allow_lossy_mix` is `false`.
+    pub fn loudness_range_multiple_mixed<'a>(
+        iter: impl IntoIterator<Item = &'a Self>,
+        allow_lossy_mix: bool,
+    ) -> Result<f64, Error> {
+        use smallvec::SmallVec;
+
+        let h = iter
+            .into_iter()
+            .map(|e| {
+                e.short_term_block_energy_history
+                    .as_ref()
+                    .ok_or(Error::InvalidMode)
+            })
+            .collect::<Result<SmallVec<[_; 16]>, _>>()?;
+
+        if allow_lossy_mix {
+            crate::history::History::loudness_range_multiple_lossy(&h)
+        } else {
+            crate::history::History::loudness_range_multiple(&h)
+        }
+    }
+
+    /// Get whether the histories are additionally kept with the other backend, see
+    /// [`EbuR128::set_dual_history`](struct.EbuR128.html#method.set_dual_history).
+    ///
+    /// This is only available with the `dual-history` feature.
+    #[cfg(feature = "dual-history")]
+    pub fn dual_history(&self) -> bool {
+        self.dual_history.is_some()
+    }
+
+    /// Enable or disable keeping the histories of the integrated loudness and loudness range
+    /// additionally with the other backend, i.e. as queues with `Mode::HISTOGRAM` and as
+    /// histograms otherwise.
+    ///
+    /// This is a diagnostic tool for deciding whether the quantization of the histograms is
+    /// acceptable for some content, see
+    /// [`EbuR128::loudness_global_both`](struct.EbuR128.html#method.loudness_global_both) and
+    /// [`EbuR128::loudness_range_both`](struct.EbuR128.html#method.loudness_range_both). It
+    /// needs the memory of both backends and only considers blocks that are measured after
+    /// enabling, i.e. it is not updated by
+    /// [`EbuR128::recompute_integrated`](struct.EbuR128.html#method.recompute_integrated).
+    ///
+    /// Returns `Error::InvalidMode` if neither `Mode::I` nor `Mode::LRA` is set.
+    ///
+    /// This is only available with the `dual-history` feature.
+    #[cfg(feature = "dual-history")]
+    pub fn set_dual_history(&mut self, enabled: bool) -> Result<(), Error> {
+        if !self.mode.contains(Mode::I) && !self.mode.contains(Mode::LRA) {
+            return Err(Error::InvalidMode);
+        }
+
+        if !enabled {
+            self.dual_history = None;
+            return Ok(());
+        }
+        if self.dual_history.is_some() {
+            return Ok(());
+        }
+
+        let use_histogram = !self.mode.contains(Mode::HISTOGRAM);
+        let block_energy_history = if self.mode.contains(Mode::I) {
+            Some(crate::history::History::new(
+                use_histogram,
+                self.history / 100,
+            ))
+        } else {
+            None
+        };
+        let short_term_block_energy_history = if self.mode.contains(Mode::LRA) {
+            Some(crate::history::History::new_sorted(
+                use_histogram,
+                self.history / 3000,
+            ))
+        } else {
+            None
+        };
+
+        let mut dual_history = DualHistory {
+            block_energy_history,
+            short_term_block_energy_history,
+        };
+        if let Some(ref mut history) = dual_history.block_energy_history {
+            history.set_stable_accumulation(self.stable_accumulation);
+        }
+        if let Some(ref mut history) = dual_history.short_term_block_energy_history {
+            history.set_stable_accumulation(self.stable_accumulation);
+        }
+        self.dual_history = Some(dual_history);
+
+        Ok(())
+    }
+
+    /// Order the results of the configured `history` and the `dual` one as histogram and queue.
+    #[cfg(feature = "dual-history")]
+    fn histogram_and_queue(&self, history: f64, dual: f64) -> (f64, f64) {
+        if self.mode.contains(Mode::HISTOGRAM) {
+            (history, dual)
+        } else {
+            (dual, history)
+        }
+    }
+
+    /// Get the global integrated loudness in LUFS of both the histogram and the queue backend.
+    ///
+    /// Returns `(histogram, queue)`, whose difference shows the effect of the quantization of
+    /// the histogram. Gating predicates set with
+    /// [`EbuR128::set_gating_predicate`](struct.EbuR128.html#method.set_gating_predicate) are not
+    /// applied.
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::I` is not set or the dual history is not enabled
+    /// with [`EbuR128::set_dual_history`](struct.EbuR128.html#method.set_dual_history).
+    ///
+    /// This is only available with the `dual-history` feature.
+    #[cfg(feature = "dual-history")]
+    pub fn loudness_global_both(&self) -> Result<(f64, f64), Error> {
+        match (&self.block_energy_history, &self.dual_history) {
+            (
+                Some(ref history),
+                Some(DualHistory {
+                    block_energy_history: Some(ref dual),
+                    ..
+                }),
+            ) => Ok(self.histogram_and_queue(history.gated_loudness(), dual.gated_loudness())),
+            _ => Err(Error::InvalidMode),
+        }
+    }
+
+    /// Get the loudness range (LRA) in LU of both the histogram and the queue backend.
+    ///
+    /// Returns `(histogram, queue)`, whose difference shows the effect of the quantization of
+    /// the histogram.
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::LRA` is not set, the dual history is not enabled
+    /// with [`EbuR128::set_dual_history`](struct.EbuR128.html#method.set_dual_history) or a
+    /// t-digest is used for the loudness range, see
+    /// [`EbuR128::set_loudness_range_digest`](struct.EbuR128.html#method.set_loudness_range_digest).
+    ///
+    /// This is only available with the `dual-history` feature.
+    #[cfg(feature = "dual-history")]
+    pub fn loudness_range_both(&self) -> Result<(f64, f64), Error> {
+        if self.loudness_range_digest().is_some() {
+            return Err(Error::InvalidMode);
+        }
+
+        match (&self.short_term_block_energy_history, &self.dual_history) {
+            (
+                Some(ref history),
+                Some(DualHistory {
+                    short_term_block_energy_history: Some(ref dual),
+                    ..
+                }),
+            ) => Ok(self.histogram_and_queue(history.loudness_range(), dual.loudness_range())),
+            _ => Err(Error::InvalidMode),
+        }
+    }
+
+    /// Get whether the DC offset is tracked, see
+    /// [`EbuR128::dc_offset`](struct.EbuR128.html#method.dc_offset).
+    pub fn dc_offset_tracking(&self) -> bool {
+        self.dc_sums.is_some()
+    }
+
+    /// Enable or disable tracking the DC offset, see
+    /// [`EbuR128::dc_offset`](struct.EbuR128.html#method.dc_offset).
+    ///
+    /// Only frames added after enabling are considered, and disabling discards the DC offset.
+    pub fn set_dc_offset_tracking(&mut self, enabled: bool) {
+        if !enabled {
+            self.dc_sums = None;
+            self.dc_frames = 0;
+        } else if self.dc_sums.is_none() {
+            self.dc_sums = Some(vec![0.0; self.channels as usize].into_boxed_slice());
+        }
+    }
+
+    /// Duration of segments of `seconds` in frames at `rate`.
+    fn segment_frames(seconds: f64, rate: u32) -> Result<u64, Error> {
+        let frames = f64::round(seconds * rate as f64);
+        if !(frames >= 1.0 && frames < u64::MAX as f64) {
+            return Err(Error::InvalidMode);
+        }
+
+        Ok(frames as u64)
+    }
+
+    /// Get the duration of the segments in seconds, see
+    /// [`EbuR128::set_segment_duration`](struct.EbuR128.html#method.set_segment_duration).
+    pub fn segment_duration(&self) -> Option<f64> {
+        self.segments.as_ref().map(|segments| segments.duration)
+    }
+
+    /// Measure the integrated loudness of consecutive segments of the given duration in seconds,
+    /// e.g. 6 seconds for the segments of HLS, or stop measuring with `None`.
+    ///
+    /// The segments start with the next added frame and are rounded to full frames. The loudness
+    /// of each segment is gated like the global integrated loudness, but only considers the
+    /// gating blocks that are completely inside the segment. This is the same as measuring each
+    /// segment with a separate instance, except that the filter state is kept across segments.
+    /// Frames added while paused are not measured but still count for the duration of the
+    /// segments. Changing the sample rate restarts the segments with the next added frame.
+    ///
+    /// The loudness of completed segments is available with
+    /// [`EbuR128::segment_loudness_series`](struct.EbuR128.html#method.segment_loudness_series),
+    /// and the loudness of the current, incomplete segment with
+    /// [`EbuR128::partial_segment_loudness`](struct.EbuR128.html#method.partial_segment_loudness).
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::I` is not set or the duration is shorter than a
+    /// single frame.
+    pub fn set_segment_duration(&mut self, seconds: Option<f64>) -> Result<(), Error> {
+        if !self.mode.contains(Mode::I) {
+            return Err(Error::InvalidMode);
+        }
+
+        let seconds = match seconds {
+            Some(seconds) => seconds,
+            None => {
+                self.segments = None;
+                return Ok(());
+            }
+        };
+
+        let frames = Self::segment_frames(seconds, self.rate)?;
+        let mut blocks = crate::history::History::new(false, usize::MAX);
+        blocks.set_stable_accumulation(self.stable_accumulation);
+        self.segments = Some(Segments {
+            duration: seconds,
+            frames,
+            end: self.frames_added + frames,
+            blocks,
+            series: Vec::new(),
+        });
+
+        Ok(())
+    }
+
+    /// Get the integrated loudness in LUFS of each completed segment, see
+    /// [`EbuR128::set_segment_duration`](struct.EbuR128.html#method.set_segment_duration).
+    ///
+    /// A segment is complete once its last frame was added. Segments without any gating block
+    /// above the gates have a loudness of negative infinity.
+    ///
+    /// Returns `Error::InvalidMode` if the segments are not enabled.
+    pub fn segment_loudness_series(&self) -> Result<&[f64], Error> {
+        match self.segments {
+            Some(ref segments) => Ok(&segments.series),
+            None => Err(Error::InvalidMode),
+        }
+    }
+
+    /// Get the integrated loudness in LUFS of the frames added to the current, incomplete
+    /// segment, see
+    /// [`EbuR128::set_segment_duration`](struct.EbuR128.html#method.set_segment_duration).
+    ///
+    /// At the end of the stream this is the loudness of the final partial segment, which is not
+    /// part of [`EbuR128::segment_loudness_series`](struct.EbuR128.html#method.segment_loudness_series).
+    /// If it is shorter than 400ms it contains no gating block and its loudness is negative
+    /// infinity.
+    ///
+    /// Returns `Error::InvalidMode` if the segments are not enabled.
+    pub fn partial_segment_loudness(&self) -> Result<f64, Error> {
+        match self.segments {
+            Some(ref segments) => Ok(segments.blocks.gated_loudness()),
+            None => Err(Error::InvalidMode),
+        }
+    }
+
+    /// Measure the integrated loudness of the frames from `start_frame` up to, but excluding,
+    /// `end_frame` as a region called `name`, e.g. a track of a CD image from a cue sheet.
+    ///
+    /// The positions count the frames added since the instance was created or reset, see
+    /// [`EbuR128::frames_added`](struct.EbuR128.html#method.frames_added). A gating block
+    /// belongs to every region that contains the frame at its center, so each block that
+    /// straddles the boundary between two consecutive regions is assigned to exactly one of
+    /// them. The loudness of each region is gated like the global integrated loudness and is
+    /// available with [`EbuR128::region_loudness`](struct.EbuR128.html#method.region_loudness).
+    ///
+    /// Regions can be added before or while adding frames, but only gating blocks that are
+    /// completed after adding a region are measured for it. Resetting the instance keeps the
+    /// regions but clears their measurements.
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::I` is not set, the region is empty or a region with
+    /// the same name already exists.
+    pub fn add_region(
+        &mut self,
+        name: &str,
+        start_frame: u64,
+        end_frame: u64,
+    ) -> Result<(), Error> {
+        if !self.mode.contains(Mode::I)
+            || start_frame >= end_frame
+            || self.regions.iter().any(|region| region.name == name)
+        {
+            return Err(Error::InvalidMode);
+        }
+
+        let mut blocks = crate::history::History::new(false, usize::MAX);
+        blocks.set_stable_accumulation(self.stable_accumulation);
+        self.regions.push(Region {
+            name: String::from(name),
+            start: start_frame,
+            end: end_frame,
+            blocks,
+        });
+
+        Ok(())
+    }
+
+    /// Get the integrated loudness in LUFS of the region called `name`, see
+    /// [`EbuR128::add_region`](struct.EbuR128.html#method.add_region).
+    ///
+    /// Regions without any gating block above the gates have a loudness of negative infinity.
+    ///
+    /// Returns `Error::InvalidMode` if there is no region with this name.
+    pub fn region_loudness(&self, name: &str) -> Result<f64, Error> {
+        self.regions
+            .iter()
+            .find(|region| region.name == name)
+            .map(|region| region.blocks.gated_loudness())
+            .ok_or(Error::InvalidMode)
+    }
+
+    /// Remove all regions added with
+    /// [`EbuR128::add_region`](struct.EbuR128.html#method.add_region).
+    pub fn clear_regions(&mut self) {
+        self.regions.clear();
+    }
+
+    /// Get the DC offset, i.e. the mean sample value, of all frames that have been processed
+    /// since enabling the tracking with
+    /// [`EbuR128::set_dc_offset_tracking`](struct.EbuR128.html#method.set_dc_offset_tracking).
+    ///
+    /// Samples are scaled to the range `[-1.0, 1.0)` like for the sample peak. A DC offset well
+    /// above the quantization noise usually indicates a problem with the capture hardware. Frames
+    /// added while paused and already K-weighted frames are not considered. Returns `0.0` if no
+    /// frames were processed.
+    ///
+    /// Returns `Error::InvalidMode` if tracking the DC offset is not enabled.
+    pub fn dc_offset(&self, channel_number: u32) -> Result<f64, Error> {
+        let dc_sums = match self.dc_sums {
+            Some(ref dc_sums) => dc_sums,
+            None => return Err(Error::InvalidMode),
+        };
+
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        if self.dc_frames == 0 {
+            return Ok(0.0);
+        }
+
+        Ok(dc_sums[channel_number as usize] / self.dc_frames as f64)
+    }
+
+    /// Get whether the signed sample peaks are tracked, see
+    /// [`EbuR128::sample_peak_positive`](struct.EbuR128.html#method.sample_peak_positive) and
+    /// [`EbuR128::sample_peak_negative`](struct.EbuR128.html#method.sample_peak_negative).
+    pub fn signed_sample_peaks(&self) -> bool {
+        self.signed_peaks.is_some()
+    }
+
+    /// Enable or disable tracking the signed sample peaks, see
+    /// [`EbuR128::sample_peak_positive`](struct.EbuR128.html#method.sample_peak_positive) and
+    /// [`EbuR128::sample_peak_negative`](struct.EbuR128.html#method.sample_peak_negative).
+    ///
+    /// This does not depend on `Mode::SAMPLE_PEAK`. Only frames added after enabling are
+    /// considered, and disabling discards the signed peaks.
+    pub fn set_signed_sample_peaks(&mut self, enabled: bool) {
+        if !enabled {
+            self.signed_peaks = None;
+        } else if self.signed_peaks.is_none() {
+            self.signed_peaks = Some(vec![(0.0, 0.0); self.channels as usize].into_boxed_slice());
+        }
+    }
+
+    fn signed_sample_peak(&self, channel_number: u32) -> Result<(f64, f64), Error> {
+        let signed_peaks = match self.signed_peaks {
+            Some(ref signed_peaks) => signed_peaks,
+            None => return Err(Error::InvalidMode),
+        };
+
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        Ok(signed_peaks[channel_number as usize])
+    }
+
+    /// Get the maximum sample value from all frames that have been processed since enabling the
+    /// tracking with
+    /// [`EbuR128::set_signed_sample_peaks`](struct.EbuR128.html#method.set_signed_sample_peaks).
+    ///
+    /// Unlike [`EbuR128::sample_peak`](struct.EbuR128.html#method.sample_peak) this only
+    /// considers positive samples, so that an asymmetric waveform, e.g. from distortion or a DC
+    /// offset, shows as a difference to the magnitude of
+    /// [`EbuR128::sample_peak_negative`](struct.EbuR128.html#method.sample_peak_negative).
+    /// Frames added while paused and already K-weighted frames are not considered. Returns `0.0`
+    /// if there were no positive samples.
+    ///
+    /// Returns `Error::InvalidMode` if tracking the signed sample peaks is not enabled.
+    pub fn sample_peak_positive(&self, channel_number: u32) -> Result<f64, Error> {
+        self.signed_sample_peak(channel_number).map(|(max, _)| max)
+    }
+
+    /// Get the minimum sample value from all frames that have been processed since enabling the
+    /// tracking with
+    /// [`EbuR128::set_signed_sample_peaks`](struct.EbuR128.html#method.set_signed_sample_peaks).
+    ///
+    /// This is the counterpart of
+    /// [`EbuR128::sample_peak_positive`](struct.EbuR128.html#method.sample_peak_positive) and
+    /// returns a negative value, or `0.0` if there were no negative samples.
+    ///
+    /// Returns `Error::InvalidMode` if tracking the signed sample peaks is not enabled.
+    pub fn sample_peak_negative(&self, channel_number: u32) -> Result<f64, Error> {
+        self.signed_sample_peak(channel_number).map(|(_, min)| min)
+    }
+
+    /// Get maximum sample peak from all frames that have been processed.
+    ///
+    /// The equation to convert to dBFS is: 20 * log10(out)
+    pub fn sample_peak(&self, channel_number: u32) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::SAMPLE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        Ok(self.sample_peak[channel_number as usize])
+    }
+
+    /// Get maximum sample peak from the last call to
+    /// [`EbuR128::add_frames`](struct.EbuR128.html#method.add_frames_i16).
+    ///
+    /// The equation to convert to dBFS is: 20 * log10(out)
+    pub fn prev_sample_peak(&self, channel_number: u32) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::SAMPLE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        Ok(self.filter.sample_peak()[channel_number as usize])
+    }
+
+    /// Get maximum true peak from all frames that have been processed.
+    ///
+    /// Uses an implementation defined algorithm to calculate the true peak. Do not try to compare
+    /// resulting values across different versions of the library, as the algorithm may change.
+    ///
+    /// The current implementation uses a custom polyphase FIR interpolator to calculate true peak.
+    /// Will oversample 4x for sample rates < 96000 Hz, 2x for sample rates < 192000 Hz and leave
+    /// the signal unchanged for 192000 Hz.
+    ///
+    /// The equation to convert to dBTP is: 20 * log10(out)
+    pub fn true_peak(&self, channel_number: u32) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        if self.sample_peak[channel_number as usize] > self.true_peak[channel_number as usize] {
+            Ok(self.sample_peak[channel_number as usize])
+        } else {
+            Ok(self.true_peak[channel_number as usize])
+        }
+    }
+
+    /// Get maximum true peak from the last call to
+    /// [`EbuR128::add_frames`](struct.EbuR128.html#method.add_frames_i16).
+    ///
+    /// Uses an implementation defined algorithm to calculate the true peak. Do not try to compare
+    /// resulting values across different versions of the library, as the algorithm may change.
+    ///
+    /// The current implementation uses a custom polyphase FIR interpolator to calculate true peak.
+    /// Will oversample 4x for sample rates < 96000 Hz, 2x for sample rates < 192000 Hz and leave
+    /// the signal unchanged for 192000 Hz.
+    ///
+    /// The equation to convert to dBTP is: 20 * log10(out)
+    pub fn prev_true_peak(&self, channel_number: u32) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        let sample_peak = self.filter.sample_peak();
+        let true_peak = self.filter.true_peak();
+
+        if sample_peak[channel_number as usize] > true_peak[channel_number as usize] {
+            Ok(sample_peak[channel_number as usize])
+        } else {
+            Ok(true_peak[channel_number as usize])
+        }
+    }
+
+    /// Get the difference in dB between the true peak and the sample peak of a channel from all
+    /// frames that have been processed.
+    ///
+    /// This is the headroom consumed by inter-sample peaks: a large value means that there are
+    /// peaks between the samples that a limiter which only looks at the sample peak would miss.
+    /// The value is never negative, and is 0.0 if the channel only contained silence so far.
+    pub fn intersample_peak_margin(&self, channel_number: u32) -> Result<f64, Error> {
+        let true_peak = self.true_peak(channel_number)?;
+        let sample_peak = self.sample_peak[channel_number as usize];
+
+        if sample_peak <= 0.0 {
+            return Ok(0.0);
+        }
+
+        Ok(20.0 * f64::log10(true_peak / sample_peak))
+    }
+
+    fn true_peak_window_frames(rate: u32, window: usize) -> usize {
+        // Round up to not miss any peak in the window
+        (rate as usize * window + 999) / 1000
+    }
+
+    /// Get the configured maximum true peak window duration in ms.
+    pub fn max_true_peak_window(&self) -> usize {
+        self.true_peak_window
+    }
+
+    /// Set the maximum true peak window duration.
+    ///
+    /// Set the maximum duration in ms that will be used for
+    /// [`EbuR128::true_peak_window`](struct.EbuR128.html#method.true_peak_window). This keeps the
+    /// true peak of each of the most recent frames and is disabled by default. Setting it destroys
+    /// the currently kept true peaks.
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::TRUE_PEAK` is not set, or if the window is shorter
+    /// than 400ms while the
+    /// [true peak of each gating block](struct.EbuR128.html#method.set_true_peak_per_block) is
+    /// recorded.
+    pub fn set_max_true_peak_window(&mut self, window: u32) -> Result<(), Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) || (self.block_true_peaks.is_some() && window < 400)
+        {
+            return Err(Error::InvalidMode);
+        }
+
+        let frames = (self.rate as usize)
+            .checked_mul(window as usize)
+            .ok_or(Error::NoMem)?;
+        if frames.checked_mul(self.channels as usize).is_none() {
+            return Err(Error::NoMem);
+        }
+
+        self.true_peak_window = window as usize;
+        self.filter
+            .set_true_peak_window(Self::true_peak_window_frames(
+                self.rate,
+                self.true_peak_window,
+            ));
+
+        Ok(())
+    }
+
+    /// Check if the true peak of each gating block is recorded, see
+    /// [`EbuR128::set_true_peak_per_block`](struct.EbuR128.html#method.set_true_peak_per_block).
+    pub fn true_peak_per_block_enabled(&self) -> bool {
+        self.block_true_peaks.is_some()
+    }
+
+    /// Record the maximum true peak over all channels of each completed gating block.
+    ///
+    /// This allows to precompute the gain envelope of a look-ahead limiter offline: the
+    /// attenuation in dB that is needed to keep a block below a ceiling in dBTP is
+    /// `f64::min(0.0, ceiling - 20.0 * f64::log10(peak))`.
+    ///
+    /// The peaks are taken from the windowed true peak, so enabling this raises the
+    /// [maximum true peak window](struct.EbuR128.html#method.set_max_true_peak_window) to 400ms if
+    /// it is shorter. Like the windowed true peak the peaks are delayed by the
+    /// [latency of the true peak calculation](struct.EbuR128.html#method.true_peak_latency).
+    /// Blocks completed while paused are not recorded.
+    ///
+    /// One `f32` is stored per block, i.e. 40 bytes per second or about 144kB per hour of audio.
+    /// The recorded peaks are cleared by [`EbuR128::reset`](struct.EbuR128.html#method.reset) and
+    /// when disabling it.
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::TRUE_PEAK` is not set or no gating blocks are
+    /// calculated because only peaks are measured.
+    pub fn set_true_peak_per_block(&mut self, enabled: bool) -> Result<(), Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) || Self::is_peak_only(self.mode) {
+            return Err(Error::InvalidMode);
+        }
+
+        if !enabled {
+            self.block_true_peaks = None;
+        } else if self.block_true_peaks.is_none() {
+            if self.true_peak_window < 400 {
+                self.set_max_true_peak_window(400)?;
+            }
+            self.block_true_peaks = Some(Vec::new());
+        }
+
+        Ok(())
+    }
+
+    /// Get the maximum true peak over all channels of each completed gating block as a linear
+    /// amplitude, see
+    /// [`EbuR128::set_true_peak_per_block`](struct.EbuR128.html#method.set_true_peak_per_block).
+    ///
+    /// Returns `Error::InvalidMode` if recording the true peak per block is not enabled.
+    pub fn true_peak_per_block(&self) -> Result<&[f32], Error> {
+        match self.block_true_peaks {
+            Some(ref block_true_peaks) => Ok(block_true_peaks),
+            None => Err(Error::InvalidMode),
+        }
+    }
+
+    /// Get the configured sample peak threshold for the true peak calculation in dBFS.
+    pub fn true_peak_threshold(&self) -> Option<f64> {
+        self.true_peak_threshold
+    }
+
+    /// Only calculate the true peak of blocks whose sample peak reaches `threshold_dbfs`.
+    ///
+    /// Oversampling for the true peak is by far the most expensive part of the measurement. With
+    /// a threshold, the samples are first checked in blocks of 1024 frames and blocks whose
+    /// sample peak is below the threshold in all channels are not oversampled. Their true peak
+    /// is assumed to be their sample peak instead. Inter-sample peaks of typical program
+    /// material exceed the sample peak by less than 3dB, so with a threshold of -3 dBFS the true
+    /// peaks of all blocks that could exceed 0 dBTP are calculated exactly. The maximum true
+    /// peak is exact as long as it is found in a block that reaches the threshold.
+    ///
+    /// `None` disables the threshold, which is the default. The threshold is ignored while a true
+    /// peak window is kept, see
+    /// [`EbuR128::set_max_true_peak_window`](struct.EbuR128.html#method.set_max_true_peak_window).
+    /// Returns `Error::InvalidMode` if `Mode::TRUE_PEAK` is not set or the threshold is not
+    /// finite.
+    pub fn set_true_peak_threshold(&mut self, threshold_dbfs: Option<f64>) -> Result<(), Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        if threshold_dbfs.map_or(false, |threshold| !threshold.is_finite()) {
+            return Err(Error::InvalidMode);
+        }
+
+        self.true_peak_threshold = threshold_dbfs;
+        self.filter
+            .set_true_peak_threshold(threshold_dbfs.map(Self::dbfs_to_amplitude));
+
+        Ok(())
+    }
+
+    /// Get the configured ceiling in dBTP above which true peak events are counted, see
+    /// [`EbuR128::set_true_peak_over_ceiling`](struct.EbuR128.html#method.set_true_peak_over_ceiling).
+    pub fn true_peak_over_ceiling(&self) -> Option<f64> {
+        self.true_peak_over_ceiling
+    }
+
+    /// Count the events where the true peak exceeds `ceiling_dbtp`, or stop counting with `None`.
+    ///
+    /// Each interpolated peak is compared against the ceiling and each run of consecutive frames
+    /// above it counts as a single event, per channel. Frames added while paused are not counted.
+    /// This is more expensive than only measuring the maximum true peak, as the peak of every
+    /// frame is needed, and the threshold of
+    /// [`EbuR128::set_true_peak_threshold`](struct.EbuR128.html#method.set_true_peak_threshold)
+    /// is ignored while counting.
+    ///
+    /// The counts are reset when setting the ceiling, by
+    /// [`EbuR128::reset_peaks`](struct.EbuR128.html#method.reset_peaks) and by
+    /// [`EbuR128::reset`](struct.EbuR128.html#method.reset). Returns `Error::InvalidMode` if
+    /// `Mode::TRUE_PEAK` is not set or the ceiling is not finite.
+    pub fn set_true_peak_over_ceiling(&mut self, ceiling_dbtp: Option<f64>) -> Result<(), Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        if ceiling_dbtp.map_or(false, |ceiling| !ceiling.is_finite()) {
+            return Err(Error::InvalidMode);
+        }
+
+        self.true_peak_over_ceiling = ceiling_dbtp;
+        self.true_peak_over_counts.fill(0);
+        self.filter
+            .set_true_peak_over_ceiling(ceiling_dbtp.map(Self::dbfs_to_amplitude));
+
+        Ok(())
+    }
+
+    /// Get the number of events where the true peak of the given channel exceeded the ceiling,
+    /// see
+    /// [`EbuR128::set_true_peak_over_ceiling`](struct.EbuR128.html#method.set_true_peak_over_ceiling).
+    ///
+    /// Returns `Error::InvalidMode` if no ceiling is set.
+    pub fn true_peak_over_count_channel(&self, channel_number: u32) -> Result<u64, Error> {
+        if self.true_peak_over_ceiling.is_none() {
+            return Err(Error::InvalidMode);
+        }
+
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        Ok(self.true_peak_over_counts[channel_number as usize])
+    }
+
+    /// Get the number of events where the true peak exceeded the ceiling summed over all
+    /// channels, see
+    /// [`EbuR128::set_true_peak_over_ceiling`](struct.EbuR128.html#method.set_true_peak_over_ceiling).
+    ///
+    /// Returns `Error::InvalidMode` if no ceiling is set.
+    pub fn true_peak_over_count(&self) -> Result<u64, Error> {
+        if self.true_peak_over_ceiling.is_none() {
+            return Err(Error::InvalidMode);
+        }
+
+        Ok(self.true_peak_over_counts.iter().sum())
+    }
+
+    fn dbfs_to_amplitude(dbfs: f64) -> f64 {
+        f64::powf(10.0, dbfs / 20.0)
+    }
+
+    /// Get maximum true peak of the most recent `window` ms of the given channel.
+    ///
+    /// This is useful e.g. for the lookahead of a limiter. `window` must not be larger than the
+    /// current true peak window, which can be changed by calling
+    /// [`EbuR128::set_max_true_peak_window`](struct.EbuR128.html#method.set_max_true_peak_window).
+    ///
+    /// The true peaks are delayed by the latency of the interpolator, see
+    /// [`EbuR128::true_peak_latency`](struct.EbuR128.html#method.true_peak_latency).
+    pub fn true_peak_window(&self, channel_number: u32, window: u32) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) || window as usize > self.true_peak_window {
+            return Err(Error::InvalidMode);
+        }
+
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        let frames = Self::true_peak_window_frames(self.rate, window as usize);
+        Ok(self
+            .filter
+            .true_peak_window(channel_number as usize, frames)
+            .unwrap_or(0.0))
+    }
+
+    /// Get maximum true peak of all channels from the last call to
+    /// [`EbuR128::add_frames`](struct.EbuR128.html#method.add_frames_i16).
+    ///
+    /// Returns an iterator over `(channel_number, true_peak)`, see
+    /// [`EbuR128::prev_true_peak`](struct.EbuR128.html#method.prev_true_peak). This is what a
+    /// real-time peak meter would query after every processed buffer.
+    ///
+    /// The peaks are reset at the start of each call to `add_frames`. The state of the true peak
+    /// interpolator is kept across calls, so inter-sample peaks between the last frame of the
+    /// previous and the first frame of the current buffer are attributed to the current buffer.
+    pub fn prev_true_peaks(&self) -> Result<impl Iterator<Item = (u32, f64)> + '_, Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        Ok(Iterator::zip(
+            self.filter.sample_peak().iter(),
+            self.filter.true_peak().iter(),
+        )
+        .enumerate()
+        .map(|(c, (sample_peak, true_peak))| (c as u32, f64::max(*sample_peak, *true_peak))))
+    }
+
+    /// Get the peak to loudness ratio (PLR) in dB.
+    ///
+    /// This is the difference between the maximum peak of all channels in dB and the global
+    /// integrated loudness, and a rough indicator for how much the dynamics of the audio are
+    /// compressed. The true peak is used if `Mode::TRUE_PEAK` is set, otherwise the sample peak.
+    ///
+    /// Returns `None` if the loudness is not finite or the peak is zero, e.g. because only silence
+    /// was processed so far.
+    pub fn crest_factor_db(&self) -> Result<Option<f64>, Error> {
+        if !self.mode.contains(Mode::I | Mode::SAMPLE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        let peak = self.max_peak()?;
+        let loudness = self.loudness_global()?;
+
+        if peak <= 0.0 || !loudness.is_finite() {
+            return Ok(None);
+        }
+
+        Ok(Some(20.0 * f64::log10(peak) - loudness))
+    }
+
+    /// Get a plan for normalizing the audio to `target_lufs` without exceeding a true peak of
+    /// `max_tp_dbtp`.
+    ///
+    /// The plan contains the gain for reaching the target loudness, by how much the peaks would
+    /// have to be limited with that gain, and the gain and resulting loudness if the gain is
+    /// reduced instead so that no limiting is needed. The true peak is used if `Mode::TRUE_PEAK`
+    /// is set, otherwise the sample peak.
+    ///
+    /// Returns `None` if the loudness is not finite, e.g. because only silence was processed so
+    /// far.
+    pub fn normalization_plan(
+        &self,
+        target_lufs: f64,
+        max_tp_dbtp: f64,
+    ) -> Result<Option<crate::NormalizationPlan>, Error> {
+        if !self.mode.contains(Mode::I | Mode::SAMPLE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        let peak = self.max_peak()?;
+        let loudness = self.loudness_global()?;
+
+        if !loudness.is_finite() {
+            return Ok(None);
+        }
+
+        Ok(Some(crate::NormalizationPlan::new(
+            loudness,
+            20.0 * f64::log10(peak),
+            target_lufs,
+            max_tp_dbtp,
+        )))
+    }
+
+    /// Check the measurements against the loudness requirements of a deliverable.
+    ///
+    /// Checks the global integrated loudness, the maximum true peak of all channels and, if
+    /// `spec.max_lra` is set, the loudness range. Silence never passes the loudness check.
+    /// Requires `Mode::I` and `Mode::TRUE_PEAK`, and `Mode::LRA` if the loudness range is
+    /// checked.
+    pub fn check_compliance(
+        &self,
+        spec: &crate::ComplianceSpec,
+    ) -> Result<crate::ComplianceResult, Error> {
+        if !self.mode.contains(Mode::I | Mode::TRUE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        let loudness_range = if spec.max_lra.is_some() {
+            Some(self.loudness_range()?)
+        } else {
+            None
+        };
+
+        Ok(crate::ComplianceResult::new(
+            spec,
+            self.loudness_global()?,
+            20.0 * f64::log10(self.max_peak()?),
+            loudness_range,
+        ))
+    }
+
+    /// Check whether the maximum true peak of all channels would exceed `ceiling_dbtp` after
+    /// applying a gain of `gain_db`.
+    pub fn would_clip_at_gain(&self, gain_db: f64, ceiling_dbtp: f64) -> Result<bool, Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        let peak = self.max_peak()?;
+        if peak <= 0.0 {
+            return Ok(false);
+        }
+
+        Ok(20.0 * f64::log10(peak) + gain_db > ceiling_dbtp)
+    }
+
+    /// Maximum true peak of all channels if `Mode::TRUE_PEAK` is set, otherwise the maximum
+    /// sample peak.
+    fn max_peak(&self) -> Result<f64, Error> {
+        (0..self.channels)
+            .map(|c| {
+                if self.mode.contains(Mode::TRUE_PEAK) {
+                    self.true_peak(c)
+                } else {
+                    self.sample_peak(c)
+                }
+            })
+            .try_fold(0.0, |max, peak| peak.map(|peak| f64::max(max, peak)))
+    }
+
+    /// Get the sample and true peaks of all channels together with their maxima.
+    ///
+    /// If both `Mode::SAMPLE_PEAK` and `Mode::TRUE_PEAK` are set, both are calculated in a
+    /// single pass over the samples.
+    pub fn peak_report(&self) -> crate::PeakReport {
+        crate::PeakReport::new(self)
+    }
+
+    /// Get the latency of the true peak interpolation in frames.
+    ///
+    /// The polyphase FIR interpolator used for the true peak measurement has a group delay of half
+    /// its length. An interpolated peak found while processing frame `n` therefore belongs to a
+    /// position around frame `n - latency` of the input. This is 0 if the signal is not
+    /// oversampled, i.e. for sample rates >= 192000 Hz.
+    pub fn true_peak_latency(&self) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        Ok(self.filter.true_peak_latency())
+    }
+
+    /// Get relative threshold in LUFS.
+    pub fn relative_threshold(&self) -> Result<f64, Error> {
+        let block_energy_history = self
+            .block_energy_history
+            .as_ref()
+            .ok_or(Error::InvalidMode)?;
+
+        Ok(block_energy_history.relative_threshold())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "c-tests")]
+    use crate::tests::Signal;
+    use float_eq::assert_float_eq;
+    #[cfg(feature = "c-tests")]
+    use quickcheck_macros::quickcheck;
+
+    fn f64_max(mut values: impl Iterator<Item = f64>) -> Option<f64> {
+        let mut v = values.next()?;
+        for candidate in values {
+            if candidate > v {
+                v = candidate
+            }
+        }
+        Some(v)
+    }
+
+    fn sine_f32(frames: usize, channels: usize) -> Vec<f32> {
+        let mut data = vec![0.0f32; frames * channels];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(channels) {
+            let val = f32::sin(accumulator);
+            out.fill(val);
+            accumulator += step;
+        }
+        data
+    }
+
+    #[test]
+    fn sine_stereo_i16() {
+        let mut data = vec![0i16; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator) * (i16::MAX - 1) as f32;
+            out[0] = val as i16;
+            out[1] = val as i16;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu.add_frames_i16(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6500000000000054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6820309226891973,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6834583474398446,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.875007988101488,
+            abs <= 0.000001
+        );
+        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.sample_peak(0).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.sample_peak(1).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_sample_peak(0).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_sample_peak(1).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.650000000000006,
+            abs <= 0.000001
+        );
+
+        ebu.reset();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -f64::INFINITY,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -f64::INFINITY,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -f64::INFINITY,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -f64::INFINITY,
+            abs <= 0.000001
+        );
+        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 0.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 0.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 0.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 0.0, abs <= 0.000001);
+
+        assert_float_eq!(ebu.true_peak(0).unwrap(), 0.0, abs <= 0.000001);
+        assert_float_eq!(ebu.true_peak(1).unwrap(), 0.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_true_peak(0).unwrap(), 0.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_true_peak(1).unwrap(), 0.0, abs <= 0.000001);
+
+        assert_float_eq!(ebu.relative_threshold().unwrap(), -70.0, abs <= 0.000001);
+    }
+
+    #[test]
+    fn sine_stereo_i32() {
+        let mut data = vec![0i32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator) * (i32::MAX - 1) as f32;
+            out[0] = val as i32;
+            out[1] = val as i32;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu.add_frames_i32(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6500000000000054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598274425,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715105212,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620040943,
+            abs <= 0.000001
+        );
+        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.650000000000006,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f32() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
+            out[1] = val;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6500000000000054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598268921,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715100236,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620008693,
+            abs <= 0.000001
+        );
+        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.650000000000006,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f64() {
+        let mut data = vec![0.0f64; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val as f64;
+            out[1] = val as f64;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu.add_frames_f64(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6500000000000054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598268921,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715100236,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620008693,
+            abs <= 0.000001
+        );
+        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.650000000000006,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_i16_no_histogram() {
+        let mut data = vec![0i16; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator) * (i16::MAX - 1) as f32;
+            out[0] = val as i16;
+            out[1] = val as i16;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_i16(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.683303243667768,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6820309226891973,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6834583474398446,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.875007988101488,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006950793233284625,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.sample_peak(0).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.sample_peak(1).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_sample_peak(0).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_sample_peak(1).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.683303243667767,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_i32_no_histogram() {
+        let mut data = vec![0i32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator) * (i32::MAX - 1) as f32;
+            out[0] = val as i32;
+            out[1] = val as i32;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_i32(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6826039914171368,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598274425,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715105212,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620040943,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006921150165073442,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.682603991417135,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f32_no_histogram() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
+            out[1] = val;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6826039914165554,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598268921,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715100236,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620008693,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006921150169403312,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.682603991416554,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f64_no_histogram() {
+        let mut data = vec![0.0f64; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val as f64;
+            out[1] = val as f64;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_f64(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6826039914165554,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598268921,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715100236,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620008693,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006921150169403312,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.682603991416554,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_i16_planar_no_histogram() {
+        let mut data = vec![0i16; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        let (fst, snd) = data.split_at_mut(48_000 * 5);
+        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
+            let val = f32::sin(accumulator) * (i16::MAX - 1) as f32;
+            *fst = val as i16;
+            *snd = val as i16;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_planar_i16(&[fst, snd]).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.683303243667768,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6820309226891973,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6834583474398446,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.875007988101488,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006950793233284625,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.sample_peak(0).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.sample_peak(1).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_sample_peak(0).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_sample_peak(1).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.683303243667767,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_i32_planar_no_histogram() {
+        let mut data = vec![0i32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        let (fst, snd) = data.split_at_mut(48_000 * 5);
+        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
+            let val = f32::sin(accumulator) * (i32::MAX - 1) as f32;
+            *fst = val as i32;
+            *snd = val as i32;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_planar_i32(&[fst, snd]).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6826039914171368,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598274425,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715105212,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620040943,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006921150165073442,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.682603991417135,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f32_planar_no_histogram() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        let (fst, snd) = data.split_at_mut(48_000 * 5);
+        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
+            let val = f32::sin(accumulator);
+            *fst = val;
+            *snd = val;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_planar_f32(&[fst, snd]).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6826039914165554,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598268921,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715100236,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620008693,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006921150169403312,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.682603991416554,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f64_planar_no_histogram() {
+        let mut data = vec![0.0f64; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        let (fst, snd) = data.split_at_mut(48_000 * 5);
+        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
+            let val = f32::sin(accumulator);
+            *fst = val as f64;
+            *snd = val as f64;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_planar_f64(&[fst, snd]).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6826039914165554,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598268921,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715100236,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620008693,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006921150169403312,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.682603991416554,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f32_multiple() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
+            out[1] = val;
+            accumulator += step;
+        }
+
+        let mut ebu1 = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu1.add_frames_f32(&data).unwrap();
+
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 880.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = 0.5 * val;
+            out[1] = 0.5 * val;
+            accumulator += step;
+        }
+
+        let mut ebu2 = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu2.add_frames_f32(&data).unwrap();
+
+        assert_float_eq!(
+            EbuR128::loudness_global_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
+            -2.603757953612454,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            EbuR128::loudness_range_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
+            5.599999999999995,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f32_no_histogram_multiple() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
+            out[1] = val;
+            accumulator += step;
+        }
+
+        let mut ebu1 = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu1.add_frames_f32(&data).unwrap();
+
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 880.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = 0.5 * val;
+            out[1] = 0.5 * val;
+            accumulator += step;
+        }
+
+        let mut ebu2 = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu2.add_frames_f32(&data).unwrap();
+
+        assert_float_eq!(
+            EbuR128::loudness_global_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
+            -2.6302830567858275,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            EbuR128::loudness_range_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
+            5.571749801957784,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn chunks_queue_with_true_peak() {
+        let mut data = vec![0.0f32; 48_000 * 3];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(1) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
+            accumulator += step;
+        }
+
+        let mut ebu1 = EbuR128::new(1, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu1.add_frames_f32(&data).unwrap();
+
+        let mut ebu_chunks = Vec::new();
+        for i in 0..3usize {
+            let mut ebu_chunk = EbuR128::new(1, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+            let start_index = std::cmp::max(i as isize * 48_000, 0) as usize;
+            let stop_index = std::cmp::min(start_index + 48_000 + (48_00 * 3), data.len());
+            if start_index > 0 {
+                ebu_chunk
+                    .seed_frames_f32(&data[start_index - 48_00..start_index])
+                    .unwrap();
+            }
+            ebu_chunk
+                .add_frames_f32(&data[start_index..stop_index])
+                .unwrap();
+            ebu_chunks.push(ebu_chunk);
+        }
+
+        assert_float_eq!(
+            ebu1.sample_peak(0).unwrap(),
+            f64_max(ebu_chunks.iter().map(|meter| meter.sample_peak(0).unwrap())).unwrap(),
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu1.true_peak(0).unwrap(),
+            f64_max(ebu_chunks.iter().map(|meter| meter.true_peak(0).unwrap())).unwrap(),
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu1.loudness_global().unwrap(),
+            EbuR128::loudness_global_multiple(ebu_chunks.iter()).unwrap(),
+            abs <= 0.000001
+        );
+    }
 
     #[test]
-    fn sine_stereo_i32() {
-        let mut data = vec![0i32; 48_000 * 5 * 2];
+    fn chunks_histogram_with_true_peak() {
+        let mut data = vec![0.0f32; 48_000 * 3];
         let mut accumulator = 0.0;
         let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator) * (i32::MAX - 1) as f32;
-            out[0] = val as i32;
-            out[1] = val as i32;
+        for out in data.chunks_exact_mut(1) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
             accumulator += step;
         }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
-        ebu.add_frames_i32(&data).unwrap();
+        let mut ebu1 = EbuR128::new(1, 48_000, Mode::all() | Mode::HISTOGRAM).unwrap();
+        ebu1.add_frames_f32(&data).unwrap();
+
+        let mut ebu_chunks = Vec::new();
+        for i in 0..3usize {
+            let mut ebu_chunk =
+                EbuR128::new(1, 48_000, Mode::all() | Mode::HISTOGRAM & !Mode::HISTOGRAM).unwrap();
+            let start_index = std::cmp::max(i as isize * 48_000, 0) as usize;
+            let stop_index = std::cmp::min(start_index + 48_000 + (48_00 * 3), data.len());
+            if start_index > 0 {
+                ebu_chunk
+                    .seed_frames_f32(&data[start_index - 48_00..start_index])
+                    .unwrap();
+            }
+            ebu_chunk
+                .add_frames_f32(&data[start_index..stop_index])
+                .unwrap();
+            ebu_chunks.push(ebu_chunk);
+        }
 
         assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.6500000000000054,
+            ebu1.sample_peak(0).unwrap(),
+            f64_max(ebu_chunks.iter().map(|meter| meter.sample_peak(0).unwrap())).unwrap(),
             abs <= 0.000001
         );
+
         assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598274425,
+            ebu1.true_peak(0).unwrap(),
+            f64_max(ebu_chunks.iter().map(|meter| meter.true_peak(0).unwrap())).unwrap(),
             abs <= 0.000001
         );
+
         assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715105212,
+            ebu1.loudness_global().unwrap(),
+            EbuR128::loudness_global_multiple(ebu_chunks.iter()).unwrap(),
             abs <= 0.000001
         );
+    }
+
+    #[cfg(feature = "c-tests")]
+    fn compare_results(ebu: &EbuR128, ebu_c: &ebur128_c::EbuR128, channels: u32) {
         assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620040943,
-            abs <= 0.000001
+            ebu.loudness_global().unwrap(),
+            ebu_c.loudness_global().unwrap(),
+            ulps <= 2
         );
-        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
-
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-
         assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+            ebu.loudness_momentary().unwrap(),
+            ebu_c.loudness_momentary().unwrap(),
+            ulps <= 2
         );
         assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+            ebu.loudness_shortterm().unwrap(),
+            ebu_c.loudness_shortterm().unwrap(),
+            ulps <= 2
         );
         assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+            ebu.loudness_window(1).unwrap(),
+            ebu_c.loudness_window(1).unwrap(),
+            ulps <= 2
         );
         assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+            ebu.loudness_range().unwrap(),
+            ebu_c.loudness_range().unwrap(),
+            ulps <= 2
+        );
+
+        for c in 0..channels {
+            assert_float_eq!(
+                ebu.sample_peak(c).unwrap(),
+                ebu_c.sample_peak(c).unwrap(),
+                ulps <= 2
+            );
+            assert_float_eq!(
+                ebu.prev_sample_peak(c).unwrap(),
+                ebu_c.prev_sample_peak(c).unwrap(),
+                ulps <= 2
+            );
+
+            assert_float_eq!(
+                ebu.true_peak(c).unwrap(),
+                ebu_c.true_peak(c).unwrap(),
+                // For a performance-boost, filter is defined as f32, causing slightly lower precision
+                abs <= 0.000004,
+            );
+            assert_float_eq!(
+                ebu.prev_true_peak(c).unwrap(),
+                ebu_c.prev_true_peak(c).unwrap(),
+                // For a performance-boost, filter is defined as f32, causing slightly lower precision
+                abs <= 0.000004,
+            );
+        }
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            ebu_c.relative_threshold().unwrap(),
+            ulps <= 2
         );
+    }
+
+    #[cfg(feature = "c-tests")]
+    #[quickcheck]
+    fn compare_c_impl_i16(signal: Signal<i16>) {
+        let mut ebu = EbuR128::new(signal.channels, signal.rate, Mode::all()).unwrap();
+        ebu.add_frames_i16(&signal.data).unwrap();
+
+        let mut ebu_c =
+            ebur128_c::EbuR128::new(signal.channels, signal.rate, ebur128_c::Mode::all()).unwrap();
+        ebu_c.add_frames_i16(&signal.data).unwrap();
+
+        compare_results(&ebu, &ebu_c, signal.channels);
+    }
+
+    #[cfg(feature = "c-tests")]
+    #[quickcheck]
+    fn compare_c_impl_i32(signal: Signal<i32>) {
+        let mut ebu = EbuR128::new(signal.channels, signal.rate, Mode::all()).unwrap();
+        ebu.add_frames_i32(&signal.data).unwrap();
+
+        let mut ebu_c =
+            ebur128_c::EbuR128::new(signal.channels, signal.rate, ebur128_c::Mode::all()).unwrap();
+        ebu_c.add_frames_i32(&signal.data).unwrap();
+
+        compare_results(&ebu, &ebu_c, signal.channels);
+    }
+
+    #[cfg(feature = "c-tests")]
+    #[quickcheck]
+    fn compare_c_impl_f32(signal: Signal<f32>) {
+        let mut ebu = EbuR128::new(signal.channels, signal.rate, Mode::all()).unwrap();
+        ebu.add_frames_f32(&signal.data).unwrap();
+
+        let mut ebu_c =
+            ebur128_c::EbuR128::new(signal.channels, signal.rate, ebur128_c::Mode::all()).unwrap();
+        ebu_c.add_frames_f32(&signal.data).unwrap();
+
+        compare_results(&ebu, &ebu_c, signal.channels);
+    }
+
+    #[cfg(feature = "c-tests")]
+    #[quickcheck]
+    fn compare_c_impl_f64(signal: Signal<f64>) {
+        let mut ebu = EbuR128::new(signal.channels, signal.rate, Mode::all()).unwrap();
+        ebu.add_frames_f64(&signal.data).unwrap();
+
+        let mut ebu_c =
+            ebur128_c::EbuR128::new(signal.channels, signal.rate, ebur128_c::Mode::all()).unwrap();
+        ebu_c.add_frames_f64(&signal.data).unwrap();
+
+        compare_results(&ebu, &ebu_c, signal.channels);
+    }
+
+    #[cfg(feature = "c-tests")]
+    #[quickcheck]
+    fn compare_c_impl_i16_no_histogram(signal: Signal<i16>) {
+        let mut ebu =
+            EbuR128::new(signal.channels, signal.rate, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_i16(&signal.data).unwrap();
+
+        let mut ebu_c = ebur128_c::EbuR128::new(
+            signal.channels,
+            signal.rate,
+            ebur128_c::Mode::all() & !ebur128_c::Mode::HISTOGRAM,
+        )
+        .unwrap();
+        ebu_c.add_frames_i16(&signal.data).unwrap();
+
+        compare_results(&ebu, &ebu_c, signal.channels);
+    }
+
+    #[cfg(feature = "c-tests")]
+    #[quickcheck]
+    fn compare_c_impl_i32_no_histogram(signal: Signal<i32>) {
+        let mut ebu =
+            EbuR128::new(signal.channels, signal.rate, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_i32(&signal.data).unwrap();
+
+        let mut ebu_c = ebur128_c::EbuR128::new(
+            signal.channels,
+            signal.rate,
+            ebur128_c::Mode::all() & !ebur128_c::Mode::HISTOGRAM,
+        )
+        .unwrap();
+        ebu_c.add_frames_i32(&signal.data).unwrap();
+
+        compare_results(&ebu, &ebu_c, signal.channels);
+    }
+
+    #[cfg(feature = "c-tests")]
+    #[quickcheck]
+    fn compare_c_impl_f32_no_histogram(signal: Signal<f32>) {
+        let mut ebu =
+            EbuR128::new(signal.channels, signal.rate, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_f32(&signal.data).unwrap();
+
+        let mut ebu_c = ebur128_c::EbuR128::new(
+            signal.channels,
+            signal.rate,
+            ebur128_c::Mode::all() & !ebur128_c::Mode::HISTOGRAM,
+        )
+        .unwrap();
+        ebu_c.add_frames_f32(&signal.data).unwrap();
+
+        compare_results(&ebu, &ebu_c, signal.channels);
+    }
+
+    #[cfg(feature = "c-tests")]
+    #[quickcheck]
+    fn compare_c_impl_f64_no_histogram(signal: Signal<f64>) {
+        let mut ebu =
+            EbuR128::new(signal.channels, signal.rate, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_f64(&signal.data).unwrap();
+
+        let mut ebu_c = ebur128_c::EbuR128::new(
+            signal.channels,
+            signal.rate,
+            ebur128_c::Mode::all() & !ebur128_c::Mode::HISTOGRAM,
+        )
+        .unwrap();
+        ebu_c.add_frames_f64(&signal.data).unwrap();
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.650000000000006,
-            abs <= 0.000001
-        );
+        compare_results(&ebu, &ebu_c, signal.channels);
     }
 
     #[test]
-    fn sine_stereo_f32() {
-        let mut data = vec![0.0f32; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+    fn infinity_handling() {
+        let mut data = vec![0.0f32; 44_100 * 80];
         for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator);
-            out[0] = val;
-            out[1] = val;
-            accumulator += step;
+            out[0] = f32::INFINITY;
+            out[1] = f32::NEG_INFINITY;
         }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
-        ebu.add_frames_f32(&data).unwrap();
-
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.6500000000000054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598268921,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715100236,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620008693,
-            abs <= 0.000001
-        );
-        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
-
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
+        let mut ebu = EbuR128::new(2, 44_100, Mode::all() - Mode::HISTOGRAM).unwrap();
+        assert!(ebu.add_frames_f32(&data).is_ok());
+        assert_eq!(ebu.sample_peak(0).unwrap().abs(), f64::INFINITY);
+        assert_eq!(ebu.true_peak(0).unwrap().abs(), f64::INFINITY);
+        assert!(ebu.loudness_global().unwrap().is_nan());
+        assert!(ebu.loudness_momentary().unwrap().is_nan());
+        assert!(ebu.energy_shortterm().unwrap().is_nan());
+        assert!(ebu.loudness_shortterm().unwrap().is_nan());
+        assert!(ebu.loudness_range().unwrap().is_nan());
+        assert!(ebu.relative_threshold().unwrap().is_nan());
 
+        // With histogram mode the first bin is taken for NaN
+        let mut ebu = EbuR128::new(2, 44_100, Mode::all()).unwrap();
+        assert!(ebu.add_frames_f32(&data).is_ok());
+        assert_eq!(ebu.sample_peak(0).unwrap().abs(), f64::INFINITY);
+        assert_eq!(ebu.true_peak(0).unwrap().abs(), f64::INFINITY);
+        assert_float_eq!(ebu.loudness_global().unwrap(), -69.95, abs <= 0.000_000_1);
+        assert!(ebu.loudness_momentary().unwrap().is_nan());
+        assert!(ebu.energy_shortterm().unwrap().is_nan());
+        assert!(ebu.loudness_shortterm().unwrap().is_nan(),);
+        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000_000_1);
         assert_float_eq!(
             ebu.relative_threshold().unwrap(),
-            -10.650000000000006,
-            abs <= 0.000001
+            -79.95,
+            abs <= 0.000_000_1
         );
     }
 
     #[test]
-    fn sine_stereo_f64() {
-        let mut data = vec![0.0f64; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+    fn nan_handling() {
+        let mut data = vec![0.0f32; 44_100 * 80];
         for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator);
-            out[0] = val as f64;
-            out[1] = val as f64;
-            accumulator += step;
+            out[0] = f32::NAN;
+            out[1] = f32::NAN;
         }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
-        ebu.add_frames_f64(&data).unwrap();
-
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.6500000000000054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598268921,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715100236,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620008693,
-            abs <= 0.000001
-        );
-        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
-
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
+        let mut ebu = EbuR128::new(2, 44_100, Mode::all() - Mode::HISTOGRAM).unwrap();
+        assert!(ebu.add_frames_f32(&data).is_ok());
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 0.0, abs <= f64::EPSILON);
+        assert_float_eq!(ebu.true_peak(0).unwrap(), 0.0, abs <= f64::EPSILON);
+        assert!(ebu.loudness_global().unwrap().is_nan());
+        assert!(ebu.loudness_momentary().unwrap().is_nan());
+        assert!(ebu.energy_shortterm().unwrap().is_nan());
+        assert!(ebu.loudness_shortterm().unwrap().is_nan());
+        assert!(ebu.relative_threshold().unwrap().is_nan());
 
+        // With histogram mode the first bin is taken for NaN
+        let mut ebu = EbuR128::new(2, 44_100, Mode::all()).unwrap();
+        assert!(ebu.add_frames_f32(&data).is_ok());
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 0.0, abs <= f64::EPSILON);
+        assert_float_eq!(ebu.true_peak(0).unwrap(), 0.0, abs <= f64::EPSILON);
+        assert_float_eq!(ebu.loudness_global().unwrap(), -69.95, abs <= 0.000_000_1);
+        assert!(ebu.loudness_momentary().unwrap().is_nan());
+        assert!(ebu.energy_shortterm().unwrap().is_nan());
+        assert!(ebu.loudness_shortterm().unwrap().is_nan(),);
+        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000_000_1);
         assert_float_eq!(
             ebu.relative_threshold().unwrap(),
-            -10.650000000000006,
-            abs <= 0.000001
+            -79.95,
+            abs <= 0.000_000_1
         );
     }
 
     #[test]
-    fn sine_stereo_i16_no_histogram() {
-        let mut data = vec![0i16; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator) * (i16::MAX - 1) as f32;
-            out[0] = val as i16;
-            out[1] = val as i16;
-            accumulator += step;
-        }
+    fn reserve_duration() {
+        let data = sine_f32(48_000 * 5, 2);
 
         let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_i16(&data).unwrap();
-
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.683303243667768,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6820309226891973,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6834583474398446,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.875007988101488,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_range().unwrap(),
-            0.00006950793233284625,
-            abs <= 0.000001
-        );
+        ebu.add_frames_f32(&data).unwrap();
 
-        assert_float_eq!(
-            ebu.sample_peak(0).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.sample_peak(1).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_sample_peak(0).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_sample_peak(1).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
-        );
+        let mut ebu_reserved = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu_reserved.reserve_duration(10.0).unwrap();
+        assert_eq!(ebu_reserved.max_history(), usize::MAX);
+        ebu_reserved.add_frames_f32(&data).unwrap();
 
         assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0007814168930054,
+            ebu.loudness_global().unwrap(),
+            ebu_reserved.loudness_global().unwrap(),
             abs <= 0.000001
         );
         assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0007814168930054,
+            ebu.loudness_range().unwrap(),
+            ebu_reserved.loudness_range().unwrap(),
             abs <= 0.000001
         );
 
+        // No-op for histograms
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu.reserve_duration(10.0).unwrap();
+        ebu.reserve_duration(f64::NAN).unwrap();
+
+        // Too much memory for the default unlimited maximum history
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::LRA).unwrap();
+        assert_eq!(ebu.reserve_duration(1e300), Err(Error::NoMem));
+        ebu.add_frames_f32(&data).unwrap();
         assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.683303243667767,
+            ebu.loudness_global().unwrap(),
+            ebu_reserved.loudness_global().unwrap(),
             abs <= 0.000001
         );
     }
 
     #[test]
-    fn sine_stereo_i32_no_histogram() {
-        let mut data = vec![0i32; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator) * (i32::MAX - 1) as f32;
-            out[0] = val as i32;
-            out[1] = val as i32;
-            accumulator += step;
+    fn true_peak_latency() {
+        let ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.true_peak_latency().unwrap(), 6.0);
+
+        let ebu = EbuR128::new(2, 96_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.true_peak_latency().unwrap(), 12.0);
+
+        let ebu = EbuR128::new(2, 192_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.true_peak_latency().unwrap(), 0.0);
+
+        let ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.true_peak_latency(), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn peak_report() {
+        let mut data = sine_f32(48_000, 2);
+        for v in data.chunks_exact_mut(2) {
+            v[1] *= 0.5;
         }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_i32(&data).unwrap();
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
 
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.6826039914171368,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598274425,
-            abs <= 0.000001
-        );
+        let report = ebu.peak_report();
+        assert_eq!(report.sample_peak.len(), 2);
+        assert_eq!(report.true_peak.len(), 2);
+        for c in 0..2 {
+            assert_eq!(report.sample_peak[c], ebu.sample_peak(c as u32).unwrap());
+            assert_eq!(report.true_peak[c], ebu.true_peak(c as u32).unwrap());
+        }
+        assert_float_eq!(report.sample_peak_max.unwrap(), 1.0, abs <= 0.000001);
+        assert_eq!(report.sample_peak_max, Some(report.sample_peak[0]));
+        assert_eq!(report.true_peak_max, Some(report.true_peak[0]));
+
+        let ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        let report = ebu.peak_report();
+        assert!(report.sample_peak.is_empty());
+        assert_eq!(report.true_peak_max, None);
+    }
+
+    #[test]
+    fn shortterm_window() {
+        let data = sine_f32(48_000 * 5, 2);
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        assert_eq!(ebu.shortterm_window(), 3000);
+        assert_eq!(ebu.set_shortterm_window(4000), Err(Error::InvalidMode));
+        assert_eq!(ebu.set_shortterm_window(150), Err(Error::InvalidMode));
+        assert_eq!(ebu.set_shortterm_window(0), Err(Error::InvalidMode));
+
+        ebu.set_max_window(4000).unwrap();
+        ebu.set_shortterm_window(4000).unwrap();
+        assert_eq!(ebu.shortterm_window(), 4000);
+        ebu.add_frames_f32(&data).unwrap();
         assert_float_eq!(
             ebu.loudness_shortterm().unwrap(),
-            -0.6827591715105212,
+            ebu.loudness_window(4000).unwrap(),
             abs <= 0.000001
         );
+
+        // The maximum window can't be smaller than the short-term window
+        assert_eq!(ebu.set_max_window(1000), Err(Error::InvalidMode));
+        assert_eq!(ebu.max_window(), 4000);
+
+        ebu.set_shortterm_window(1000).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
         assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620040943,
+            ebu.loudness_shortterm().unwrap(),
+            ebu.loudness_window(1000).unwrap(),
             abs <= 0.000001
         );
+        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
+    }
+
+    #[test]
+    fn required_window_and_history() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        assert_eq!(ebu.required_window(), 3000);
+        assert_eq!(ebu.required_history(), 3000);
+        assert_eq!(ebu.set_max_window(2000), Err(Error::InvalidMode));
+        assert_eq!(ebu.set_max_history(2000), Err(Error::InvalidMode));
+        ebu.set_max_window(3000).unwrap();
+        ebu.set_max_history(3000).unwrap();
+        assert_eq!(ebu.set_shortterm_window(3000), Ok(()));
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.required_window(), 400);
+        assert_eq!(ebu.required_history(), 400);
+        assert_eq!(ebu.set_max_history(300), Err(Error::InvalidMode));
+        ebu.set_max_history(400).unwrap();
+        ebu.set_max_window(400).unwrap();
+
+        // The history is only required for the integrated loudness and the loudness range
+        for (mode, window, history) in [
+            (Mode::M, 400, 0),
+            (Mode::S, 3000, 0),
+            (Mode::I, 400, 400),
+            (Mode::LRA, 3000, 3000),
+            (Mode::SAMPLE_PEAK, 400, 0),
+            (Mode::TRUE_PEAK, 400, 0),
+        ] {
+            let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+            assert_eq!(ebu.required_window(), window, "{:?}", mode);
+            assert_eq!(ebu.required_history(), history, "{:?}", mode);
+            ebu.set_max_history(history as u32).unwrap();
+            if history > 0 {
+                assert_eq!(
+                    ebu.set_max_history(history as u32 - 1),
+                    Err(Error::InvalidMode)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn loudness_delta() {
+        let data = sine_f32(48_000 * 5, 2);
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+
+        let quiet_data = data.iter().map(|v| v * 0.5).collect::<Vec<_>>();
+        let mut reference = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        reference.add_frames_f32(&quiet_data).unwrap();
+
         assert_float_eq!(
-            ebu.loudness_range().unwrap(),
-            0.00006921150165073442,
+            ebu.loudness_delta(&reference).unwrap().unwrap(),
+            ebu.loudness_global().unwrap() - reference.loudness_global().unwrap(),
             abs <= 0.000001
         );
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        reference.reset();
+        assert_eq!(ebu.loudness_delta(&reference), Ok(None));
+        assert_eq!(reference.loudness_delta(&ebu), Ok(None));
+
+        let other = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.loudness_delta(&other), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn gating_predicate() {
+        let mut data = sine_f32(48_000 * 10, 2);
+        // Second half at -20dB
+        for v in &mut data[48_000 * 10..] {
+            *v *= 0.1;
+        }
+
+        for mode in [Mode::I, Mode::I | Mode::HISTOGRAM] {
+            let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+            ebu.add_frames_f32(&data).unwrap();
+            let gated = ebu.loudness_global().unwrap();
+
+            // Everything above the absolute gate
+            ebu.set_gating_predicate(Some(Box::new(|_| true)));
+            let ungated = ebu.loudness_global().unwrap();
+            assert!(ungated < gated - 1.0);
+
+            ebu.set_gating_predicate(Some(Box::new(|_| false)));
+            assert_eq!(ebu.loudness_global().unwrap(), -f64::INFINITY);
+
+            ebu.set_gating_predicate(None);
+            assert_eq!(ebu.loudness_global().unwrap(), gated);
+
+            // Stateful predicates keep their state in atomics
+            let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            let predicate_calls = calls.clone();
+            ebu.set_gating_predicate(Some(Box::new(move |_| {
+                predicate_calls.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                true
+            })));
+            assert_eq!(ebu.loudness_global().unwrap(), ungated);
+            assert!(calls.load(std::sync::atomic::Ordering::Relaxed) > 0);
+        }
+    }
+
+    #[test]
+    fn prev_true_peaks() {
+        let data = sine_f32(48_000, 2);
+        let quiet_data = data.iter().map(|v| v * 0.5).collect::<Vec<_>>();
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        for (c, peak) in ebu.prev_true_peaks().unwrap() {
+            assert_eq!(peak, ebu.prev_true_peak(c).unwrap());
+            assert_eq!(peak, ebu.true_peak(c).unwrap());
+        }
+
+        ebu.add_frames_f32(&quiet_data).unwrap();
+        let peaks = ebu.prev_true_peaks().unwrap().collect::<Vec<_>>();
+        assert_eq!(peaks.len(), 2);
+        for (c, peak) in peaks {
+            assert_eq!(peak, ebu.prev_true_peak(c).unwrap());
+            assert!(peak < ebu.true_peak(c).unwrap());
+        }
+
+        let ebu = EbuR128::new(2, 48_000, Mode::SAMPLE_PEAK).unwrap();
+        assert!(ebu.prev_true_peaks().is_err());
+    }
+
+    #[test]
+    fn filter_stability() {
+        for rate in [8_000, 16_000, 44_100, 48_000, 96_000, 192_000, 2_822_400] {
+            assert!(EbuR128::check_filter_stability(rate));
+            assert!(EbuR128::new(2, rate, Mode::all()).is_ok());
+        }
+
+        for rate in [1_000, 3_000] {
+            assert!(!EbuR128::check_filter_stability(rate));
+            assert_eq!(
+                EbuR128::new(2, rate, Mode::all()).unwrap_err(),
+                Error::UnsupportedRate
+            );
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        assert_eq!(ebu.change_parameters(2, 3_000), Err(Error::UnsupportedRate));
+        assert_eq!(ebu.rate(), 48_000);
+    }
+
+    #[test]
+    fn add_frames_from() {
+        struct Frame<'a> {
+            data: &'a [f32],
+            channels: u32,
+            rate: u32,
+        }
+
+        impl<'a> crate::FrameSource for Frame<'a> {
+            type Samples = std::iter::Map<std::slice::Iter<'a, f32>, fn(&f32) -> f64>;
+
+            fn channels(&self) -> Option<u32> {
+                Some(self.channels)
+            }
+
+            fn rate(&self) -> Option<u32> {
+                Some(self.rate)
+            }
+
+            fn into_samples(self) -> Self::Samples {
+                self.data.iter().map((|s| *s as f64) as fn(&f32) -> f64)
+            }
+        }
+
+        let data = sine_f32(48_000, 2);
+        let data_i16 = data
+            .iter()
+            .map(|s| (s * i16::MAX as f32) as i16)
+            .collect::<Vec<_>>();
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
 
+        let mut ebu_slice = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu_slice.add_frames_from(&data[..]).unwrap();
+
+        let mut ebu_frame = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu_frame
+            .add_frames_from(Frame {
+                data: &data,
+                channels: 2,
+                rate: 48_000,
+            })
+            .unwrap();
+
+        let mut ebu_i16 = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu_i16.add_frames_i16(&data_i16).unwrap();
+
+        let mut ebu_slice_i16 = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu_slice_i16.add_frames_from(&data_i16[..]).unwrap();
+
+        for other in [&ebu_slice, &ebu_frame] {
+            assert_float_eq!(
+                ebu.loudness_global().unwrap(),
+                other.loudness_global().unwrap(),
+                abs <= 0.000001
+            );
+            assert_float_eq!(
+                ebu.true_peak(0).unwrap(),
+                other.true_peak(0).unwrap(),
+                abs <= 0.000001
+            );
+            // The peaks of the previous call cover all chunks of the source
+            assert_eq!(ebu.prev_sample_peak(0), other.prev_sample_peak(0));
+            assert_eq!(other.frames_added(), 48_000);
+        }
         assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
+            ebu_i16.loudness_global().unwrap(),
+            ebu_slice_i16.loudness_global().unwrap(),
             abs <= 0.000001
         );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+
+        assert_eq!(
+            ebu.add_frames_from(Frame {
+                data: &data,
+                channels: 1,
+                rate: 48_000,
+            }),
+            Err(Error::BufferSizeMismatch)
         );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+        assert_eq!(
+            ebu.add_frames_from(Frame {
+                data: &data,
+                channels: 2,
+                rate: 44_100,
+            }),
+            Err(Error::UnsupportedRate)
         );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+
+        // The complete frames before a trailing incomplete frame are processed
+        ebu.reset();
+        assert_eq!(
+            ebu.add_frames_from(&data[..5]),
+            Err(Error::BufferSizeMismatch)
+        );
+        assert_eq!(ebu.frames_added(), 2);
+
+        // A source without a size hint that exceeds the maximum duration halfway keeps the peaks
+        // of the chunks that were processed
+        struct Unsized<'a>(&'a [f32]);
+
+        impl<'a> crate::FrameSource for Unsized<'a> {
+            type Samples = std::iter::Filter<
+                std::iter::Map<std::slice::Iter<'a, f32>, fn(&f32) -> f64>,
+                fn(&f64) -> bool,
+            >;
+
+            fn into_samples(self) -> Self::Samples {
+                self.0
+                    .iter()
+                    .map((|s| *s as f64) as fn(&f32) -> f64)
+                    .filter((|_| true) as fn(&f64) -> bool)
+            }
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu.set_max_duration(Some(0.5)).unwrap();
+        assert_eq!(
+            ebu.add_frames_from(Unsized(&data)),
+            Err(Error::MaxDurationExceeded)
+        );
+        let frames = ebu.frames_added() as usize;
+        assert!(frames > 0 && frames <= 24_000);
+
+        let mut ebu_reference = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu_reference.add_frames_f32(&data[..frames * 2]).unwrap();
+        for c in 0..2 {
+            assert!(ebu.sample_peak(c).unwrap() > 0.0);
+            assert_eq!(ebu.sample_peak(c), ebu_reference.sample_peak(c));
+            assert_eq!(ebu.prev_sample_peak(c), ebu_reference.prev_sample_peak(c));
+            assert_float_eq!(
+                ebu.true_peak(c).unwrap(),
+                ebu_reference.true_peak(c).unwrap(),
+                abs <= 0.000001
+            );
+        }
+    }
+
+    #[test]
+    fn integrated_stddev() {
+        for mode in [Mode::I, Mode::I | Mode::HISTOGRAM] {
+            let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+            assert!(ebu.integrated_stddev().unwrap().is_nan());
+
+            // A constant sine has the same loudness in all blocks
+            let data = sine_f32(48_000 * 5, 2);
+            ebu.add_frames_f32(&data).unwrap();
+            assert_float_eq!(ebu.integrated_stddev().unwrap(), 0.0, abs <= 0.01);
+
+            // Half the amplitude is 6 dB quieter, which results in two equally large clusters of
+            // blocks 6 LU apart and a standard deviation of about 3 LU.
+            let quiet = data.iter().map(|s| s * 0.5).collect::<Vec<_>>();
+            ebu.add_frames_f32(&quiet).unwrap();
+            assert_float_eq!(ebu.integrated_stddev().unwrap(), 3.0, abs <= 0.2);
+        }
+
+        let ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.integrated_stddev(), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn mid_side() {
+        let left = sine_f32(48_000 * 5, 1);
+        let right = left.iter().map(|s| s * 0.25).collect::<Vec<_>>();
+        let mid = Iterator::zip(left.iter(), right.iter())
+            .map(|(l, r)| (l + r) * std::f32::consts::FRAC_1_SQRT_2)
+            .collect::<Vec<_>>();
+        let side = Iterator::zip(left.iter(), right.iter())
+            .map(|(l, r)| (l - r) * std::f32::consts::FRAC_1_SQRT_2)
+            .collect::<Vec<_>>();
+
+        let mut ebu_lr = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu_lr
+            .add_frames_planar_f32(&[left.as_slice(), right.as_slice()])
+            .unwrap();
+
+        let mut ebu_ms = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu_ms
+            .set_channel_map(&[Channel::Mid, Channel::Side])
+            .unwrap();
+        ebu_ms
+            .add_frames_planar_f32(&[mid.as_slice(), side.as_slice()])
+            .unwrap();
+
+        assert_float_eq!(
+            ebu_lr.loudness_global().unwrap(),
+            ebu_ms.loudness_global().unwrap(),
+            abs <= 0.0001
         );
+    }
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.682603991417135,
-            abs <= 0.000001
+    #[test]
+    fn noise_floor_lufs() {
+        for mode in [Mode::I, Mode::I | Mode::HISTOGRAM] {
+            let mut ebu = EbuR128::new(1, 48_000, mode).unwrap();
+            assert_eq!(ebu.noise_floor_lufs().unwrap(), -f64::INFINITY);
+
+            // 2s of quiet noise floor at -40 dB, followed by 18s of signal
+            let data = sine_f32(48_000 * 20, 1);
+            let quiet = data[..96_000].iter().map(|s| s * 0.01).collect::<Vec<_>>();
+            ebu.add_frames_f32(&quiet).unwrap();
+
+            let loudness_quiet = ebu.loudness_global().unwrap();
+            assert_float_eq!(ebu.noise_floor_lufs().unwrap(), loudness_quiet, abs <= 0.1);
+
+            ebu.add_frames_f32(&data[48_000..]).unwrap();
+            assert_float_eq!(ebu.noise_floor_lufs().unwrap(), loudness_quiet, abs <= 0.1);
+            assert!(ebu.loudness_global().unwrap() > loudness_quiet + 39.0);
+        }
+
+        let ebu = EbuR128::new(1, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.noise_floor_lufs(), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn sample_peak_only() {
+        let data = sine_f32(48_000, 2);
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::SAMPLE_PEAK).unwrap();
+        let mut ebu_full = EbuR128::new(2, 48_000, Mode::SAMPLE_PEAK | Mode::S).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        ebu_full.add_frames_f32(&data).unwrap();
+
+        for c in 0..2 {
+            assert_eq!(
+                ebu.sample_peak(c).unwrap(),
+                ebu_full.sample_peak(c).unwrap()
+            );
+            assert_eq!(
+                ebu.prev_sample_peak(c).unwrap(),
+                ebu_full.prev_sample_peak(c).unwrap()
+            );
+        }
+
+        assert_eq!(ebu.loudness_momentary(), Err(Error::InvalidMode));
+        assert_eq!(ebu.loudness_window(400), Err(Error::InvalidMode));
+        assert_eq!(ebu.loudness_window(0), Err(Error::InvalidMode));
+        assert!(ebu_full.loudness_momentary().is_ok());
+
+        let ebu_true_peak = EbuR128::new(1, 44_100, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu_true_peak.loudness_window(0), Err(Error::InvalidMode));
+
+        // Changing the parameters keeps only tracking the sample peak
+        ebu.change_parameters(1, 44_100).unwrap();
+        ebu.add_frames_f32(&data[..44_100]).unwrap();
+        assert_eq!(
+            ebu.sample_peak(0).unwrap(),
+            ebu_full.sample_peak(0).unwrap()
         );
+        assert_eq!(ebu.loudness_momentary(), Err(Error::InvalidMode));
     }
 
     #[test]
-    fn sine_stereo_f32_no_histogram() {
-        let mut data = vec![0.0f32; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator);
-            out[0] = val;
-            out[1] = val;
-            accumulator += step;
+    fn error_code() {
+        assert_eq!(ErrorCode::from(Error::NoMem) as i32, 1);
+        assert_eq!(ErrorCode::from(Error::InvalidMode) as i32, 2);
+        assert_eq!(ErrorCode::from(Error::InvalidChannelIndex) as i32, 3);
+        assert_eq!(ErrorCode::from(Error::UnsupportedRate) as i32, 1);
+        assert_eq!(ErrorCode::from(Error::BufferSizeMismatch) as i32, 1);
+        assert_eq!(ErrorCode::from(Error::MaxDurationExceeded) as i32, 1);
+        assert_eq!(ErrorCode::from(Error::MixedHistoryTypes) as i32, 2);
+        assert_eq!(ErrorCode::from(Error::DurationMismatch) as i32, 1);
+        assert_eq!(ErrorCode::from(Error::AnalysisPanicked) as i32, 1);
+        assert_eq!(ErrorCode::NoChange as i32, 4);
+    }
+
+    #[test]
+    fn true_peak_window() {
+        for channels in [2, 3] {
+            for rate in [48_000, 192_000] {
+                let mut ebu = EbuR128::new(channels, rate, Mode::TRUE_PEAK).unwrap();
+                assert_eq!(ebu.true_peak_window(0, 10), Err(Error::InvalidMode));
+
+                ebu.set_max_true_peak_window(100).unwrap();
+                assert_eq!(ebu.max_true_peak_window(), 100);
+                assert_eq!(ebu.true_peak_window(0, 200), Err(Error::InvalidMode));
+                assert_eq!(
+                    ebu.true_peak_window(channels, 10),
+                    Err(Error::InvalidChannelIndex)
+                );
+
+                // 1s of loud sine followed by 200ms of quieter sine
+                let data = (0..rate * 12 / 10)
+                    .flat_map(|i| {
+                        let gain = if i < rate { 1.0 } else { 0.25 };
+                        let v = gain
+                            * f32::sin(2.0 * std::f32::consts::PI * 440.0 * i as f32 / rate as f32);
+                        std::iter::repeat(v).take(channels as usize)
+                    })
+                    .collect::<Vec<_>>();
+
+                // Add in chunks to check wrapping around in the ring buffer
+                for chunk in data.chunks(channels as usize * 1234) {
+                    ebu.add_frames_f32(chunk).unwrap();
+                }
+
+                for c in 0..channels {
+                    assert!(ebu.true_peak(c).unwrap() > 0.99);
+                    let recent = ebu.true_peak_window(c, 100).unwrap();
+                    assert_float_eq!(recent, 0.25, abs <= 0.01);
+                }
+
+                ebu.reset();
+                assert_eq!(ebu.true_peak_window(0, 100), Ok(0.0));
+
+                // The window is kept when changing the parameters
+                ebu.change_parameters(channels, 44_100).unwrap();
+                assert_eq!(ebu.max_true_peak_window(), 100);
+                ebu.add_frames_f32(&data[..channels as usize * 4410])
+                    .unwrap();
+                assert!(ebu.true_peak_window(0, 100).unwrap() > 0.99);
+            }
         }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        let mut ebu = EbuR128::new(2, 48_000, Mode::SAMPLE_PEAK).unwrap();
+        assert_eq!(ebu.set_max_true_peak_window(100), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn mode_names() {
+        assert_eq!("I".parse::<Mode>(), Ok(Mode::I));
+        assert_eq!("true_peak".parse::<Mode>(), Ok(Mode::TRUE_PEAK));
+        assert_eq!("Sample-Peak".parse::<Mode>(), Ok(Mode::SAMPLE_PEAK));
+        assert_eq!("foo".parse::<Mode>(), Err(Error::InvalidMode));
+
+        assert_eq!(
+            Mode::from_str_list(&["I", "LRA", "true_peak"]),
+            Ok(Mode::I | Mode::LRA | Mode::TRUE_PEAK)
+        );
+        assert_eq!(Mode::from_str_list(&["I", "foo"]), Err(Error::InvalidMode));
+        assert_eq!(Mode::from_str_list::<&str>(&[]), Ok(Mode::empty()));
+
+        assert_eq!(Mode::LRA.to_str_list(), vec!["LRA"]);
+        assert_eq!(
+            (Mode::I | Mode::LRA | Mode::TRUE_PEAK).to_str_list(),
+            vec!["I", "LRA", "TRUE_PEAK"]
+        );
+        assert_eq!((Mode::M | Mode::HISTOGRAM).to_string(), "M | HISTOGRAM");
+        assert_eq!(Mode::empty().to_string(), "");
+
+        // All combinations of the named modes survive a round trip
+        for i in 0..(1 << MODE_NAMES.len()) {
+            let mode = MODE_NAMES
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| i & (1 << j) != 0)
+                .fold(Mode::empty(), |acc, (_, (_, mode))| acc | *mode);
+            assert_eq!(Mode::from_str_list(&mode.to_str_list()), Ok(mode));
+        }
+    }
+
+    #[test]
+    fn loudness_windows() {
+        let mut ebu = EbuR128::new(3, 48_000, Mode::S).unwrap();
+        ebu.set_channel(2, Channel::LeftSurround).unwrap();
+
+        // Increasing amplitude to get different loudness for each window
+        let data = sine_f32(48_000 * 4, 3)
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| v * i as f32 / (48_000.0 * 12.0))
+            .collect::<Vec<_>>();
+        // Add in chunks to check the ring buffer wrapping around
+        for chunk in data.chunks(3 * 12_345) {
+            ebu.add_frames_f32(chunk).unwrap();
+        }
+
+        let windows = [3000, 100, 400, 1, 1234, 400, 2999];
+        let loudness = ebu.loudness_windows(&windows).unwrap();
+        assert_eq!(loudness.len(), windows.len());
+        for (window, loudness) in Iterator::zip(windows.iter(), loudness.iter()) {
+            assert_float_eq!(
+                *loudness,
+                ebu.loudness_window(*window).unwrap(),
+                abs <= 0.000001
+            );
+        }
+
+        assert_eq!(ebu.loudness_windows(&[]), Ok(vec![]));
+        assert_eq!(ebu.loudness_windows(&[400, 3100]), Err(Error::InvalidMode));
+
+        for mode in [Mode::SAMPLE_PEAK, Mode::TRUE_PEAK] {
+            let ebu = EbuR128::new(2, 48_000, mode).unwrap();
+            assert_eq!(ebu.loudness_windows(&[0]), Err(Error::InvalidMode));
+            assert_eq!(ebu.loudness_windows(&[]), Err(Error::InvalidMode));
+        }
+    }
+
+    #[test]
+    fn kweighting() {
+        // The pre-filter boosts high frequencies by about 4 dB and the RLB filter attenuates low
+        // frequencies, so a 10 kHz sine is louder without the RLB filter and a 20 Hz sine is
+        // louder without the pre-filter.
+        let measure = |freq: f32, kweighting: KWeighting| {
+            let data = (0..48_000 * 2)
+                .map(|i| 0.5 * f32::sin(2.0 * std::f32::consts::PI * freq * i as f32 / 48_000.0))
+                .collect::<Vec<_>>();
+
+            let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+            assert_eq!(ebu.kweighting(), KWeighting::Full);
+            ebu.set_kweighting(kweighting);
+            assert_eq!(ebu.kweighting(), kweighting);
+            ebu.add_frames_f32(&data).unwrap();
+            ebu.loudness_global().unwrap()
+        };
+
+        let full = measure(10_000.0, KWeighting::Full);
+        let pre_filter_only = measure(10_000.0, KWeighting::PreFilterOnly);
+        let rlb_only = measure(10_000.0, KWeighting::RlbOnly);
+        assert_float_eq!(full, pre_filter_only, abs <= 0.05);
+        assert_float_eq!(full - rlb_only, 4.0, abs <= 0.2);
+
+        let full = measure(20.0, KWeighting::Full);
+        let pre_filter_only = measure(20.0, KWeighting::PreFilterOnly);
+        let rlb_only = measure(20.0, KWeighting::RlbOnly);
+        assert!(pre_filter_only > full + 10.0);
+        assert_float_eq!(full, rlb_only, abs <= 0.1);
+
+        // The setting is kept when changing the parameters
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        ebu.set_kweighting(KWeighting::RlbOnly);
+        ebu.change_parameters(2, 44_100).unwrap();
+        assert_eq!(ebu.kweighting(), KWeighting::RlbOnly);
+    }
+
+    #[test]
+    fn loudness_lkfs() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::S).unwrap();
+        ebu.add_frames_f32(&sine_f32(48_000 * 4, 2)).unwrap();
+
+        assert_eq!(ebu.loudness_global_lkfs(), ebu.loudness_global());
+        assert_eq!(ebu.loudness_momentary_lkfs(), ebu.loudness_momentary());
+        assert_eq!(ebu.loudness_shortterm_lkfs(), ebu.loudness_shortterm());
+    }
+
+    #[test]
+    fn displayed_peak() {
+        let data = sine_f32(48_000, 2);
+        let silence = vec![0.0f32; 48_000 * 2];
+
+        for mode in [Mode::SAMPLE_PEAK, Mode::TRUE_PEAK] {
+            let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+            assert_eq!(ebu.peak_decay(), 0.0);
+            assert_eq!(ebu.set_peak_decay(-1.0), Err(Error::InvalidMode));
+            assert_eq!(ebu.set_peak_decay(f64::NAN), Err(Error::InvalidMode));
+
+            // Without decay the peak is held
+            ebu.add_frames_f32(&data).unwrap();
+            let peak = ebu.displayed_peak(0).unwrap();
+            if mode.contains(Mode::TRUE_PEAK) {
+                assert_eq!(peak, ebu.true_peak(0).unwrap());
+            } else {
+                assert_eq!(peak, ebu.sample_peak(0).unwrap());
+            }
+            ebu.add_frames_f32(&silence).unwrap();
+            assert_eq!(ebu.displayed_peak(0).unwrap(), peak);
+
+            // 20 dB per second decay for 0.5s and 1s
+            ebu.set_peak_decay(20.0).unwrap();
+            ebu.add_frames_f32(&silence[..48_000]).unwrap();
+            assert_float_eq!(
+                ebu.displayed_peak(0).unwrap(),
+                peak * f64::powf(10.0, -0.5),
+                abs <= 0.000001
+            );
+            ebu.add_frames_f32(&silence).unwrap();
+            assert_float_eq!(
+                ebu.displayed_peak(1).unwrap(),
+                peak * f64::powf(10.0, -1.5),
+                abs <= 0.000001
+            );
+
+            // The maximum is kept
+            if mode.contains(Mode::TRUE_PEAK) {
+                assert_eq!(ebu.true_peak(0).unwrap(), peak);
+            } else {
+                assert_eq!(ebu.sample_peak(0).unwrap(), peak);
+            }
+
+            // A new peak is displayed immediately
+            ebu.add_frames_f32(&data).unwrap();
+            assert_eq!(ebu.displayed_peak(0).unwrap(), peak);
+
+            assert_eq!(ebu.displayed_peak(2), Err(Error::InvalidChannelIndex));
+            ebu.reset();
+            assert_eq!(ebu.displayed_peak(0).unwrap(), 0.0);
+        }
+
+        let ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.displayed_peak(0), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn crest_factor_db() {
+        for mode in [Mode::I | Mode::SAMPLE_PEAK, Mode::I | Mode::TRUE_PEAK] {
+            let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+            assert_eq!(ebu.crest_factor_db(), Ok(None));
+
+            ebu.add_frames_f32(&sine_f32(48_000 * 2, 2)).unwrap();
+            let peak = if mode.contains(Mode::TRUE_PEAK) {
+                ebu.true_peak(0).unwrap()
+            } else {
+                ebu.sample_peak(0).unwrap()
+            };
+            assert_float_eq!(
+                ebu.crest_factor_db().unwrap().unwrap(),
+                20.0 * f64::log10(peak) - ebu.loudness_global().unwrap(),
+                abs <= 0.000001
+            );
+        }
+
+        let ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.crest_factor_db(), Err(Error::InvalidMode));
+        let ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.crest_factor_db(), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn add_filtered_frames() {
+        let data = sine_f32(48_000 * 5, 2);
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::LRA).unwrap();
         ebu.add_frames_f32(&data).unwrap();
 
+        // Filter the frames with the same filter upfront
+        let data_f64 = data.iter().map(|v| *v as f64).collect::<Vec<_>>();
+        let mut filtered = vec![0.0; data_f64.len()];
+        let mut filter = crate::filter::Filter::new(48_000, 2, false, false);
+        filter.process(
+            crate::Interleaved::new(&data_f64, 2).unwrap(),
+            &mut filtered,
+            0,
+            &[Channel::Left, Channel::Right],
+        );
+        // Filter::process() writes planar
+        let filtered = (0..48_000 * 5)
+            .flat_map(|i| [filtered[i], filtered[48_000 * 5 + i]])
+            .collect::<Vec<_>>();
+
+        let mut ebu_filtered = EbuR128::new(2, 48_000, Mode::I | Mode::LRA).unwrap();
+        for chunk in filtered.chunks(2 * 1234) {
+            ebu_filtered.add_filtered_frames_f64(chunk).unwrap();
+        }
+
         assert_float_eq!(
             ebu.loudness_global().unwrap(),
-            -0.6826039914165554,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598268921,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715100236,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620008693,
+            ebu_filtered.loudness_global().unwrap(),
             abs <= 0.000001
         );
         assert_float_eq!(
             ebu.loudness_range().unwrap(),
-            0.00006921150169403312,
-            abs <= 0.000001
-        );
-
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
+            ebu_filtered.loudness_range().unwrap(),
             abs <= 0.000001
         );
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.682603991416554,
-            abs <= 0.000001
+        let mut ebu = EbuR128::new(2, 48_000, Mode::SAMPLE_PEAK).unwrap();
+        assert_eq!(
+            ebu.add_filtered_frames_f64(&filtered),
+            Err(Error::InvalidMode)
         );
     }
 
     #[test]
-    fn sine_stereo_f64_no_histogram() {
-        let mut data = vec![0.0f64; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator);
-            out[0] = val as f64;
-            out[1] = val as f64;
-            accumulator += step;
+    fn true_peak_only() {
+        let data = sine_f32(48_000, 2);
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        let mut ebu_full = EbuR128::new(2, 48_000, Mode::TRUE_PEAK | Mode::I).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        ebu_full.add_frames_f32(&data).unwrap();
+
+        for c in 0..2 {
+            assert_eq!(ebu.true_peak(c), ebu_full.true_peak(c));
+            assert_eq!(ebu.sample_peak(c), ebu_full.sample_peak(c));
+            assert_eq!(ebu.prev_true_peak(c), ebu_full.prev_true_peak(c));
         }
+        assert!(ebu.true_peak(0).unwrap() > 0.0);
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_f64(&data).unwrap();
+        assert_eq!(ebu.loudness_global(), Err(Error::InvalidMode));
+        assert_eq!(ebu.loudness_momentary(), Err(Error::InvalidMode));
+        assert_eq!(ebu.loudness_range(), Err(Error::InvalidMode));
+        assert_eq!(ebu.relative_threshold(), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn mixed_sample_types() {
+        let data_i16 = (0..48_000 * 5)
+            .flat_map(|i| {
+                let v = f32::sin(2.0 * std::f32::consts::PI * 440.0 * i as f32 / 48_000.0);
+                let v = (v * 0.5 * 32768.0) as i16;
+                [v, v]
+            })
+            .collect::<Vec<_>>();
+        let data_i32 = data_i16
+            .iter()
+            .map(|v| (*v as i32) << 16)
+            .collect::<Vec<_>>();
+        let data_f32 = data_i16
+            .iter()
+            .map(|v| *v as f32 / 32768.0)
+            .collect::<Vec<_>>();
+        let data_f64 = data_i16
+            .iter()
+            .map(|v| *v as f64 / 32768.0)
+            .collect::<Vec<_>>();
+
+        let mode = Mode::I | Mode::LRA | Mode::SAMPLE_PEAK | Mode::TRUE_PEAK;
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu.add_frames_i16(&data_i16).unwrap();
+
+        // Switch the sample type for every chunk of 0.25s
+        let mut ebu_mixed = EbuR128::new(2, 48_000, mode).unwrap();
+        let chunk = 2 * 12_000;
+        for i in 0..data_i16.len() / chunk {
+            let range = i * chunk..(i + 1) * chunk;
+            match i % 4 {
+                0 => ebu_mixed.add_frames_i16(&data_i16[range]).unwrap(),
+                1 => ebu_mixed.add_frames_f32(&data_f32[range]).unwrap(),
+                2 => ebu_mixed.add_frames_i32(&data_i32[range]).unwrap(),
+                _ => ebu_mixed.add_frames_f64(&data_f64[range]).unwrap(),
+            }
+        }
 
         assert_float_eq!(
             ebu.loudness_global().unwrap(),
-            -0.6826039914165554,
+            ebu_mixed.loudness_global().unwrap(),
             abs <= 0.000001
         );
         assert_float_eq!(
             ebu.loudness_momentary().unwrap(),
-            -0.6813325598268921,
+            ebu_mixed.loudness_momentary().unwrap(),
             abs <= 0.000001
         );
         assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715100236,
+            ebu.loudness_range().unwrap(),
+            ebu_mixed.loudness_range().unwrap(),
             abs <= 0.000001
         );
         assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620008693,
+            ebu.sample_peak(0).unwrap(),
+            ebu_mixed.sample_peak(0).unwrap(),
             abs <= 0.000001
         );
         assert_float_eq!(
-            ebu.loudness_range().unwrap(),
-            0.00006921150169403312,
+            ebu.true_peak(0).unwrap(),
+            ebu_mixed.true_peak(0).unwrap(),
             abs <= 0.000001
         );
+    }
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+    #[test]
+    fn block_size_frames() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.block_size_frames(), 4_800);
+        ebu.change_parameters(2, 44_100).unwrap();
+        assert_eq!(ebu.block_size_frames(), 4_410);
+        ebu.change_parameters(2, 22_059).unwrap();
+        assert_eq!(ebu.block_size_frames(), 2_206);
+
+        // Chunking at block boundaries gives the same result
+        ebu.change_parameters(2, 48_000).unwrap();
+        let data = sine_f32(48_000 * 5, 2);
+        let mut ebu_full = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu_full.add_frames_f32(&data).unwrap();
+        for chunk in data.chunks(ebu.block_size_frames() * 2) {
+            ebu.add_frames_f32(chunk).unwrap();
+        }
+        assert_eq!(ebu.loudness_global(), ebu_full.loudness_global());
+    }
 
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+    #[test]
+    fn histogram_bins() {
+        // Sine with a slowly changing level, which results in loudness values that are not
+        // aligned to the histogram bins
+        let data = (0..48_000 * 60)
+            .flat_map(|i| {
+                let t = i as f32 / 48_000.0;
+                let gain = f32::powf(10.0, -(t * 0.37) / 20.0);
+                let v = gain * f32::sin(2.0 * std::f32::consts::PI * 440.0 * t);
+                [v, v]
+            })
+            .collect::<Vec<_>>();
+
+        let mut ebu_queue = EbuR128::new(2, 48_000, Mode::I | Mode::LRA).unwrap();
+        ebu_queue.add_frames_f32(&data).unwrap();
+        let expected_global = ebu_queue.loudness_global().unwrap();
+        let expected_range = ebu_queue.loudness_range().unwrap();
+
+        let mode = Mode::I | Mode::LRA | Mode::HISTOGRAM;
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        assert_eq!(ebu.histogram_bins(), 1000);
+        ebu.add_frames_f32(&data).unwrap();
+        let global_error = (ebu.loudness_global().unwrap() - expected_global).abs();
+        let range_error = (ebu.loudness_range().unwrap() - expected_range).abs();
+
+        let mut ebu_fine = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu_fine.set_histogram_bins(10_000).unwrap();
+        assert_eq!(ebu_fine.histogram_bins(), 10_000);
+        ebu_fine.add_frames_f32(&data).unwrap();
+        let global_error_fine = (ebu_fine.loudness_global().unwrap() - expected_global).abs();
+        let range_error_fine = (ebu_fine.loudness_range().unwrap() - expected_range).abs();
+
+        assert!(global_error_fine < global_error);
+        assert!(range_error_fine < range_error);
+        assert!(global_error_fine <= 0.01);
+        assert!(range_error_fine <= 0.02);
+
+        // Histograms with different bins can only be combined for the loudness range with the
+        // resolution of the finer one
+        assert_eq!(
+            EbuR128::loudness_range_multiple([&ebu, &ebu_fine]),
+            Err(Error::MixedHistoryTypes)
         );
         assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+            EbuR128::loudness_range_multiple_mixed([&ebu, &ebu_fine], true).unwrap(),
+            expected_range,
+            abs <= 0.1
         );
+        assert!(EbuR128::loudness_global_multiple([&ebu, &ebu_fine].iter().copied()).is_ok());
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.682603991416554,
-            abs <= 0.000001
-        );
+        // Changing the bins resets the histograms
+        ebu_fine.set_histogram_bins(1000).unwrap();
+        assert_eq!(ebu_fine.loudness_global().unwrap(), -f64::INFINITY);
+
+        assert_eq!(ebu.set_histogram_bins(0), Err(Error::InvalidMode));
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.set_histogram_bins(10_000), Err(Error::InvalidMode));
     }
 
+    #[cfg(feature = "resampling")]
     #[test]
-    fn sine_stereo_i16_planar_no_histogram() {
-        let mut data = vec![0i16; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        let (fst, snd) = data.split_at_mut(48_000 * 5);
-        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
-            let val = f32::sin(accumulator) * (i16::MAX - 1) as f32;
-            *fst = val as i16;
-            *snd = val as i16;
-            accumulator += step;
+    fn resampling() {
+        fn sine(rate: u32, seconds: u32) -> Vec<f32> {
+            (0..rate * seconds)
+                .flat_map(|i| {
+                    let v =
+                        0.5 * f32::sin(2.0 * std::f32::consts::PI * 997.0 * i as f32 / rate as f32);
+                    [v, v]
+                })
+                .collect()
         }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_planar_i16(&[fst, snd]).unwrap();
+        let mode = Mode::I | Mode::SAMPLE_PEAK;
+        let data = sine(44_100, 5);
+        let mut ebu = EbuR128::new(2, 44_100, mode).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
 
+        let mut ebu_resampled = EbuR128::with_resampling(2, 44_100, mode, 48_000).unwrap();
+        assert_eq!(ebu_resampled.rate(), 48_000);
+        for chunk in data.chunks(2 * 1000) {
+            ebu_resampled.add_frames_f32(chunk).unwrap();
+        }
         assert_float_eq!(
             ebu.loudness_global().unwrap(),
-            -0.683303243667768,
-            abs <= 0.000001
+            ebu_resampled.loudness_global().unwrap(),
+            abs <= 0.02
         );
         assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6820309226891973,
-            abs <= 0.000001
+            ebu.sample_peak(0).unwrap(),
+            ebu_resampled.sample_peak(0).unwrap(),
+            abs <= 0.001
         );
+
+        // Rates at which the filter is unstable can be measured too
+        assert!(EbuR128::new(2, 3_000, mode).is_err());
+        let mut ebu_resampled = EbuR128::with_resampling(2, 3_000, mode, 48_000).unwrap();
+        ebu_resampled.add_frames_f32(&sine(3_000, 5)).unwrap();
+        assert!(ebu_resampled.loudness_global().unwrap().is_finite());
+
+        // Changing the parameters changes the input rate
+        ebu_resampled.change_parameters(2, 44_100).unwrap();
+        assert_eq!(ebu_resampled.rate(), 48_000);
+        ebu_resampled.reset();
+        ebu_resampled.add_frames_f32(&data).unwrap();
         assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6834583474398446,
-            abs <= 0.000001
+            ebu.loudness_global().unwrap(),
+            ebu_resampled.loudness_global().unwrap(),
+            abs <= 0.02
         );
+
+        // Frame sources have the input rate
+        struct Frame<'a>(&'a [f32]);
+        impl<'a> crate::FrameSource for Frame<'a> {
+            type Samples = std::iter::Map<std::slice::Iter<'a, f32>, fn(&f32) -> f64>;
+
+            fn rate(&self) -> Option<u32> {
+                Some(44_100)
+            }
+
+            fn into_samples(self) -> Self::Samples {
+                self.0.iter().map((|s| *s as f64) as fn(&f32) -> f64)
+            }
+        }
+        ebu_resampled.reset();
+        ebu_resampled.add_frames_from(Frame(&data)).unwrap();
         assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.875007988101488,
-            abs <= 0.000001
-        );
+            ebu.loudness_global().unwrap(),
+            ebu_resampled.loudness_global().unwrap(),
+            abs <= 0.02
+        );
+        let mut ebu_unresampled = EbuR128::new(2, 48_000, mode).unwrap();
+        assert_eq!(
+            ebu_unresampled.add_frames_from(Frame(&data)),
+            Err(Error::UnsupportedRate)
+        );
+
+        // The maximum duration is checked after resampling. 5s of input are one frame less and
+        // the next input frame is resampled to two frames.
+        ebu_resampled.reset();
+        ebu_resampled.set_max_duration(Some(5.0)).unwrap();
+        ebu_resampled.add_frames_f32(&data).unwrap();
+        assert_eq!(ebu_resampled.frames_added(), 5 * 48_000 - 1);
+        assert_eq!(
+            ebu_resampled.add_frames_f32(&data[..2]),
+            Err(Error::MaxDurationExceeded)
+        );
+        assert_eq!(ebu_resampled.frames_added(), 5 * 48_000 - 1);
+
+        // Downsampling filters out frequencies that would otherwise be aliased
+        let data = sine(96_000, 5);
+        let mut ebu = EbuR128::new(2, 96_000, mode).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        let mut ebu_resampled = EbuR128::with_resampling(2, 96_000, mode, 48_000).unwrap();
+        ebu_resampled.add_frames_f32(&data).unwrap();
         assert_float_eq!(
-            ebu.loudness_range().unwrap(),
-            0.00006950793233284625,
-            abs <= 0.000001
+            ebu.loudness_global().unwrap(),
+            ebu_resampled.loudness_global().unwrap(),
+            abs <= 0.02
         );
+    }
 
-        assert_float_eq!(
-            ebu.sample_peak(0).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
+    #[test]
+    fn would_clip_at_gain() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.would_clip_at_gain(100.0, -1.0), Ok(false));
+
+        let mut data = sine_f32(48_000, 2);
+        // Only the second channel has a peak
+        for frame in data.chunks_exact_mut(2) {
+            frame[0] *= 0.1;
+        }
+        ebu.add_frames_f32(&data).unwrap();
+
+        let peak_dbtp = 20.0 * f64::log10(ebu.true_peak(1).unwrap());
+        assert_eq!(
+            ebu.would_clip_at_gain(-1.0 - peak_dbtp - 0.01, -1.0),
+            Ok(false)
         );
-        assert_float_eq!(
-            ebu.sample_peak(1).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
+        assert_eq!(
+            ebu.would_clip_at_gain(-1.0 - peak_dbtp + 0.01, -1.0),
+            Ok(true)
         );
-        assert_float_eq!(
-            ebu.prev_sample_peak(0).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
+        assert_eq!(ebu.would_clip_at_gain(0.0, peak_dbtp - 0.01), Ok(true));
+
+        let ebu = EbuR128::new(2, 48_000, Mode::I | Mode::SAMPLE_PEAK).unwrap();
+        assert_eq!(ebu.would_clip_at_gain(0.0, -1.0), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn frames_seconds_conversion() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.frames_to_seconds(24_000), 0.5);
+        assert_eq!(ebu.seconds_to_frames(0.5), 24_000);
+        assert_eq!(ebu.seconds_to_frames(-1.0), 0);
+        assert_eq!(ebu.seconds_to_frames(f64::NAN), 0);
+
+        ebu.change_parameters(2, 44_100).unwrap();
+        assert_eq!(ebu.seconds_to_frames(0.1), 4_410);
+        assert_eq!(ebu.seconds_to_frames(1.0 / 88_200.0 * 0.99), 0);
+        for frames in [0, 1, 4_410, 44_099, 1_000_000_007] {
+            assert_eq!(ebu.seconds_to_frames(ebu.frames_to_seconds(frames)), frames);
+        }
+    }
+
+    #[test]
+    fn partial_frames() {
+        let data = sine_f32(48_000 * 2, 2);
+        let data_i16 = data
+            .iter()
+            .map(|v| (v * 32768.0) as i16)
+            .collect::<Vec<_>>();
+        let mode = Mode::I | Mode::SAMPLE_PEAK;
+
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        assert!(!ebu.allow_partial_frames());
+        assert_eq!(
+            ebu.add_frames_f32(&data[..3]),
+            Err(Error::BufferSizeMismatch)
+        );
+        assert_eq!(
+            ebu.add_frames_i16(&data_i16[..3]),
+            Err(Error::BufferSizeMismatch)
         );
+        ebu.add_frames_f32(&data).unwrap();
+
+        // Odd chunk sizes with partial frames give the same result
+        let mut ebu_partial = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu_partial.set_allow_partial_frames(true);
+        assert!(ebu_partial.allow_partial_frames());
+        for chunk in data.chunks(1001) {
+            ebu_partial.add_frames_f32(chunk).unwrap();
+        }
+        assert!(ebu_partial.partial_frame.is_empty());
+        assert_eq!(ebu.loudness_global(), ebu_partial.loudness_global());
+        assert_eq!(ebu.sample_peak(0), ebu_partial.sample_peak(0));
+
+        // Also when mixing sample types
+        let mut ebu_i16 = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu_i16.add_frames_i16(&data_i16).unwrap();
+        ebu_partial.reset();
+        for (i, chunk) in data_i16.chunks(777).enumerate() {
+            if i % 2 == 0 {
+                ebu_partial.add_frames_i16(chunk).unwrap();
+            } else {
+                let chunk = chunk
+                    .iter()
+                    .map(|v| *v as f32 / 32768.0)
+                    .collect::<Vec<_>>();
+                ebu_partial.add_frames_f32(&chunk).unwrap();
+            }
+        }
         assert_float_eq!(
-            ebu.prev_sample_peak(1).unwrap(),
-            0.99993896484375,
+            ebu_i16.loudness_global().unwrap(),
+            ebu_partial.loudness_global().unwrap(),
             abs <= 0.000001
         );
 
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
+        // The buffered samples are dropped when disabling partial frames
+        ebu_partial.add_frames_f32(&data[..1]).unwrap();
+        assert_eq!(ebu_partial.partial_frame.len(), 1);
+        ebu_partial.set_allow_partial_frames(false);
+        assert!(ebu_partial.partial_frame.is_empty());
+
+        // The buffered samples are kept if the frames can't be added
+        ebu_partial.reset();
+        ebu_partial.set_allow_partial_frames(true);
+        ebu_partial.set_max_duration(Some(1.0)).unwrap();
+        ebu_partial.add_frames_f32(&data[..47_999 * 2 + 1]).unwrap();
+        assert_eq!(
+            ebu_partial.add_frames_f32(&data[47_999 * 2 + 1..48_001 * 2]),
+            Err(Error::MaxDurationExceeded)
+        );
+        assert_eq!(ebu_partial.partial_frame, [data[47_999 * 2] as f64]);
+        ebu_partial
+            .add_frames_f32(&data[47_999 * 2 + 1..48_000 * 2 + 1])
+            .unwrap();
+        assert_eq!(ebu_partial.frames_added(), 48_000);
+        assert_eq!(ebu_partial.partial_frame, [data[48_000 * 2] as f64]);
+    }
+
+    #[test]
+    fn add_frames_circular() {
+        let data = sine_f32(48_000 * 2, 3);
+        let mode = Mode::I | Mode::SAMPLE_PEAK | Mode::TRUE_PEAK;
+
+        let mut ebu = EbuR128::new(3, 48_000, mode).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+
+        // Split at a frame boundary, inside a frame, and with one empty part
+        for split in [0, 3 * 1000, 3 * 1000 + 1, 3 * 1000 + 2, data.len()] {
+            let mut ebu_circular = EbuR128::new(3, 48_000, mode).unwrap();
+            ebu_circular
+                .add_frames_circular_f32(&data[..split], &data[split..])
+                .unwrap();
+
+            assert_eq!(ebu.loudness_global(), ebu_circular.loudness_global());
+            assert_eq!(ebu.sample_peak(0), ebu_circular.sample_peak(0));
+            assert_eq!(ebu.prev_sample_peak(1), ebu_circular.prev_sample_peak(1));
+            assert_float_eq!(
+                ebu.true_peak(2).unwrap(),
+                ebu_circular.true_peak(2).unwrap(),
+                abs <= 0.000001
+            );
+        }
+
+        assert_eq!(
+            ebu.add_frames_circular_f32(&data[..2], &data[2..4]),
+            Err(Error::BufferSizeMismatch)
+        );
+    }
+
+    #[test]
+    fn loudness_histogram() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        let mut ebu_hist = EbuR128::new(2, 48_000, Mode::I | Mode::HISTOGRAM).unwrap();
+
+        // 1s of silence, which is below the absolute gate, then 2s of the sine
+        let mut data = vec![0.0; 48_000 * 2];
+        data.extend(sine_f32(48_000 * 2, 2));
+        ebu.add_frames_f32(&data).unwrap();
+        ebu_hist.add_frames_f32(&data).unwrap();
+
+        let histogram = ebu.loudness_histogram(100).unwrap();
+        assert_eq!(histogram.len(), 100);
+        assert_float_eq!(histogram[0].0, -69.5, abs <= 0.000001);
+        assert_float_eq!(histogram[99].0, 29.5, abs <= 0.000001);
+        // 27 blocks, of which the first 7 only contain silence
+        assert_eq!(histogram.iter().map(|(_, count)| count).sum::<u64>(), 20);
+
+        // All blocks that contain only the sine are in the same bin
+        let loudness = ebu.loudness_momentary().unwrap();
+        let (center, count) = histogram[(loudness + 70.0) as usize];
+        assert!((center - loudness).abs() <= 0.5);
+        assert_eq!(count, 17);
+
+        assert_eq!(ebu_hist.loudness_histogram(100).unwrap(), histogram);
+        assert!(ebu.loudness_histogram(0).unwrap().is_empty());
+
+        let ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.loudness_histogram(100), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn paused() {
+        let mode = Mode::I | Mode::LRA | Mode::TRUE_PEAK;
+        let data = sine_f32(48_000 * 5, 2);
+        let loud = data.iter().map(|s| s * 4.0).collect::<Vec<_>>();
+
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        assert!(!ebu.is_paused());
+        ebu.add_frames_f32(&data).unwrap();
+        ebu.set_paused(true);
+        assert!(ebu.is_paused());
+        ebu.add_frames_f32(&loud).unwrap();
+
+        // Nothing was added to the measurement while paused
+        let mut ebu_reference = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu_reference.add_frames_f32(&data).unwrap();
+        assert_eq!(ebu.loudness_global(), ebu_reference.loudness_global());
+        assert_eq!(ebu.loudness_range(), ebu_reference.loudness_range());
+        assert_eq!(ebu.sample_peak(0), ebu_reference.sample_peak(0));
+        assert_eq!(ebu.true_peak(1), ebu_reference.true_peak(1));
+
+        // But the filters were still running
+        let mut ebu_loud = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu_loud.add_frames_f32(&data).unwrap();
+        ebu_loud.add_frames_f32(&loud).unwrap();
+        assert_eq!(ebu.loudness_momentary(), ebu_loud.loudness_momentary());
+        assert_eq!(ebu.loudness_shortterm(), ebu_loud.loudness_shortterm());
+        assert_eq!(ebu.prev_sample_peak(0), ebu_loud.prev_sample_peak(0));
+
+        ebu.set_paused(false);
+        ebu.add_frames_f32(&data).unwrap();
+        assert!(ebu.loudness_global().unwrap() < ebu_loud.loudness_global().unwrap());
+        assert_eq!(ebu.sample_peak(0), ebu_reference.sample_peak(0));
+    }
+
+    #[test]
+    fn loudness_global_polling() {
+        let mut data = sine_f32(48_000 * 3, 2);
+        // Get louder over time so that each block changes the integrated loudness
+        for (i, frame) in data.chunks_exact_mut(2).enumerate() {
+            for s in frame {
+                *s *= 0.1 + i as f32 / (48_000.0 * 3.0);
+            }
+        }
+
+        for (mode, sorted) in [
+            (Mode::I, false),
+            (Mode::I, true),
+            (Mode::I | Mode::HISTOGRAM, false),
+        ] {
+            let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+            ebu.set_sorted_integrated(sorted);
+            assert_eq!(ebu.sorted_integrated(), sorted);
+            let mut ebu_reference = EbuR128::new(2, 48_000, mode).unwrap();
+            for end in (4_410 * 2..data.len()).step_by(4_410 * 2) {
+                ebu.add_frames_f32(&data[end - 4_410 * 2..end]).unwrap();
+                assert_eq!(ebu.loudness_global(), ebu.loudness_global());
+
+                // Mid-stream values are the same as the ones of a fresh instance, except for the
+                // rounding of the sums of the sorted energies
+                ebu_reference.reset();
+                ebu_reference.add_frames_f32(&data[..end]).unwrap();
+                if sorted {
+                    assert_float_eq!(
+                        ebu.loudness_global().unwrap(),
+                        ebu_reference.loudness_global().unwrap(),
+                        abs <= 0.000001
+                    );
+                } else {
+                    assert_eq!(ebu.loudness_global(), ebu_reference.loudness_global());
+                }
+            }
+
+            // Keeps the blocks measured so far
+            ebu.set_sorted_integrated(false);
+            assert_eq!(ebu.loudness_global(), ebu_reference.loudness_global());
+            ebu.set_sorted_integrated(sorted);
+
+            let loudness = ebu.loudness_global().unwrap();
+            ebu.set_gating_predicate(Some(Box::new(|_| true)));
+            assert!(ebu.loudness_global().unwrap() < loudness);
+            ebu.set_gating_predicate(None);
+            assert_eq!(ebu.loudness_global().unwrap(), loudness);
+
+            // Only the last, loudest second is kept
+            if !mode.contains(Mode::HISTOGRAM) {
+                ebu.set_max_history(1_000).unwrap();
+                assert!(ebu.loudness_global().unwrap() > loudness);
+            }
+
+            ebu.reset();
+            assert_eq!(ebu.loudness_global().unwrap(), -f64::INFINITY);
+        }
+    }
+
+    #[test]
+    fn shortterm_centered() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::S).unwrap();
+        assert!(!ebu.shortterm_centered());
+        assert_eq!(ebu.shortterm_latency(), Ok(0.0));
+
+        ebu.set_shortterm_centered(true);
+        assert!(ebu.shortterm_centered());
+        assert_eq!(ebu.shortterm_latency(), Ok(72_000.0));
+        ebu.set_shortterm_window(1000).unwrap();
+        assert_eq!(ebu.shortterm_latency(), Ok(24_000.0));
+
+        // A 500ms burst in silence, the window centered on the burst covers all of it. The only
+        // difference to a window ending with the burst is the filter's decay after the burst.
+        let mut data = vec![0.0; 48_000 * 2 * 2];
+        data.extend(sine_f32(24_000, 2));
+        data.extend(vec![0.0; 48_000 * 2 * 2]);
+        let burst_center = 48_000 * 2 + 12_000;
+        let latency = ebu.shortterm_latency().unwrap() as usize;
+        ebu.add_frames_f32(&data[..(burst_center + latency) * 2])
+            .unwrap();
+        let centered = ebu.loudness_shortterm().unwrap();
+
+        let mut ebu_burst = EbuR128::new(2, 48_000, Mode::S).unwrap();
+        ebu_burst.set_shortterm_window(1000).unwrap();
+        ebu_burst
+            .add_frames_f32(&data[..(48_000 * 2 + 24_000) * 2])
+            .unwrap();
+        assert_float_eq!(
+            centered,
+            ebu_burst.loudness_shortterm().unwrap(),
+            abs <= 0.01
+        );
+
+        let ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.shortterm_latency(), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn i32_full_scale() {
+        // Ramps close to positive and negative full scale, which f32 can't represent exactly
+        let mut data = Vec::new();
+        for i in 0..48_000 {
+            data.push(i32::MAX - i);
+            data.push(i32::MIN + 1 + i);
+        }
+        let data_f64 = data
+            .iter()
+            .map(|s| *s as f64 / 2_147_483_648.0)
+            .collect::<Vec<_>>();
+        assert_ne!(i32::MAX as f32 as f64, i32::MAX as f64);
+
+        for mode in [Mode::SAMPLE_PEAK, Mode::I | Mode::SAMPLE_PEAK] {
+            let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+            ebu.add_frames_i32(&data).unwrap();
+            assert_eq!(ebu.sample_peak(0), Ok(i32::MAX as f64 / 2_147_483_648.0));
+            assert_eq!(ebu.sample_peak(1), Ok(i32::MAX as f64 / 2_147_483_648.0));
+
+            let mut ebu_f64 = EbuR128::new(2, 48_000, mode).unwrap();
+            ebu_f64.add_frames_f64(&data_f64).unwrap();
+            assert_eq!(ebu.sample_peak(0), ebu_f64.sample_peak(0));
+            if mode.contains(Mode::I) {
+                assert_eq!(ebu.loudness_global(), ebu_f64.loudness_global());
+                assert_eq!(ebu.loudness_momentary(), ebu_f64.loudness_momentary());
+            }
+        }
+
+        let mut ebu = EbuR128::new(1, 48_000, Mode::SAMPLE_PEAK).unwrap();
+        ebu.add_frames_i32(&[i32::MIN]).unwrap();
+        assert_eq!(ebu.sample_peak(0), Ok(1.0));
+    }
+
+    #[test]
+    fn loudness_global_ungated() {
+        // A loud and a much quieter part, which is below the relative gate
+        let mut data = sine_f32(48_000 * 2, 2);
+        data.extend(sine_f32(48_000 * 2, 2).iter().map(|s| s * 0.01));
+
+        for mode in [Mode::I, Mode::I | Mode::HISTOGRAM] {
+            let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+            assert_eq!(ebu.loudness_global_ungated(), Ok(-f64::INFINITY));
+
+            ebu.add_frames_f32(&data).unwrap();
+            let ungated = ebu.loudness_global_ungated().unwrap();
+            assert!(ungated < ebu.loudness_global().unwrap() - 2.0);
+
+            ebu.set_gating_predicate(Some(Box::new(|_| true)));
+            assert_float_eq!(ungated, ebu.loudness_global().unwrap(), abs <= 0.000001);
+        }
+
+        let ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.loudness_global_ungated(), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn true_peak_threshold() {
+        // Quiet sine with a loud part in the middle
+        let mut data = sine_f32(48_000 * 3, 2);
+        for (i, frame) in data.chunks_exact_mut(2).enumerate() {
+            let gain = if (60_000..70_000).contains(&i) {
+                1.8
+            } else {
+                0.2
+            };
+            frame[0] *= gain;
+            frame[1] *= gain * 0.5;
+        }
+
+        let mode = Mode::TRUE_PEAK | Mode::SAMPLE_PEAK;
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        let mut ebu_threshold = EbuR128::new(2, 48_000, mode).unwrap();
+        assert_eq!(ebu_threshold.true_peak_threshold(), None);
+        ebu_threshold.set_true_peak_threshold(Some(-3.0)).unwrap();
+        assert_eq!(ebu_threshold.true_peak_threshold(), Some(-3.0));
+
+        for chunk in data.chunks(2 * 1_234) {
+            ebu.add_frames_f32(chunk).unwrap();
+            ebu_threshold.add_frames_f32(chunk).unwrap();
+        }
+
+        // The loud block is above the threshold in the first channel, and as the blocks are
+        // checked for all channels together the second channel is exact too
+        assert_eq!(ebu_threshold.true_peak(0), ebu.true_peak(0));
+        assert_eq!(ebu_threshold.true_peak(1), ebu.true_peak(1));
+        assert_eq!(ebu_threshold.sample_peak(0), ebu.sample_peak(0));
+        assert_eq!(ebu_threshold.sample_peak(1), ebu.sample_peak(1));
+
+        // Without the loud part only sample peaks are reported
+        ebu.reset();
+        ebu_threshold.reset();
+        let quiet = &data[..2 * 50_000];
+        ebu.add_frames_f32(quiet).unwrap();
+        ebu_threshold.add_frames_f32(quiet).unwrap();
+        let true_peak = ebu_threshold.true_peak(0).unwrap();
+        assert_eq!(true_peak, ebu_threshold.sample_peak(0).unwrap());
+        assert!(true_peak <= ebu.true_peak(0).unwrap());
+        assert_float_eq!(true_peak, ebu.true_peak(0).unwrap(), abs <= 0.001);
+
+        // Disabling the threshold gives the exact true peak again
+        ebu_threshold.set_true_peak_threshold(None).unwrap();
+        ebu.reset();
+        ebu_threshold.reset();
+        ebu.add_frames_f32(quiet).unwrap();
+        ebu_threshold.add_frames_f32(quiet).unwrap();
+        assert_eq!(ebu_threshold.true_peak(0), ebu.true_peak(0));
+
+        assert_eq!(
+            ebu_threshold.set_true_peak_threshold(Some(f64::NAN)),
+            Err(Error::InvalidMode)
         );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
+        let mut ebu = EbuR128::new(2, 48_000, Mode::SAMPLE_PEAK).unwrap();
+        assert_eq!(
+            ebu.set_true_peak_threshold(Some(-3.0)),
+            Err(Error::InvalidMode)
         );
+    }
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.683303243667767,
-            abs <= 0.000001
+    #[test]
+    fn momentary_callback() {
+        use std::sync::{Arc, Mutex};
+
+        let data = sine_f32(48_000, 2)
+            .iter()
+            .enumerate()
+            .map(|(i, s)| s * i as f32 / 96_000.0)
+            .collect::<Vec<_>>();
+
+        let values = Arc::new(Mutex::new(Vec::new()));
+        let mut ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        let values_clone = values.clone();
+        ebu.set_momentary_callback(Some(Box::new(move |position, loudness| {
+            values_clone.lock().unwrap().push((position, loudness));
+        })))
+        .unwrap();
+
+        for chunk in data.chunks(2 * 1_000) {
+            ebu.add_frames_f32(chunk).unwrap();
+        }
+        assert_eq!(ebu.frames_added(), 48_000);
+
+        let values = std::mem::take(&mut *values.lock().unwrap());
+        assert_eq!(
+            values
+                .iter()
+                .map(|(position, _)| *position)
+                .collect::<Vec<_>>(),
+            (4..=10).map(|i| i * 4_800).collect::<Vec<_>>()
+        );
+
+        let mut ebu_reference = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        for (position, loudness) in values {
+            ebu_reference.reset();
+            ebu_reference
+                .add_frames_f32(&data[..position as usize * 2])
+                .unwrap();
+            assert_eq!(loudness, ebu_reference.loudness_momentary().unwrap());
+        }
+
+        ebu.reset();
+        assert_eq!(ebu.frames_added(), 0);
+        ebu.set_momentary_callback(None).unwrap();
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(
+            ebu.set_momentary_callback(Some(Box::new(|_, _| {}))),
+            Err(Error::InvalidMode)
         );
+        ebu.add_frames_f32(&data).unwrap();
+        assert_eq!(ebu.frames_added(), 48_000);
     }
 
     #[test]
-    fn sine_stereo_i32_planar_no_histogram() {
-        let mut data = vec![0i32; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        let (fst, snd) = data.split_at_mut(48_000 * 5);
-        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
-            let val = f32::sin(accumulator) * (i32::MAX - 1) as f32;
-            *fst = val as i32;
-            *snd = val as i32;
-            accumulator += step;
+    fn threshold_alarm() {
+        use std::sync::{Arc, Mutex};
+
+        // Quiet, loud, slightly quieter, and quiet again
+        let mut data = Vec::new();
+        for (seconds, gain) in [(2, 0.05), (2, 1.0), (2, 0.9), (2, 0.05)] {
+            data.extend(sine_f32(48_000 * seconds, 2).iter().map(|s| s * gain));
         }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_planar_i32(&[fst, snd]).unwrap();
+        let crossings = Arc::new(Mutex::new(Vec::new()));
+        let mut ebu = EbuR128::new(2, 48_000, Mode::S).unwrap();
+        let crossings_clone = crossings.clone();
+        let mut reference = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        reference.add_frames_f32(&data[..2 * 48_000 * 3]).unwrap();
+        // Between the loudness of the loud and the slightly quieter part
+        let threshold = reference.loudness_momentary().unwrap() - 0.5;
+        ebu.set_threshold_alarm(
+            LoudnessKind::Momentary,
+            threshold,
+            Some(Box::new(move |crossing| {
+                crossings_clone.lock().unwrap().push(*crossing);
+            })),
+        )
+        .unwrap();
 
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.6826039914171368,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598274425,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715105212,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620040943,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_range().unwrap(),
-            0.00006921150165073442,
-            abs <= 0.000001
+        ebu.add_frames_f32(&data).unwrap();
+        let crossings = std::mem::take(&mut *crossings.lock().unwrap());
+
+        // Rising above during the loud part, and only falling below in the quiet part because of
+        // the hysteresis
+        assert_eq!(crossings.len(), 2, "{:?}", crossings);
+        assert!(crossings[0].above);
+        assert_eq!(crossings[0].kind, LoudnessKind::Momentary);
+        assert!(crossings[0].loudness > threshold);
+        assert!((96_000..=96_000 + 19_200).contains(&crossings[0].position));
+        assert!(!crossings[1].above);
+        assert!(crossings[1].loudness < threshold - THRESHOLD_ALARM_HYSTERESIS);
+        assert!((288_000..=288_000 + 19_200).contains(&crossings[1].position));
+
+        ebu.set_threshold_alarm(LoudnessKind::ShortTerm, -10.0, Some(Box::new(|_| {})))
+            .unwrap();
+        ebu.set_threshold_alarm(LoudnessKind::ShortTerm, -10.0, None)
+            .unwrap();
+        assert_eq!(
+            ebu.set_threshold_alarm(LoudnessKind::Integrated, -10.0, Some(Box::new(|_| {}))),
+            Err(Error::InvalidMode)
+        );
+        assert_eq!(
+            ebu.set_threshold_alarm(LoudnessKind::Momentary, f64::NAN, Some(Box::new(|_| {}))),
+            Err(Error::InvalidMode)
         );
+    }
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+    #[test]
+    fn mono_sum() {
+        let data = sine_f32(48_000 * 5, 2);
+        let mono = sine_f32(48_000 * 5, 1);
+
+        let mut reference = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        reference.add_frames_f32(&mono).unwrap();
+        let reference = reference.loudness_global().unwrap();
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert!(ebu.mono_sum().is_none());
+        assert_eq!(ebu.set_mono_sum(Some(&[])), Err(Error::InvalidChannelIndex));
+        assert_eq!(
+            ebu.set_mono_sum(Some(&[0, 2])),
+            Err(Error::InvalidChannelIndex)
+        );
+        ebu.set_mono_sum(Some(&[0, 1])).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
 
+        // Identical channels are averaged to the loudness of a single one
+        let mono_sum = ebu.mono_sum().unwrap();
+        assert_eq!(mono_sum.channels(), 1);
         assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
+            mono_sum.loudness_global().unwrap(),
+            reference,
             abs <= 0.000001
         );
         assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
+            ebu.loudness_global().unwrap(),
+            reference + 10.0 * f64::log10(2.0),
             abs <= 0.000001
         );
+
+        // Silence on one of the channels halves the amplitude of the sum
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu.set_mono_sum(Some(&[0, 1])).unwrap();
+        let mut data = data;
+        for frame in data.chunks_exact_mut(2) {
+            frame[1] = 0.0;
+        }
+        ebu.add_frames_f32(&data).unwrap();
         assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
+            ebu.mono_sum().unwrap().loudness_global().unwrap(),
+            reference - 20.0 * f64::log10(2.0),
             abs <= 0.000001
         );
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.682603991417135,
-            abs <= 0.000001
+        ebu.reset();
+        assert_eq!(
+            ebu.mono_sum().unwrap().loudness_global().unwrap(),
+            -f64::INFINITY
         );
+        ebu.set_mono_sum(None).unwrap();
+        assert!(ebu.mono_sum().is_none());
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.set_mono_sum(Some(&[0])), Err(Error::InvalidMode));
     }
 
     #[test]
-    fn sine_stereo_f32_planar_no_histogram() {
-        let mut data = vec![0.0f32; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        let (fst, snd) = data.split_at_mut(48_000 * 5);
-        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
-            let val = f32::sin(accumulator);
-            *fst = val;
-            *snd = val;
-            accumulator += step;
-        }
+    fn reset_peaks() {
+        let data = sine_f32(48_000 * 5, 2);
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_planar_f32(&[fst, snd]).unwrap();
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::LRA | Mode::TRUE_PEAK).unwrap();
+        ebu.set_max_true_peak_window(1000).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        let global = ebu.loudness_global().unwrap();
+        let range = ebu.loudness_range().unwrap();
+        let momentary = ebu.loudness_momentary().unwrap();
+        assert!(ebu.sample_peak(0).unwrap() > 0.99);
+        assert!(ebu.true_peak(0).unwrap() > 0.99);
+
+        ebu.reset_peaks();
+        for c in 0..2 {
+            assert_eq!(ebu.sample_peak(c).unwrap(), 0.0);
+            assert_eq!(ebu.prev_sample_peak(c).unwrap(), 0.0);
+            assert_eq!(ebu.true_peak(c).unwrap(), 0.0);
+            assert_eq!(ebu.prev_true_peak(c).unwrap(), 0.0);
+            assert_eq!(ebu.true_peak_window(c, 1000).unwrap(), 0.0);
+            assert_eq!(ebu.displayed_peak(c).unwrap(), 0.0);
+        }
+        assert_eq!(ebu.loudness_global().unwrap(), global);
+        assert_eq!(ebu.loudness_range().unwrap(), range);
+        assert_eq!(ebu.loudness_momentary().unwrap(), momentary);
+
+        // Peaks are tracked again for the next frames
+        ebu.add_frames_f32(&data[..2 * 4800]).unwrap();
+        assert!(ebu.sample_peak(0).unwrap() > 0.99);
+        assert!(ebu.true_peak(0).unwrap() > 0.99);
+    }
 
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.6826039914165554,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598268921,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715100236,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620008693,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_range().unwrap(),
-            0.00006921150169403312,
-            abs <= 0.000001
+    #[test]
+    fn sample_peak_integer() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::SAMPLE_PEAK).unwrap();
+        ebu.add_frames_i16(&[-12345, 3, 1234, i16::MIN, 0, i16::MAX])
+            .unwrap();
+        assert_eq!(ebu.sample_peak(0).unwrap(), 12345.0 / 32768.0);
+        assert_eq!(ebu.sample_peak(1).unwrap(), 1.0);
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::SAMPLE_PEAK).unwrap();
+        ebu.add_frames_i32(&[i32::MAX, -7, 123_456_789, i32::MIN])
+            .unwrap();
+        assert_eq!(
+            ebu.sample_peak(0).unwrap(),
+            i32::MAX as f64 / 2_147_483_648.0
         );
+        assert_eq!(ebu.sample_peak(1).unwrap(), 1.0);
+    }
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+    #[test]
+    fn loudness_relative_to() {
+        let data = sine_f32(48_000 * 5, 2);
 
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::S).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        assert_eq!(
+            ebu.loudness_global_relative_to(-23.0).unwrap(),
+            ebu.loudness_global().unwrap() + 23.0
         );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+        assert_eq!(
+            ebu.loudness_momentary_relative_to(-23.0).unwrap(),
+            ebu.loudness_momentary().unwrap() + 23.0
         );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+        assert_eq!(
+            ebu.loudness_shortterm_relative_to(-24.0).unwrap(),
+            ebu.loudness_shortterm().unwrap() + 24.0
         );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+
+        ebu.reset();
+        assert_eq!(
+            ebu.loudness_global_relative_to(-23.0).unwrap(),
+            -f64::INFINITY
         );
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.682603991416554,
-            abs <= 0.000001
+        let ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(
+            ebu.loudness_global_relative_to(-23.0),
+            Err(Error::InvalidMode)
         );
     }
 
     #[test]
-    fn sine_stereo_f64_planar_no_histogram() {
-        let mut data = vec![0.0f64; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        let (fst, snd) = data.split_at_mut(48_000 * 5);
-        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
-            let val = f32::sin(accumulator);
-            *fst = val as f64;
-            *snd = val as f64;
-            accumulator += step;
+    fn mode_is_valid() {
+        for &(_, mode) in MODE_NAMES.iter() {
+            assert_eq!(mode.is_valid(), mode != Mode::HISTOGRAM);
+        }
+        assert!(Mode::all().is_valid());
+        assert!((Mode::LRA | Mode::TRUE_PEAK | Mode::HISTOGRAM).is_valid());
+        assert!(!Mode::empty().is_valid());
+
+        // Only some bits of a mode
+        for &bits in [0b10, 0b100, 0b1001, 0b10_0001, 0b10_0101].iter() {
+            let mode = Mode::from_bits_truncate(bits);
+            assert!(!mode.is_valid(), "{:?}", mode);
+            assert_eq!(
+                EbuR128::new(2, 48_000, mode).unwrap_err(),
+                Error::InvalidMode
+            );
         }
+    }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_planar_f64(&[fst, snd]).unwrap();
+    #[test]
+    fn loudness_of_channels() {
+        // Sine on the left and the center, silence on the right and the surround channels
+        let mut data = sine_f32(48_000 * 5, 5);
+        for frame in data.chunks_exact_mut(5) {
+            frame[1] = 0.0;
+            frame[3] = 0.0;
+            frame[4] = 0.0;
+        }
+
+        for &mode in [Mode::I, Mode::I | Mode::HISTOGRAM].iter() {
+            let mut ebu = EbuR128::new(5, 48_000, mode).unwrap();
+            assert_eq!(ebu.loudness_of_channels(&[0]), Err(Error::InvalidMode));
+            ebu.set_channel_energies(true).unwrap();
+            assert!(ebu.channel_energies());
+            ebu.add_frames_f32(&data).unwrap();
+
+            let global = ebu.loudness_global().unwrap();
+            assert_float_eq!(
+                ebu.loudness_of_channels(&[0, 1, 2, 3, 4]).unwrap(),
+                global,
+                abs <= 0.000001
+            );
+            assert_float_eq!(
+                ebu.loudness_of_channels(&[0, 1, 2]).unwrap(),
+                global,
+                abs <= 0.000001
+            );
+            assert_float_eq!(
+                ebu.loudness_of_channels(&[0]).unwrap(),
+                global - 10.0 * f64::log10(2.0),
+                abs <= 0.1
+            );
+            assert_eq!(
+                ebu.loudness_of_channels(&[1, 3, 4]).unwrap(),
+                -f64::INFINITY
+            );
+            assert_eq!(
+                ebu.loudness_of_channels(&[5]),
+                Err(Error::InvalidChannelIndex)
+            );
+
+            ebu.reset();
+            assert_eq!(ebu.loudness_of_channels(&[0]).unwrap(), -f64::INFINITY);
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::S).unwrap();
+        assert_eq!(ebu.set_channel_energies(true), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn channel_energies_max_history() {
+        let data = sine_f32(48_000 * 5, 2);
 
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu.set_channel_energies(true).unwrap();
+        ebu.set_max_history(1000).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        assert_eq!(ebu.channel_block_energies.as_ref().unwrap().len(), 2 * 10);
         assert_float_eq!(
+            ebu.loudness_of_channels(&[0, 1]).unwrap(),
             ebu.loudness_global().unwrap(),
-            -0.6826039914165554,
             abs <= 0.000001
         );
+    }
+
+    #[test]
+    fn dc_offset() {
+        let mut data = sine_f32(48_000, 2);
+        for frame in data.chunks_exact_mut(2) {
+            frame[1] += 0.25;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.dc_offset(0), Err(Error::InvalidMode));
+        ebu.set_dc_offset_tracking(true);
+        assert!(ebu.dc_offset_tracking());
+        assert_eq!(ebu.dc_offset(0).unwrap(), 0.0);
+        assert_eq!(ebu.dc_offset(2), Err(Error::InvalidChannelIndex));
+
+        ebu.add_frames_f32(&data).unwrap();
+        assert_float_eq!(ebu.dc_offset(0).unwrap(), 0.0, abs <= 0.001);
+        assert_float_eq!(ebu.dc_offset(1).unwrap(), 0.25, abs <= 0.001);
+
+        let data_i16 = data
+            .iter()
+            .map(|s| (s * 16384.0) as i16)
+            .collect::<Vec<_>>();
+        ebu.add_frames_i16(&data_i16).unwrap();
+        assert_float_eq!(ebu.dc_offset(1).unwrap(), 0.1875, abs <= 0.001);
+
+        // Paused frames are not considered
+        ebu.set_paused(true);
+        ebu.add_frames_f32(&vec![1.0; 2 * 48_000]).unwrap();
+        assert_float_eq!(ebu.dc_offset(1).unwrap(), 0.1875, abs <= 0.001);
+        ebu.set_paused(false);
+
+        ebu.reset();
+        assert_eq!(ebu.dc_offset(1).unwrap(), 0.0);
+        ebu.add_frames_f32(&data).unwrap();
+        assert_float_eq!(ebu.dc_offset(1).unwrap(), 0.25, abs <= 0.001);
+
+        ebu.set_dc_offset_tracking(false);
+        assert_eq!(ebu.dc_offset(0), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn recompute_integrated() {
+        let data = sine_f32(48_000 * 5, 5);
+
+        let mut ebu = EbuR128::new(5, 48_000, Mode::I).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        let reference = ebu.loudness_global().unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        let reference_twice = ebu.loudness_global().unwrap();
+
+        // Wrongly configured as five front channels
+        let mut ebu = EbuR128::new(5, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.recompute_integrated(), Err(Error::InvalidMode));
+        ebu.set_channel_energies(true).unwrap();
+        ebu.set_channel_map(&[Channel::Left; 5]).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        assert!(ebu.loudness_global().unwrap() < reference - 0.5);
+
+        ebu.set_channel_map(&default_channel_map(5)).unwrap();
+        ebu.recompute_integrated().unwrap();
+        assert_float_eq!(ebu.loudness_global().unwrap(), reference, abs <= 0.000001);
+
+        // Further frames are measured with the corrected channel map
+        ebu.add_frames_f32(&data).unwrap();
         assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598268921,
+            ebu.loudness_global().unwrap(),
+            reference_twice,
             abs <= 0.000001
         );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715100236,
-            abs <= 0.000001
+    }
+
+    #[test]
+    fn loudness_range_digest() {
+        let mut data = sine_f32(48_000 * 60, 1);
+        for (i, second) in data.chunks_exact_mut(48_000).enumerate() {
+            let gain = f32::powf(10.0, -((i * 7 % 30) as f32) / 20.0);
+            for sample in second {
+                *sample *= gain;
+            }
+        }
+
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        assert_eq!(
+            ebu.set_loudness_range_digest(Some(500)),
+            Err(Error::InvalidMode)
         );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620008693,
-            abs <= 0.000001
+
+        let mut ebu = EbuR128::new(1, 48_000, Mode::LRA).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        let reference = ebu.loudness_range().unwrap();
+
+        let mut ebu = EbuR128::new(1, 48_000, Mode::LRA).unwrap();
+        assert_eq!(ebu.loudness_range_digest(), None);
+        assert_eq!(
+            ebu.set_loudness_range_digest(Some(0)),
+            Err(Error::InvalidMode)
         );
-        assert_float_eq!(
-            ebu.loudness_range().unwrap(),
-            0.00006921150169403312,
-            abs <= 0.000001
+        ebu.set_loudness_range_digest(Some(500)).unwrap();
+        assert_eq!(ebu.loudness_range_digest(), Some(500));
+
+        // All short-term blocks still fit into the buffer of the t-digest
+        ebu.add_frames_f32(&data).unwrap();
+        assert_float_eq!(ebu.loudness_range().unwrap(), reference, abs <= 0.000001);
+
+        // Only instances with a t-digest can be combined
+        let exact = EbuR128::new(1, 48_000, Mode::LRA).unwrap();
+        assert_eq!(
+            EbuR128::loudness_range_multiple([&ebu, &exact]),
+            Err(Error::MixedHistoryTypes)
         );
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        ebu.set_loudness_range_digest(None).unwrap();
+        assert_eq!(ebu.loudness_range_digest(), None);
+        assert_eq!(ebu.loudness_range().unwrap(), 0.0);
+    }
 
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+    #[test]
+    fn window_fill() {
+        let data = sine_f32(48_000 * 3, 1);
+
+        let ebu = EbuR128::new(1, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.window_fill(LoudnessKind::Momentary), Ok(0.0));
+        assert_eq!(
+            ebu.window_fill(LoudnessKind::ShortTerm),
+            Err(Error::InvalidMode)
         );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+        assert_eq!(
+            ebu.window_fill(LoudnessKind::Integrated),
+            Err(Error::InvalidMode)
         );
+
+        let mut ebu = EbuR128::new(1, 48_000, Mode::S | Mode::I).unwrap();
+        ebu.add_frames_f32(&data[..9_600]).unwrap();
         assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
+            ebu.window_fill(LoudnessKind::Momentary).unwrap(),
+            0.5,
             abs <= 0.000001
         );
         assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
+            ebu.window_fill(LoudnessKind::ShortTerm).unwrap(),
+            0.2 / 3.0,
             abs <= 0.000001
         );
-
         assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.682603991416554,
+            ebu.window_fill(LoudnessKind::Integrated).unwrap(),
+            0.5,
             abs <= 0.000001
         );
+
+        ebu.add_frames_f32(&data[9_600..19_200]).unwrap();
+        assert_eq!(ebu.window_fill(LoudnessKind::Momentary), Ok(1.0));
+        assert_eq!(ebu.window_fill(LoudnessKind::Integrated), Ok(1.0));
+        assert!(ebu.window_fill(LoudnessKind::ShortTerm).unwrap() < 1.0);
+
+        ebu.add_frames_f32(&data[19_200..]).unwrap();
+        assert_eq!(ebu.window_fill(LoudnessKind::ShortTerm), Ok(1.0));
+
+        // Clearing the audio buffer keeps the integrated history
+        ebu.set_max_window(4000).unwrap();
+        assert_eq!(ebu.window_fill(LoudnessKind::Momentary), Ok(0.0));
+        assert_eq!(ebu.window_fill(LoudnessKind::ShortTerm), Ok(0.0));
+        assert_eq!(ebu.window_fill(LoudnessKind::Integrated), Ok(1.0));
+
+        ebu.reset();
+        assert_eq!(ebu.window_fill(LoudnessKind::Integrated), Ok(0.0));
     }
 
     #[test]
-    fn sine_stereo_f32_multiple() {
-        let mut data = vec![0.0f32; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator);
-            out[0] = val;
-            out[1] = val;
-            accumulator += step;
-        }
+    fn signed_sample_peaks() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert!(!ebu.signed_sample_peaks());
+        assert_eq!(ebu.sample_peak_positive(0), Err(Error::InvalidMode));
+        assert_eq!(ebu.sample_peak_negative(0), Err(Error::InvalidMode));
+
+        ebu.set_signed_sample_peaks(true);
+        assert!(ebu.signed_sample_peaks());
+        assert_eq!(ebu.sample_peak_positive(2), Err(Error::InvalidChannelIndex));
+        assert_eq!(ebu.sample_peak_positive(0), Ok(0.0));
+
+        // Asymmetric waveform on the first channel, only positive samples on the second
+        let data = [0.5f32, 0.25, -0.25, 0.0, 0.75, 0.5, -0.5, 0.0];
+        ebu.add_frames_f32(&data).unwrap();
+        assert_eq!(ebu.sample_peak_positive(0), Ok(0.75));
+        assert_eq!(ebu.sample_peak_negative(0), Ok(-0.5));
+        assert_eq!(ebu.sample_peak_positive(1), Ok(0.5));
+        assert_eq!(ebu.sample_peak_negative(1), Ok(0.0));
 
-        let mut ebu1 = EbuR128::new(2, 48_000, Mode::all()).unwrap();
-        ebu1.add_frames_f32(&data).unwrap();
+        // Integers are scaled like for the sample peak
+        ebu.add_frames_i16(&[i16::MIN, 0]).unwrap();
+        assert_eq!(ebu.sample_peak_negative(0), Ok(-1.0));
 
-        let mut data = vec![0.0f32; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 880.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator);
-            out[0] = 0.5 * val;
-            out[1] = 0.5 * val;
-            accumulator += step;
-        }
+        ebu.set_paused(true);
+        ebu.add_frames_f32(&[1.0, 1.0]).unwrap();
+        assert_eq!(ebu.sample_peak_positive(0), Ok(0.75));
+        ebu.set_paused(false);
 
-        let mut ebu2 = EbuR128::new(2, 48_000, Mode::all()).unwrap();
-        ebu2.add_frames_f32(&data).unwrap();
+        ebu.reset_peaks();
+        assert_eq!(ebu.sample_peak_positive(0), Ok(0.0));
+        assert_eq!(ebu.sample_peak_negative(0), Ok(0.0));
 
-        assert_float_eq!(
-            EbuR128::loudness_global_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
-            -2.603757953612454,
-            abs <= 0.000001
-        );
+        ebu.add_frames_f32(&data).unwrap();
+        ebu.set_signed_sample_peaks(false);
+        ebu.set_signed_sample_peaks(true);
+        assert_eq!(ebu.sample_peak_positive(0), Ok(0.0));
+    }
 
+    #[test]
+    fn flush() {
+        let data = sine_f32(48_000, 1);
+
+        // Shorter than a gating block
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        ebu.add_frames_f32(&data[..14_400]).unwrap();
+        ebu.flush();
+        assert_eq!(ebu.loudness_global().unwrap(), -f64::INFINITY);
+
+        assert_eq!(
+            EbuR128::new(1, 48_000, Mode::M)
+                .unwrap()
+                .set_finalize_partial(true),
+            Err(Error::InvalidMode)
+        );
+        ebu.set_finalize_partial(true).unwrap();
+        assert!(ebu.finalize_partial());
+        ebu.flush();
+        let loudness = ebu.loudness_global().unwrap();
+        // Full scale sine at 997Hz is -3.01 LUFS, at 440Hz slightly less
+        assert!(loudness > -4.0 && loudness < -3.0, "{}", loudness);
+
+        // Only added once
+        ebu.flush();
+        assert_eq!(ebu.loudness_global().unwrap(), loudness);
+
+        // Only frames after the last complete gating block form a partial block
+        let mut reference = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        reference.add_frames_f32(&data[..24_000]).unwrap();
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        ebu.set_finalize_partial(true).unwrap();
+        ebu.add_frames_f32(&data[..24_000]).unwrap();
+        ebu.flush();
+        assert_eq!(ebu.loudness_global(), reference.loudness_global());
+
+        reference.add_frames_f32(&data[24_000..28_800]).unwrap();
+        ebu.add_frames_f32(&data[24_000..26_400]).unwrap();
+        ebu.flush();
+        // The partial block ends 50ms earlier than the next complete one
         assert_float_eq!(
-            EbuR128::loudness_range_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
-            5.599999999999995,
-            abs <= 0.000001
+            ebu.loudness_global().unwrap(),
+            reference.loudness_global().unwrap(),
+            abs <= 0.01
         );
+        assert_ne!(ebu.loudness_global(), reference.loudness_global());
     }
 
+    #[cfg(feature = "dual-history")]
     #[test]
-    fn sine_stereo_f32_no_histogram_multiple() {
-        let mut data = vec![0.0f32; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator);
-            out[0] = val;
-            out[1] = val;
-            accumulator += step;
+    fn dual_history() {
+        let mut data = sine_f32(48_000 * 20, 1);
+        for (i, second) in data.chunks_exact_mut(48_000).enumerate() {
+            let gain = f32::powf(10.0, -((i * 7 % 20) as f32) / 20.0);
+            for sample in second {
+                *sample *= gain;
+            }
         }
 
-        let mut ebu1 = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu1.add_frames_f32(&data).unwrap();
+        assert_eq!(
+            EbuR128::new(1, 48_000, Mode::M)
+                .unwrap()
+                .set_dual_history(true),
+            Err(Error::InvalidMode)
+        );
+
+        for mode in [Mode::I | Mode::LRA, Mode::I | Mode::LRA | Mode::HISTOGRAM] {
+            let mut ebu = EbuR128::new(1, 48_000, mode).unwrap();
+            assert!(!ebu.dual_history());
+            assert_eq!(ebu.loudness_range_both(), Err(Error::InvalidMode));
+            ebu.set_dual_history(true).unwrap();
+            assert!(ebu.dual_history());
+            ebu.add_frames_f32(&data).unwrap();
+
+            let mut histogram = EbuR128::new(1, 48_000, mode | Mode::HISTOGRAM).unwrap();
+            histogram.add_frames_f32(&data).unwrap();
+            let mut queue = EbuR128::new(1, 48_000, mode & !Mode::HISTOGRAM).unwrap();
+            queue.add_frames_f32(&data).unwrap();
+
+            assert_eq!(
+                ebu.loudness_global_both(),
+                Ok((
+                    histogram.loudness_global().unwrap(),
+                    queue.loudness_global().unwrap()
+                ))
+            );
+            assert_eq!(
+                ebu.loudness_range_both(),
+                Ok((
+                    histogram.loudness_range().unwrap(),
+                    queue.loudness_range().unwrap()
+                ))
+            );
 
-        let mut data = vec![0.0f32; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 880.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator);
-            out[0] = 0.5 * val;
-            out[1] = 0.5 * val;
-            accumulator += step;
-        }
+            ebu.reset();
+            assert_eq!(
+                ebu.loudness_global_both(),
+                Ok((-f64::INFINITY, -f64::INFINITY))
+            );
+            assert_eq!(ebu.loudness_range_both(), Ok((0.0, 0.0)));
 
-        let mut ebu2 = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu2.add_frames_f32(&data).unwrap();
+            ebu.set_dual_history(false).unwrap();
+            assert_eq!(ebu.loudness_global_both(), Err(Error::InvalidMode));
+        }
+    }
 
+    #[test]
+    fn min_loudness_lufs() {
         assert_float_eq!(
-            EbuR128::loudness_global_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
-            -2.6302830567858275,
+            energy_to_loudness(crate::histogram_bins::BOUNDARIES[0]),
+            EbuR128::min_loudness_lufs(),
             abs <= 0.000001
         );
 
-        assert_float_eq!(
-            EbuR128::loudness_range_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
-            5.571749801957784,
-            abs <= 0.000001
-        );
+        // Blocks just below the absolute gate are not measured
+        let gain = f32::powf(10.0, -71.0 / 20.0) * std::f32::consts::SQRT_2;
+        let data = sine_f32(48_000, 1)
+            .into_iter()
+            .map(|s| s * gain)
+            .collect::<Vec<_>>();
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        assert!(ebu.loudness_momentary().unwrap() < EbuR128::min_loudness_lufs());
+        assert_eq!(ebu.loudness_global().unwrap(), -f64::INFINITY);
     }
 
     #[test]
-    fn chunks_queue_with_true_peak() {
-        let mut data = vec![0.0f32; 48_000 * 3];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(1) {
-            let val = f32::sin(accumulator);
-            out[0] = val;
-            accumulator += step;
-        }
-
-        let mut ebu1 = EbuR128::new(1, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu1.add_frames_f32(&data).unwrap();
+    fn block_log_callback() {
+        use std::sync::{Arc, Mutex};
+
+        let data = sine_f32(48_000, 2)
+            .iter()
+            .enumerate()
+            .map(|(i, s)| s * i as f32 / 96_000.0)
+            .collect::<Vec<_>>();
+
+        let mode = Mode::I | Mode::S | Mode::TRUE_PEAK;
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        let logs_clone = logs.clone();
+        ebu.set_block_log_callback(Some(Box::new(move |log| {
+            logs_clone.lock().unwrap().push(*log);
+        })))
+        .unwrap();
 
-        let mut ebu_chunks = Vec::new();
-        for i in 0..3usize {
-            let mut ebu_chunk = EbuR128::new(1, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-            let start_index = std::cmp::max(i as isize * 48_000, 0) as usize;
-            let stop_index = std::cmp::min(start_index + 48_000 + (48_00 * 3), data.len());
-            if start_index > 0 {
-                ebu_chunk
-                    .seed_frames_f32(&data[start_index - 48_00..start_index])
-                    .unwrap();
-            }
-            ebu_chunk
-                .add_frames_f32(&data[start_index..stop_index])
-                .unwrap();
-            ebu_chunks.push(ebu_chunk);
+        for chunk in data.chunks(2 * 1_000) {
+            ebu.add_frames_f32(chunk).unwrap();
         }
 
-        assert_float_eq!(
-            ebu1.sample_peak(0).unwrap(),
-            f64_max(ebu_chunks.iter().map(|meter| meter.sample_peak(0).unwrap())).unwrap(),
-            abs <= 0.000001
+        let logs = std::mem::take(&mut *logs.lock().unwrap());
+        assert_eq!(
+            logs.iter().map(|log| log.position).collect::<Vec<_>>(),
+            (4..=10).map(|i| i * 4_800).collect::<Vec<_>>()
         );
 
-        assert_float_eq!(
-            ebu1.true_peak(0).unwrap(),
-            f64_max(ebu_chunks.iter().map(|meter| meter.true_peak(0).unwrap())).unwrap(),
-            abs <= 0.000001
-        );
+        let mut ebu_reference = EbuR128::new(2, 48_000, mode).unwrap();
+        for log in logs {
+            ebu_reference.reset();
+            ebu_reference
+                .add_frames_f32(&data[..log.position as usize * 2])
+                .unwrap();
+            assert_eq!(
+                log,
+                BlockLog {
+                    position: log.position,
+                    momentary: ebu_reference.loudness_momentary().unwrap(),
+                    shortterm: Some(ebu_reference.loudness_shortterm().unwrap()),
+                    integrated: Some(ebu_reference.loudness_global().unwrap()),
+                    true_peak: Some(f64::max(
+                        ebu_reference.true_peak(0).unwrap(),
+                        ebu_reference.true_peak(1).unwrap()
+                    )),
+                }
+            );
+        }
 
-        assert_float_eq!(
-            ebu1.loudness_global().unwrap(),
-            EbuR128::loudness_global_multiple(ebu_chunks.iter()).unwrap(),
-            abs <= 0.000001
+        // Only available values are logged
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let mut ebu = EbuR128::new(1, 48_000, Mode::M).unwrap();
+        let logs_clone = logs.clone();
+        ebu.set_block_log_callback(Some(Box::new(move |log| {
+            logs_clone.lock().unwrap().push(*log);
+        })))
+        .unwrap();
+        ebu.add_frames_f32(&data[..19_200]).unwrap();
+        let logs = logs.lock().unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].shortterm, None);
+        assert_eq!(logs[0].integrated, None);
+        assert_eq!(logs[0].true_peak, None);
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(
+            ebu.set_block_log_callback(Some(Box::new(|_| {}))),
+            Err(Error::InvalidMode)
         );
     }
 
     #[test]
-    fn chunks_histogram_with_true_peak() {
-        let mut data = vec![0.0f32; 48_000 * 3];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(1) {
-            let val = f32::sin(accumulator);
-            out[0] = val;
-            accumulator += step;
+    fn segment_loudness() {
+        let mut data = sine_f32(48_000 * 13, 1);
+        for (i, second) in data.chunks_exact_mut(48_000).enumerate() {
+            let gain = f32::powf(10.0, -((i * 7 % 20) as f32) / 20.0);
+            for sample in second {
+                *sample *= gain;
+            }
         }
 
-        let mut ebu1 = EbuR128::new(1, 48_000, Mode::all() | Mode::HISTOGRAM).unwrap();
-        ebu1.add_frames_f32(&data).unwrap();
+        let mut ebu = EbuR128::new(1, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.set_segment_duration(Some(6.0)), Err(Error::InvalidMode));
 
-        let mut ebu_chunks = Vec::new();
-        for i in 0..3usize {
-            let mut ebu_chunk =
-                EbuR128::new(1, 48_000, Mode::all() | Mode::HISTOGRAM & !Mode::HISTOGRAM).unwrap();
-            let start_index = std::cmp::max(i as isize * 48_000, 0) as usize;
-            let stop_index = std::cmp::min(start_index + 48_000 + (48_00 * 3), data.len());
-            if start_index > 0 {
-                ebu_chunk
-                    .seed_frames_f32(&data[start_index - 48_00..start_index])
-                    .unwrap();
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.segment_loudness_series(), Err(Error::InvalidMode));
+        assert_eq!(ebu.set_segment_duration(Some(0.0)), Err(Error::InvalidMode));
+        assert_eq!(
+            ebu.set_segment_duration(Some(f64::NAN)),
+            Err(Error::InvalidMode)
+        );
+        ebu.set_segment_duration(Some(6.0)).unwrap();
+        assert_eq!(ebu.segment_duration(), Some(6.0));
+
+        for chunk in data.chunks(1_000) {
+            ebu.add_frames_f32(chunk).unwrap();
+            if ebu.frames_added() == 6 * 48_000 {
+                assert_eq!(ebu.segment_loudness_series().unwrap().len(), 1);
             }
-            ebu_chunk
-                .add_frames_f32(&data[start_index..stop_index])
-                .unwrap();
-            ebu_chunks.push(ebu_chunk);
         }
 
+        // Each segment is measured like with a separate instance
+        let mut expected = Vec::new();
+        for segment in data.chunks(6 * 48_000) {
+            let mut reference = EbuR128::new(1, 48_000, Mode::I).unwrap();
+            reference.add_frames_f32(segment).unwrap();
+            expected.push(reference.loudness_global().unwrap());
+        }
+
+        let series = ebu.segment_loudness_series().unwrap();
+        assert_eq!(series.len(), 2);
+        for (loudness, expected) in Iterator::zip(series.iter(), expected.iter()) {
+            // Only the filter state differs at the start of the segments
+            assert_float_eq!(*loudness, *expected, abs <= 0.01);
+        }
         assert_float_eq!(
-            ebu1.sample_peak(0).unwrap(),
-            f64_max(ebu_chunks.iter().map(|meter| meter.sample_peak(0).unwrap())).unwrap(),
-            abs <= 0.000001
+            ebu.partial_segment_loudness().unwrap(),
+            expected[2],
+            abs <= 0.01
         );
 
+        ebu.reset();
+        assert!(ebu.segment_loudness_series().unwrap().is_empty());
+        assert_eq!(ebu.partial_segment_loudness(), Ok(-f64::INFINITY));
+
+        ebu.set_segment_duration(None).unwrap();
+        assert_eq!(ebu.segment_duration(), None);
+    }
+
+    #[test]
+    fn true_peak_per_block() {
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.set_true_peak_per_block(true), Err(Error::InvalidMode));
+
+        let mut ebu = EbuR128::new(1, 48_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.set_true_peak_per_block(true), Err(Error::InvalidMode));
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.true_peak_per_block(), Err(Error::InvalidMode));
+        ebu.set_true_peak_per_block(true).unwrap();
+        assert!(ebu.true_peak_per_block_enabled());
+        assert_eq!(ebu.max_true_peak_window(), 400);
+        assert_eq!(ebu.set_max_true_peak_window(100), Err(Error::InvalidMode));
+
+        // One second of a full-scale sine on the first channel followed by one second at half the
+        // amplitude on the second channel
+        let mut data = vec![0.0f32; 48_000 * 2 * 2];
+        for (i, frame) in data.chunks_exact_mut(2).enumerate() {
+            let v = f32::sin(2.0 * std::f32::consts::PI * 1_000.0 * i as f32 / 48_000.0);
+            if i < 48_000 {
+                frame[0] = v;
+            } else {
+                frame[1] = 0.5 * v;
+            }
+        }
+        ebu.add_frames_f32(&data).unwrap();
+
+        let peaks = ebu.true_peak_per_block().unwrap();
+        assert_eq!(peaks.len(), 17);
+        for peak in &peaks[..10] {
+            assert_float_eq!(*peak, 1.0, abs <= 0.01);
+        }
+        for peak in &peaks[14..] {
+            assert_float_eq!(*peak, 0.5, abs <= 0.01);
+        }
         assert_float_eq!(
-            ebu1.true_peak(0).unwrap(),
-            f64_max(ebu_chunks.iter().map(|meter| meter.true_peak(0).unwrap())).unwrap(),
+            peaks.iter().copied().fold(0.0, f32::max) as f64,
+            ebu.true_peak(0).unwrap(),
             abs <= 0.000001
         );
 
-        assert_float_eq!(
-            ebu1.loudness_global().unwrap(),
-            EbuR128::loudness_global_multiple(ebu_chunks.iter()).unwrap(),
-            abs <= 0.000001
+        ebu.reset();
+        assert!(ebu.true_peak_per_block().unwrap().is_empty());
+
+        ebu.set_true_peak_per_block(false).unwrap();
+        assert_eq!(ebu.true_peak_per_block(), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn check_duration() {
+        let data = sine_f32(96_000 * 2, 1);
+
+        // Two seconds at 96kHz were added to an instance for 48kHz
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        assert_eq!(ebu.check_duration(2.0), Err(Error::DurationMismatch));
+        assert_eq!(ebu.check_duration(4.0), Ok(()));
+        assert_eq!(ebu.check_duration(f64::NAN), Err(Error::DurationMismatch));
+
+        let mut ebu = EbuR128::new(1, 96_000, Mode::I).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        assert_eq!(ebu.check_duration(2.0), Ok(()));
+        assert_eq!(
+            ebu.check_duration(2.0 * 48_000.0 / 44_100.0),
+            Err(Error::DurationMismatch)
         );
+
+        // Short durations have an absolute tolerance
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        ebu.add_frames_f32(&data[..4_800]).unwrap();
+        assert_eq!(ebu.check_duration(0.0), Ok(()));
+        assert_eq!(ebu.check_duration(0.25), Err(Error::DurationMismatch));
     }
 
-    #[cfg(feature = "c-tests")]
-    fn compare_results(ebu: &EbuR128, ebu_c: &ebur128_c::EbuR128, channels: u32) {
+    #[test]
+    fn upgrade_mode() {
+        let data = sine_f32(48_000 * 5, 2);
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.upgrade_mode(Mode::HISTOGRAM), Err(Error::InvalidMode));
+        ebu.add_frames_f32(&data[..48_000 * 2 * 2]).unwrap();
+        let momentary = ebu.loudness_momentary().unwrap();
+        ebu.upgrade_mode(Mode::I | Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.mode(), Mode::I | Mode::TRUE_PEAK);
+        assert_eq!(ebu.loudness_momentary(), Ok(momentary));
+        assert_eq!(ebu.loudness_global(), Ok(-f64::INFINITY));
+        assert_eq!(ebu.loudness_range(), Err(Error::InvalidMode));
+        assert_eq!(ebu.true_peak(0), Ok(0.0));
+
+        // The upgraded measurements only consider frames added afterwards
+        ebu.add_frames_f32(&data[48_000 * 2 * 2..]).unwrap();
+        let mut expected = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        expected.add_frames_f32(&data[48_000 * 2 * 2..]).unwrap();
         assert_float_eq!(
             ebu.loudness_global().unwrap(),
-            ebu_c.loudness_global().unwrap(),
-            ulps <= 2
+            expected.loudness_global().unwrap(),
+            abs <= 0.01
         );
         assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            ebu_c.loudness_momentary().unwrap(),
-            ulps <= 2
+            ebu.true_peak(0).unwrap(),
+            expected.true_peak(0).unwrap(),
+            abs <= 0.01
+        );
+
+        // Adding the short-term window reallocates the audio buffer
+        ebu.upgrade_mode(Mode::LRA).unwrap();
+        assert_eq!(ebu.max_window(), 3000);
+        assert_eq!(ebu.loudness_momentary(), Ok(-f64::INFINITY));
+        ebu.add_frames_f32(&data).unwrap();
+        assert!(ebu.loudness_range().unwrap() >= 0.0);
+
+        // Instances only measuring peaks start filtering
+        let mut ebu = EbuR128::new(2, 48_000, Mode::SAMPLE_PEAK).unwrap();
+        ebu.add_frames_f32(&data[..48_000 * 2]).unwrap();
+        ebu.upgrade_mode(Mode::I).unwrap();
+        ebu.add_frames_f32(&data[48_000 * 2..]).unwrap();
+        let mut expected = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        expected.add_frames_f32(&data[48_000 * 2..]).unwrap();
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            expected.loudness_global().unwrap(),
+            abs <= 0.01
+        );
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.01);
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu.set_max_history(1000).unwrap();
+        assert_eq!(ebu.upgrade_mode(Mode::LRA), Err(Error::InvalidMode));
+        assert_eq!(ebu.mode(), Mode::I);
+    }
+
+    #[test]
+    fn max_channels() {
+        assert_eq!(
+            EbuR128::new(MAX_CHANNELS + 1, 48_000, Mode::I).unwrap_err(),
+            Error::InvalidChannelIndex
+        );
+        assert_eq!(
+            EbuR128::new(u32::MAX, 48_000, Mode::I).unwrap_err(),
+            Error::InvalidChannelIndex
+        );
+        assert_eq!(EbuR128::new(0, 48_000, Mode::I).unwrap_err(), Error::NoMem);
+
+        let mut ebu = EbuR128::new(MAX_CHANNELS, 48_000, Mode::I).unwrap();
+        assert_eq!(
+            ebu.change_parameters(MAX_CHANNELS + 1, 48_000),
+            Err(Error::InvalidChannelIndex)
         );
+        assert_eq!(ebu.channels(), MAX_CHANNELS);
+    }
+
+    #[test]
+    fn true_peak_over_count() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(
+            ebu.set_true_peak_over_ceiling(Some(-1.0)),
+            Err(Error::InvalidMode)
+        );
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.true_peak_over_count(), Err(Error::InvalidMode));
+        assert_eq!(
+            ebu.set_true_peak_over_ceiling(Some(f64::NAN)),
+            Err(Error::InvalidMode)
+        );
+        ebu.set_true_peak_over_ceiling(Some(-1.0)).unwrap();
+        assert_eq!(ebu.true_peak_over_ceiling(), Some(-1.0));
+
+        // Three bursts of a full-scale sine on the first channel and one on the second channel,
+        // separated by silence. Each burst is a single event.
+        let mut data = vec![0.0f32; 48_000 * 2 * 2];
+        for (i, frame) in data.chunks_exact_mut(2).enumerate() {
+            let burst = i / 16_000;
+            if burst % 2 == 0 {
+                let v = f32::sin(2.0 * std::f32::consts::PI * 997.0 * i as f32 / 48_000.0);
+                frame[0] = v;
+                if burst == 4 {
+                    frame[1] = v;
+                }
+            }
+        }
+
+        // A run that continues across calls is only counted once
+        for chunk in data.chunks(1_234) {
+            ebu.add_frames_f32(chunk).unwrap();
+        }
+        // Each half-period of the sine is above the ceiling
+        let events_per_burst = 2 * 16_000 * 997 / 48_000;
+        let count0 = ebu.true_peak_over_count_channel(0).unwrap();
+        let count1 = ebu.true_peak_over_count_channel(1).unwrap();
+        assert!(((3 * events_per_burst)..=(3 * events_per_burst + 6)).contains(&count0));
+        assert!((events_per_burst..=(events_per_burst + 2)).contains(&count1));
+        assert_eq!(ebu.true_peak_over_count(), Ok(count0 + count1));
+        assert_eq!(
+            ebu.true_peak_over_count_channel(2),
+            Err(Error::InvalidChannelIndex)
+        );
+
+        // Nothing exceeds a ceiling above the true peak
+        ebu.set_true_peak_over_ceiling(Some(0.5)).unwrap();
+        assert_eq!(ebu.true_peak_over_count(), Ok(0));
+        ebu.add_frames_f32(&data).unwrap();
+        assert_eq!(ebu.true_peak_over_count(), Ok(0));
+
+        ebu.set_true_peak_over_ceiling(Some(-1.0)).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        assert_eq!(ebu.true_peak_over_count_channel(1), Ok(count1));
+        ebu.reset_peaks();
+        assert_eq!(ebu.true_peak_over_count(), Ok(0));
+    }
+
+    #[test]
+    fn measurement_pregain() {
+        let data = sine_f32(48_000 * 5, 2);
+        let scaled = data
+            .iter()
+            .map(|sample| sample * f32::powf(10.0, -6.0 / 20.0))
+            .collect::<Vec<_>>();
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        assert_eq!(
+            ebu.set_measurement_pregain(f64::INFINITY),
+            Err(Error::InvalidMode)
+        );
+        ebu.set_measurement_pregain(-6.0).unwrap();
+        assert_eq!(ebu.measurement_pregain(), -6.0);
+
+        let data_i16 = data
+            .iter()
+            .map(|sample| (sample * 16_384.0) as i16)
+            .collect::<Vec<_>>();
+        ebu.add_frames_f32(&data).unwrap();
+
+        let mut expected = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        expected.add_frames_f32(&scaled).unwrap();
         assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            ebu_c.loudness_shortterm().unwrap(),
-            ulps <= 2
+            ebu.loudness_global().unwrap(),
+            expected.loudness_global().unwrap(),
+            abs <= 0.000001
         );
         assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            ebu_c.loudness_window(1).unwrap(),
-            ulps <= 2
+            ebu.sample_peak(0).unwrap(),
+            expected.sample_peak(0).unwrap(),
+            abs <= 0.000001
         );
         assert_float_eq!(
-            ebu.loudness_range().unwrap(),
-            ebu_c.loudness_range().unwrap(),
-            ulps <= 2
+            ebu.true_peak(1).unwrap(),
+            expected.true_peak(1).unwrap(),
+            abs <= 0.000001
         );
 
-        for c in 0..channels {
-            assert_float_eq!(
-                ebu.sample_peak(c).unwrap(),
-                ebu_c.sample_peak(c).unwrap(),
-                ulps <= 2
-            );
-            assert_float_eq!(
-                ebu.prev_sample_peak(c).unwrap(),
-                ebu_c.prev_sample_peak(c).unwrap(),
-                ulps <= 2
-            );
+        // Integer samples are scaled as well
+        let mut ebu = EbuR128::new(2, 48_000, Mode::SAMPLE_PEAK).unwrap();
+        ebu.set_measurement_pregain(6.0).unwrap();
+        ebu.add_frames_i16(&data_i16).unwrap();
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 0.998, abs <= 0.001);
 
-            assert_float_eq!(
-                ebu.true_peak(c).unwrap(),
-                ebu_c.true_peak(c).unwrap(),
-                // For a performance-boost, filter is defined as f32, causing slightly lower precision
-                abs <= 0.000004,
-            );
-            assert_float_eq!(
-                ebu.prev_true_peak(c).unwrap(),
-                ebu_c.prev_true_peak(c).unwrap(),
-                // For a performance-boost, filter is defined as f32, causing slightly lower precision
-                abs <= 0.000004,
-            );
+        ebu.set_measurement_pregain(0.0).unwrap();
+        assert_eq!(ebu.measurement_pregain(), 0.0);
+    }
+
+    #[test]
+    #[cfg(feature = "block-energies")]
+    fn gated_block_energies() {
+        let mut data = sine_f32(48_000 * 2, 1);
+        // The first second is below the absolute gate
+        for sample in &mut data[..48_000] {
+            *sample *= 0.0001;
         }
 
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+
+        let energies = ebu.gated_block_energies().unwrap();
+        // 17 blocks in total, of which the first 7 lie mostly in the first second
+        assert_eq!(energies.len(), 17 - 7);
+        let mean = energies.iter().sum::<f64>() / energies.len() as f64;
         assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            ebu_c.relative_threshold().unwrap(),
-            ulps <= 2
+            crate::energy_to_loudness(mean),
+            ebu.loudness_global_ungated().unwrap(),
+            abs <= 0.000001
         );
-    }
+        // The blocks with quiet audio come first
+        assert!(energies[0] < energies[energies.len() - 1]);
 
-    #[cfg(feature = "c-tests")]
-    #[quickcheck]
-    fn compare_c_impl_i16(signal: Signal<i16>) {
-        let mut ebu = EbuR128::new(signal.channels, signal.rate, Mode::all()).unwrap();
-        ebu.add_frames_i16(&signal.data).unwrap();
+        let ebu = EbuR128::new(1, 48_000, Mode::I | Mode::HISTOGRAM).unwrap();
+        assert_eq!(ebu.gated_block_energies(), Err(Error::InvalidMode));
+        let ebu = EbuR128::new(1, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.gated_block_energies(), Err(Error::InvalidMode));
+    }
 
-        let mut ebu_c =
-            ebur128_c::EbuR128::new(signal.channels, signal.rate, ebur128_c::Mode::all()).unwrap();
-        ebu_c.add_frames_i16(&signal.data).unwrap();
+    #[test]
+    fn intersample_peak_margin() {
+        let ebu = EbuR128::new(2, 48_000, Mode::SAMPLE_PEAK).unwrap();
+        assert_eq!(ebu.intersample_peak_margin(0), Err(Error::InvalidMode));
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(
+            ebu.intersample_peak_margin(2),
+            Err(Error::InvalidChannelIndex)
+        );
+        assert_eq!(ebu.intersample_peak_margin(0), Ok(0.0));
+
+        // A sine at a quarter of the sample rate that is sampled 45° off its peaks in the first
+        // channel has inter-sample peaks 3 dB above its samples, the second channel is silent
+        let data = (0..48_000)
+            .flat_map(|i| [if i % 4 < 2 { 0.5f32 } else { -0.5 }, 0.0])
+            .collect::<Vec<_>>();
+        ebu.add_frames_f32(&data).unwrap();
 
-        compare_results(&ebu, &ebu_c, signal.channels);
+        let margin = ebu.intersample_peak_margin(0).unwrap();
+        assert!((margin - 3.0).abs() < 0.2, "{}", margin);
+        assert_eq!(ebu.intersample_peak_margin(1), Ok(0.0));
     }
 
-    #[cfg(feature = "c-tests")]
-    #[quickcheck]
-    fn compare_c_impl_i32(signal: Signal<i32>) {
-        let mut ebu = EbuR128::new(signal.channels, signal.rate, Mode::all()).unwrap();
-        ebu.add_frames_i32(&signal.data).unwrap();
+    #[test]
+    fn add_frames_at() {
+        let data = sine_f32(48_000, 2);
+
+        // Contiguous timestamps give the same result as adding the frames directly
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::SAMPLE_PEAK).unwrap();
+        let mut ebu_ts = EbuR128::new(2, 48_000, Mode::I | Mode::SAMPLE_PEAK).unwrap();
+        for (i, packet) in data.chunks(960 * 2).enumerate() {
+            ebu.add_frames_f32(packet).unwrap();
+            ebu_ts
+                .add_frames_at_f32(packet, 1_000 + i as u64 * 960)
+                .unwrap();
+        }
+        assert_eq!(ebu_ts.frames_added(), 48_000);
+        assert_float_eq!(
+            ebu_ts.loudness_global().unwrap(),
+            ebu.loudness_global().unwrap(),
+            abs <= 0.000001
+        );
+        assert_eq!(
+            ebu_ts.add_frames_at_f32(&data[..3], 49_000),
+            Err(Error::BufferSizeMismatch)
+        );
 
-        let mut ebu_c =
-            ebur128_c::EbuR128::new(signal.channels, signal.rate, ebur128_c::Mode::all()).unwrap();
-        ebu_c.add_frames_i32(&signal.data).unwrap();
+        // Duplicated frames are dropped
+        ebu_ts.add_frames_at_f32(&data[..960 * 2], 48_080).unwrap();
+        assert_eq!(ebu_ts.frames_added(), 48_000 + 40);
 
-        compare_results(&ebu, &ebu_c, signal.channels);
-    }
+        // A gap of a second is filled with silence
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.gap_policy(), GapPolicy::InsertSilence);
+        ebu.add_frames_at_f32(&data, 0).unwrap();
+        ebu.add_frames_at_f32(&data, 96_000).unwrap();
+        assert_eq!(ebu.frames_added(), 3 * 48_000);
 
-    #[cfg(feature = "c-tests")]
-    #[quickcheck]
-    fn compare_c_impl_f32(signal: Signal<f32>) {
-        let mut ebu = EbuR128::new(signal.channels, signal.rate, Mode::all()).unwrap();
-        ebu.add_frames_f32(&signal.data).unwrap();
+        let mut ebu_silence = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu_silence.add_frames_f32(&data).unwrap();
+        ebu_silence.add_frames_f32(&vec![0.0; 48_000 * 2]).unwrap();
+        ebu_silence.add_frames_f32(&data).unwrap();
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            ebu_silence.loudness_global().unwrap(),
+            abs <= 0.000001
+        );
 
-        let mut ebu_c =
-            ebur128_c::EbuR128::new(signal.channels, signal.rate, ebur128_c::Mode::all()).unwrap();
-        ebu_c.add_frames_f32(&signal.data).unwrap();
+        // Or skipped with the filter state reset
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu.set_gap_policy(GapPolicy::ResetFilter);
+        ebu.add_frames_at_f32(&data, 0).unwrap();
+        ebu.add_frames_at_f32(&data, 96_000).unwrap();
+        assert_eq!(ebu.frames_added(), 2 * 48_000);
+        assert!(
+            (ebu.loudness_global().unwrap() - ebu_silence.loudness_global().unwrap()).abs() > 0.1
+        );
 
-        compare_results(&ebu, &ebu_c, signal.channels);
+        // After a reset the next timestamp is taken as is
+        ebu.reset();
+        ebu.add_frames_at_f32(&data, 0).unwrap();
+        assert_eq!(ebu.frames_added(), 48_000);
+
+        // Gaps above the maximum, e.g. of a wrapped RTP timestamp, are skipped as well
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.max_silence_gap(), 10.0);
+        ebu.add_frames_at_f32(&data, 0).unwrap();
+        ebu.add_frames_at_f32(&data, 1 << 32).unwrap();
+        assert_eq!(ebu.frames_added(), 2 * 48_000);
+
+        assert_eq!(ebu.set_max_silence_gap(-1.0), Err(Error::InvalidMode));
+        assert_eq!(ebu.set_max_silence_gap(f64::NAN), Err(Error::InvalidMode));
+        ebu.set_max_silence_gap(0.5).unwrap();
+        assert_eq!(ebu.max_silence_gap(), 0.5);
+        ebu.add_frames_at_f32(&data, (1 << 32) + 48_000 + 24_000)
+            .unwrap();
+        assert_eq!(ebu.frames_added(), 3 * 48_000 + 24_000);
+        ebu.add_frames_at_f32(&data, (1 << 32) + 5 * 48_000)
+            .unwrap();
+        assert_eq!(ebu.frames_added(), 4 * 48_000 + 24_000);
     }
 
-    #[cfg(feature = "c-tests")]
-    #[quickcheck]
-    fn compare_c_impl_f64(signal: Signal<f64>) {
-        let mut ebu = EbuR128::new(signal.channels, signal.rate, Mode::all()).unwrap();
-        ebu.add_frames_f64(&signal.data).unwrap();
-
-        let mut ebu_c =
-            ebur128_c::EbuR128::new(signal.channels, signal.rate, ebur128_c::Mode::all()).unwrap();
-        ebu_c.add_frames_f64(&signal.data).unwrap();
+    #[test]
+    fn reset_loudness_range() {
+        let data = sine_f32(48_000 * 10, 2);
+        let quiet = data.iter().map(|s| s * 0.1).collect::<Vec<_>>();
 
-        compare_results(&ebu, &ebu_c, signal.channels);
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::LRA | Mode::TRUE_PEAK).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        ebu.add_frames_f32(&quiet).unwrap();
+        let global = ebu.loudness_global().unwrap();
+        let true_peak = ebu.true_peak(0).unwrap();
+        assert!(ebu.loudness_range().unwrap() > 15.0);
+
+        ebu.reset_loudness_range();
+        assert_eq!(ebu.loudness_range().unwrap(), 0.0);
+        assert_eq!(ebu.loudness_global().unwrap(), global);
+        assert_eq!(ebu.true_peak(0).unwrap(), true_peak);
+
+        // Only the constant quiet part after the reset contributes to the loudness range
+        ebu.add_frames_f32(&quiet).unwrap();
+        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.01);
     }
 
-    #[cfg(feature = "c-tests")]
-    #[quickcheck]
-    fn compare_c_impl_i16_no_histogram(signal: Signal<i16>) {
-        let mut ebu =
-            EbuR128::new(signal.channels, signal.rate, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_i16(&signal.data).unwrap();
-
-        let mut ebu_c = ebur128_c::EbuR128::new(
-            signal.channels,
-            signal.rate,
-            ebur128_c::Mode::all() & !ebur128_c::Mode::HISTOGRAM,
-        )
-        .unwrap();
-        ebu_c.add_frames_i16(&signal.data).unwrap();
+    #[test]
+    fn regions() {
+        let loud = sine_f32(48_000 * 2, 2);
+        let quiet = loud.iter().map(|s| s * 0.5).collect::<Vec<_>>();
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.add_region("1", 0, 96_000), Err(Error::InvalidMode));
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.add_region("1", 96_000, 96_000), Err(Error::InvalidMode));
+        ebu.add_region("1", 0, 96_000).unwrap();
+        assert_eq!(ebu.add_region("1", 0, 48_000), Err(Error::InvalidMode));
+        ebu.add_region("2", 96_000, 192_000).unwrap();
+        ebu.add_region("all", 0, 192_000).unwrap();
+        assert_eq!(ebu.region_loudness("3"), Err(Error::InvalidMode));
+        assert_eq!(ebu.region_loudness("1"), Ok(f64::NEG_INFINITY));
+
+        ebu.add_frames_f32(&loud).unwrap();
+        ebu.add_frames_f32(&quiet).unwrap();
+
+        let mut ebu_loud = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu_loud.add_frames_f32(&loud).unwrap();
+        let mut ebu_quiet = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu_quiet.add_frames_f32(&quiet).unwrap();
+
+        // Only the blocks around the boundary differ from measuring the tracks separately
+        let loudness_1 = ebu.region_loudness("1").unwrap();
+        let loudness_2 = ebu.region_loudness("2").unwrap();
+        assert!((loudness_1 - ebu_loud.loudness_global().unwrap()).abs() < 0.1);
+        assert!(loudness_2 > ebu_quiet.loudness_global().unwrap());
+        assert!(loudness_2 < ebu_quiet.loudness_global().unwrap() + 1.0);
+        assert_eq!(ebu.region_loudness("all"), ebu.loudness_global());
 
-        compare_results(&ebu, &ebu_c, signal.channels);
+        ebu.reset();
+        assert_eq!(ebu.region_loudness("1"), Ok(f64::NEG_INFINITY));
+        ebu.clear_regions();
+        assert_eq!(ebu.region_loudness("1"), Err(Error::InvalidMode));
     }
 
-    #[cfg(feature = "c-tests")]
-    #[quickcheck]
-    fn compare_c_impl_i32_no_histogram(signal: Signal<i32>) {
-        let mut ebu =
-            EbuR128::new(signal.channels, signal.rate, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_i32(&signal.data).unwrap();
+    #[test]
+    fn leveling_step() {
+        let ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.leveling_step(-23.0, 1.0), Err(Error::InvalidMode));
 
-        let mut ebu_c = ebur128_c::EbuR128::new(
-            signal.channels,
-            signal.rate,
-            ebur128_c::Mode::all() & !ebur128_c::Mode::HISTOGRAM,
-        )
-        .unwrap();
-        ebu_c.add_frames_i32(&signal.data).unwrap();
+        let mut ebu = EbuR128::new(2, 48_000, Mode::S).unwrap();
+        assert_eq!(ebu.leveling_step(-23.0, -1.0), Err(Error::InvalidMode));
+        assert_eq!(ebu.leveling_step(-23.0, f64::NAN), Err(Error::InvalidMode));
+        assert_eq!(ebu.leveling_step(-23.0, 1.0), Ok(0.0));
 
-        compare_results(&ebu, &ebu_c, signal.channels);
+        ebu.add_frames_f32(&sine_f32(48_000 * 3, 2)).unwrap();
+        let loudness = ebu.loudness_shortterm().unwrap();
+
+        // Limited in both directions, exact within the limit
+        assert_eq!(ebu.leveling_step(loudness - 10.0, 1.5), Ok(-1.5));
+        assert_eq!(ebu.leveling_step(loudness + 10.0, 1.5), Ok(1.5));
+        assert_float_eq!(
+            ebu.leveling_step(loudness - 0.5, 1.5).unwrap(),
+            -0.5,
+            abs <= 0.000001
+        );
+        assert_eq!(ebu.leveling_step(loudness, 0.0), Ok(0.0));
     }
 
-    #[cfg(feature = "c-tests")]
-    #[quickcheck]
-    fn compare_c_impl_f32_no_histogram(signal: Signal<f32>) {
-        let mut ebu =
-            EbuR128::new(signal.channels, signal.rate, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_f32(&signal.data).unwrap();
+    #[test]
+    fn stable_accumulation() {
+        let data = sine_f32(48_000 * 5, 2);
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::LRA).unwrap();
+        assert!(!ebu.stable_accumulation());
+        ebu.add_frames_f32(&data).unwrap();
+        let loudness = ebu.loudness_global().unwrap();
+        let loudness_range = ebu.loudness_range().unwrap();
 
-        let mut ebu_c = ebur128_c::EbuR128::new(
-            signal.channels,
-            signal.rate,
-            ebur128_c::Mode::all() & !ebur128_c::Mode::HISTOGRAM,
-        )
-        .unwrap();
-        ebu_c.add_frames_f32(&signal.data).unwrap();
+        // Keeps the blocks measured so far and only changes the rounding
+        ebu.set_stable_accumulation(true);
+        assert!(ebu.stable_accumulation());
+        assert_float_eq!(ebu.loudness_global().unwrap(), loudness, abs <= 0.000001);
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            loudness_range,
+            abs <= 0.000001
+        );
 
-        compare_results(&ebu, &ebu_c, signal.channels);
+        // Instances created later, e.g. for the segments, use it too
+        ebu.set_segment_duration(Some(1.0)).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        ebu.set_stable_accumulation(false);
+        assert!(!ebu.stable_accumulation());
+        assert_float_eq!(ebu.loudness_global().unwrap(), loudness, abs <= 0.0001);
+        for loudness in ebu.segment_loudness_series().unwrap() {
+            assert!(loudness.is_finite());
+        }
     }
 
-    #[cfg(feature = "c-tests")]
-    #[quickcheck]
-    fn compare_c_impl_f64_no_histogram(signal: Signal<f64>) {
-        let mut ebu =
-            EbuR128::new(signal.channels, signal.rate, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_f64(&signal.data).unwrap();
+    #[test]
+    fn max_duration() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.set_max_duration(Some(-1.0)), Err(Error::InvalidMode));
+        assert_eq!(
+            ebu.set_max_duration(Some(f64::NAN)),
+            Err(Error::InvalidMode)
+        );
+        assert_eq!(ebu.max_duration(), None);
 
-        let mut ebu_c = ebur128_c::EbuR128::new(
-            signal.channels,
-            signal.rate,
-            ebur128_c::Mode::all() & !ebur128_c::Mode::HISTOGRAM,
-        )
-        .unwrap();
-        ebu_c.add_frames_f64(&signal.data).unwrap();
+        ebu.set_max_duration(Some(2.0)).unwrap();
+        assert_eq!(ebu.max_duration(), Some(2.0));
 
-        compare_results(&ebu, &ebu_c, signal.channels);
+        let data = sine_f32(48_000, 2);
+        ebu.add_frames_f32(&data).unwrap();
+        ebu.add_frames_f32(&data[..2 * 47_999]).unwrap();
+        let loudness = ebu.loudness_global().unwrap();
+
+        // The call exceeding the cap is rejected as a whole
+        assert_eq!(
+            ebu.add_frames_f32(&data[..4]),
+            Err(Error::MaxDurationExceeded)
+        );
+        assert_eq!(ebu.frames_added(), 2 * 48_000 - 1);
+        assert_eq!(ebu.loudness_global(), Ok(loudness));
+        ebu.add_frames_f32(&data[..2]).unwrap();
+        assert_eq!(
+            ebu.add_frames_f32(&data[..2]),
+            Err(Error::MaxDurationExceeded)
+        );
+        assert_eq!(ebu.frames_added(), 2 * 48_000);
+
+        ebu.reset();
+        ebu.add_frames_f32(&data).unwrap();
+        ebu.set_max_duration(None).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        assert_eq!(ebu.frames_added(), 3 * 48_000);
     }
 
     #[test]
-    fn infinity_handling() {
-        let mut data = vec![0.0f32; 44_100 * 80];
-        for out in data.chunks_exact_mut(2) {
-            out[0] = f32::INFINITY;
-            out[1] = f32::NEG_INFINITY;
-        }
-
-        let mut ebu = EbuR128::new(2, 44_100, Mode::all() - Mode::HISTOGRAM).unwrap();
-        assert!(ebu.add_frames_f32(&data).is_ok());
-        assert_eq!(ebu.sample_peak(0).unwrap().abs(), f64::INFINITY);
-        assert_eq!(ebu.true_peak(0).unwrap().abs(), f64::INFINITY);
-        assert!(ebu.loudness_global().unwrap().is_nan());
-        assert!(ebu.loudness_momentary().unwrap().is_nan());
-        assert!(ebu.energy_shortterm().unwrap().is_nan());
-        assert!(ebu.loudness_shortterm().unwrap().is_nan());
-        assert!(ebu.loudness_range().unwrap().is_nan());
-        assert!(ebu.relative_threshold().unwrap().is_nan());
+    fn input_highpass() {
+        let sine = |freq: f32| {
+            let mut data = vec![0.0f32; 48_000 * 5];
+            let step = 2.0 * std::f32::consts::PI * freq / 48_000.0;
+            for (i, out) in data.iter_mut().enumerate() {
+                *out = f32::sin(step * i as f32);
+            }
+            data
+        };
+        let measure = |data: &[f32], cutoff_hz: Option<f64>| {
+            let mut ebu = EbuR128::new(1, 48_000, Mode::I | Mode::SAMPLE_PEAK).unwrap();
+            ebu.set_input_highpass(cutoff_hz).unwrap();
+            assert_eq!(ebu.input_highpass(), cutoff_hz);
+            ebu.add_frames_f32(data).unwrap();
+            (ebu.loudness_global().unwrap(), ebu.sample_peak(0).unwrap())
+        };
 
-        // With histogram mode the first bin is taken for NaN
-        let mut ebu = EbuR128::new(2, 44_100, Mode::all()).unwrap();
-        assert!(ebu.add_frames_f32(&data).is_ok());
-        assert_eq!(ebu.sample_peak(0).unwrap().abs(), f64::INFINITY);
-        assert_eq!(ebu.true_peak(0).unwrap().abs(), f64::INFINITY);
-        assert_float_eq!(ebu.loudness_global().unwrap(), -69.95, abs <= 0.000_000_1);
-        assert!(ebu.loudness_momentary().unwrap().is_nan());
-        assert!(ebu.energy_shortterm().unwrap().is_nan());
-        assert!(ebu.loudness_shortterm().unwrap().is_nan(),);
-        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000_000_1);
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -79.95,
-            abs <= 0.000_000_1
-        );
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        for cutoff_hz in [0.0, -1.0, 24_000.0, f64::NAN] {
+            assert_eq!(
+                ebu.set_input_highpass(Some(cutoff_hz)),
+                Err(Error::InvalidMode)
+            );
+        }
+        assert_eq!(ebu.input_highpass(), None);
+
+        // -3 dB at the cutoff frequency and -12 dB per octave below it, but the peaks are
+        // measured before the filter
+        let rumble = sine(20.0);
+        let (loudness, peak) = measure(&rumble, None);
+        let (filtered, filtered_peak) = measure(&rumble, Some(80.0));
+        assert_float_eq!(loudness - filtered, 24.1, abs <= 0.1);
+        assert_eq!(peak, filtered_peak);
+
+        let low = sine(80.0);
+        let (loudness, _) = measure(&low, None);
+        let (filtered, _) = measure(&low, Some(80.0));
+        assert_float_eq!(loudness - filtered, 3.0, abs <= 0.1);
+
+        let tone = sine(1_000.0);
+        let (loudness, _) = measure(&tone, None);
+        let (filtered, _) = measure(&tone, Some(80.0));
+        assert_float_eq!(loudness, filtered, abs <= 0.01);
+
+        // The cutoff frequency has to stay valid for a new sample rate
+        ebu.set_input_highpass(Some(10_000.0)).unwrap();
+        assert_eq!(
+            ebu.change_parameters(1, 16_000),
+            Err(Error::UnsupportedRate)
+        );
+        ebu.set_input_highpass(None).unwrap();
+        ebu.change_parameters(1, 16_000).unwrap();
     }
 
     #[test]
-    fn nan_handling() {
-        let mut data = vec![0.0f32; 44_100 * 80];
-        for out in data.chunks_exact_mut(2) {
-            out[0] = f32::NAN;
-            out[1] = f32::NAN;
+    fn loudness_range_multiple_mixed() {
+        let mut data = sine_f32(48_000 * 10, 2);
+        for (i, v) in data.iter_mut().enumerate() {
+            // Ramp from -40 dB to 0 dB over the 10 seconds
+            *v *= f32::powf(10.0, -2.0 + 2.0 * i as f32 / (48_000.0 * 20.0));
         }
 
-        let mut ebu = EbuR128::new(2, 44_100, Mode::all() - Mode::HISTOGRAM).unwrap();
-        assert!(ebu.add_frames_f32(&data).is_ok());
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 0.0, abs <= f64::EPSILON);
-        assert_float_eq!(ebu.true_peak(0).unwrap(), 0.0, abs <= f64::EPSILON);
-        assert!(ebu.loudness_global().unwrap().is_nan());
-        assert!(ebu.loudness_momentary().unwrap().is_nan());
-        assert!(ebu.energy_shortterm().unwrap().is_nan());
-        assert!(ebu.loudness_shortterm().unwrap().is_nan());
-        assert!(ebu.relative_threshold().unwrap().is_nan());
+        let mut queue = EbuR128::new(2, 48_000, Mode::LRA).unwrap();
+        let mut histogram = EbuR128::new(2, 48_000, Mode::LRA | Mode::HISTOGRAM).unwrap();
+        queue.add_frames_f32(&data).unwrap();
+        histogram.add_frames_f32(&data).unwrap();
 
-        // With histogram mode the first bin is taken for NaN
-        let mut ebu = EbuR128::new(2, 44_100, Mode::all()).unwrap();
-        assert!(ebu.add_frames_f32(&data).is_ok());
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 0.0, abs <= f64::EPSILON);
-        assert_float_eq!(ebu.true_peak(0).unwrap(), 0.0, abs <= f64::EPSILON);
-        assert_float_eq!(ebu.loudness_global().unwrap(), -69.95, abs <= 0.000_000_1);
-        assert!(ebu.loudness_momentary().unwrap().is_nan());
-        assert!(ebu.energy_shortterm().unwrap().is_nan());
-        assert!(ebu.loudness_shortterm().unwrap().is_nan(),);
-        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000_000_1);
+        assert_eq!(
+            EbuR128::loudness_range_multiple([&queue, &histogram]),
+            Err(Error::MixedHistoryTypes)
+        );
+        assert_eq!(
+            EbuR128::loudness_range_multiple_mixed([&queue, &histogram], false),
+            Err(Error::MixedHistoryTypes)
+        );
+
+        let expected = EbuR128::loudness_range_multiple([&queue, &queue]).unwrap();
+        assert!(expected > 10.0);
         assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -79.95,
-            abs <= 0.000_000_1
+            EbuR128::loudness_range_multiple_mixed([&queue, &histogram], true).unwrap(),
+            expected,
+            abs <= 0.1
+        );
+        assert_eq!(
+            EbuR128::loudness_range_multiple_mixed([&queue, &queue], true),
+            Ok(expected)
         );
     }
 }
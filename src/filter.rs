@@ -21,8 +21,10 @@
 
 use std::fmt;
 
-use crate::ebur128::Channel;
+use crate::ebur128::{Channel, KWeighting};
+use crate::true_peak::FramePeaks;
 use crate::utils::Sample;
+use smallvec::{smallvec, SmallVec};
 
 /// BS.1770 filter and optional sample/true peak measurement context.
 pub struct Filter {
@@ -33,6 +35,8 @@ pub struct Filter {
     a: [f64; 5],
     /// One filter state per channel.
     filter_state: Box<[[f64; 5]]>,
+    /// Additional high-pass filter if enabled.
+    highpass: Option<HighPass>,
 
     /// Whether to measure sample peak.
     calculate_sample_peak: bool,
@@ -43,8 +47,19 @@ pub struct Filter {
     tp: Option<crate::true_peak::TruePeak>,
     /// Previously measured true peak.
     true_peak: Box<[f64]>,
+    /// Peaks of the most recent frames if enabled.
+    true_peak_window: Option<crate::true_peak::PeakWindow>,
+    /// Sample peak below which blocks are not oversampled if enabled.
+    true_peak_threshold: Option<f64>,
+    /// Events where the true peak exceeds a ceiling if enabled.
+    true_peak_over: Option<crate::true_peak::OverCounter>,
 }
 
+/// Number of frames that are compared against the true peak threshold at once.
+const TRUE_PEAK_THRESHOLD_BLOCK_FRAMES: usize = 1024;
+/// Number of frames that fill the history of all true peak interpolators.
+const TRUE_PEAK_HISTORY_FRAMES: usize = 24;
+
 impl fmt::Debug for Filter {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Filter")
@@ -52,16 +67,50 @@ impl fmt::Debug for Filter {
             .field("b", &self.b)
             .field("a", &self.a)
             .field("filter_state", &self.filter_state)
+            .field("highpass", &self.highpass)
             .field("calculate_sample_peak", &self.calculate_sample_peak)
             .field("sample_peak", &self.sample_peak)
             .field("calculate_true_peak", &self.tp.is_some())
             .field("true_peak", &self.true_peak)
+            .field(
+                "true_peak_window",
+                &self.true_peak_window.as_ref().map(|w| w.frames()),
+            )
+            .field("true_peak_threshold", &self.true_peak_threshold)
+            .field("true_peak_over", &self.true_peak_over)
             .finish()
     }
 }
 
+/// Coefficients of the two biquads of the BS.1770 filter: the pre-filter (shelving filter)
+/// and the RLB (high-pass) filter.
+#[derive(Clone, Copy)]
+struct Biquads {
+    /// Pre-filter numerator.
+    pb: [f64; 3],
+    /// Pre-filter denominator.
+    pa: [f64; 3],
+    /// RLB filter numerator.
+    rb: [f64; 3],
+    /// RLB filter denominator.
+    ra: [f64; 3],
+}
+
+/// Calculate the biquads for the given sample rate.
+///
+/// The biquads of the common sample rates are precomputed and never calculated.
+fn biquads(rate: f64) -> Biquads {
+    if let Some(index) = crate::biquads::RATES.iter().position(|r| *r as f64 == rate) {
+        let [pb, pa, rb, ra] = crate::biquads::BIQUADS[index];
+        return Biquads { pb, pa, rb, ra };
+    }
+
+    calc_biquads(rate)
+}
+
+// Keep in sync with `examples/generate_biquads.rs`
 #[allow(non_snake_case)]
-fn filter_coefficients(rate: f64) -> ([f64; 5], [f64; 5]) {
+fn calc_biquads(rate: f64) -> Biquads {
     let f0 = 1681.974450955533;
     let G = 3.999843853973347;
     let Q = 0.7071752369554196;
@@ -89,6 +138,70 @@ fn filter_coefficients(rate: f64) -> ([f64; 5], [f64; 5]) {
     ra[1] = 2.0 * (K * K - 1.0) / (1.0 + K / Q + K * K);
     ra[2] = (1.0 - K / Q + K * K) / (1.0 + K / Q + K * K);
 
+    Biquads { pb, pa, rb, ra }
+}
+
+/// Second order Butterworth high-pass filter in addition to the BS.1770 filter.
+#[derive(Debug)]
+struct HighPass {
+    /// Filter coefficients (numerator).
+    b: [f64; 3],
+    /// Filter coefficients (denominator).
+    a: [f64; 3],
+    /// One filter state per channel.
+    state: Box<[[f64; 2]]>,
+}
+
+impl HighPass {
+    #[allow(non_snake_case)]
+    fn new(rate: f64, cutoff: f64, channels: u32) -> Self {
+        let Q = std::f64::consts::FRAC_1_SQRT_2;
+        let K = f64::tan(std::f64::consts::PI * cutoff / rate);
+
+        let a0 = 1.0 + K / Q + K * K;
+        let b = [1.0 / a0, -2.0 / a0, 1.0 / a0];
+        let a = [1.0, 2.0 * (K * K - 1.0) / a0, (1.0 - K / Q + K * K) / a0];
+
+        HighPass {
+            b,
+            a,
+            state: vec![[0.0; 2]; channels as usize].into_boxed_slice(),
+        }
+    }
+
+    #[inline]
+    fn filter(b: &[f64; 3], a: &[f64; 3], state: &mut [f64; 2], x: f64) -> f64 {
+        let s = x - a[1] * state[0] - a[2] * state[1];
+        let y = b[0] * s + b[1] * state[0] + b[2] * state[1];
+
+        state[1] = state[0];
+        state[0] = s;
+
+        y
+    }
+}
+
+fn filter_coefficients(rate: f64, kweighting: KWeighting) -> ([f64; 5], [f64; 5]) {
+    let Biquads {
+        mut pb,
+        mut pa,
+        mut rb,
+        mut ra,
+    } = biquads(rate);
+
+    // Replace the skipped stage by a pass-through biquad
+    match kweighting {
+        KWeighting::Full => (),
+        KWeighting::PreFilterOnly => {
+            rb = [1.0, 0.0, 0.0];
+            ra = [1.0, 0.0, 0.0];
+        }
+        KWeighting::RlbOnly => {
+            pb = [1.0, 0.0, 0.0];
+            pa = [1.0, 0.0, 0.0];
+        }
+    }
+
     (
         // Numerator
         [
@@ -109,6 +222,35 @@ fn filter_coefficients(rate: f64) -> ([f64; 5], [f64; 5]) {
     )
 }
 
+/// Check if both biquads of the BS.1770 filter are stable at the given sample rate, i.e. if all
+/// their poles are inside the unit circle.
+pub fn is_stable(rate: u32) -> bool {
+    // A biquad with denominator 1 + a1 z^-1 + a2 z^-2 is stable if (a1, a2) is inside the
+    // stability triangle.
+    fn biquad_is_stable(a: &[f64; 3]) -> bool {
+        a[1].is_finite() && a[2].is_finite() && a[2].abs() < 1.0 && a[1].abs() < 1.0 + a[2]
+    }
+
+    let Biquads { pa, ra, .. } = biquads(rate as f64);
+
+    biquad_is_stable(&pa) && biquad_is_stable(&ra)
+}
+
+/// Weight of the channel in the sum of all channels, or `None` if the channel has the default
+/// weight of 1.0.
+pub(crate) fn channel_weight(channel: Channel) -> Option<f64> {
+    match channel {
+        Channel::LeftSurround
+        | Channel::RightSurround
+        | Channel::Mp060
+        | Channel::Mm060
+        | Channel::Mp090
+        | Channel::Mm090 => Some(1.41),
+        Channel::DualMono => Some(2.0),
+        _ => None,
+    }
+}
+
 impl Filter {
     pub fn new(
         rate: u32,
@@ -119,7 +261,13 @@ impl Filter {
         assert!(rate > 0);
         assert!(channels > 0);
 
-        let (b, a) = filter_coefficients(rate as f64);
+        let (b, a) = filter_coefficients(rate as f64, KWeighting::Full);
+        trace!(
+            "Filter coefficients for {} Hz: b = {:?}, a = {:?}",
+            rate,
+            b,
+            a
+        );
 
         let tp = if calculate_true_peak {
             crate::true_peak::TruePeak::new(rate, channels)
@@ -132,26 +280,139 @@ impl Filter {
             b,
             a,
             filter_state: vec![[0.0; 5]; channels as usize].into_boxed_slice(),
+            highpass: None,
             calculate_sample_peak,
             sample_peak: vec![0.0; channels as usize].into_boxed_slice(),
             tp,
             true_peak: vec![0.0; channels as usize].into_boxed_slice(),
+            true_peak_window: None,
+            true_peak_threshold: None,
+            true_peak_over: None,
+        }
+    }
+
+    /// Heap memory in bytes that [`Filter::new`] allocates for the given configuration.
+    pub fn heap_size(rate: u32, channels: u32, calculate_true_peak: bool) -> usize {
+        // Filter state, sample peak and true peak
+        let mut size = channels as usize * (std::mem::size_of::<[f64; 5]>() + 2 * 8);
+        if calculate_true_peak {
+            size += crate::true_peak::TruePeak::heap_size(rate, channels);
         }
+        size
+    }
+
+    /// Recalculate the filter coefficients for the given filter stages and reset the filter state.
+    pub fn set_kweighting(&mut self, rate: u32, kweighting: KWeighting) {
+        let (b, a) = filter_coefficients(rate as f64, kweighting);
+        trace!(
+            "Filter coefficients for {} Hz with {:?}: b = {:?}, a = {:?}",
+            rate,
+            kweighting,
+            b,
+            a
+        );
+
+        self.b = b;
+        self.a = a;
+        self.filter_state.iter_mut().for_each(|f| f.fill(0.0));
+    }
+
+    /// Apply a high-pass filter with the given cutoff frequency in Hz before the BS.1770 filter,
+    /// or none if `None`.
+    pub fn set_highpass(&mut self, rate: u32, cutoff: Option<f64>) {
+        trace!("High-pass cutoff for {} Hz: {:?}", rate, cutoff);
+        self.highpass = cutoff.map(|cutoff| HighPass::new(rate as f64, cutoff, self.channels));
+    }
+
+    /// Additionally measure the sample and/or true peak from now on without resetting the filter
+    /// state.
+    pub fn enable_peaks(
+        &mut self,
+        rate: u32,
+        calculate_sample_peak: bool,
+        calculate_true_peak: bool,
+    ) {
+        self.calculate_sample_peak |= calculate_sample_peak;
+        if calculate_true_peak && self.tp.is_none() {
+            self.tp = crate::true_peak::TruePeak::new(rate, self.channels);
+        }
+    }
+
+    /// Keep the true peaks of the most recent `frames` frames, or none if `frames` is 0.
+    ///
+    /// If no interpolation is happening because of a high sample rate, the sample peaks are kept.
+    pub fn set_true_peak_window(&mut self, frames: usize) {
+        self.true_peak_window = if frames == 0 {
+            None
+        } else {
+            Some(crate::true_peak::PeakWindow::new(self.channels, frames))
+        };
+    }
+
+    /// Set the sample peak below which blocks are not oversampled for the true peak, or `None`
+    /// for oversampling all samples.
+    pub fn set_true_peak_threshold(&mut self, threshold: Option<f64>) {
+        self.true_peak_threshold = threshold;
+    }
+
+    /// Count the events where the true peak exceeds `ceiling`, or none if `None`.
+    ///
+    /// If no interpolation is happening because of a high sample rate, the sample peaks are
+    /// compared against the ceiling.
+    pub fn set_true_peak_over_ceiling(&mut self, ceiling: Option<f64>) {
+        self.true_peak_over =
+            ceiling.map(|ceiling| crate::true_peak::OverCounter::new(self.channels, ceiling));
+    }
+
+    /// Events where the true peak exceeded the ceiling since the last call to
+    /// [`Filter::reset_peaks`], or `None` if they are not counted.
+    pub fn true_peak_over_counts(&self) -> Option<&[u64]> {
+        self.true_peak_over.as_ref().map(|over| over.counts())
+    }
+
+    /// Maximum true peak of the most recent `frames` frames of the given channel, or `None` if no
+    /// true peaks are kept.
+    pub fn true_peak_window(&self, channel: usize, frames: usize) -> Option<f64> {
+        self.true_peak_window
+            .as_ref()
+            .map(|window| window.max(channel, frames))
     }
 
     pub fn reset_peaks(&mut self) {
         self.sample_peak.fill(0.0);
         self.true_peak.fill(0.0);
+        if let Some(ref mut over) = self.true_peak_over {
+            over.reset_counts();
+        }
+    }
+
+    pub fn reset_true_peak_window(&mut self) {
+        if let Some(ref mut window) = self.true_peak_window {
+            window.reset();
+        }
     }
 
     pub fn reset(&mut self) {
         self.reset_peaks();
+        self.reset_state();
+        self.reset_true_peak_window();
+    }
 
+    /// Reset the state of the filter and the true peak interpolator, but not the peaks.
+    pub fn reset_state(&mut self) {
         self.filter_state.iter_mut().for_each(|f| f.fill(0.0));
 
+        if let Some(ref mut highpass) = self.highpass {
+            highpass.state.iter_mut().for_each(|s| s.fill(0.0));
+        }
+
         if let Some(ref mut tp) = self.tp {
             tp.reset();
         }
+
+        if let Some(ref mut over) = self.true_peak_over {
+            over.reset();
+        }
     }
 
     pub fn sample_peak(&self) -> &[f64] {
@@ -162,6 +423,153 @@ impl Filter {
         &self.true_peak
     }
 
+    /// Latency of the true peak interpolator in input samples, or 0 if no interpolation is
+    /// happening.
+    pub fn true_peak_latency(&self) -> f64 {
+        self.tp
+            .as_ref()
+            .map(|tp| tp.latency_samples())
+            .unwrap_or(0.0)
+    }
+
+    /// Update the sample peak with the given samples without filtering them.
+    pub fn update_sample_peak<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(&mut self, src: &S) {
+        assert!(src.channels() == self.channels as usize);
+        assert!(self.sample_peak.len() == self.channels as usize);
+
+        for (c, sample_peak) in self.sample_peak.iter_mut().enumerate() {
+            let max = T::sample_peak(src, c);
+            if max > *sample_peak {
+                *sample_peak = max;
+            }
+        }
+    }
+
+    /// Update the sample and true peaks without filtering.
+    pub fn update_peaks<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(&mut self, src: S) {
+        assert!(src.channels() == self.channels as usize);
+
+        ftz::with_ftz(|_| {
+            if self.calculate_sample_peak && self.tp.is_none() {
+                self.update_sample_peak(&src);
+            }
+
+            self.update_true_peak(src);
+        });
+    }
+
+    /// Update the true peak, and the sample peak together with it if true peak is calculated.
+    fn update_true_peak<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(&mut self, src: S) {
+        let mut frame_peaks = (self.true_peak_window.as_mut(), self.true_peak_over.as_mut());
+        let frame_peaks_enabled = frame_peaks.0.is_some() || frame_peaks.1.is_some();
+
+        if let Some(ref mut tp) = self.tp {
+            assert!(self.true_peak.len() == self.channels as usize);
+            if frame_peaks_enabled {
+                let sample_peaks = if self.calculate_sample_peak {
+                    Some(&mut *self.sample_peak)
+                } else {
+                    None
+                };
+                tp.check_true_peak_windowed(
+                    src,
+                    &mut self.true_peak,
+                    sample_peaks,
+                    &mut frame_peaks,
+                );
+            } else if let Some(threshold) = self.true_peak_threshold {
+                let sample_peaks = if self.calculate_sample_peak {
+                    Some(&mut *self.sample_peak)
+                } else {
+                    None
+                };
+                Self::update_true_peak_thresholded(
+                    tp,
+                    src,
+                    &mut self.true_peak,
+                    sample_peaks,
+                    threshold,
+                );
+            } else if self.calculate_sample_peak {
+                assert!(self.sample_peak.len() == self.channels as usize);
+                tp.check_true_and_sample_peak(src, &mut self.true_peak, &mut self.sample_peak);
+            } else {
+                tp.check_true_peak(src, &mut self.true_peak);
+            }
+        } else if frame_peaks_enabled {
+            // Without interpolation the true peak is the sample peak
+            for c in 0..self.channels as usize {
+                let mut offset = 0;
+                src.foreach_sample(c, |sample| {
+                    let v = sample.as_f64_raw().abs() / T::MAX_AMPLITUDE;
+                    frame_peaks.set(c, offset, v as f32);
+                    offset += 1;
+                });
+            }
+            frame_peaks.advance(src.frames());
+        }
+    }
+
+    /// Update the true peaks and, if given, the sample peaks but only oversample blocks whose
+    /// sample peak reaches `threshold` in any channel. The true peak of all other blocks is
+    /// assumed to be their sample peak.
+    fn update_true_peak_thresholded<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        tp: &mut crate::true_peak::TruePeak,
+        mut src: S,
+        true_peaks: &mut [f64],
+        mut sample_peaks: Option<&mut [f64]>,
+        threshold: f64,
+    ) {
+        let channels = src.channels();
+        let mut block_peaks: SmallVec<[f64; 8]> = smallvec![0.0; channels];
+        let mut ignored_peaks: SmallVec<[f64; 8]> = smallvec![0.0; channels];
+
+        while src.frames() > 0 {
+            let block_frames = usize::min(src.frames(), TRUE_PEAK_THRESHOLD_BLOCK_FRAMES);
+            let (block, next) = src.split_at(block_frames);
+            src = next;
+
+            for (c, block_peak) in block_peaks.iter_mut().enumerate() {
+                let mut max = 0.0;
+                block.foreach_sample(c, |sample| {
+                    let v = sample.as_f64_raw().abs();
+                    if v > max {
+                        max = v;
+                    }
+                });
+                *block_peak = max / T::MAX_AMPLITUDE;
+            }
+
+            if let Some(ref mut sample_peaks) = sample_peaks {
+                for (sample_peak, block_peak) in
+                    Iterator::zip(sample_peaks.iter_mut(), block_peaks.iter())
+                {
+                    if *block_peak > *sample_peak {
+                        *sample_peak = *block_peak;
+                    }
+                }
+            }
+
+            if block_peaks.iter().any(|peak| *peak >= threshold) {
+                tp.check_true_peak(block, true_peaks);
+                continue;
+            }
+
+            // Only fill the history of the interpolator with the last frames of the block. The
+            // interpolated peaks are discarded as they also depend on the frames before.
+            let frames = block.frames();
+            let (_, history) = block.split_at(frames.saturating_sub(TRUE_PEAK_HISTORY_FRAMES));
+            tp.check_true_peak(history, &mut ignored_peaks);
+
+            for (true_peak, block_peak) in Iterator::zip(true_peaks.iter_mut(), block_peaks.iter())
+            {
+                if *block_peak > *true_peak {
+                    *true_peak = *block_peak;
+                }
+            }
+        }
+    }
+
     pub fn process<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
         &mut self,
         src: S,
@@ -175,26 +583,10 @@ impl Filter {
         assert!(self.filter_state.len() == self.channels as usize);
 
         ftz::with_ftz(|ftz| {
-            if self.calculate_sample_peak {
-                assert!(self.sample_peak.len() == self.channels as usize);
-
-                for (c, sample_peak) in self.sample_peak.iter_mut().enumerate() {
-                    let mut max = 0.0;
-
-                    assert!(c < src.channels());
-
-                    src.foreach_sample(c, |sample| {
-                        let v = sample.as_f64_raw().abs();
-                        if v > max {
-                            max = v;
-                        }
-                    });
-
-                    max /= T::MAX_AMPLITUDE;
-                    if max > *sample_peak {
-                        *sample_peak = max;
-                    }
-                }
+            // If true peak is calculated too then the sample peak is calculated together with it
+            // below to only iterate once over the samples.
+            if self.calculate_sample_peak && self.tp.is_none() {
+                self.update_sample_peak(&src);
             }
 
             let dest_stride = dest.len() / self.channels as usize;
@@ -213,6 +605,7 @@ impl Filter {
                     ref mut filter_state,
                     ref a,
                     ref b,
+                    ref mut highpass,
                     ..
                 } = *self;
                 let filter_state = &mut filter_state[c];
@@ -235,8 +628,20 @@ impl Filter {
                     filter_state[1] = filter_state[0];
                 });
 
+                // Both filters are linear and time-invariant, so filtering the output of the
+                // BS.1770 filter is the same as filtering its input. This keeps the loop above
+                // as fast as without the high-pass filter.
+                let highpass_state = highpass.as_mut().map(|highpass| {
+                    let hs = &mut highpass.state[c];
+                    for v in &mut dest[dest_index..dest_index + src.frames()] {
+                        *v = HighPass::filter(&highpass.b, &highpass.a, hs, *v);
+                    }
+                    hs
+                });
+
                 if ftz.is_none() {
-                    for v in filter_state {
+                    let highpass_state = highpass_state.map(|hs| &mut hs[..]).unwrap_or(&mut []);
+                    for v in filter_state.iter_mut().chain(highpass_state) {
                         if v.abs() < f64::EPSILON {
                             *v = 0.0;
                         }
@@ -244,13 +649,32 @@ impl Filter {
                 }
             }
 
-            if let Some(ref mut tp) = self.tp {
-                assert!(self.true_peak.len() == self.channels as usize);
-                tp.check_true_peak(src, &mut self.true_peak);
-            }
+            self.update_true_peak(src);
         });
     }
 
+    /// Apply the BS.1770 filter to a single sample.
+    #[inline]
+    fn kweight(filter_state: &mut [f64; 5], a: &[f64; 5], b: &[f64; 5], x: f64) -> f64 {
+        filter_state[0] = x
+            - a[1] * filter_state[1]
+            - a[2] * filter_state[2]
+            - a[3] * filter_state[3]
+            - a[4] * filter_state[4];
+        let y = b[0] * filter_state[0]
+            + b[1] * filter_state[1]
+            + b[2] * filter_state[2]
+            + b[3] * filter_state[3]
+            + b[4] * filter_state[4];
+
+        filter_state[4] = filter_state[3];
+        filter_state[3] = filter_state[2];
+        filter_state[2] = filter_state[1];
+        filter_state[1] = filter_state[0];
+
+        y
+    }
+
     pub fn seed<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
         &mut self,
         src: S,
@@ -271,25 +695,46 @@ impl Filter {
                 let Filter {
                     ref mut filter_state,
                     ref a,
+                    ref b,
+                    ref mut highpass,
                     ..
                 } = *self;
                 let filter_state = &mut filter_state[c];
 
-                src.foreach_sample(c, |src| {
-                    filter_state[0] = (*src).to_sample::<f64>()
-                        - a[1] * filter_state[1]
-                        - a[2] * filter_state[2]
-                        - a[3] * filter_state[3]
-                        - a[4] * filter_state[4];
-
-                    filter_state[4] = filter_state[3];
-                    filter_state[3] = filter_state[2];
-                    filter_state[2] = filter_state[1];
-                    filter_state[1] = filter_state[0];
-                });
+                // The high-pass filter needs the output of the BS.1770 filter, see `process`
+                let highpass_state = match *highpass {
+                    None => {
+                        src.foreach_sample(c, |src| {
+                            filter_state[0] = (*src).to_sample::<f64>()
+                                - a[1] * filter_state[1]
+                                - a[2] * filter_state[2]
+                                - a[3] * filter_state[3]
+                                - a[4] * filter_state[4];
+
+                            filter_state[4] = filter_state[3];
+                            filter_state[3] = filter_state[2];
+                            filter_state[2] = filter_state[1];
+                            filter_state[1] = filter_state[0];
+                        });
+                        None
+                    }
+                    Some(HighPass {
+                        b: ref hb,
+                        a: ref ha,
+                        ref mut state,
+                    }) => {
+                        let hs = &mut state[c];
+                        src.foreach_sample(c, |src| {
+                            let y = Self::kweight(filter_state, a, b, (*src).to_sample::<f64>());
+                            HighPass::filter(hb, ha, hs, y);
+                        });
+                        Some(hs)
+                    }
+                };
 
                 if ftz.is_none() {
-                    for v in filter_state {
+                    let highpass_state = highpass_state.map(|hs| &mut hs[..]).unwrap_or(&mut []);
+                    for v in filter_state.iter_mut().chain(highpass_state) {
                         if v.abs() < f64::EPSILON {
                             *v = 0.0;
                         }
@@ -328,48 +773,168 @@ impl Filter {
             }
 
             assert!(c < channels);
-            assert!(audio_data_index <= audio_data.len());
 
-            let mut channel_sum = 0.0;
+            let mut channel_sum =
+                Self::channel_block_sum(frames_per_block, audio_data, audio_data_index);
 
-            // XXX: Don't use channel_sum += sum() here because that gives slightly different
-            // results than the C version because of rounding errors
-            if audio_data_index < frames_per_block {
-                for frame in &audio_data[..audio_data_index] {
-                    channel_sum += *frame * *frame;
-                }
+            if let Some(weight) = channel_weight(*channel) {
+                channel_sum *= weight;
+            }
 
-                for frame in &audio_data[(audio_data.len() - frames_per_block + audio_data_index)..]
-                {
-                    channel_sum += *frame * *frame;
-                }
-            } else {
-                for frame in &audio_data[(audio_data_index - frames_per_block)..audio_data_index] {
-                    channel_sum += *frame * *frame;
+            sum += channel_sum;
+        }
+
+        sum /= frames_per_block as f64;
+
+        sum
+    }
+
+    /// Like [`Filter::calc_gating_block`] but adds the unweighted energy of each channel
+    /// separately to `energies`, with zero for unused channels.
+    pub fn calc_channel_gating_blocks(
+        frames_per_block: usize,
+        audio_data: &[f64],
+        audio_data_index: usize,
+        channel_map: &[Channel],
+        energies: &mut impl Extend<f64>,
+    ) {
+        let channels = channel_map.len();
+        assert!(audio_data.len() % channels == 0);
+        let audio_data_stride = audio_data.len() / channels;
+        assert!(audio_data_index <= audio_data_stride);
+
+        energies.extend(
+            Iterator::zip(
+                channel_map.iter(),
+                audio_data.chunks_exact(audio_data_stride),
+            )
+            .map(|(channel, audio_data)| {
+                if *channel == Channel::Unused {
+                    return 0.0;
                 }
+
+                let channel_sum =
+                    Self::channel_block_sum(frames_per_block, audio_data, audio_data_index);
+
+                channel_sum / frames_per_block as f64
+            }),
+        );
+    }
+
+    /// Sum of the squared samples of the block of one channel that ends at `audio_data_index`.
+    fn channel_block_sum(
+        frames_per_block: usize,
+        audio_data: &[f64],
+        audio_data_index: usize,
+    ) -> f64 {
+        assert!(audio_data_index <= audio_data.len());
+
+        let mut channel_sum = 0.0;
+
+        // XXX: Don't use channel_sum += sum() here because that gives slightly different
+        // results than the C version because of rounding errors
+        if audio_data_index < frames_per_block {
+            for frame in &audio_data[..audio_data_index] {
+                channel_sum += *frame * *frame;
+            }
+
+            for frame in &audio_data[(audio_data.len() - frames_per_block + audio_data_index)..] {
+                channel_sum += *frame * *frame;
+            }
+        } else {
+            for frame in &audio_data[(audio_data_index - frames_per_block)..audio_data_index] {
+                channel_sum += *frame * *frame;
+            }
+        }
+
+        channel_sum
+    }
+
+    /// Copy already filtered samples to `dest` like [`Filter::process`] would do after filtering.
+    pub fn copy<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        src: S,
+        dest: &mut [f64],
+        dest_index: usize,
+        channel_map: &[Channel],
+    ) {
+        let channels = channel_map.len();
+        assert!(dest.len() % channels == 0);
+        assert!(src.channels() == channels);
+
+        let dest_stride = dest.len() / channels;
+        assert!(dest_index + src.frames() <= dest_stride);
+
+        for (c, (channel_map, dest)) in
+            Iterator::zip(channel_map.iter(), dest.chunks_exact_mut(dest_stride)).enumerate()
+        {
+            if *channel_map == Channel::Unused {
+                continue;
+            }
+
+            src.foreach_sample_zipped(c, dest[dest_index..].iter_mut(), |src, dest| {
+                *dest = src.to_sample::<f64>();
+            });
+        }
+    }
+
+    /// Like [`Filter::calc_gating_block`] but for multiple block lengths at once, sharing the sums
+    /// of the overlapping parts of the blocks.
+    pub fn calc_gating_blocks(
+        frames_per_block: &[usize],
+        audio_data: &[f64],
+        audio_data_index: usize,
+        channel_map: &[Channel],
+    ) -> Vec<f64> {
+        let channels = channel_map.len();
+        assert!(audio_data.len() % channels == 0);
+        let audio_data_stride = audio_data.len() / channels;
+        assert!(audio_data_index <= audio_data_stride);
+        assert!(frames_per_block.iter().all(|f| *f <= audio_data_stride));
+
+        // Visit the block lengths in ascending order
+        let mut order = (0..frames_per_block.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| frames_per_block[*i]);
+
+        let mut sums = vec![0.0; frames_per_block.len()];
+
+        for (channel, audio_data) in Iterator::zip(
+            channel_map.iter(),
+            audio_data.chunks_exact(audio_data_stride),
+        ) {
+            if *channel == Channel::Unused {
+                continue;
             }
 
-            match channel {
-                Channel::LeftSurround
-                | Channel::RightSurround
-                | Channel::Mp060
-                | Channel::Mm060
-                | Channel::Mp090
-                | Channel::Mm090 => {
-                    channel_sum *= 1.41;
+            let weight = channel_weight(*channel).unwrap_or(1.0);
+
+            // Go backwards from the most recent frame and remember the sum whenever the next
+            // block length is reached.
+            let mut channel_sum = 0.0;
+            let mut order = order.iter().peekable();
+            let recent_first = Iterator::chain(
+                audio_data[..audio_data_index].iter().rev(),
+                audio_data[audio_data_index..].iter().rev(),
+            );
+            for (frames, frame) in recent_first.enumerate() {
+                while let Some(i) = order.next_if(|i| frames_per_block[**i] == frames) {
+                    sums[*i] += channel_sum * weight;
                 }
-                Channel::DualMono => {
-                    channel_sum *= 2.0;
+                if order.peek().is_none() {
+                    break;
                 }
-                _ => (),
-            }
 
-            sum += channel_sum;
+                channel_sum += *frame * *frame;
+            }
+            for i in order {
+                sums[*i] += channel_sum * weight;
+            }
         }
 
-        sum /= frames_per_block as f64;
+        for (sum, frames) in Iterator::zip(sums.iter_mut(), frames_per_block.iter()) {
+            *sum /= *frames as f64;
+        }
 
-        sum
+        sums
     }
 }
 
@@ -481,6 +1046,25 @@ extern "C" {
     ) -> f64;
 }
 
+#[cfg(test)]
+mod biquads_tests {
+    use super::*;
+
+    #[test]
+    fn precomputed_biquads() {
+        // The precomputed biquads must be exactly the same as the calculated ones, otherwise
+        // `examples/generate_biquads.rs` needs to be run again
+        for rate in crate::biquads::RATES.iter() {
+            let precomputed = biquads(*rate as f64);
+            let calculated = calc_biquads(*rate as f64);
+            assert_eq!(precomputed.pb, calculated.pb, "{}", rate);
+            assert_eq!(precomputed.pa, calculated.pa, "{}", rate);
+            assert_eq!(precomputed.rb, calculated.rb, "{}", rate);
+            assert_eq!(precomputed.ra, calculated.ra, "{}", rate);
+        }
+    }
+}
+
 #[cfg(feature = "c-tests")]
 #[cfg(test)]
 mod tests {
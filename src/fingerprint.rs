@@ -0,0 +1,316 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use crate::{EbuR128, Error};
+
+/// Number of bins of the coarse loudness histogram of a
+/// [`LoudnessFingerprint`](struct.LoudnessFingerprint.html).
+const HISTOGRAM_BINS: usize = 12;
+/// Width of each histogram bin in LU.
+const HISTOGRAM_BIN_WIDTH: f64 = 2.0;
+/// Start of the first histogram bin in LU relative to the integrated loudness.
+const HISTOGRAM_START: f64 = -20.0;
+
+/// Quantized value of a value that is not available.
+const NOT_AVAILABLE: u8 = u8::MAX;
+/// Distance contributed by a value that is only available in one of two fingerprints.
+const NOT_AVAILABLE_DISTANCE: f64 = 10.0;
+/// Distance contributed by completely different histograms.
+const HISTOGRAM_DISTANCE: f64 = 10.0;
+
+/// Compact descriptor of the loudness characteristics of audio, e.g. for finding duplicates or
+/// clustering tracks.
+///
+/// The fingerprint is a fixed-size byte array that can be stored, compared and hashed. Its
+/// layout in version 1 is:
+///
+/// | Byte   | Content                                                                  |
+/// |--------|--------------------------------------------------------------------------|
+/// | 0      | Version, currently 1                                                     |
+/// | 1      | Global integrated loudness in steps of 0.5 LU from -70 LUFS              |
+/// | 2      | Loudness range in steps of 0.25 LU from 0 LU                             |
+/// | 3      | Maximum momentary loudness in steps of 0.5 LU from -70 LUFS              |
+/// | 4      | Crest factor, i.e. the peak to loudness ratio, in steps of 0.25 dB       |
+/// | 5..17  | Fraction of the gating blocks in 2 LU wide bins, in steps of 1/255       |
+///
+/// Values that are not available, e.g. because the required mode is not set or only silence
+/// was processed, are stored as 255. Other values are rounded to the nearest step and clamped
+/// to the range of 0 to 254 steps. The histogram bins cover the loudness range from 20 LU below
+/// to 4 LU above the integrated loudness, and blocks outside of it are counted in the first or
+/// last bin. All histogram bins are 0 if the integrated loudness is not available.
+///
+/// Fingerprints with the same bytes describe audio with the same loudness characteristics
+/// within the precision of the steps. Use
+/// [`LoudnessFingerprint::distance`](#method.distance) for a measure of how similar they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LoudnessFingerprint {
+    bytes: [u8; LoudnessFingerprint::SIZE],
+}
+
+impl LoudnessFingerprint {
+    /// Size of a fingerprint in bytes.
+    pub const SIZE: usize = 5 + HISTOGRAM_BINS;
+    /// Version of the layout of the fingerprints created by this crate.
+    pub const VERSION: u8 = 1;
+
+    /// Create a fingerprint of the current measurements of `ebu`.
+    ///
+    /// The loudness values and the histogram require `Mode::I`, the loudness range `Mode::LRA`
+    /// and the crest factor `Mode::SAMPLE_PEAK`, see
+    /// [`EbuR128::crest_factor_db`](struct.EbuR128.html#method.crest_factor_db). The maximum
+    /// momentary loudness is the one of the loudest gating block in the history.
+    pub fn new(ebu: &EbuR128) -> Self {
+        let loudness = ebu
+            .loudness_global()
+            .ok()
+            .filter(|loudness| loudness.is_finite());
+
+        let mut bytes = [0; Self::SIZE];
+        bytes[0] = Self::VERSION;
+        bytes[1] = quantize(loudness, -70.0, 0.5);
+        bytes[2] = quantize(ebu.loudness_range().ok(), 0.0, 0.25);
+        bytes[3] = quantize(ebu.max_block_loudness().ok(), -70.0, 0.5);
+        bytes[4] = quantize(ebu.crest_factor_db().ok().flatten(), 0.0, 0.25);
+
+        if let Some(loudness) = loudness {
+            // Bins of 1 LU that are merged into the coarse bins
+            let histogram = ebu.loudness_histogram(100).unwrap();
+            let mut counts = [0u64; HISTOGRAM_BINS];
+            for (center, count) in histogram {
+                let bin = (center - loudness - HISTOGRAM_START) / HISTOGRAM_BIN_WIDTH;
+                let bin = f64::max(bin, 0.0) as usize;
+                counts[usize::min(bin, HISTOGRAM_BINS - 1)] += count;
+            }
+
+            let total = counts.iter().sum::<u64>();
+            if total > 0 {
+                for (byte, count) in Iterator::zip(bytes[5..].iter_mut(), counts.iter()) {
+                    *byte = (*count as f64 / total as f64 * 255.0).round() as u8;
+                }
+            }
+        }
+
+        LoudnessFingerprint { bytes }
+    }
+
+    /// Create a fingerprint from its bytes, e.g. as stored from
+    /// [`LoudnessFingerprint::as_bytes`](#method.as_bytes).
+    ///
+    /// Returns `Error::InvalidMode` if the version is not supported.
+    pub fn from_bytes(bytes: [u8; Self::SIZE]) -> Result<Self, Error> {
+        if bytes[0] != Self::VERSION {
+            return Err(Error::InvalidMode);
+        }
+
+        Ok(LoudnessFingerprint { bytes })
+    }
+
+    /// Get the bytes of the fingerprint.
+    pub fn as_bytes(&self) -> &[u8; Self::SIZE] {
+        &self.bytes
+    }
+
+    /// Get the version of the layout of the fingerprint.
+    pub fn version(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    /// Get the quantized global integrated loudness in LUFS.
+    pub fn loudness_global(&self) -> Option<f64> {
+        dequantize(self.bytes[1], -70.0, 0.5)
+    }
+
+    /// Get the quantized loudness range in LU.
+    pub fn loudness_range(&self) -> Option<f64> {
+        dequantize(self.bytes[2], 0.0, 0.25)
+    }
+
+    /// Get the quantized maximum momentary loudness in LUFS.
+    pub fn loudness_momentary_max(&self) -> Option<f64> {
+        dequantize(self.bytes[3], -70.0, 0.5)
+    }
+
+    /// Get the quantized crest factor in dB.
+    pub fn crest_factor_db(&self) -> Option<f64> {
+        dequantize(self.bytes[4], 0.0, 0.25)
+    }
+
+    /// Get the fraction of the gating blocks in each histogram bin, from the quietest to the
+    /// loudest bin.
+    pub fn histogram(&self) -> [f64; HISTOGRAM_BINS] {
+        let mut histogram = [0.0; HISTOGRAM_BINS];
+        for (fraction, byte) in Iterator::zip(histogram.iter_mut(), self.bytes[5..].iter()) {
+            *fraction = *byte as f64 / 255.0;
+        }
+
+        histogram
+    }
+
+    /// Calculate how different the loudness characteristics of two fingerprints are.
+    ///
+    /// The distance is the sum of the absolute differences of the quantized values in LU or dB.
+    /// A value that is only available in one of the fingerprints adds 10, and the histograms add
+    /// up to 10 depending on the fraction of the gating blocks that are in different bins. The
+    /// distance is 0 for identical fingerprints and symmetric, so a threshold of a few LU
+    /// finds audio with similar loudness characteristics.
+    pub fn distance(&self, other: &Self) -> f64 {
+        let values = [
+            (self.loudness_global(), other.loudness_global()),
+            (self.loudness_range(), other.loudness_range()),
+            (
+                self.loudness_momentary_max(),
+                other.loudness_momentary_max(),
+            ),
+            (self.crest_factor_db(), other.crest_factor_db()),
+        ];
+
+        let mut distance = 0.0;
+        for value in values {
+            distance += match value {
+                (Some(a), Some(b)) => f64::abs(a - b),
+                (None, None) => 0.0,
+                _ => NOT_AVAILABLE_DISTANCE,
+            };
+        }
+
+        let histogram_difference = Iterator::zip(self.histogram().iter(), other.histogram().iter())
+            .map(|(a, b)| f64::abs(a - b))
+            .sum::<f64>();
+
+        distance + histogram_difference / 2.0 * HISTOGRAM_DISTANCE
+    }
+}
+
+/// Quantize `value` to steps of `step` starting at `min`.
+fn quantize(value: Option<f64>, min: f64, step: f64) -> u8 {
+    match value {
+        Some(value) if value.is_finite() => f64::clamp(
+            ((value - min) / step).round(),
+            0.0,
+            (NOT_AVAILABLE - 1) as f64,
+        ) as u8,
+        _ => NOT_AVAILABLE,
+    }
+}
+
+/// Inverse of [`quantize`].
+fn dequantize(value: u8, min: f64, step: f64) -> Option<f64> {
+    if value == NOT_AVAILABLE {
+        None
+    } else {
+        Some(min + value as f64 * step)
+    }
+}
+
+impl EbuR128 {
+    /// Get a fingerprint of the loudness characteristics, see
+    /// [`LoudnessFingerprint`](struct.LoudnessFingerprint.html).
+    pub fn fingerprint(&self) -> LoudnessFingerprint {
+        LoudnessFingerprint::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mode;
+    use float_eq::assert_float_eq;
+
+    fn sine_f32(seconds: usize, amplitude: f32) -> Vec<f32> {
+        (0..48_000 * seconds)
+            .flat_map(|i| {
+                let v = f32::sin(2.0 * std::f32::consts::PI * 440.0 * i as f32 / 48_000.0);
+                [v * amplitude; 2]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fingerprint() {
+        let mode = Mode::I | Mode::LRA | Mode::SAMPLE_PEAK;
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu.add_frames_f32(&sine_f32(5, 1.0)).unwrap();
+
+        let fingerprint = ebu.fingerprint();
+        assert_eq!(fingerprint.version(), LoudnessFingerprint::VERSION);
+        assert_float_eq!(
+            fingerprint.loudness_global().unwrap(),
+            ebu.loudness_global().unwrap(),
+            abs <= 0.25
+        );
+        assert_float_eq!(fingerprint.loudness_range().unwrap(), 0.0, abs <= 0.25);
+        assert_float_eq!(
+            fingerprint.loudness_momentary_max().unwrap(),
+            ebu.loudness_global().unwrap(),
+            abs <= 0.25
+        );
+        assert_float_eq!(
+            fingerprint.crest_factor_db().unwrap(),
+            ebu.crest_factor_db().unwrap().unwrap(),
+            abs <= 0.125
+        );
+        // All blocks have the integrated loudness
+        let mut histogram = [0.0; HISTOGRAM_BINS];
+        histogram[10] = 1.0;
+        assert_eq!(fingerprint.histogram(), histogram);
+
+        assert_eq!(
+            LoudnessFingerprint::from_bytes(*fingerprint.as_bytes()),
+            Ok(fingerprint)
+        );
+        let mut bytes = *fingerprint.as_bytes();
+        bytes[0] = 0;
+        assert_eq!(
+            LoudnessFingerprint::from_bytes(bytes),
+            Err(Error::InvalidMode)
+        );
+
+        // Same audio, same fingerprint
+        let mut same = EbuR128::new(2, 48_000, mode).unwrap();
+        same.add_frames_f32(&sine_f32(5, 1.0)).unwrap();
+        assert_eq!(same.fingerprint(), fingerprint);
+        assert_eq!(fingerprint.distance(&same.fingerprint()), 0.0);
+
+        // 10 dB quieter: only the loudness values differ
+        let mut quieter = EbuR128::new(2, 48_000, mode).unwrap();
+        quieter
+            .add_frames_f32(&sine_f32(5, f32::powf(10.0, -0.5)))
+            .unwrap();
+        let distance = fingerprint.distance(&quieter.fingerprint());
+        assert_eq!(distance, quieter.fingerprint().distance(&fingerprint));
+        assert_float_eq!(distance, 20.0, abs <= 1.0);
+
+        // Silence and no integrated loudness
+        let mut silence = EbuR128::new(2, 48_000, mode).unwrap();
+        silence.add_frames_f32(&sine_f32(5, 0.0)).unwrap();
+        let silence = silence.fingerprint();
+        assert_eq!(silence.loudness_global(), None);
+        assert_eq!(silence.histogram(), [0.0; HISTOGRAM_BINS]);
+        // Three values only available in one fingerprint, the same loudness range of 0 LU and
+        // histograms without common bins
+        assert_eq!(silence.loudness_range(), Some(0.0));
+        assert_float_eq!(fingerprint.distance(&silence), 35.0, abs <= 0.25);
+
+        let ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        let fingerprint = ebu.fingerprint();
+        assert_eq!(&fingerprint.as_bytes()[1..5], &[NOT_AVAILABLE; 4]);
+    }
+}
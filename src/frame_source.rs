@@ -0,0 +1,66 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+/// Trait for sources of audio frames, e.g. decoded frames of a media framework.
+///
+/// Implementing this trait once for a custom frame type allows passing it directly to
+/// [`EbuR128::add_frames_from`](crate::EbuR128::add_frames_from).
+pub trait FrameSource {
+    /// Iterator over the interleaved samples.
+    type Samples: Iterator<Item = f64>;
+
+    /// Number of channels of the frames, or `None` if unknown.
+    ///
+    /// If known, this must match the number of channels of the `EbuR128` instance.
+    fn channels(&self) -> Option<u32> {
+        None
+    }
+
+    /// Sample rate of the frames, or `None` if unknown.
+    ///
+    /// If known, this must match the sample rate of the `EbuR128` instance.
+    fn rate(&self) -> Option<u32> {
+        None
+    }
+
+    /// Interleaved samples, normalized to the range `[-1.0, 1.0]`.
+    fn into_samples(self) -> Self::Samples;
+}
+
+macro_rules! impl_frame_source_for_slice {
+    ($t:ty) => {
+        impl<'a> FrameSource for &'a [$t] {
+            type Samples =
+                std::iter::Map<std::iter::Copied<std::slice::Iter<'a, $t>>, fn($t) -> f64>;
+
+            fn into_samples(self) -> Self::Samples {
+                self.iter()
+                    .copied()
+                    .map(dasp_sample::Sample::to_sample::<f64> as fn($t) -> f64)
+            }
+        }
+    };
+}
+
+impl_frame_source_for_slice!(i16);
+impl_frame_source_for_slice!(i32);
+impl_frame_source_for_slice!(f32);
+impl_frame_source_for_slice!(f64);
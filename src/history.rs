@@ -21,6 +21,7 @@
 
 use crate::{energy_to_loudness, Error};
 
+use std::borrow::Cow;
 use std::collections::VecDeque;
 use std::fmt;
 
@@ -28,14 +29,26 @@ use std::fmt;
 use crate::histogram_bins::BOUNDARIES as HISTOGRAM_BOUNDARIES;
 use crate::histogram_bins::ENERGIES as HISTOGRAM_ENERGIES;
 
-fn find_histogram_index(energy: f64) -> usize {
+/// Default number of histogram bins, i.e. a resolution of 0.1 LU.
+pub const DEFAULT_HISTOGRAM_BINS: usize = 1000;
+
+/// Range covered by the histogram bins in LU, i.e. from -70 LUFS to +30 LUFS.
+const HISTOGRAM_RANGE: f64 = 100.0;
+
+/// Index of the value at `percentile` (between 0.0 and 1.0) of `size` sorted values, rounded to
+/// the nearest index. Ties are rounded up. `size` must not be 0.
+fn percentile_index(size: u64, percentile: f64) -> u64 {
+    ((size - 1) as f64 * percentile + 0.5) as u64
+}
+
+fn find_histogram_index(boundaries: &[f64], energy: f64) -> usize {
     let mut min = 0;
-    let mut max = 1000;
+    let mut max = boundaries.len() - 1;
 
     // Binary search
     loop {
         let mid = (min + max) / 2;
-        if energy >= HISTOGRAM_BOUNDARIES[mid] {
+        if energy >= boundaries[mid] {
             min = mid;
         } else {
             max = mid;
@@ -49,44 +62,190 @@ fn find_histogram_index(energy: f64) -> usize {
     min
 }
 
-/// Histogram of measured energies. See HISTOGRAM_BOUNDARIES and HISTOGRAM_ENERGIES for
-/// the bins of the histogram.
-pub struct Histogram(Box<[u64; 1000]>);
+/// Sum that is either calculated naively like in libebur128 or with Neumaier's compensation of
+/// the rounding errors.
+///
+/// Summing the energies of millions of blocks of a long stream naively accumulates rounding
+/// errors, e.g. the mean of a constant energy would drift away from the energy itself. The
+/// compensated sum stays within a rounding error of the exact sum independent of the number of
+/// values, but its results differ slightly from the ones of libebur128.
+#[derive(Debug, Clone, Copy)]
+struct StableSum {
+    sum: f64,
+    /// Rounding errors of the additions to `sum` so far.
+    compensation: f64,
+    /// Whether the rounding errors are compensated.
+    compensated: bool,
+}
+
+impl StableSum {
+    fn new(compensated: bool) -> Self {
+        Self::with_value(0.0, compensated)
+    }
+
+    fn with_value(value: f64, compensated: bool) -> Self {
+        StableSum {
+            sum: value,
+            compensation: 0.0,
+            compensated,
+        }
+    }
+
+    /// Sum of all `values`.
+    fn of(values: impl IntoIterator<Item = f64>, compensated: bool) -> Self {
+        let mut sum = StableSum::new(compensated);
+        for value in values {
+            sum.add(value);
+        }
+        sum
+    }
+
+    fn add(&mut self, value: f64) {
+        if !self.compensated {
+            self.sum += value;
+            return;
+        }
+
+        let sum = self.sum + value;
+        if self.sum.abs() >= value.abs() {
+            self.compensation += (self.sum - sum) + value;
+        } else {
+            self.compensation += (value - sum) + self.sum;
+        }
+        self.sum = sum;
+    }
+
+    /// Add the product `a * b`, including its rounding error if compensated.
+    fn add_product(&mut self, a: f64, b: f64) {
+        let product = a * b;
+        self.add(product);
+        if self.compensated && product.is_finite() {
+            self.compensation += f64::mul_add(a, b, -product);
+        }
+    }
+
+    fn merge(&mut self, other: &StableSum) {
+        self.add(other.sum);
+        self.compensation += other.compensation;
+    }
+
+    fn value(&self) -> f64 {
+        // The compensation is NaN once an infinite value was added
+        if self.compensated && self.sum.is_finite() {
+            self.sum + self.compensation
+        } else {
+            self.sum
+        }
+    }
+
+    /// Mean of the `count` summed values.
+    ///
+    /// If compensated, the quotient is corrected by its remainder so that e.g. the mean of a
+    /// constant value is exactly that value, which is not the case for `value() / count` in
+    /// general.
+    fn mean(&self, count: u64) -> f64 {
+        let count = count as f64;
+        let mean = self.value() / count;
+        if !self.compensated || !mean.is_finite() {
+            return mean;
+        }
+
+        let remainder = f64::mul_add(-mean, count, self.sum) + self.compensation;
+        mean + remainder / count
+    }
+}
+
+/// Histogram of measured energies. `energies` and `boundaries` are the bins of the histogram,
+/// which are HISTOGRAM_ENERGIES and HISTOGRAM_BOUNDARIES with the default number of bins.
+pub struct Histogram {
+    counts: Box<[u64]>,
+    /// Energy at the center of each bin.
+    energies: Cow<'static, [f64]>,
+    /// Energies between each bin, one more than there are bins.
+    boundaries: Cow<'static, [f64]>,
+    /// Whether the sums of the energies are compensated.
+    compensated: bool,
+}
 
 impl Histogram {
-    fn new() -> Self {
-        Histogram(Box::new([0; 1000]))
+    fn new(bins: usize) -> Self {
+        assert!(bins > 0);
+
+        let (energies, boundaries) = if bins == DEFAULT_HISTOGRAM_BINS {
+            (
+                Cow::Borrowed(&HISTOGRAM_ENERGIES[..]),
+                Cow::Borrowed(&HISTOGRAM_BOUNDARIES[..]),
+            )
+        } else {
+            // Same as in examples/generate_histogram_bins.rs but with a different resolution
+            let step = HISTOGRAM_RANGE / bins as f64;
+            let energies = (0..bins)
+                .map(|i| f64::powf(10.0, ((i as f64 + 0.5) * step - 70.0 + 0.691) / 10.0))
+                .collect::<Vec<_>>();
+            let boundaries = (0..=bins)
+                .map(|i| f64::powf(10.0, (i as f64 * step - 70.0 + 0.691) / 10.0))
+                .collect::<Vec<_>>();
+            (Cow::Owned(energies), Cow::Owned(boundaries))
+        };
+
+        Histogram {
+            counts: vec![0; bins].into_boxed_slice(),
+            energies,
+            boundaries,
+            compensated: false,
+        }
     }
 
     fn add(&mut self, energy: f64) {
-        let idx = find_histogram_index(energy);
-        self.0[idx] += 1;
+        let idx = find_histogram_index(&self.boundaries, energy);
+        self.counts[idx] += 1;
+    }
+
+    /// Add `count` times the same energy.
+    fn add_count(&mut self, energy: f64, count: u64) {
+        let idx = find_histogram_index(&self.boundaries, energy);
+        self.counts[idx] += count;
     }
 
     fn reset(&mut self) {
-        self.0.fill(0);
+        self.counts.fill(0);
+    }
+
+    /// Index of the first bin whose energy is not below `threshold`.
+    fn start_index(&self, threshold: f64) -> usize {
+        if threshold < self.boundaries[0] {
+            0
+        } else {
+            let index = find_histogram_index(&self.boundaries, threshold);
+            if threshold > self.energies[index] {
+                index + 1
+            } else {
+                index
+            }
+        }
     }
 
-    fn calc_relative_threshold(&self) -> (u64, f64) {
+    fn calc_relative_threshold(&self) -> (u64, StableSum) {
         let mut above_thresh_counter = 0;
-        let mut relative_threshold = 0.0;
+        let mut relative_threshold = StableSum::new(self.compensated);
 
-        for (count, energy) in Iterator::zip(self.0.iter(), HISTOGRAM_ENERGIES.iter()) {
-            relative_threshold += *count as f64 * *energy;
+        for (count, energy) in Iterator::zip(self.counts.iter(), self.energies.iter()) {
+            relative_threshold.add_product(*count as f64, *energy);
             above_thresh_counter += *count;
         }
 
         (above_thresh_counter, relative_threshold)
     }
 
-    fn loudness_range(h: &[u64; 1000]) -> f64 {
-        let mut h_sum = [0; 1000];
+    /// Loudness range of the histogram counts `h`, which have the same bins as `self`.
+    fn loudness_range(&self, h: &[u64]) -> f64 {
+        let mut h_sum = vec![0; h.len()];
         let mut size = 0;
         let mut power = 0.0;
 
         for ((count, count_sum), energy) in Iterator::zip(
             Iterator::zip(h.iter(), h_sum.iter_mut()),
-            HISTOGRAM_ENERGIES.iter(),
+            self.energies.iter(),
         ) {
             size += *count;
             *count_sum = size;
@@ -105,16 +264,7 @@ impl Histogram {
         let minus_twenty_decibels = f64::powf(10.0, -20.0 / 10.0);
         let integrated = minus_twenty_decibels * power;
 
-        let index = if integrated < HISTOGRAM_BOUNDARIES[0] {
-            0
-        } else {
-            let index = find_histogram_index(integrated);
-            if integrated > HISTOGRAM_ENERGIES[index] {
-                index + 1
-            } else {
-                index
-            }
-        };
+        let index = self.start_index(integrated);
         let before = if let Some(prev_index) = index.checked_sub(1) {
             h_sum.get(prev_index).cloned().unwrap_or(0)
         } else {
@@ -125,8 +275,8 @@ impl Histogram {
             return 0.0;
         }
 
-        let percentile_low = ((size - 1) as f64 * 0.1 + 0.5) as u64 + before;
-        let percentile_high = ((size - 1) as f64 * 0.95 + 0.5) as u64 + before;
+        let percentile_low = percentile_index(size, 0.1) + before;
+        let percentile_high = percentile_index(size, 0.95) + before;
 
         let j = h_sum[index..]
             .binary_search(&(percentile_low + 1))
@@ -138,7 +288,7 @@ impl Histogram {
             Some(j) => j + 1,
             None => 0,
         };
-        let l_en = HISTOGRAM_ENERGIES[j];
+        let l_en = self.energies[j];
 
         let j = h_sum[index..]
             .binary_search(&(percentile_high + 1))
@@ -150,7 +300,351 @@ impl Histogram {
             Some(j) => j + 1,
             None => 0,
         };
-        let h_en = HISTOGRAM_ENERGIES[j];
+        let h_en = self.energies[j];
+
+        energy_to_loudness(h_en) - energy_to_loudness(l_en)
+    }
+}
+
+/// Index of a missing child in [`SortedEnergies`].
+const NIL: u32 = u32::MAX;
+
+/// Initial state of the priority generator of [`SortedEnergies`], so that the same energies
+/// always give the same tree and the same sums.
+const PRIORITY_SEED: u32 = 0x9E37_79B9;
+
+/// Node of the treap of [`SortedEnergies`].
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    energy: f64,
+    /// Random heap priority that keeps the tree balanced.
+    priority: u32,
+    left: u32,
+    right: u32,
+    /// Number of energies in the subtree.
+    size: u64,
+    /// Sum of the energies in the subtree.
+    sum: StableSum,
+}
+
+/// Sorted copy of the energies of a queue, allowing to calculate the loudness range without
+/// sorting all energies every time.
+///
+/// The energies are kept in a treap, i.e. a binary search tree balanced by random priorities,
+/// whose nodes also store the number and the sum of the energies in their subtree. Inserting and
+/// removing an energy, finding the energy at a rank and summing all energies above a threshold
+/// are all O(log n). The nodes are kept in a vector and removed nodes are reused, so that a full
+/// queue doesn't allocate anymore.
+struct SortedEnergies {
+    nodes: Vec<Node>,
+    /// First removed node in `nodes`, which are linked by their `left` index.
+    free: u32,
+    /// Number of removed nodes.
+    free_count: usize,
+    root: u32,
+    /// Number of NaN energies, which are not part of the tree.
+    nan_count: usize,
+    /// State of the xorshift generator for the priorities.
+    seed: u32,
+    /// Whether the sums of the energies are compensated.
+    compensated: bool,
+}
+
+impl SortedEnergies {
+    fn new(queue: &VecDeque<f64>, compensated: bool) -> Self {
+        let mut sorted = SortedEnergies {
+            nodes: Vec::with_capacity(queue.capacity()),
+            free: NIL,
+            free_count: 0,
+            root: NIL,
+            nan_count: 0,
+            seed: PRIORITY_SEED,
+            compensated,
+        };
+        sorted.rebuild(queue);
+        sorted
+    }
+
+    fn rebuild(&mut self, queue: &VecDeque<f64>) {
+        self.clear();
+        for energy in queue.iter() {
+            self.insert(*energy);
+        }
+    }
+
+    fn set_compensated(&mut self, compensated: bool) {
+        if self.compensated == compensated {
+            return;
+        }
+
+        self.compensated = compensated;
+        self.update_all(self.root);
+    }
+
+    /// Number of energies in the tree, excluding NaN energies.
+    #[cfg(test)]
+    fn len(&self) -> u64 {
+        self.size(self.root)
+    }
+
+    #[cfg(test)]
+    fn capacity(&self) -> usize {
+        self.nodes.capacity()
+    }
+
+    fn reserve(&mut self, size: usize) -> Result<(), Error> {
+        let used = self.nodes.len() - self.free_count;
+        if size > used {
+            self.nodes
+                .try_reserve((size - used).saturating_sub(self.free_count))
+                .map_err(|_| Error::NoMem)?;
+        }
+
+        Ok(())
+    }
+
+    fn size(&self, node: u32) -> u64 {
+        if node == NIL {
+            0
+        } else {
+            self.nodes[node as usize].size
+        }
+    }
+
+    /// Recalculate the size and sum of `node` from its children.
+    fn update(&mut self, node: u32) {
+        let Node {
+            energy,
+            left,
+            right,
+            ..
+        } = self.nodes[node as usize];
+        let mut sum = if left == NIL {
+            StableSum::new(self.compensated)
+        } else {
+            self.nodes[left as usize].sum
+        };
+        sum.add(energy);
+        if right != NIL {
+            sum.merge(&self.nodes[right as usize].sum);
+        }
+        let size = 1 + self.size(left) + self.size(right);
+
+        let n = &mut self.nodes[node as usize];
+        n.size = size;
+        n.sum = sum;
+    }
+
+    /// Recalculate the sizes and sums of the whole subtree at `node`.
+    fn update_all(&mut self, node: u32) {
+        if node != NIL {
+            let n = self.nodes[node as usize];
+            self.update_all(n.left);
+            self.update_all(n.right);
+            self.update(node);
+        }
+    }
+
+    /// Split the subtree at `node` into the energies below `energy` and the others.
+    fn split(&mut self, node: u32, energy: f64) -> (u32, u32) {
+        if node == NIL {
+            return (NIL, NIL);
+        }
+
+        if self.nodes[node as usize].energy < energy {
+            let (left, right) = self.split(self.nodes[node as usize].right, energy);
+            self.nodes[node as usize].right = left;
+            self.update(node);
+            (node, right)
+        } else {
+            let (left, right) = self.split(self.nodes[node as usize].left, energy);
+            self.nodes[node as usize].left = right;
+            self.update(node);
+            (left, node)
+        }
+    }
+
+    /// Merge two subtrees where all energies of `left` are not above those of `right`.
+    fn merge(&mut self, left: u32, right: u32) -> u32 {
+        if left == NIL {
+            return right;
+        }
+        if right == NIL {
+            return left;
+        }
+
+        if self.nodes[left as usize].priority > self.nodes[right as usize].priority {
+            let merged = self.merge(self.nodes[left as usize].right, right);
+            self.nodes[left as usize].right = merged;
+            self.update(left);
+            left
+        } else {
+            let merged = self.merge(left, self.nodes[right as usize].left);
+            self.nodes[right as usize].left = merged;
+            self.update(right);
+            right
+        }
+    }
+
+    /// Remove the lowest energy of the subtree at `node`.
+    fn remove_first(&mut self, node: u32) -> u32 {
+        let left = self.nodes[node as usize].left;
+        if left == NIL {
+            let right = self.nodes[node as usize].right;
+            self.nodes[node as usize].left = self.free;
+            self.free = node;
+            self.free_count += 1;
+            return right;
+        }
+
+        let left = self.remove_first(left);
+        self.nodes[node as usize].left = left;
+        self.update(node);
+        node
+    }
+
+    fn next_priority(&mut self) -> u32 {
+        // xorshift32
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed
+    }
+
+    fn insert(&mut self, energy: f64) {
+        if energy.is_nan() {
+            self.nan_count += 1;
+            return;
+        }
+
+        let node = Node {
+            energy,
+            priority: self.next_priority(),
+            left: NIL,
+            right: NIL,
+            size: 1,
+            sum: StableSum::with_value(energy, self.compensated),
+        };
+        let index = if self.free != NIL {
+            let index = self.free;
+            self.free = self.nodes[index as usize].left;
+            self.free_count -= 1;
+            self.nodes[index as usize] = node;
+            index
+        } else {
+            self.nodes.push(node);
+            (self.nodes.len() - 1) as u32
+        };
+
+        let (left, right) = self.split(self.root, energy);
+        let left = self.merge(left, index);
+        self.root = self.merge(left, right);
+    }
+
+    fn remove(&mut self, energy: f64) {
+        if energy.is_nan() {
+            self.nan_count -= 1;
+            return;
+        }
+
+        let (left, right) = self.split(self.root, energy);
+        debug_assert!(right != NIL);
+        let right = self.remove_first(right);
+        self.root = self.merge(left, right);
+    }
+
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.free = NIL;
+        self.free_count = 0;
+        self.root = NIL;
+        self.nan_count = 0;
+        self.seed = PRIORITY_SEED;
+    }
+
+    /// Energy at `rank` in ascending order. `rank` must be below [`SortedEnergies::len`].
+    fn get(&self, mut rank: u64) -> f64 {
+        let mut node = self.root;
+        loop {
+            let n = &self.nodes[node as usize];
+            let left_size = self.size(n.left);
+            if rank < left_size {
+                node = n.left;
+            } else if rank == left_size {
+                return n.energy;
+            } else {
+                rank -= left_size + 1;
+                node = n.right;
+            }
+        }
+    }
+
+    /// Number of energies below `energy`.
+    fn count_below(&self, energy: f64) -> u64 {
+        let mut count = 0;
+        let mut node = self.root;
+        while node != NIL {
+            let n = &self.nodes[node as usize];
+            if n.energy < energy {
+                count += self.size(n.left) + 1;
+                node = n.right;
+            } else {
+                node = n.left;
+            }
+        }
+        count
+    }
+
+    /// Number and sum of all energies.
+    fn total(&self) -> (u64, StableSum) {
+        if self.root == NIL {
+            (0, StableSum::new(self.compensated))
+        } else {
+            let n = &self.nodes[self.root as usize];
+            (n.size, n.sum)
+        }
+    }
+
+    /// Number and sum of the energies that are not below `threshold`.
+    fn sum_from(&self, threshold: f64) -> (u64, StableSum) {
+        let mut count = 0;
+        let mut sum = StableSum::new(self.compensated);
+        let mut node = self.root;
+        while node != NIL {
+            let n = &self.nodes[node as usize];
+            if n.energy >= threshold {
+                count += 1 + self.size(n.right);
+                sum.add(n.energy);
+                if n.right != NIL {
+                    sum.merge(&self.nodes[n.right as usize].sum);
+                }
+                node = n.left;
+            } else {
+                node = n.right;
+            }
+        }
+        (count, sum)
+    }
+
+    /// Loudness range of the energies like [`Queue::loudness_range`].
+    fn loudness_range(&self) -> f64 {
+        let (size, power) = self.total();
+        if size == 0 {
+            return 0.0;
+        }
+
+        let minus_twenty_decibels = f64::powf(10.0, -20.0 / 10.0);
+        let integrated = minus_twenty_decibels * power.mean(size);
+
+        let relgated = self.count_below(integrated);
+        let relgated_size = size - relgated;
+
+        if relgated_size == 0 {
+            return 0.0;
+        }
+
+        let h_en = self.get(relgated + percentile_index(relgated_size, 0.95));
+        let l_en = self.get(relgated + percentile_index(relgated_size, 0.1));
 
         energy_to_loudness(h_en) - energy_to_loudness(l_en)
     }
@@ -160,6 +654,10 @@ impl Histogram {
 pub struct Queue {
     queue: VecDeque<f64>,
     max: usize,
+    /// Sorted energies if enabled.
+    sorted: Option<SortedEnergies>,
+    /// Whether the sums of the energies are compensated.
+    compensated: bool,
 }
 
 impl Queue {
@@ -167,170 +665,608 @@ impl Queue {
         Queue {
             queue: VecDeque::with_capacity(std::cmp::min(max, 5000)),
             max,
+            sorted: None,
+            compensated: false,
         }
     }
 
     fn add(&mut self, energy: f64) {
         // Remove last element to keep the size
         if self.max == self.queue.len() {
-            self.queue.pop_front();
+            if let Some(old) = self.queue.pop_front() {
+                if let Some(ref mut sorted) = self.sorted {
+                    sorted.remove(old);
+                }
+            }
         }
         self.queue.push_back(energy);
+        if let Some(ref mut sorted) = self.sorted {
+            sorted.insert(energy);
+        }
     }
 
     fn set_max_size(&mut self, max: usize) {
-        if self.queue.len() < max {
-            // FIXME: Use shrink() once stabilized
-            self.queue.resize(max, 0.0);
+        self.max = max;
+
+        if self.queue.len() > max {
+            // Drop the oldest energies right away and free the memory
+            let excess = self.queue.len() - max;
+            self.queue.drain(..excess);
             self.queue.shrink_to_fit();
+
+            if let Some(ref mut sorted) = self.sorted {
+                *sorted = SortedEnergies::new(&self.queue, self.compensated);
+            }
         }
-        self.max = max;
+    }
+
+    fn reserve(&mut self, size: usize) -> Result<(), Error> {
+        let size = std::cmp::min(size, self.max);
+        if size > self.queue.len() {
+            self.queue
+                .try_reserve(size - self.queue.len())
+                .map_err(|_| Error::NoMem)?;
+        }
+        if let Some(ref mut sorted) = self.sorted {
+            sorted.reserve(size)?;
+        }
+
+        Ok(())
     }
 
     fn reset(&mut self) {
         self.queue.clear();
+        if let Some(ref mut sorted) = self.sorted {
+            sorted.clear();
+        }
     }
 
-    fn calc_relative_threshold(&self) -> (u64, f64) {
-        (self.queue.len() as u64, self.queue.iter().sum::<f64>())
+    fn calc_relative_threshold(&self) -> (u64, StableSum) {
+        match self.sorted {
+            Some(ref sorted) if sorted.nan_count == 0 => sorted.total(),
+            Some(_) => (
+                self.queue.len() as u64,
+                StableSum::with_value(f64::NAN, self.compensated),
+            ),
+            None => (
+                self.queue.len() as u64,
+                StableSum::of(self.queue.iter().copied(), self.compensated),
+            ),
+        }
     }
 
-    fn loudness_range(q: &[f64]) -> f64 {
+    fn loudness_range(q: &[f64], compensated: bool) -> f64 {
         if q.is_empty() {
             return 0.0;
         }
 
-        let power = q.iter().sum::<f64>() / q.len() as f64;
+        let power = StableSum::of(q.iter().copied(), compensated).mean(q.len() as u64);
         let minus_twenty_decibels = f64::powf(10.0, -20.0 / 10.0);
         let integrated = minus_twenty_decibels * power;
 
-        let relgated = q.iter().take_while(|&v| *v < integrated).count();
+        let relgated = q.partition_point(|v| *v < integrated);
         let relgated_size = q.len() - relgated;
 
-        if let Some(relgated_size) = relgated_size.checked_sub(1) {
-            let relgated_size = relgated_size as f64;
-            let h_en = q[relgated + (relgated_size * 0.95 + 0.5) as usize];
-            let l_en = q[relgated + (relgated_size * 0.1 + 0.5) as usize];
-
-            energy_to_loudness(h_en) - energy_to_loudness(l_en)
-        } else {
-            0.0
+        if relgated_size == 0 {
+            return 0.0;
         }
+
+        let h_en = q[relgated + percentile_index(relgated_size as u64, 0.95) as usize];
+        let l_en = q[relgated + percentile_index(relgated_size as u64, 0.1) as usize];
+
+        energy_to_loudness(h_en) - energy_to_loudness(l_en)
     }
 }
 
-/// History of measured energies, either as histogram or a vector.
-pub enum History {
-    Queue(Queue),
-    Histogram(Histogram),
+/// Centroid of a [`TDigest`].
+#[derive(Clone, Copy)]
+struct Centroid {
+    /// Mean of the energies in dB, i.e. `10 * log10(energy)`.
+    level: f64,
+    /// Sum of the energies.
+    energy: StableSum,
+    count: u64,
 }
 
-impl fmt::Debug for History {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            History::Histogram(..) => f.debug_struct("History::Histogram").finish(),
-            History::Queue(..) => f.debug_struct("History::Queue").finish(),
+impl Centroid {
+    fn new(energy: f64, compensated: bool) -> Self {
+        Centroid {
+            level: 10.0 * f64::log10(energy),
+            energy: StableSum::with_value(energy, compensated),
+            count: 1,
         }
     }
+
+    /// Energy at the mean level of the centroid.
+    fn mean_energy(&self) -> f64 {
+        f64::powf(10.0, self.level / 10.0)
+    }
 }
 
-impl History {
-    pub fn new(use_histogram: bool, max: usize) -> Self {
-        if use_histogram {
-            History::Histogram(Histogram::new())
-        } else {
-            History::Queue(Queue::new(max))
+/// Merging t-digest of measured energies, which approximates their distribution with a bounded
+/// number of centroids.
+///
+/// Centroids at the tails of the distribution summarize fewer energies than those around the
+/// median, which keeps low and high percentiles accurate. The energies are merged by their level
+/// in dB so that percentiles are interpolated linearly in loudness, while the sum of the energies
+/// is kept exactly. Up to about `compression` centroids plus a buffer of `5 * compression` not
+/// yet merged energies are kept, independent of the number of energies.
+pub struct TDigest {
+    /// Centroids sorted by their level.
+    centroids: Vec<Centroid>,
+    /// Energies that are not merged into the centroids yet.
+    buffer: Vec<Centroid>,
+    compression: u32,
+    /// Number of NaN energies, which are not part of the centroids.
+    nan_count: u64,
+    /// Whether the sums of the energies are compensated.
+    compensated: bool,
+}
+
+impl TDigest {
+    fn new(compression: u32) -> Self {
+        TDigest {
+            centroids: Vec::new(),
+            buffer: Vec::with_capacity(Self::buffer_size(compression)),
+            compression,
+            nan_count: 0,
+            compensated: false,
         }
     }
 
-    pub fn add(&mut self, energy: f64) {
-        if energy < HISTOGRAM_BOUNDARIES[0] {
+    fn buffer_size(compression: u32) -> usize {
+        usize::max(5 * compression as usize, 1)
+    }
+
+    fn add(&mut self, energy: f64) {
+        if energy.is_nan() {
+            self.nan_count += 1;
             return;
         }
 
-        match self {
-            History::Histogram(ref mut h) => h.add(energy),
-            History::Queue(ref mut q) => q.add(energy),
+        self.buffer.push(Centroid::new(energy, self.compensated));
+        if self.buffer.len() >= Self::buffer_size(self.compression) {
+            self.centroids.append(&mut self.buffer);
+            Self::compress(&mut self.centroids, self.compression as f64);
         }
     }
 
-    pub fn set_max_size(&mut self, max: usize) {
-        match self {
-            History::Histogram(_) => (),
-            History::Queue(ref mut q) => q.set_max_size(max),
-        }
+    fn sort(centroids: &mut [Centroid]) {
+        centroids.sort_unstable_by(|a, b| a.level.partial_cmp(&b.level).unwrap());
     }
 
-    pub fn reset(&mut self) {
-        match self {
-            History::Histogram(ref mut h) => h.reset(),
-            History::Queue(ref mut q) => q.reset(),
+    /// Sort `centroids` and merge neighbours as long as they stay within the size limit given by
+    /// the `k1` scale function of the t-digest paper.
+    fn compress(centroids: &mut Vec<Centroid>, compression: f64) {
+        Self::sort(centroids);
+
+        let total = centroids.iter().map(|c| c.count).sum::<u64>() as f64;
+        let k = |q: f64| compression / (2.0 * std::f64::consts::PI) * f64::asin(2.0 * q - 1.0);
+
+        let mut current = 0;
+        let mut q_left = 0.0;
+        let mut k_left = k(0.0);
+        for i in 1..centroids.len() {
+            let next = centroids[i];
+            let c = &mut centroids[current];
+
+            if k(q_left + (c.count + next.count) as f64 / total) - k_left <= 1.0 {
+                let count = c.count + next.count;
+                c.level += (next.level - c.level) * next.count as f64 / count as f64;
+                c.energy.merge(&next.energy);
+                c.count = count;
+            } else {
+                q_left += c.count as f64 / total;
+                k_left = k(q_left);
+                current += 1;
+                centroids[current] = next;
+            }
         }
+
+        centroids.truncate(usize::min(current + 1, centroids.len()));
     }
 
-    fn calc_relative_threshold(&self) -> (u64, f64) {
-        match self {
-            History::Histogram(ref h) => h.calc_relative_threshold(),
-            History::Queue(ref q) => q.calc_relative_threshold(),
+    /// All centroids including the not yet merged energies, sorted by their level.
+    fn centroids(&self) -> Cow<'_, [Centroid]> {
+        if self.buffer.is_empty() {
+            return Cow::Borrowed(&self.centroids);
         }
+
+        let mut centroids = Vec::with_capacity(self.centroids.len() + self.buffer.len());
+        centroids.extend_from_slice(&self.centroids);
+        centroids.extend_from_slice(&self.buffer);
+        Self::sort(&mut centroids);
+        Cow::Owned(centroids)
     }
 
-    pub fn gated_loudness(&self) -> f64 {
-        Self::gated_loudness_multiple(&[self])
+    fn reset(&mut self) {
+        self.centroids.clear();
+        self.buffer.clear();
+        self.nan_count = 0;
     }
 
-    pub fn gated_loudness_multiple(s: &[&Self]) -> f64 {
-        let (above_thresh_counter, relative_threshold) = s.iter().fold((0, 0.0), |mut acc, h| {
-            let (above_thresh_counter, relative_threshold) = h.calc_relative_threshold();
-            acc.0 += above_thresh_counter;
-            acc.1 += relative_threshold;
+    fn calc_relative_threshold(&self) -> (u64, StableSum) {
+        let mut count = self.nan_count;
+        let mut sum = StableSum::with_value(
+            if self.nan_count > 0 { f64::NAN } else { 0.0 },
+            self.compensated,
+        );
 
-            acc
-        });
+        for c in Iterator::chain(self.centroids.iter(), self.buffer.iter()) {
+            count += c.count;
+            sum.merge(&c.energy);
+        }
 
-        if above_thresh_counter == 0 {
-            return -f64::INFINITY;
+        (count, sum)
+    }
+
+    /// Energy at `rank` (between 0 and the number of energies) of the sorted `centroids`, with
+    /// the level linearly interpolated between the centers of the neighbouring centroids.
+    fn energy_at_rank(centroids: &[Centroid], rank: f64) -> f64 {
+        let mut count_sum = 0.0;
+        let mut prev: Option<(f64, &Centroid)> = None;
+
+        for c in centroids {
+            let center = count_sum + c.count as f64 / 2.0;
+            if rank <= center {
+                return match prev {
+                    Some((prev_center, prev)) if rank < center => f64::powf(
+                        10.0,
+                        (prev.level
+                            + (c.level - prev.level) * (rank - prev_center)
+                                / (center - prev_center))
+                            / 10.0,
+                    ),
+                    // Exact for centroids of a single energy
+                    _ if c.count == 1 => c.energy.value(),
+                    _ => c.mean_energy(),
+                };
+            }
+            prev = Some((center, c));
+            count_sum += c.count as f64;
         }
 
-        if relative_threshold.is_nan() {
-            return f64::NAN;
+        match prev {
+            Some((_, c)) if c.count == 1 => c.energy.value(),
+            Some((_, c)) => c.mean_energy(),
+            None => 0.0,
         }
+    }
 
-        let relative_gate = -10.0;
-        let relative_gate_factor = f64::powf(10.0, relative_gate / 10.0);
-        let relative_threshold =
-            (relative_threshold / above_thresh_counter as f64) * relative_gate_factor;
+    /// Number of energies below `energy` in the sorted `centroids`, the inverse of
+    /// [`TDigest::energy_at_rank`] rounded to the nearest energy.
+    fn rank_of_energy(centroids: &[Centroid], energy: f64) -> u64 {
+        let level = 10.0 * f64::log10(energy);
+        let mut count_sum = 0.0;
+        let mut prev: Option<(f64, &Centroid)> = None;
+
+        for c in centroids {
+            let center = count_sum + c.count as f64 / 2.0;
+            if level <= c.level {
+                let rank = match prev {
+                    Some((prev_center, prev)) if c.level > prev.level => {
+                        prev_center
+                            + (center - prev_center) * (level - prev.level) / (c.level - prev.level)
+                    }
+                    // Below the first centroid or between equal levels
+                    _ => count_sum,
+                };
+                return f64::round(rank) as u64;
+            }
+            prev = Some((center, c));
+            count_sum += c.count as f64;
+        }
 
-        let mut above_thresh_counter = 0;
-        let mut gated_loudness = 0.0;
+        count_sum as u64
+    }
 
-        let start_index = if relative_threshold < HISTOGRAM_BOUNDARIES[0] {
-            0
-        } else {
-            let start_index = find_histogram_index(relative_threshold);
-            if relative_threshold > HISTOGRAM_ENERGIES[start_index] {
-                start_index + 1
-            } else {
-                start_index
-            }
-        };
+    /// Energy at `percentile` (between 0.0 and 1.0) of the sorted `centroids` with `size`
+    /// energies in total. `size` must not be 0.
+    fn percentile_energy(centroids: &[Centroid], size: u64, percentile: f64) -> f64 {
+        // Center of the energy at the percentile index, which gives exactly that energy if it is
+        // a centroid on its own
+        Self::energy_at_rank(centroids, percentile_index(size, percentile) as f64 + 0.5)
+    }
 
-        for h in s {
-            match h {
+    /// Loudness range of the sorted `centroids` like [`Queue::loudness_range`]. The number of
+    /// energies below the relative gate is interpolated like the percentiles.
+    fn loudness_range(centroids: &[Centroid], compensated: bool) -> f64 {
+        let size = centroids.iter().map(|c| c.count).sum::<u64>();
+        if size == 0 {
+            return 0.0;
+        }
+
+        let mut power = StableSum::new(compensated);
+        for c in centroids {
+            power.merge(&c.energy);
+        }
+        let power = power.mean(size);
+        let minus_twenty_decibels = f64::powf(10.0, -20.0 / 10.0);
+        let integrated = minus_twenty_decibels * power;
+
+        let relgated = Self::rank_of_energy(centroids, integrated);
+        let relgated_size = size - relgated;
+
+        if relgated_size == 0 {
+            return 0.0;
+        }
+
+        let rank =
+            |percentile| (relgated + percentile_index(relgated_size, percentile)) as f64 + 0.5;
+        let h_en = Self::energy_at_rank(centroids, rank(0.95));
+        let l_en = Self::energy_at_rank(centroids, rank(0.1));
+
+        energy_to_loudness(h_en) - energy_to_loudness(l_en)
+    }
+}
+
+/// History of measured energies, either as histogram, a vector or a t-digest.
+pub enum History {
+    Queue(Queue),
+    Histogram(Histogram),
+    TDigest(TDigest),
+}
+
+impl fmt::Debug for History {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            History::Histogram(..) => f.debug_struct("History::Histogram").finish(),
+            History::Queue(..) => f.debug_struct("History::Queue").finish(),
+            History::TDigest(ref d) => f
+                .debug_struct("History::TDigest")
+                .field("compression", &d.compression)
+                .finish(),
+        }
+    }
+}
+
+impl History {
+    pub fn new(use_histogram: bool, max: usize) -> Self {
+        if use_histogram {
+            History::Histogram(Histogram::new(DEFAULT_HISTOGRAM_BINS))
+        } else {
+            History::Queue(Queue::new(max))
+        }
+    }
+
+    /// Like [`History::new`] but additionally keeps the energies of a queue in a sorted tree. This
+    /// makes [`History::loudness_range`] O(log n) instead of O(n log n) and
+    /// [`History::gated_loudness`] O(log n) instead of O(n) for the cost of about eight times the
+    /// memory. The sums of the tree are added in a different order than the energies were added,
+    /// so [`History::gated_loudness`] can differ from the one of an unsorted queue in the last
+    /// digits.
+    pub fn new_sorted(use_histogram: bool, max: usize) -> Self {
+        let mut history = Self::new(use_histogram, max);
+        history.set_sorted(true);
+        history
+    }
+
+    /// Start or stop keeping the energies of a queue in a sorted tree, see
+    /// [`History::new_sorted`]. Does nothing for other histories.
+    pub fn set_sorted(&mut self, enabled: bool) {
+        if let History::Queue(ref mut q) = self {
+            if !enabled {
+                q.sorted = None;
+            } else if q.sorted.is_none() {
+                q.sorted = Some(SortedEnergies::new(&q.queue, q.compensated));
+            }
+        }
+    }
+
+    /// History that keeps a [`TDigest`] with the given compression instead of all energies. The
+    /// memory usage is bounded by the compression, but percentiles are only approximated.
+    pub fn new_digest(compression: u32) -> Self {
+        History::TDigest(TDigest::new(compression))
+    }
+
+    /// Compression of the t-digest, or `None` for other histories.
+    pub fn digest_compression(&self) -> Option<u32> {
+        match self {
+            History::TDigest(ref d) => Some(d.compression),
+            _ => None,
+        }
+    }
+
+    pub fn add(&mut self, energy: f64) {
+        if energy < HISTOGRAM_BOUNDARIES[0] {
+            return;
+        }
+
+        match self {
+            History::Histogram(ref mut h) => h.add(energy),
+            History::Queue(ref mut q) => q.add(energy),
+            History::TDigest(ref mut d) => d.add(energy),
+        }
+    }
+
+    /// Energies in the order they were added, or `None` if they are not kept individually.
+    #[cfg(feature = "block-energies")]
+    pub fn energies(&self) -> Option<impl Iterator<Item = f64> + '_> {
+        match self {
+            History::Queue(ref q) => Some(q.queue.iter().copied()),
+            History::Histogram(..) | History::TDigest(..) => None,
+        }
+    }
+
+    /// Whether no energies were added since the history was created or reset.
+    pub fn is_empty(&self) -> bool {
+        match self {
+            History::Histogram(ref h) => h.counts.iter().all(|c| *c == 0),
+            History::Queue(ref q) => q.queue.is_empty(),
+            History::TDigest(ref d) => {
+                d.nan_count == 0 && d.centroids.is_empty() && d.buffer.is_empty()
+            }
+        }
+    }
+
+    /// Heap memory in bytes of a history created with [`History::new`] or, if `sorted` is set,
+    /// [`History::new_sorted`] once it holds `size` energies.
+    pub fn heap_size(use_histogram: bool, sorted: bool, size: usize) -> usize {
+        if use_histogram {
+            return DEFAULT_HISTOGRAM_BINS * std::mem::size_of::<u64>();
+        }
+
+        // The queue starts with a capacity of 5000 energies
+        let size = usize::max(size, 5000);
+        let mut heap_size = size.saturating_mul(std::mem::size_of::<f64>());
+        if sorted {
+            heap_size = heap_size.saturating_add(size.saturating_mul(std::mem::size_of::<Node>()));
+        }
+        heap_size
+    }
+
+    /// Replace the histogram with an empty one with `bins` bins. Does nothing for other
+    /// histories.
+    pub fn set_histogram_bins(&mut self, bins: usize) {
+        if let History::Histogram(ref mut h) = self {
+            let compensated = h.compensated;
+            *h = Histogram::new(bins);
+            h.compensated = compensated;
+        }
+    }
+
+    /// Whether the sums of the energies are compensated, see [`History::set_stable_accumulation`].
+    fn compensated(&self) -> bool {
+        match self {
+            History::Histogram(ref h) => h.compensated,
+            History::Queue(ref q) => q.compensated,
+            History::TDigest(ref d) => d.compensated,
+        }
+    }
+
+    /// Sum the energies with compensation of the rounding errors instead of naively like
+    /// libebur128. This only affects sums calculated from now on, except for the energies already
+    /// merged into the centroids of a t-digest.
+    pub fn set_stable_accumulation(&mut self, enabled: bool) {
+        match self {
+            History::Histogram(ref mut h) => h.compensated = enabled,
+            History::Queue(ref mut q) => {
+                q.compensated = enabled;
+                if let Some(ref mut sorted) = q.sorted {
+                    sorted.set_compensated(enabled);
+                }
+            }
+            History::TDigest(ref mut d) => {
+                d.compensated = enabled;
+                for c in Iterator::chain(d.centroids.iter_mut(), d.buffer.iter_mut()) {
+                    c.energy.compensated = enabled;
+                }
+            }
+        }
+    }
+
+    pub fn set_max_size(&mut self, max: usize) {
+        match self {
+            History::Histogram(_) | History::TDigest(_) => (),
+            History::Queue(ref mut q) => q.set_max_size(max),
+        }
+    }
+
+    /// Reserve memory for `size` energies, or fail with `Error::NoMem` if that's not possible.
+    pub fn reserve(&mut self, size: usize) -> Result<(), Error> {
+        match self {
+            History::Histogram(_) | History::TDigest(_) => Ok(()),
+            History::Queue(ref mut q) => q.reserve(size),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        match self {
+            History::Histogram(ref mut h) => h.reset(),
+            History::Queue(ref mut q) => q.reset(),
+            History::TDigest(ref mut d) => d.reset(),
+        }
+    }
+
+    fn calc_relative_threshold(&self) -> (u64, StableSum) {
+        match self {
+            History::Histogram(ref h) => h.calc_relative_threshold(),
+            History::Queue(ref q) => q.calc_relative_threshold(),
+            History::TDigest(ref d) => d.calc_relative_threshold(),
+        }
+    }
+
+    /// Loudness of all energies, i.e. only with the absolute gate applied.
+    pub fn absolute_gated_loudness(&self) -> f64 {
+        let (above_thresh_counter, energy_sum) = self.calc_relative_threshold();
+
+        if above_thresh_counter == 0 {
+            return -f64::INFINITY;
+        }
+
+        energy_to_loudness(energy_sum.mean(above_thresh_counter))
+    }
+
+    pub fn gated_loudness(&self) -> f64 {
+        Self::gated_loudness_multiple(&[self])
+    }
+
+    pub fn gated_loudness_multiple(s: &[&Self]) -> f64 {
+        let compensated = s.iter().any(|h| h.compensated());
+        let (above_thresh_counter, relative_threshold) =
+            s.iter()
+                .fold((0, StableSum::new(compensated)), |mut acc, h| {
+                    let (above_thresh_counter, relative_threshold) = h.calc_relative_threshold();
+                    acc.0 += above_thresh_counter;
+                    acc.1.merge(&relative_threshold);
+
+                    acc
+                });
+
+        if above_thresh_counter == 0 {
+            return -f64::INFINITY;
+        }
+
+        if relative_threshold.value().is_nan() {
+            return f64::NAN;
+        }
+
+        let relative_gate = -10.0;
+        let relative_gate_factor = f64::powf(10.0, relative_gate / 10.0);
+        let relative_threshold =
+            relative_threshold.mean(above_thresh_counter) * relative_gate_factor;
+        trace!(
+            "Relative threshold {} LUFS from {} blocks",
+            energy_to_loudness(relative_threshold),
+            above_thresh_counter
+        );
+
+        let mut above_thresh_counter = 0;
+        let mut gated_loudness = StableSum::new(compensated);
+
+        for h in s {
+            match h {
                 History::Histogram(ref h) => {
+                    let start_index = h.start_index(relative_threshold);
                     for (count, energy) in Iterator::zip(
-                        h.0[start_index..].iter(),
-                        HISTOGRAM_ENERGIES[start_index..].iter(),
+                        h.counts[start_index..].iter(),
+                        h.energies[start_index..].iter(),
                     ) {
-                        gated_loudness += *count as f64 * *energy;
+                        gated_loudness.add_product(*count as f64, *energy);
                         above_thresh_counter += *count;
                     }
                 }
+                History::Queue(Queue {
+                    sorted: Some(ref sorted),
+                    ..
+                }) => {
+                    let (count, sum) = sorted.sum_from(relative_threshold);
+                    above_thresh_counter += count;
+                    gated_loudness.merge(&sum);
+                }
                 History::Queue(ref q) => {
                     for v in q.queue.iter() {
                         if *v >= relative_threshold {
                             above_thresh_counter += 1;
-                            gated_loudness += *v;
+                            gated_loudness.add(*v);
+                        }
+                    }
+                }
+                History::TDigest(ref d) => {
+                    for c in d.centroids().iter() {
+                        if c.mean_energy() >= relative_threshold {
+                            above_thresh_counter += c.count;
+                            gated_loudness.merge(&c.energy);
                         }
                     }
                 }
@@ -341,7 +1277,217 @@ impl History {
             return -f64::INFINITY;
         }
 
-        energy_to_loudness(gated_loudness / above_thresh_counter as f64)
+        energy_to_loudness(gated_loudness.mean(above_thresh_counter))
+    }
+
+    /// Standard deviation in LU of the loudness of all energies above the relative gate.
+    pub fn gated_loudness_stddev(&self) -> f64 {
+        let (above_thresh_counter, relative_threshold) = self.calc_relative_threshold();
+
+        if above_thresh_counter == 0 || relative_threshold.value().is_nan() {
+            return f64::NAN;
+        }
+
+        let relative_gate = -10.0;
+        let relative_gate_factor = f64::powf(10.0, relative_gate / 10.0);
+        let relative_threshold =
+            relative_threshold.mean(above_thresh_counter) * relative_gate_factor;
+
+        // Accumulate count, sum and sum of squares of the loudness of each gated energy.
+        let mut count = 0;
+        let mut sum = StableSum::new(self.compensated());
+        let mut sum_sq = StableSum::new(self.compensated());
+
+        match self {
+            History::Histogram(ref h) => {
+                let start_index = h.start_index(relative_threshold);
+
+                for (c, energy) in Iterator::zip(
+                    h.counts[start_index..].iter(),
+                    h.energies[start_index..].iter(),
+                ) {
+                    if *c > 0 {
+                        let loudness = energy_to_loudness(*energy);
+                        count += *c;
+                        sum.add_product(*c as f64, loudness);
+                        sum_sq.add_product(*c as f64, loudness * loudness);
+                    }
+                }
+            }
+            History::Queue(ref q) => {
+                for v in q.queue.iter() {
+                    if *v >= relative_threshold {
+                        let loudness = energy_to_loudness(*v);
+                        count += 1;
+                        sum.add(loudness);
+                        sum_sq.add(loudness * loudness);
+                    }
+                }
+            }
+            History::TDigest(ref d) => {
+                for c in d.centroids().iter() {
+                    if c.mean_energy() >= relative_threshold {
+                        let loudness = energy_to_loudness(c.mean_energy());
+                        count += c.count;
+                        sum.add_product(c.count as f64, loudness);
+                        sum_sq.add_product(c.count as f64, loudness * loudness);
+                    }
+                }
+            }
+        }
+
+        if count == 0 {
+            return f64::NAN;
+        }
+
+        let mean = sum.mean(count);
+        // Clamp to zero as rounding errors could otherwise result in a tiny negative variance
+        f64::sqrt(f64::max(sum_sq.mean(count) - mean * mean, 0.0))
+    }
+
+    /// Loudness of all energies for which `predicate` returns `true`. For histograms the
+    /// predicate is called with the energy at the center of each non-empty bin, for t-digests
+    /// with the energy at the mean level of each centroid.
+    pub fn predicate_gated_loudness(&self, predicate: &dyn Fn(f64) -> bool) -> f64 {
+        let mut above_thresh_counter = 0;
+        let mut gated_loudness = StableSum::new(self.compensated());
+
+        match self {
+            History::Histogram(ref h) => {
+                for (count, energy) in Iterator::zip(h.counts.iter(), h.energies.iter()) {
+                    if *count > 0 && predicate(*energy) {
+                        gated_loudness.add_product(*count as f64, *energy);
+                        above_thresh_counter += *count;
+                    }
+                }
+            }
+            History::Queue(ref q) => {
+                for v in q.queue.iter() {
+                    if predicate(*v) {
+                        above_thresh_counter += 1;
+                        gated_loudness.add(*v);
+                    }
+                }
+            }
+            History::TDigest(ref d) => {
+                for c in d.centroids().iter() {
+                    if predicate(c.mean_energy()) {
+                        above_thresh_counter += c.count;
+                        gated_loudness.merge(&c.energy);
+                    }
+                }
+            }
+        }
+
+        if above_thresh_counter == 0 {
+            return -f64::INFINITY;
+        }
+
+        energy_to_loudness(gated_loudness.mean(above_thresh_counter))
+    }
+
+    /// Loudness of the energy at the given percentile (between 0.0 and 1.0) of all energies, or
+    /// negative infinity if there are none.
+    pub fn percentile_loudness(&self, percentile: f64) -> f64 {
+        match self {
+            History::Histogram(ref h) => {
+                let size = h.counts.iter().sum::<u64>();
+                if size == 0 {
+                    return -f64::INFINITY;
+                }
+
+                let rank = percentile_index(size, percentile);
+                let mut count_sum = 0;
+                for (count, energy) in Iterator::zip(h.counts.iter(), h.energies.iter()) {
+                    count_sum += *count;
+                    if count_sum > rank {
+                        return energy_to_loudness(*energy);
+                    }
+                }
+
+                unreachable!();
+            }
+            History::Queue(ref q) => {
+                if q.queue.is_empty() {
+                    return -f64::INFINITY;
+                }
+
+                let rank = percentile_index(q.queue.len() as u64, percentile) as usize;
+                if let Some(ref sorted) = q.sorted {
+                    if sorted.nan_count > 0 {
+                        return f64::NAN;
+                    }
+
+                    return energy_to_loudness(sorted.get(rank as u64));
+                }
+
+                let mut energies = q.queue.iter().copied().collect::<Vec<_>>();
+                if energies.iter().any(|e| e.is_nan()) {
+                    return f64::NAN;
+                }
+                let (_, energy, _) =
+                    energies.select_nth_unstable_by(rank, |a, b| a.partial_cmp(b).unwrap());
+
+                energy_to_loudness(*energy)
+            }
+            History::TDigest(ref d) => {
+                if d.nan_count > 0 {
+                    return f64::NAN;
+                }
+
+                let centroids = d.centroids();
+                let size = centroids.iter().map(|c| c.count).sum::<u64>();
+                if size == 0 {
+                    return -f64::INFINITY;
+                }
+
+                energy_to_loudness(TDigest::percentile_energy(&centroids, size, percentile))
+            }
+        }
+    }
+
+    /// Distribution of the loudness of all energies in `bins` equally sized bins between -70 LUFS
+    /// and +30 LUFS, as pairs of the loudness at the center of each bin and the number of
+    /// energies in it. Energies above +30 LUFS are counted in the last bin.
+    pub fn loudness_histogram(&self, bins: usize) -> Vec<(f64, u64)> {
+        let step = HISTOGRAM_RANGE / bins as f64;
+        let mut histogram = (0..bins)
+            .map(|i| ((i as f64 + 0.5) * step - 70.0, 0))
+            .collect::<Vec<_>>();
+        if bins == 0 {
+            return histogram;
+        }
+
+        let mut add = |energy: f64, count: u64| {
+            let loudness = energy_to_loudness(energy);
+            if loudness.is_nan() {
+                return;
+            }
+            let index = f64::max((loudness + 70.0) / step, 0.0) as usize;
+            histogram[usize::min(index, bins - 1)].1 += count;
+        };
+
+        match self {
+            History::Histogram(ref h) => {
+                for (count, energy) in Iterator::zip(h.counts.iter(), h.energies.iter()) {
+                    if *count > 0 {
+                        add(*energy, *count);
+                    }
+                }
+            }
+            History::Queue(ref q) => {
+                for energy in q.queue.iter() {
+                    add(*energy, 1);
+                }
+            }
+            History::TDigest(ref d) => {
+                for c in d.centroids().iter() {
+                    add(c.mean_energy(), c.count);
+                }
+            }
+        }
+
+        histogram
     }
 
     pub fn relative_threshold(&self) -> f64 {
@@ -351,19 +1497,31 @@ impl History {
             return -70.0;
         }
 
-        if relative_threshold.is_nan() {
+        if relative_threshold.value().is_nan() {
             return f64::NAN;
         }
 
         let relative_gate = -10.0;
         let relative_gate_factor = f64::powf(10.0, relative_gate / 10.0);
         let relative_threshold =
-            (relative_threshold / above_thresh_counter as f64) * relative_gate_factor;
+            relative_threshold.mean(above_thresh_counter) * relative_gate_factor;
 
         energy_to_loudness(relative_threshold)
     }
 
     pub fn loudness_range(&self) -> f64 {
+        if let History::Queue(Queue {
+            sorted: Some(ref sorted),
+            ..
+        }) = self
+        {
+            if sorted.nan_count > 0 {
+                return f64::NAN;
+            }
+
+            return sorted.loudness_range();
+        }
+
         // This can only fail if multiple histories are passed
         // and have a mix of histograms and queues
         Self::loudness_range_multiple(&[self]).unwrap()
@@ -374,30 +1532,37 @@ impl History {
             return Ok(0.0);
         }
 
+        let compensated = s.iter().any(|h| h.compensated());
+
         match s[0] {
             History::Histogram(ref h) => {
                 let mut combined;
 
                 let combined = if s.len() == 1 {
-                    &*h.0
+                    &*h.counts
                 } else {
-                    combined = [0; 1000];
-
-                    for h in s {
-                        match h {
-                            History::Histogram(ref h) => {
-                                for (i, o) in Iterator::zip(h.0.iter(), combined.iter_mut()) {
+                    combined = vec![0; h.counts.len()];
+
+                    for other in s {
+                        match other {
+                            // Histograms can only be combined if they have the same bins
+                            History::Histogram(ref other)
+                                if other.counts.len() == h.counts.len() =>
+                            {
+                                for (i, o) in
+                                    Iterator::zip(other.counts.iter(), combined.iter_mut())
+                                {
                                     *o += *i;
                                 }
                             }
-                            _ => return Err(Error::InvalidMode),
+                            _ => return Err(Error::MixedHistoryTypes),
                         }
                     }
 
                     &combined
                 };
 
-                Ok(Histogram::loudness_range(combined))
+                Ok(h.loudness_range(combined))
             }
             History::Queue(_) => {
                 let mut len = 0;
@@ -406,7 +1571,7 @@ impl History {
                         History::Queue(ref q) => {
                             len += q.queue.len();
                         }
-                        _ => return Err(Error::InvalidMode),
+                        _ => return Err(Error::MixedHistoryTypes),
                     }
                 }
 
@@ -418,7 +1583,7 @@ impl History {
                             combined.extend_from_slice(v1);
                             combined.extend_from_slice(v2);
                         }
-                        _ => return Err(Error::InvalidMode),
+                        _ => return Err(Error::MixedHistoryTypes),
                     }
                 }
 
@@ -437,10 +1602,426 @@ impl History {
                     return Ok(f64::NAN);
                 }
 
-                Ok(Queue::loudness_range(&combined))
+                Ok(Queue::loudness_range(&combined, compensated))
+            }
+            History::TDigest(_) => {
+                let mut combined = Vec::new();
+                for h in s {
+                    match h {
+                        History::TDigest(ref d) => {
+                            if d.nan_count > 0 {
+                                return Ok(f64::NAN);
+                            }
+                            combined.extend_from_slice(&d.centroids());
+                        }
+                        _ => return Err(Error::MixedHistoryTypes),
+                    }
+                }
+
+                if s.len() > 1 {
+                    TDigest::sort(&mut combined);
+                }
+
+                Ok(TDigest::loudness_range(&combined, compensated))
             }
         }
     }
+
+    /// Like [`History::loudness_range_multiple`] but histories of different kinds, or histograms
+    /// with different bins, are combined by adding all their energies to a single histogram.
+    ///
+    /// The histogram has the finest resolution of the given histograms, or the default
+    /// resolution of 0.1 LU if there are none. This quantizes the energies of queues and
+    /// t-digests to the bins of the histogram, and the energies of coarser histograms
+    /// additionally to the bins of the combined histogram.
+    pub fn loudness_range_multiple_lossy(s: &[&Self]) -> Result<f64, Error> {
+        match Self::loudness_range_multiple(s) {
+            Err(Error::MixedHistoryTypes) => (),
+            res => return res,
+        }
+
+        let bins = s
+            .iter()
+            .filter_map(|h| match h {
+                History::Histogram(ref h) => Some(h.counts.len()),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(DEFAULT_HISTOGRAM_BINS);
+
+        let mut combined = Histogram::new(bins);
+        for h in s {
+            match h {
+                History::Histogram(ref h) => {
+                    for (count, energy) in Iterator::zip(h.counts.iter(), h.energies.iter()) {
+                        if *count > 0 {
+                            combined.add_count(*energy, *count);
+                        }
+                    }
+                }
+                History::Queue(ref q) => {
+                    for energy in q.queue.iter() {
+                        if energy.is_nan() {
+                            return Ok(f64::NAN);
+                        }
+                        combined.add(*energy);
+                    }
+                }
+                History::TDigest(ref d) => {
+                    if d.nan_count > 0 {
+                        return Ok(f64::NAN);
+                    }
+                    for c in d.centroids().iter() {
+                        combined.add_count(c.mean_energy(), c.count);
+                    }
+                }
+            }
+        }
+
+        Ok(combined.loudness_range(&combined.counts))
+    }
+}
+
+#[cfg(test)]
+mod queue_tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    #[test]
+    fn sorted_loudness_range() {
+        let mut hist = History::new(false, 100);
+        let mut hist_sorted = History::new_sorted(false, 100);
+
+        for i in 0..1000 {
+            // Some pseudo-random energies with duplicates
+            let e = f64::powf(10.0, ((i * 7919 % 600) as f64 / 10.0 - 60.0) / 10.0);
+            hist.add(e);
+            hist_sorted.add(e);
+
+            if i % 10 == 0 {
+                assert_eq!(hist.loudness_range(), hist_sorted.loudness_range());
+            }
+        }
+
+        hist_sorted.add(f64::NAN);
+        assert!(hist_sorted.loudness_range().is_nan());
+        for _ in 0..100 {
+            hist_sorted.add(1.0);
+        }
+        assert_eq!(hist_sorted.loudness_range(), 0.0);
+
+        hist_sorted.reset();
+        assert_eq!(hist_sorted.loudness_range(), 0.0);
+    }
+
+    #[test]
+    fn sorted_energies() {
+        let mut queue = VecDeque::new();
+        let mut sorted = SortedEnergies::new(&queue, false);
+
+        for i in 0..2000u64 {
+            // Some pseudo-random energies with duplicates, removing the oldest ones like a queue
+            queue.push_back((i * 7919 % 300) as f64);
+            sorted.insert((i * 7919 % 300) as f64);
+            if i % 3 == 0 {
+                sorted.remove(queue.pop_front().unwrap());
+            }
+
+            if i % 100 == 0 {
+                let mut expected = queue.iter().copied().collect::<Vec<_>>();
+                expected.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+
+                assert_eq!(sorted.len(), expected.len() as u64);
+                for (rank, energy) in expected.iter().enumerate() {
+                    assert_eq!(sorted.get(rank as u64), *energy);
+                }
+                assert_eq!(
+                    sorted.count_below(150.0),
+                    expected.partition_point(|v| *v < 150.0) as u64
+                );
+                assert_eq!(sorted.total().1.value(), expected.iter().sum::<f64>());
+                let (count, sum) = sorted.sum_from(150.0);
+                let above = &expected[expected.partition_point(|v| *v < 150.0)..];
+                assert_eq!(count, above.len() as u64);
+                assert_eq!(sum.value(), above.iter().sum::<f64>());
+            }
+        }
+    }
+
+    #[test]
+    fn gated_loudness_sequential_sums() {
+        // While polling after every added energy, the integrated loudness of a queue must be
+        // exactly the one calculated from scratch by summing the energies in the order they were
+        // added. The sorted queue sums them in tree order and only gets close to it.
+        for compensated in [false, true] {
+            let mut queue = History::new(false, 1000);
+            let mut queue_sorted = History::new_sorted(false, 1000);
+            queue.set_stable_accumulation(compensated);
+            queue_sorted.set_stable_accumulation(compensated);
+
+            for i in 0..3000u64 {
+                let e = f64::powf(10.0, ((i * 7919 % 600) as f64 / 10.0 - 60.0) / 10.0);
+                queue.add(e);
+                queue_sorted.add(e);
+
+                let q = match queue {
+                    History::Queue(ref q) => q,
+                    History::Histogram(_) | History::TDigest(_) => unreachable!(),
+                };
+                let relative_threshold = StableSum::of(q.queue.iter().copied(), compensated)
+                    .mean(q.queue.len() as u64)
+                    * f64::powf(10.0, -10.0 / 10.0);
+                let above = q
+                    .queue
+                    .iter()
+                    .copied()
+                    .filter(|e| *e >= relative_threshold)
+                    .collect::<Vec<_>>();
+                let expected = energy_to_loudness(
+                    StableSum::of(above.iter().copied(), compensated).mean(above.len() as u64),
+                );
+
+                assert_eq!(queue.gated_loudness(), expected);
+                assert_float_eq!(queue_sorted.gated_loudness(), expected, abs <= 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn histogram_queue_loudness_range() {
+        // With energies at the centers of the histogram bins there is no quantization, so
+        // histogram and queue must pick the same blocks for the percentiles. The sizes cover
+        // the cases where the percentile indices are exactly between two blocks.
+        for size in 1..100 {
+            let mut hist = History::new(true, usize::MAX);
+            let mut queue = History::new(false, usize::MAX);
+            let mut queue_sorted = History::new_sorted(false, usize::MAX);
+
+            for i in 0..size {
+                let e = HISTOGRAM_ENERGIES[500 + i * 7919 % 200];
+                hist.add(e);
+                queue.add(e);
+                queue_sorted.add(e);
+            }
+
+            let loudness_range = hist.loudness_range();
+            assert_float_eq!(queue.loudness_range(), loudness_range, abs <= 0.000001);
+            assert_float_eq!(
+                queue_sorted.loudness_range(),
+                loudness_range,
+                abs <= 0.000001
+            );
+            for percentile in [0.0, 0.1, 0.5, 0.95, 1.0] {
+                assert_float_eq!(
+                    queue.percentile_loudness(percentile),
+                    hist.percentile_loudness(percentile),
+                    abs <= 0.000001
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unbounded_growth() {
+        // Default maximum size of the short-term history
+        let mut hist = History::new_sorted(false, usize::MAX / 3000);
+        let mut hist_reserved = History::new_sorted(false, usize::MAX / 3000);
+        hist_reserved.reserve(86_400).unwrap();
+
+        let mut reallocations = 0;
+        let mut capacity = 0;
+        // One day of short-term blocks
+        for i in 0..86_400 {
+            hist.add(1.0 + (i % 100) as f64);
+            hist_reserved.add(1.0 + (i % 100) as f64);
+
+            let q = match hist {
+                History::Queue(ref q) => q,
+                History::Histogram(_) | History::TDigest(_) => unreachable!(),
+            };
+            if q.queue.capacity() != capacity {
+                capacity = q.queue.capacity();
+                reallocations += 1;
+            }
+        }
+
+        assert_eq!(hist.loudness_range(), hist_reserved.loudness_range());
+        // The capacity is doubled on every reallocation
+        assert!(reallocations <= 6, "{} reallocations", reallocations);
+    }
+
+    #[test]
+    fn shrink_max_size() {
+        for sorted in [false, true] {
+            let mut hist = if sorted {
+                History::new_sorted(false, 1000)
+            } else {
+                History::new(false, 1000)
+            };
+            for i in 0..1000 {
+                hist.add(i as f64);
+            }
+
+            hist.set_max_size(100);
+            let q = match hist {
+                History::Queue(ref q) => q,
+                History::Histogram(_) | History::TDigest(_) => unreachable!(),
+            };
+            assert_eq!(q.queue.len(), 100);
+            assert_eq!(q.queue.front(), Some(&900.0));
+            if let Some(ref sorted) = q.sorted {
+                assert_eq!(sorted.len(), 100);
+                assert_eq!(sorted.get(0), 900.0);
+                assert!(sorted.capacity() < 1000);
+            }
+
+            // Growing again keeps the current energies
+            hist.set_max_size(1000);
+            let q = match hist {
+                History::Queue(ref q) => q,
+                History::Histogram(_) | History::TDigest(_) => unreachable!(),
+            };
+            assert_eq!(q.queue.len(), 100);
+        }
+    }
+
+    #[test]
+    fn digest_loudness_range() {
+        let mut queue = History::new_sorted(false, usize::MAX);
+        let mut digest = History::new_digest(500);
+        assert_eq!(digest.loudness_range(), 0.0);
+        assert_eq!(digest.percentile_loudness(0.5), -f64::INFINITY);
+
+        // With fewer energies than the buffer size nothing is merged yet and the result is exact
+        for i in 0..400 {
+            let e = f64::powf(10.0, ((i * 7919 % 600) as f64 / 10.0 - 60.0) / 10.0);
+            queue.add(e);
+            digest.add(e);
+        }
+        assert_float_eq!(
+            digest.loudness_range(),
+            queue.loudness_range(),
+            abs <= 0.000001
+        );
+
+        // One day of short-term blocks with a pseudo-random loudness between -50 and -10 LUFS
+        let mut x = 1u64;
+        for _ in 0..86_400 {
+            x = x
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let loudness = -50.0 + (x >> 11) as f64 / (1u64 << 53) as f64 * 40.0;
+            let e = f64::powf(10.0, (loudness + 0.691) / 10.0);
+            queue.add(e);
+            digest.add(e);
+        }
+
+        if let History::TDigest(ref d) = digest {
+            assert!(d.centroids.len() <= 500, "{} centroids", d.centroids.len());
+        }
+        assert_float_eq!(digest.loudness_range(), queue.loudness_range(), abs <= 0.1);
+        assert_float_eq!(digest.gated_loudness(), queue.gated_loudness(), abs <= 0.01);
+        for percentile in [0.1, 0.5, 0.95] {
+            assert_float_eq!(
+                digest.percentile_loudness(percentile),
+                queue.percentile_loudness(percentile),
+                abs <= 0.1
+            );
+        }
+
+        digest.add(f64::NAN);
+        assert!(digest.loudness_range().is_nan());
+        digest.reset();
+        assert_eq!(digest.loudness_range(), 0.0);
+
+        // Digests can only be combined with other digests
+        let other = History::new_digest(500);
+        assert!(History::loudness_range_multiple(&[&digest, &other]).is_ok());
+        assert_eq!(
+            History::loudness_range_multiple(&[&digest, &queue]),
+            Err(Error::MixedHistoryTypes)
+        );
+    }
+
+    #[test]
+    fn loudness_range_multiple_lossy() {
+        let mut queue = History::new(false, usize::MAX);
+        let mut histogram = History::new(true, usize::MAX);
+        let mut digest = History::new_digest(500);
+        for i in 0..1000 {
+            let energy = f64::powf(10.0, (-40.0 + 0.02 * i as f64 + 0.691) / 10.0);
+            queue.add(energy);
+            histogram.add(energy);
+            digest.add(energy);
+        }
+
+        assert_eq!(
+            History::loudness_range_multiple(&[&queue, &histogram]),
+            Err(Error::MixedHistoryTypes)
+        );
+
+        // Same result as without conversion if the histories are of the same kind
+        assert_eq!(
+            History::loudness_range_multiple_lossy(&[&queue, &queue]),
+            History::loudness_range_multiple(&[&queue, &queue])
+        );
+
+        // Quantized to the bins of 0.1 LU
+        let expected = History::loudness_range_multiple(&[&queue, &queue]).unwrap();
+        for mix in [
+            [&queue, &histogram],
+            [&histogram, &queue],
+            [&digest, &queue],
+            [&histogram, &digest],
+        ] {
+            assert_float_eq!(
+                History::loudness_range_multiple_lossy(&mix).unwrap(),
+                expected,
+                abs <= 0.2
+            );
+        }
+
+        queue.add(f64::NAN);
+        assert!(
+            History::loudness_range_multiple_lossy(&[&histogram, &queue])
+                .unwrap()
+                .is_nan()
+        );
+    }
+    #[test]
+    fn long_run_stability() {
+        // Ten hours of a constant signal at about -23 LUFS
+        let energy = f64::powf(10.0, (-23.0 + 0.691) / 10.0);
+        for mut hist in [
+            History::new(true, usize::MAX),
+            History::new(false, usize::MAX),
+            History::new_digest(500),
+        ] {
+            hist.set_stable_accumulation(true);
+            hist.add(energy);
+            let loudness = hist.gated_loudness();
+            let relative_threshold = hist.relative_threshold();
+
+            for _ in 1..360_000 {
+                hist.add(energy);
+            }
+            assert_eq!(hist.gated_loudness(), loudness);
+            assert_eq!(hist.absolute_gated_loudness(), loudness);
+            assert_eq!(hist.relative_threshold(), relative_threshold);
+            assert_eq!(hist.gated_loudness_stddev(), 0.0);
+        }
+
+        // The naive sums are the same as in libebur128
+        let mut queue = History::new(false, usize::MAX);
+        for _ in 0..360_000 {
+            queue.add(energy);
+        }
+        let sum = (0..360_000).fold(0.0, |sum, _| sum + energy);
+        assert_eq!(
+            queue.absolute_gated_loudness(),
+            energy_to_loudness(sum / 360_000.0)
+        );
+    }
 }
 
 #[cfg(feature = "c-tests")]
@@ -69,6 +69,56 @@ impl<T, const N: usize> AsRef<[T; N]> for RollingBuffer<T, N> {
     }
 }
 
+/// Calculate the coefficients of the interpolation filter for upsampling by `factor`.
+///
+/// This is a windowed sinc lowpass filter with a cutoff frequency at the original Nyquist
+/// frequency and `taps` coefficients. The `k`-th polyphase subfilter used for calculating the
+/// `k`-th output sample per input sample consists of the coefficients `k`, `k + factor`,
+/// `k + 2 * factor`, ...
+///
+/// This is the same filter as used by libebur128 with `taps + 1` coefficients, whose last
+/// coefficient is always zero and is dropped here.
+// Keep in sync with `examples/generate_interp_coefficients.rs`
+pub fn coefficients(factor: usize, taps: usize) -> Vec<f32> {
+    (0..taps)
+        .map(|j| {
+            let j = j as f64;
+            // Calculate Hanning window,
+            // Ignore one tap. (Last tap is zero anyways, and we want to hit an even multiple of 48)
+            let window = taps as f64;
+            let w = 0.5 * (1.0 - f64::cos(2.0 * PI * j / window));
+
+            // Calculate sinc and apply hanning window
+            let m = j - window / 2.0;
+            let c = if m.abs() > ALMOST_ZERO {
+                w * f64::sin(m * PI / factor as f64) / (m * PI / factor as f64)
+            } else {
+                w
+            };
+
+            c as f32
+        })
+        .collect()
+}
+
+/// Call `func` with the [`coefficients`] for the given upsampling factor and taps.
+///
+/// The coefficients of the factors used for the true peak are precomputed, as calculating them
+/// involves a sine and cosine per coefficient and is thus a significant part of creating many
+/// instances.
+fn with_coefficients(factor: usize, taps: usize, func: impl FnOnce(&[f32])) {
+    if taps == TAPS {
+        if let Some(index) = crate::interp_coefficients::FACTORS
+            .iter()
+            .position(|f| *f == factor)
+        {
+            return func(&crate::interp_coefficients::COEFFICIENTS[index]);
+        }
+    }
+
+    func(&coefficients(factor, taps))
+}
+
 #[derive(Debug, Clone)]
 pub struct InterpF<const ACTIVE_TAPS: usize, const FACTOR: usize, F: FrameAccumulator> {
     filter: [[f32; FACTOR]; ACTIVE_TAPS],
@@ -92,22 +142,13 @@ where
         assert_eq!(ACTIVE_TAPS * FACTOR, TAPS);
 
         let mut filter: [[_; FACTOR]; ACTIVE_TAPS] = [[0f32; FACTOR]; ACTIVE_TAPS];
-        for (j, coeff) in filter.iter_mut().flat_map(|x| x.iter_mut()).enumerate() {
-            let j = j as f64;
-            // Calculate Hanning window,
-            let window = TAPS + 1;
-            // Ignore one tap. (Last tap is zero anyways, and we want to hit an even multiple of 48)
-            let window = (window - 1) as f64;
-            let w = 0.5 * (1.0 - f64::cos(2.0 * PI * j / window));
-
-            // Calculate sinc and apply hanning window
-            let m = j - window / 2.0;
-            *coeff = if m.abs() > ALMOST_ZERO {
-                w * f64::sin(m * PI / FACTOR as f64) / (m * PI / FACTOR as f64)
-            } else {
-                w
-            } as f32;
-        }
+        with_coefficients(FACTOR, TAPS, |coefficients| {
+            for (coeff, c) in
+                Iterator::zip(filter.iter_mut().flat_map(|x| x.iter_mut()), coefficients)
+            {
+                *coeff = *c;
+            }
+        });
 
         Self {
             filter,
@@ -135,6 +176,138 @@ where
     pub fn reset(&mut self) {
         self.buffer = RollingBuffer::new();
     }
+
+    /// Latency of the interpolator in input samples.
+    ///
+    /// The interpolation filter is a linear-phase FIR filter with its center at half of its
+    /// `TAPS` length, which results in a group delay of `TAPS / 2` output samples or
+    /// `ACTIVE_TAPS / 2` input samples. The `k`-th output sample of [`InterpF::interpolate`]
+    /// for input sample `n` therefore corresponds to the position
+    /// `n - latency_samples() + k / FACTOR` of the input signal.
+    pub fn latency_samples(&self) -> f64 {
+        ACTIVE_TAPS as f64 / 2.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    fn impulse_response_peak<const ACTIVE_TAPS: usize, const FACTOR: usize>() -> (usize, f64) {
+        let mut interp = InterpF::<ACTIVE_TAPS, FACTOR, [f32; 1]>::new();
+
+        let mut max = (0, 0.0);
+        for n in 0..ACTIVE_TAPS {
+            let input = if n == 0 { 1.0 } else { 0.0 };
+            for (k, [v]) in interp.interpolate([input]).iter().enumerate() {
+                if *v > max.1 {
+                    max = (n * FACTOR + k, *v);
+                }
+            }
+        }
+
+        (max.0, interp.latency_samples())
+    }
+
+    #[test]
+    fn precomputed_coefficients() {
+        // The precomputed coefficients must be exactly the same as the calculated ones,
+        // otherwise `examples/generate_interp_coefficients.rs` needs to be run again
+        for factor in crate::interp_coefficients::FACTORS.iter() {
+            let mut precomputed = Vec::new();
+            with_coefficients(*factor, TAPS, |c| precomputed.extend_from_slice(c));
+            assert_eq!(precomputed, coefficients(*factor, TAPS), "{}", factor);
+        }
+    }
+
+    /// Coefficients of the 4x interpolator of libebur128, which uses 49 taps.
+    const LIBEBUR128_COEFFICIENTS_4X: [f64; 49] = [
+        0.0,
+        -1.674419759e-04,
+        -9.860133051e-04,
+        -1.631726164e-03,
+        2.611279986e-18,
+        4.895983143e-03,
+        1.035897857e-02,
+        1.035995497e-02,
+        -9.745429581e-18,
+        -1.852600594e-02,
+        -3.370360363e-02,
+        -3.010774829e-02,
+        1.949085916e-17,
+        4.626505349e-02,
+        8.013890939e-02,
+        6.915846968e-02,
+        -2.923628874e-17,
+        -1.034567260e-01,
+        -1.811296551e-01,
+        -1.614585273e-01,
+        3.637043834e-17,
+        2.886833556e-01,
+        6.257736260e-01,
+        8.964651507e-01,
+        1.000000000e+00,
+        8.964651507e-01,
+        6.257736260e-01,
+        2.886833556e-01,
+        3.637043834e-17,
+        -1.614585273e-01,
+        -1.811296551e-01,
+        -1.034567260e-01,
+        -2.923628874e-17,
+        6.915846968e-02,
+        8.013890939e-02,
+        4.626505349e-02,
+        1.949085916e-17,
+        -3.010774829e-02,
+        -3.370360363e-02,
+        -1.852600594e-02,
+        -9.745429581e-18,
+        1.035995497e-02,
+        1.035897857e-02,
+        4.895983143e-03,
+        2.611279986e-18,
+        -1.631726164e-03,
+        -9.860133051e-04,
+        -1.674419759e-04,
+        0.0,
+    ];
+
+    #[test]
+    fn coefficients_4x() {
+        let coeffs = coefficients(4, TAPS);
+        assert_eq!(coeffs.len(), 48);
+
+        for (c, expected) in Iterator::zip(coeffs.iter(), LIBEBUR128_COEFFICIENTS_4X.iter()) {
+            assert_float_eq!(*c as f64, *expected, abs <= 0.000001);
+        }
+        assert_eq!(LIBEBUR128_COEFFICIENTS_4X[48], 0.0);
+
+        // The filter is symmetric around its center
+        for j in 1..24 {
+            assert_float_eq!(coeffs[24 - j], coeffs[24 + j], abs <= 0.000001);
+        }
+        assert_eq!(coeffs[24], 1.0);
+
+        // And used as such by the interpolator
+        let interp = InterpF::<12, 4, [f32; 1]>::new();
+        assert_eq!(
+            interp.filter.iter().flatten().copied().collect::<Vec<_>>(),
+            coeffs
+        );
+    }
+
+    #[test]
+    fn latency() {
+        let (peak, latency) = impulse_response_peak::<12, 4>();
+        assert_eq!(latency, 6.0);
+        assert_eq!(peak, 6 * 4);
+
+        let (peak, latency) = impulse_response_peak::<24, 2>();
+        assert_eq!(latency, 12.0);
+        assert_eq!(peak, 12 * 2);
+    }
 }
 
 #[cfg(feature = "c-tests")]
@@ -0,0 +1,130 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// DO NOT EDIT: This file is autogenerated by `examples/generate_interp_coefficients.rs`
+
+/// Upsampling factors with precomputed interpolator coefficients.
+pub static FACTORS: [usize; 2] = [2, 4];
+
+/// Coefficients of the interpolation filter with 48 taps for each of the factors in
+/// `FACTORS`.
+pub static COEFFICIENTS: [[f32; 48]; 2] = [
+    [
+        -0.0,
+        -0.00011839936,
+        2.4156464e-18,
+        0.0011538046,
+        -2.61128e-18,
+        -0.0034619828,
+        5.7087407e-18,
+        0.0073255943,
+        -9.745429e-18,
+        -0.013099864,
+        1.4446253e-17,
+        0.021289393,
+        -1.9490858e-17,
+        -0.032714333,
+        2.4535464e-17,
+        0.048902422,
+        -2.923629e-17,
+        -0.073154956,
+        3.327298e-17,
+        0.11416842,
+        -3.6370437e-17,
+        -0.20412996,
+        3.8317583e-17,
+        0.6338966,
+        1.0,
+        0.6338966,
+        3.8317583e-17,
+        -0.20412996,
+        -3.6370437e-17,
+        0.11416842,
+        3.327298e-17,
+        -0.073154956,
+        -2.923629e-17,
+        0.048902422,
+        2.4535464e-17,
+        -0.032714333,
+        -1.9490858e-17,
+        0.021289393,
+        1.4446253e-17,
+        -0.013099864,
+        -9.745429e-18,
+        0.0073255943,
+        5.7087407e-18,
+        -0.0034619828,
+        -2.61128e-18,
+        0.0011538046,
+        2.4156464e-18,
+        -0.00011839936,
+    ],
+    [
+        -0.0,
+        -0.00016744198,
+        -0.0009860133,
+        -0.0016317262,
+        2.61128e-18,
+        0.0048959833,
+        0.010358979,
+        0.010359955,
+        -9.745429e-18,
+        -0.018526006,
+        -0.033703603,
+        -0.030107748,
+        1.9490858e-17,
+        0.046265054,
+        0.08013891,
+        0.06915847,
+        -2.923629e-17,
+        -0.10345673,
+        -0.18112965,
+        -0.16145852,
+        3.6370437e-17,
+        0.28868335,
+        0.6257736,
+        0.8964651,
+        1.0,
+        0.8964651,
+        0.6257736,
+        0.28868335,
+        3.6370437e-17,
+        -0.16145852,
+        -0.18112965,
+        -0.10345673,
+        -2.923629e-17,
+        0.06915847,
+        0.08013891,
+        0.046265054,
+        1.9490858e-17,
+        -0.030107748,
+        -0.033703603,
+        -0.018526006,
+        -9.745429e-18,
+        0.010359955,
+        0.010358979,
+        0.0048959833,
+        2.61128e-18,
+        -0.0016317262,
+        -0.0009860133,
+        -0.00016744198,
+    ],
+];
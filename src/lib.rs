@@ -34,9 +34,48 @@
 //!   * True peak scanning
 //!   * Supports all samplerates by recalculation of the filter coefficients
 
+// Trace-level logging of internal decisions if the `log` feature is enabled, no-op otherwise.
+#[cfg(feature = "log")]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        log::trace!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! trace {
+    ($($arg:tt)*) => {};
+}
+
 mod ebur128;
 pub use self::ebur128::*;
 
+mod measurement;
+pub use self::measurement::*;
+
+mod frame_source;
+pub use self::frame_source::*;
+
+mod stem_group;
+pub use self::stem_group::*;
+
+mod channel_profile;
+pub use self::channel_profile::*;
+
+mod fingerprint;
+pub use self::fingerprint::*;
+
+mod reader;
+pub use self::reader::*;
+
+#[cfg(feature = "resampling")]
+mod resampler;
+
+#[cfg(feature = "async")]
+mod stream;
+#[cfg(feature = "async")]
+pub use self::stream::*;
+
 #[cfg(feature = "internal-tests")]
 pub mod interp;
 #[cfg(not(feature = "internal-tests"))]
@@ -55,6 +94,11 @@ pub(crate) mod history;
 #[allow(clippy::excessive_precision)]
 mod histogram_bins;
 
+#[allow(clippy::excessive_precision)]
+mod biquads;
+
+mod interp_coefficients;
+
 #[cfg(feature = "internal-tests")]
 pub mod filter;
 #[cfg(not(feature = "internal-tests"))]
@@ -0,0 +1,922 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use crate::{EbuR128, Error, Mode};
+
+use std::fmt;
+
+/// Snapshot of the measurements of an [`EbuR128`](struct.EbuR128.html) instance.
+///
+/// Values that were not enabled via the [`Mode`](struct.Mode.html) of the instance are `None` or
+/// empty.
+///
+/// The `Display` implementation shows the loudness values and the maximum peaks of all channels
+/// in dB with one decimal by default. A different number of decimals can be given as the
+/// precision of the format, e.g. `{:.0}` to report whole LU. The values are rounded with
+/// [`round_loudness`](fn.round_loudness.html), while the stored values keep their full
+/// precision.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    /// Global integrated loudness in LUFS.
+    pub loudness_global: Option<f64>,
+    /// Loudness range in LU.
+    pub loudness_range: Option<f64>,
+    /// Maximum sample peak per channel.
+    pub sample_peak: Vec<f64>,
+    /// Maximum true peak per channel.
+    pub true_peak: Vec<f64>,
+}
+
+/// Difference between two [`Measurement`](struct.Measurement.html)s.
+///
+/// Loudness differences are in LU and peak differences in dB. A difference is `None` if it is not
+/// available in both measurements or if one of the values is not finite, e.g. the loudness of
+/// silence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasurementDiff {
+    /// Difference of the global integrated loudness in LU.
+    pub loudness_global: Option<f64>,
+    /// Difference of the loudness range in LU.
+    pub loudness_range: Option<f64>,
+    /// Difference of the sample peak per channel in dB.
+    pub sample_peak: Vec<Option<f64>>,
+    /// Difference of the true peak per channel in dB.
+    pub true_peak: Vec<Option<f64>>,
+}
+
+/// Reference loudness in LUFS of the R128 gain tags of Ogg Opus, as defined by
+/// [RFC 7845](https://www.rfc-editor.org/rfc/rfc7845#section-5.2.1).
+pub const R128_REFERENCE_LUFS: f64 = -23.0;
+
+/// Measured values in the layout of the R128 loudness metadata of Ogg Opus.
+///
+/// Gains are given in dB and, like in the `R128_TRACK_GAIN` comment and the output gain of the
+/// Opus header, as Q7.8 fixed-point numbers, i.e. in units of 1/256 dB rounded to the nearest
+/// integer and clamped to the range of an `i16`. All gains normalize to
+/// [`R128_REFERENCE_LUFS`](constant.R128_REFERENCE_LUFS.html) and are `None` if the integrated
+/// loudness is not available or not finite, e.g. for silence.
+///
+/// See [`Measurement::to_r128_metadata`](struct.Measurement.html#method.to_r128_metadata).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct R128Metadata {
+    /// Integrated loudness in LUFS.
+    pub loudness_lufs: Option<f64>,
+    /// Loudness range in LU.
+    pub loudness_range_lu: Option<f64>,
+    /// Maximum true peak of all channels in dBTP.
+    pub true_peak_dbtp: Option<f64>,
+    /// Gain in dB to the reference loudness.
+    pub track_gain_db: Option<f64>,
+    /// Value of the `R128_TRACK_GAIN` comment if the output gain of the Opus header is 0.
+    pub track_gain_q78: Option<i16>,
+    /// Output gain of the Opus header that applies the gain to the reference loudness during
+    /// decoding.
+    pub opus_output_gain_q78: Option<i16>,
+    /// Value of the `R128_TRACK_GAIN` comment if the output gain of the Opus header is
+    /// `opus_output_gain_q78`. The comment is relative to the output gain, so this is only the
+    /// part of the gain that couldn't be represented by the output gain because of clamping,
+    /// usually 0.
+    pub opus_track_gain_q78: Option<i16>,
+}
+
+/// Convert a gain in dB to a Q7.8 fixed-point number, clamped to the range of an `i16`.
+fn gain_to_q78(gain_db: f64) -> i16 {
+    f64::round(gain_db * 256.0).clamp(i16::MIN as f64, i16::MAX as f64) as i16
+}
+
+impl R128Metadata {
+    /// Value of the `R128_TRACK_GAIN` comment for the given output gain of the Opus header in
+    /// Q7.8 fixed-point, or `None` if the track gain is not available.
+    ///
+    /// The comment is relative to the output gain, which is applied to the decoded audio first.
+    pub fn track_gain_q78_for_output_gain(&self, output_gain_q78: i16) -> Option<i16> {
+        self.track_gain_db
+            .map(|gain_db| gain_to_q78(gain_db - output_gain_q78 as f64 / 256.0))
+    }
+}
+
+/// Sample and true peaks of an [`EbuR128`](struct.EbuR128.html) instance.
+///
+/// See [`EbuR128::peak_report`](struct.EbuR128.html#method.peak_report).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeakReport {
+    /// Maximum sample peak per channel, or empty if `Mode::SAMPLE_PEAK` is not set.
+    pub sample_peak: Vec<f64>,
+    /// Maximum true peak per channel, or empty if `Mode::TRUE_PEAK` is not set.
+    pub true_peak: Vec<f64>,
+    /// Maximum sample peak over all channels.
+    pub sample_peak_max: Option<f64>,
+    /// Maximum true peak over all channels.
+    pub true_peak_max: Option<f64>,
+}
+
+/// Plan for normalizing the loudness of audio with true peak protection.
+///
+/// See [`EbuR128::normalization_plan`](struct.EbuR128.html#method.normalization_plan).
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalizationPlan {
+    /// Measured global integrated loudness in LUFS.
+    pub loudness_lufs: f64,
+    /// Measured maximum peak of all channels in dBTP.
+    pub peak_dbtp: f64,
+    /// Target loudness in LUFS.
+    pub target_lufs: f64,
+    /// Maximum allowed true peak in dBTP.
+    pub max_tp_dbtp: f64,
+    /// Gain in dB for reaching the target loudness.
+    pub gain_db: f64,
+    /// By how much the peaks exceed the maximum true peak in dB after applying `gain_db`, or
+    /// `None` if no limiting is needed.
+    pub limiting_db: Option<f64>,
+    /// Gain in dB for getting as close as possible to the target loudness without exceeding the
+    /// maximum true peak. This is `gain_db` if no limiting is needed.
+    pub peak_safe_gain_db: f64,
+    /// Loudness in LUFS after applying `peak_safe_gain_db`. This is quieter than the target
+    /// loudness if limiting would be needed otherwise.
+    pub peak_safe_lufs: f64,
+}
+
+impl NormalizationPlan {
+    pub(crate) fn new(loudness: f64, peak_dbtp: f64, target_lufs: f64, max_tp_dbtp: f64) -> Self {
+        let gain_db = target_lufs - loudness;
+        let headroom_db = max_tp_dbtp - peak_dbtp;
+
+        let (limiting_db, peak_safe_gain_db) = if gain_db > headroom_db {
+            (Some(gain_db - headroom_db), headroom_db)
+        } else {
+            (None, gain_db)
+        };
+
+        NormalizationPlan {
+            loudness_lufs: loudness,
+            peak_dbtp,
+            target_lufs,
+            max_tp_dbtp,
+            gain_db,
+            limiting_db,
+            peak_safe_gain_db,
+            peak_safe_lufs: loudness + peak_safe_gain_db,
+        }
+    }
+}
+
+impl fmt::Display for NormalizationPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Gain: {:+.1} dB to {:.1} LUFS",
+            self.gain_db, self.target_lufs
+        )?;
+
+        match self.limiting_db {
+            Some(limiting_db) => write!(
+                f,
+                ", Limiting: {:.1} dB above {:.1} dBTP, Peak-safe gain: {:+.1} dB to {:.1} LUFS",
+                limiting_db, self.max_tp_dbtp, self.peak_safe_gain_db, self.peak_safe_lufs
+            ),
+            None => write!(f, ", Limiting: none"),
+        }
+    }
+}
+
+/// Specification of a deliverable for checking loudness compliance.
+///
+/// See [`EbuR128::check_compliance`](struct.EbuR128.html#method.check_compliance).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComplianceSpec {
+    /// Target integrated loudness in LUFS.
+    pub target_lufs: f64,
+    /// Allowed deviation from the target loudness in LU in either direction.
+    pub tolerance_lu: f64,
+    /// Maximum allowed true peak in dBTP.
+    pub max_true_peak_dbtp: f64,
+    /// Maximum allowed loudness range in LU, or `None` if it is not checked.
+    pub max_lra: Option<f64>,
+}
+
+/// Result of checking a single criterion of a [`ComplianceSpec`](struct.ComplianceSpec.html).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComplianceCheck {
+    /// Measured value.
+    pub measured: f64,
+    /// Distance of the measured value to the limit, which is positive if the criterion is met
+    /// and negative otherwise, or `None` if the measured value is not finite.
+    pub margin: Option<f64>,
+    /// Whether the criterion is met.
+    pub passed: bool,
+}
+
+/// Result of checking measurements against a [`ComplianceSpec`](struct.ComplianceSpec.html).
+///
+/// See [`EbuR128::check_compliance`](struct.EbuR128.html#method.check_compliance).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComplianceResult {
+    /// Integrated loudness in LUFS. The margin is the tolerance minus the deviation from the
+    /// target. Silence, i.e. negative infinity, never passes.
+    pub loudness: ComplianceCheck,
+    /// Maximum true peak of all channels in dBTP. The margin is the headroom to the maximum.
+    pub true_peak: ComplianceCheck,
+    /// Loudness range in LU if it is checked. The margin is the distance to the maximum.
+    pub loudness_range: Option<ComplianceCheck>,
+}
+
+impl ComplianceCheck {
+    /// Check `measured` against the upper limit `max`.
+    fn max(measured: f64, max: f64) -> Self {
+        ComplianceCheck {
+            measured,
+            margin: if measured.is_finite() {
+                Some(max - measured)
+            } else {
+                None
+            },
+            // NaN never passes, negative infinity always does
+            passed: measured <= max,
+        }
+    }
+}
+
+impl ComplianceResult {
+    pub(crate) fn new(
+        spec: &ComplianceSpec,
+        loudness: f64,
+        true_peak_dbtp: f64,
+        loudness_range: Option<f64>,
+    ) -> Self {
+        let loudness_margin = if loudness.is_finite() {
+            Some(spec.tolerance_lu - (loudness - spec.target_lufs).abs())
+        } else {
+            None
+        };
+
+        ComplianceResult {
+            loudness: ComplianceCheck {
+                measured: loudness,
+                margin: loudness_margin,
+                passed: loudness_margin.map_or(false, |margin| margin >= 0.0),
+            },
+            true_peak: ComplianceCheck::max(true_peak_dbtp, spec.max_true_peak_dbtp),
+            loudness_range: Option::zip(loudness_range, spec.max_lra)
+                .map(|(loudness_range, max_lra)| ComplianceCheck::max(loudness_range, max_lra)),
+        }
+    }
+
+    /// Whether all checked criteria are met.
+    pub fn passed(&self) -> bool {
+        self.loudness.passed
+            && self.true_peak.passed
+            && self.loudness_range.map_or(true, |check| check.passed)
+    }
+}
+
+impl fmt::Display for ComplianceResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_check(
+            f: &mut fmt::Formatter,
+            name: &str,
+            check: &ComplianceCheck,
+            unit: &str,
+            margin_unit: &str,
+        ) -> fmt::Result {
+            write!(
+                f,
+                "{name}: {:.1} {unit} ({}",
+                check.measured,
+                if check.passed { "pass" } else { "fail" }
+            )?;
+            if let Some(margin) = check.margin {
+                write!(f, ", margin {margin:+.1} {margin_unit}")?;
+            }
+            write!(f, ")")
+        }
+
+        write_check(f, "Integrated loudness", &self.loudness, "LUFS", "LU")?;
+        write!(f, ", ")?;
+        write_check(f, "True peak", &self.true_peak, "dBTP", "dB")?;
+        if let Some(ref loudness_range) = self.loudness_range {
+            write!(f, ", ")?;
+            write_check(f, "Loudness range", loudness_range, "LU", "LU")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let decimals = f.precision().unwrap_or(1);
+        let step = f64::powi(10.0, -(decimals as i32));
+        let mut separator = "";
+        let mut write_value = |f: &mut fmt::Formatter, name: &str, value: f64, unit: &str| {
+            let value = round_loudness(value, step);
+            let res = write!(f, "{separator}{name}: {value:.decimals$} {unit}");
+            separator = ", ";
+            res
+        };
+
+        if let Some(loudness_global) = self.loudness_global {
+            write_value(f, "Integrated loudness", loudness_global, "LUFS")?;
+        }
+        if let Some(loudness_range) = self.loudness_range {
+            write_value(f, "Loudness range", loudness_range, "LU")?;
+        }
+        if let Some(sample_peak) = max(&self.sample_peak) {
+            write_value(f, "Sample peak", 20.0 * f64::log10(sample_peak), "dBFS")?;
+        }
+        if let Some(true_peak) = max(&self.true_peak) {
+            write_value(f, "True peak", 20.0 * f64::log10(true_peak), "dBTP")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Round a loudness or gain `value` to a multiple of `step`, e.g. 0.1 or 1.0 LU as required by
+/// different specifications.
+///
+/// Values exactly halfway between two multiples are rounded away from zero, and a result of zero
+/// is always positive, so that e.g. `-0.04` is not reported as `-0.0`. Values that are not
+/// finite, and any value if `step` is not positive, are returned unchanged.
+pub fn round_loudness(value: f64, step: f64) -> f64 {
+    if !value.is_finite() || step.is_nan() || step <= 0.0 {
+        return value;
+    }
+
+    // Divide by the inverse of steps like 0.1 as that is exact, unlike multiplying by 0.1
+    let inverse = 1.0 / step;
+    let rounded = if inverse.fract() == 0.0 {
+        f64::round(value * inverse) / inverse
+    } else {
+        f64::round(value / step) * step
+    };
+
+    rounded + 0.0
+}
+
+/// Compare two loudness values with a tolerance in LU.
+///
+/// Negative infinity, as returned for silence, is only equal to negative infinity. `NaN` is never
+/// equal to anything.
+pub fn loudness_approx_eq(a: f64, b: f64, tol_lu: f64) -> bool {
+    if a.is_infinite() || b.is_infinite() {
+        return a == b;
+    }
+
+    (a - b).abs() <= tol_lu
+}
+
+/// Calculate the gains in dB for two tracks that bring both to their average integrated
+/// loudness, e.g. for a smooth crossfade from `a` to `b`.
+///
+/// The gains are symmetric: the louder track is attenuated by half the loudness difference and
+/// the quieter one amplified by the same amount. If either track is silent, i.e. its loudness is
+/// negative infinity, there is no average loudness and both gains are 0 dB.
+///
+/// Returns `Error::InvalidMode` if `Mode::I` is not set for both instances.
+pub fn crossfade_gains(a: &EbuR128, b: &EbuR128) -> Result<(f64, f64), Error> {
+    let loudness_a = a.loudness_global()?;
+    let loudness_b = b.loudness_global()?;
+
+    if !loudness_a.is_finite() || !loudness_b.is_finite() {
+        return Ok((0.0, 0.0));
+    }
+
+    let gain = (loudness_b - loudness_a) / 2.0;
+    Ok((gain, -gain))
+}
+
+/// Calculate the integrated loudness in LUFS of the given energies of gating blocks.
+///
+/// Each energy is the mean square of the K-weighted samples of a 400ms block, summed over the
+/// channels with their weights, as measured by
+/// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global). The energies are
+/// gated exactly like there: first with the absolute gate at -70 LUFS and then with the relative
+/// gate 10 LU below the loudness of the remaining blocks. This allows to reuse the gating for
+/// energies that were calculated elsewhere, e.g. by a different filter implementation.
+///
+/// Returns negative infinity if no energy is above the gates and `NaN` if any energy is `NaN`.
+pub fn integrated_loudness_from_energies(energies: &[f64]) -> f64 {
+    let mut history = crate::history::History::new(false, usize::MAX);
+    for energy in energies {
+        history.add(*energy);
+    }
+
+    history.gated_loudness()
+}
+
+fn delta(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) if a.is_finite() && b.is_finite() => Some(a - b),
+        _ => None,
+    }
+}
+
+fn peak_deltas(a: &[f64], b: &[f64]) -> Vec<Option<f64>> {
+    Iterator::zip(a.iter(), b.iter())
+        .map(|(a, b)| delta(Some(20.0 * f64::log10(*a)), Some(20.0 * f64::log10(*b))))
+        .collect()
+}
+
+fn max(peaks: &[f64]) -> Option<f64> {
+    peaks.iter().copied().reduce(f64::max)
+}
+
+impl PeakReport {
+    /// Create a report of the current peaks of `ebu`.
+    pub fn new(ebu: &EbuR128) -> Self {
+        let Measurement {
+            sample_peak,
+            true_peak,
+            ..
+        } = Measurement::new(ebu);
+
+        PeakReport {
+            sample_peak_max: max(&sample_peak),
+            true_peak_max: max(&true_peak),
+            sample_peak,
+            true_peak,
+        }
+    }
+}
+
+impl Measurement {
+    /// Create a snapshot of the current measurements of `ebu`.
+    pub fn new(ebu: &EbuR128) -> Self {
+        let mode = ebu.mode();
+
+        let peaks = |enabled: bool, peak: &dyn Fn(u32) -> f64| {
+            if enabled {
+                (0..ebu.channels()).map(peak).collect()
+            } else {
+                Vec::new()
+            }
+        };
+
+        Measurement {
+            loudness_global: ebu.loudness_global().ok(),
+            loudness_range: ebu.loudness_range().ok(),
+            sample_peak: peaks(mode.contains(Mode::SAMPLE_PEAK), &|c| {
+                ebu.sample_peak(c).unwrap()
+            }),
+            true_peak: peaks(mode.contains(Mode::TRUE_PEAK), &|c| {
+                ebu.true_peak(c).unwrap()
+            }),
+        }
+    }
+
+    /// Convert this measurement to the layout of the R128 loudness metadata of Ogg Opus.
+    ///
+    /// The Opus header has an output gain that decoders always apply, and the `R128_TRACK_GAIN`
+    /// comment gives the gain relative to it. Encoders either keep the output gain at 0 and write
+    /// the whole gain into the comment, i.e. `track_gain_q78`, or apply the gain with the output
+    /// gain, i.e. `opus_output_gain_q78`, and write the remainder `opus_track_gain_q78` into the
+    /// comment. Other output gains are supported by
+    /// [`R128Metadata::track_gain_q78_for_output_gain`](struct.R128Metadata.html#method.track_gain_q78_for_output_gain).
+    pub fn to_r128_metadata(&self) -> R128Metadata {
+        let loudness = self.loudness_global.filter(|loudness| loudness.is_finite());
+        let track_gain_db = loudness.map(|loudness| R128_REFERENCE_LUFS - loudness);
+        let true_peak_dbtp = self
+            .true_peak
+            .iter()
+            .copied()
+            .reduce(f64::max)
+            .map(|peak| 20.0 * f64::log10(peak));
+
+        let mut metadata = R128Metadata {
+            loudness_lufs: self.loudness_global,
+            loudness_range_lu: self.loudness_range,
+            true_peak_dbtp,
+            track_gain_db,
+            track_gain_q78: track_gain_db.map(gain_to_q78),
+            opus_output_gain_q78: track_gain_db.map(gain_to_q78),
+            opus_track_gain_q78: None,
+        };
+        metadata.opus_track_gain_q78 = metadata
+            .opus_output_gain_q78
+            .and_then(|output_gain| metadata.track_gain_q78_for_output_gain(output_gain));
+
+        metadata
+    }
+
+    /// Calculate the difference of this measurement to `other`, i.e. `self - other`.
+    ///
+    /// This is useful for checking the effect of processing, e.g. normalization, by comparing the
+    /// measurement of the processed audio against the one of the original audio.
+    pub fn diff(&self, other: &Measurement) -> MeasurementDiff {
+        MeasurementDiff {
+            loudness_global: delta(self.loudness_global, other.loudness_global),
+            loudness_range: delta(self.loudness_range, other.loudness_range),
+            sample_peak: peak_deltas(&self.sample_peak, &other.sample_peak),
+            true_peak: peak_deltas(&self.true_peak, &other.true_peak),
+        }
+    }
+}
+
+impl fmt::Display for MeasurementDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_value(f: &mut fmt::Formatter, value: Option<f64>, unit: &str) -> fmt::Result {
+            match value {
+                Some(v) => write!(f, "{v:+.1} {unit}"),
+                None => write!(f, "n/a"),
+            }
+        }
+
+        write!(f, "Integrated loudness: ")?;
+        write_value(f, self.loudness_global, "LU")?;
+        write!(f, ", Loudness range: ")?;
+        write_value(f, self.loudness_range, "LU")?;
+
+        for (name, peaks) in [
+            ("Sample peak", &self.sample_peak),
+            ("True peak", &self.true_peak),
+        ] {
+            if peaks.is_empty() {
+                continue;
+            }
+
+            write!(f, ", {name}:")?;
+            for (c, peak) in peaks.iter().enumerate() {
+                write!(f, " {c}: ")?;
+                write_value(f, *peak, "dB")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    #[test]
+    fn diff() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
+            out[1] = val;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        let original = Measurement::new(&ebu);
+
+        for v in data.iter_mut() {
+            *v *= 0.5;
+        }
+        ebu.reset();
+        ebu.add_frames_f32(&data).unwrap();
+        let processed = Measurement::new(&ebu);
+
+        let diff = processed.diff(&original);
+        assert_float_eq!(
+            diff.loudness_global.unwrap(),
+            20.0 * f64::log10(0.5),
+            abs <= 0.000001
+        );
+        assert_float_eq!(diff.loudness_range.unwrap(), 0.0, abs <= 0.000001);
+        assert_eq!(diff.sample_peak.len(), 2);
+        assert_float_eq!(
+            diff.sample_peak[0].unwrap(),
+            20.0 * f64::log10(0.5),
+            abs <= 0.000001
+        );
+        assert_eq!(diff.true_peak.len(), 2);
+        let s = format!("{diff}");
+        assert!(s.starts_with("Integrated loudness: -6.0 LU, Loudness range: "));
+        assert!(s.ends_with("Sample peak: 0: -6.0 dB 1: -6.0 dB, True peak: 0: -6.0 dB 1: -6.0 dB"));
+
+        ebu.reset();
+        let silence = Measurement::new(&ebu);
+        let diff = silence.diff(&original);
+        assert_eq!(diff.loudness_global, None);
+        assert_eq!(diff.sample_peak, vec![None, None]);
+    }
+
+    #[test]
+    fn r128_metadata() {
+        let measurement = Measurement {
+            loudness_global: Some(-16.3),
+            loudness_range: Some(7.5),
+            sample_peak: vec![0.5, 0.25],
+            true_peak: vec![0.5, 1.0],
+        };
+        let metadata = measurement.to_r128_metadata();
+        assert_eq!(metadata.loudness_lufs, Some(-16.3));
+        assert_eq!(metadata.loudness_range_lu, Some(7.5));
+        assert_eq!(metadata.true_peak_dbtp, Some(0.0));
+        assert_float_eq!(metadata.track_gain_db.unwrap(), -6.7, abs <= 0.000001);
+        // -6.7 * 256 = -1715.2
+        assert_eq!(metadata.track_gain_q78, Some(-1715));
+        assert_eq!(metadata.opus_output_gain_q78, Some(-1715));
+        assert_eq!(metadata.opus_track_gain_q78, Some(0));
+        // An output gain of -3 dB leaves -3.7 dB for the comment
+        assert_eq!(metadata.track_gain_q78_for_output_gain(-768), Some(-947));
+
+        // Gains beyond the range of the fixed-point numbers are clamped, and the comment
+        // contains the remainder
+        let measurement = Measurement {
+            loudness_global: Some(-200.0),
+            loudness_range: None,
+            sample_peak: Vec::new(),
+            true_peak: Vec::new(),
+        };
+        let metadata = measurement.to_r128_metadata();
+        assert_eq!(metadata.true_peak_dbtp, None);
+        assert_eq!(metadata.track_gain_q78, Some(i16::MAX));
+        assert_eq!(metadata.opus_output_gain_q78, Some(i16::MAX));
+        assert_eq!(metadata.opus_track_gain_q78, Some(12_545));
+
+        let measurement = Measurement {
+            loudness_global: Some(-f64::INFINITY),
+            ..measurement
+        };
+        let metadata = measurement.to_r128_metadata();
+        assert_eq!(metadata.loudness_lufs, Some(-f64::INFINITY));
+        assert_eq!(metadata.track_gain_db, None);
+        assert_eq!(metadata.track_gain_q78, None);
+        assert_eq!(metadata.opus_output_gain_q78, None);
+        assert_eq!(metadata.opus_track_gain_q78, None);
+    }
+
+    #[test]
+    fn normalization_plan() {
+        let plan = NormalizationPlan::new(-30.0, -12.0, -23.0, -1.0);
+        assert_eq!(plan.gain_db, 7.0);
+        assert_eq!(plan.limiting_db, None);
+        assert_eq!(plan.peak_safe_gain_db, 7.0);
+        assert_eq!(plan.peak_safe_lufs, -23.0);
+        assert_eq!(
+            format!("{plan}"),
+            "Gain: +7.0 dB to -23.0 LUFS, Limiting: none"
+        );
+
+        // Peaks would end up at +1 dBTP
+        let plan = NormalizationPlan::new(-30.0, -6.0, -23.0, -1.0);
+        assert_eq!(plan.gain_db, 7.0);
+        assert_eq!(plan.limiting_db, Some(2.0));
+        assert_eq!(plan.peak_safe_gain_db, 5.0);
+        assert_eq!(plan.peak_safe_lufs, -25.0);
+        assert_eq!(
+            format!("{plan}"),
+            "Gain: +7.0 dB to -23.0 LUFS, Limiting: 2.0 dB above -1.0 dBTP, Peak-safe gain: +5.0 dB to -25.0 LUFS"
+        );
+
+        // Attenuation that still exceeds the maximum peak
+        let plan = NormalizationPlan::new(-10.0, 4.0, -14.0, -1.0);
+        assert_eq!(plan.gain_db, -4.0);
+        assert_eq!(plan.limiting_db, Some(1.0));
+        assert_eq!(plan.peak_safe_gain_db, -5.0);
+        assert_eq!(plan.peak_safe_lufs, -15.0);
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.normalization_plan(-23.0, -1.0), Ok(None));
+        let data = (0..48_000 * 2 * 2)
+            .map(|i| 0.5 * f32::sin(2.0 * std::f32::consts::PI * 440.0 * (i / 2) as f32 / 48_000.0))
+            .collect::<Vec<_>>();
+        ebu.add_frames_f32(&data).unwrap();
+        let plan = ebu.normalization_plan(-23.0, -1.0).unwrap().unwrap();
+        assert_eq!(plan.loudness_lufs, ebu.loudness_global().unwrap());
+        assert_float_eq!(
+            plan.peak_dbtp,
+            20.0 * f64::log10(ebu.true_peak(0).unwrap()),
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            plan.peak_safe_lufs,
+            plan.loudness_lufs + plan.peak_safe_gain_db,
+            abs <= 0.000001
+        );
+
+        let ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(
+            ebu.normalization_plan(-23.0, -1.0),
+            Err(crate::Error::InvalidMode)
+        );
+    }
+
+    #[test]
+    fn crossfade_gains() {
+        let data = (0..48_000 * 2 * 2)
+            .map(|i| f32::sin(2.0 * std::f32::consts::PI * 440.0 * (i / 2) as f32 / 48_000.0))
+            .collect::<Vec<_>>();
+        let quiet_data = data.iter().map(|v| v * 0.25).collect::<Vec<_>>();
+
+        let mut loud = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        loud.add_frames_f32(&data).unwrap();
+        let mut quiet = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        quiet.add_frames_f32(&quiet_data).unwrap();
+
+        let difference = 20.0 * f64::log10(0.25);
+        let (gain_loud, gain_quiet) = super::crossfade_gains(&loud, &quiet).unwrap();
+        assert_float_eq!(gain_loud, difference / 2.0, abs <= 0.000001);
+        assert_float_eq!(gain_quiet, -difference / 2.0, abs <= 0.000001);
+        assert_float_eq!(
+            loud.loudness_global().unwrap() + gain_loud,
+            quiet.loudness_global().unwrap() + gain_quiet,
+            abs <= 0.000001
+        );
+
+        let silent = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(super::crossfade_gains(&loud, &silent), Ok((0.0, 0.0)));
+        assert_eq!(super::crossfade_gains(&silent, &silent), Ok((0.0, 0.0)));
+
+        let momentary = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(
+            super::crossfade_gains(&loud, &momentary),
+            Err(Error::InvalidMode)
+        );
+    }
+
+    #[test]
+    fn approx_eq() {
+        assert!(loudness_approx_eq(-23.0, -23.05, 0.1));
+        assert!(loudness_approx_eq(-23.05, -23.0, 0.1));
+        assert!(!loudness_approx_eq(-23.0, -23.2, 0.1));
+        assert!(loudness_approx_eq(-f64::INFINITY, -f64::INFINITY, 0.1));
+        assert!(!loudness_approx_eq(-f64::INFINITY, -70.0, 100.0));
+        assert!(!loudness_approx_eq(-70.0, -f64::INFINITY, 100.0));
+        assert!(!loudness_approx_eq(f64::NAN, f64::NAN, 0.1));
+        assert!(!loudness_approx_eq(-23.0, f64::NAN, 0.1));
+    }
+
+    #[test]
+    fn compliance() {
+        let spec = ComplianceSpec {
+            target_lufs: -23.0,
+            tolerance_lu: 0.5,
+            max_true_peak_dbtp: -1.0,
+            max_lra: Some(20.0),
+        };
+
+        let result = ComplianceResult::new(&spec, -23.25, -3.0, Some(8.0));
+        assert!(result.passed());
+        assert_eq!(result.loudness.margin, Some(0.25));
+        assert_eq!(result.true_peak.margin, Some(2.0));
+        assert_eq!(result.loudness_range.unwrap().margin, Some(12.0));
+        assert_eq!(
+            format!("{result}"),
+            "Integrated loudness: -23.2 LUFS (pass, margin +0.2 LU), \
+             True peak: -3.0 dBTP (pass, margin +2.0 dB), \
+             Loudness range: 8.0 LU (pass, margin +12.0 LU)"
+        );
+
+        // Too loud and clipping
+        let result = ComplianceResult::new(&spec, -22.0, 0.5, Some(8.0));
+        assert!(!result.passed());
+        assert!(!result.loudness.passed);
+        assert_eq!(result.loudness.margin, Some(-0.5));
+        assert!(!result.true_peak.passed);
+        assert_eq!(result.true_peak.margin, Some(-1.5));
+
+        // Silence
+        let result = ComplianceResult::new(&spec, -f64::INFINITY, -f64::INFINITY, Some(0.0));
+        assert!(!result.passed());
+        assert!(!result.loudness.passed);
+        assert_eq!(result.loudness.margin, None);
+        assert!(result.true_peak.passed);
+        assert_eq!(result.true_peak.margin, None);
+        assert_eq!(
+            format!("{result}"),
+            "Integrated loudness: -inf LUFS (fail), True peak: -inf dBTP (pass), \
+             Loudness range: 0.0 LU (pass, margin +20.0 LU)"
+        );
+
+        let result = ComplianceResult::new(&spec, f64::NAN, f64::NAN, None);
+        assert!(!result.loudness.passed);
+        assert!(!result.true_peak.passed);
+        assert_eq!(result.loudness_range, None);
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::LRA | Mode::TRUE_PEAK).unwrap();
+        let data = (0..48_000 * 5 * 2)
+            .map(|i| 0.1 * f32::sin(2.0 * std::f32::consts::PI * 440.0 * (i / 2) as f32 / 48_000.0))
+            .collect::<Vec<_>>();
+        ebu.add_frames_f32(&data).unwrap();
+        let result = ebu.check_compliance(&spec).unwrap();
+        assert_eq!(result.loudness.measured, ebu.loudness_global().unwrap());
+        assert_float_eq!(
+            result.true_peak.measured,
+            20.0 * f64::log10(ebu.true_peak(0).unwrap()),
+            abs <= 0.000001
+        );
+        assert_eq!(
+            result.loudness_range.unwrap().measured,
+            ebu.loudness_range().unwrap()
+        );
+
+        let ebu = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.check_compliance(&spec), Err(crate::Error::InvalidMode));
+        let spec = ComplianceSpec {
+            max_lra: None,
+            ..spec
+        };
+        assert_eq!(ebu.check_compliance(&spec).unwrap().loudness_range, None);
+        let ebu = EbuR128::new(2, 48_000, Mode::I | Mode::SAMPLE_PEAK).unwrap();
+        assert_eq!(ebu.check_compliance(&spec), Err(crate::Error::InvalidMode));
+    }
+
+    #[test]
+    fn integrated_loudness_from_energies() {
+        let energy = |loudness: f64| f64::powf(10.0, (loudness + 0.691) / 10.0);
+
+        assert_eq!(
+            super::integrated_loudness_from_energies(&[]),
+            f64::NEG_INFINITY
+        );
+        assert_eq!(
+            super::integrated_loudness_from_energies(&[energy(-80.0)]),
+            f64::NEG_INFINITY
+        );
+
+        let loudness = super::integrated_loudness_from_energies(&[energy(-20.0)]);
+        assert_float_eq!(loudness, -20.0, abs <= 0.000001);
+
+        // Blocks below the absolute and the relative gate are ignored
+        let mut energies = vec![energy(-20.0); 10];
+        energies.extend_from_slice(&[energy(-40.0); 10]);
+        energies.extend_from_slice(&[energy(-80.0); 10]);
+        assert_eq!(
+            super::integrated_loudness_from_energies(&energies),
+            loudness
+        );
+
+        // Blocks above the relative gate are averaged by their energy
+        let energies = [energy(-20.0), energy(-25.0)];
+        let expected = 10.0 * f64::log10((energies[0] + energies[1]) / 2.0) - 0.691;
+        assert_float_eq!(
+            super::integrated_loudness_from_energies(&energies),
+            expected,
+            abs <= 0.000001
+        );
+
+        assert!(super::integrated_loudness_from_energies(&[energy(-20.0), f64::NAN]).is_nan());
+    }
+
+    #[test]
+    fn round_loudness() {
+        assert_eq!(super::round_loudness(-23.04, 0.1), -23.0);
+        assert_eq!(super::round_loudness(-22.96, 0.1), -23.0);
+        assert_eq!(super::round_loudness(-22.94, 0.1), -22.9);
+        assert_eq!(super::round_loudness(-22.94, 1.0), -23.0);
+        assert_eq!(super::round_loudness(-23.5, 1.0), -24.0);
+        assert_eq!(super::round_loudness(7.2, 0.5), 7.0);
+        assert_eq!(super::round_loudness(7.3, 0.5), 7.5);
+        assert!(super::round_loudness(-0.04, 0.1).is_sign_positive());
+        assert_eq!(super::round_loudness(-f64::INFINITY, 0.1), -f64::INFINITY);
+        assert_eq!(super::round_loudness(-23.04, 0.0), -23.04);
+        assert!(super::round_loudness(f64::NAN, 0.1).is_nan());
+    }
+
+    #[test]
+    fn measurement_display() {
+        let measurement = Measurement {
+            loudness_global: Some(-23.04),
+            loudness_range: Some(6.46),
+            sample_peak: vec![0.5, 0.25],
+            true_peak: vec![0.5, 1.0],
+        };
+        assert_eq!(
+            measurement.to_string(),
+            "Integrated loudness: -23.0 LUFS, Loudness range: 6.5 LU, \
+             Sample peak: -6.0 dBFS, True peak: 0.0 dBTP"
+        );
+        assert_eq!(
+            format!("{:.0}", measurement),
+            "Integrated loudness: -23 LUFS, Loudness range: 6 LU, \
+             Sample peak: -6 dBFS, True peak: 0 dBTP"
+        );
+        // The stored values are not rounded
+        assert_eq!(measurement.loudness_global, Some(-23.04));
+
+        let measurement = Measurement {
+            loudness_global: Some(-f64::INFINITY),
+            loudness_range: None,
+            sample_peak: Vec::new(),
+            true_peak: Vec::new(),
+        };
+        assert_eq!(measurement.to_string(), "Integrated loudness: -inf LUFS");
+    }
+}
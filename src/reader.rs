@@ -0,0 +1,367 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use crate::utils::Sample;
+use crate::{EbuR128, Measurement, Mode};
+
+use std::io;
+
+/// Sample format of raw PCM data, see
+/// [`EbuR128::analyze_reader`](struct.EbuR128.html#method.analyze_reader).
+///
+/// All formats are interleaved and in native byte order, e.g. `s16le` or `f32le` in the
+/// terminology of `ffmpeg` on little-endian platforms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// Signed 16 bit integer samples.
+    S16,
+    /// Signed 32 bit integer samples.
+    S32,
+    /// 32 bit floating point samples in the range `[-1.0, 1.0]`.
+    F32,
+    /// 64 bit floating point samples in the range `[-1.0, 1.0]`.
+    F64,
+}
+
+impl SampleFormat {
+    /// Size of a single sample in bytes.
+    pub fn bytes_per_sample(&self) -> usize {
+        match *self {
+            SampleFormat::S16 => 2,
+            SampleFormat::S32 => 4,
+            SampleFormat::F32 => 4,
+            SampleFormat::F64 => 8,
+        }
+    }
+}
+
+/// Number of frames that are read at once.
+const READ_FRAMES: usize = 4096;
+
+impl EbuR128 {
+    /// Analyze raw PCM data from `reader` until the end of the stream.
+    ///
+    /// The integrated loudness, the loudness range and the sample and true peaks are measured,
+    /// i.e. the data is analyzed with `Mode::I | Mode::LRA | Mode::TRUE_PEAK`. Reads don't have
+    /// to return whole frames, and reads that are interrupted are retried.
+    ///
+    /// Returns an error of kind `InvalidInput` if the analyzer can't be created for the given
+    /// number of channels and sample rate, and of kind `UnexpectedEof` if the stream doesn't end
+    /// with a whole frame. The underlying [`Error`](enum.Error.html) is available via
+    /// `io::Error::get_ref()` in the former case. Errors of `reader` are returned as is.
+    pub fn analyze_reader<R: io::Read>(
+        mut reader: R,
+        format: SampleFormat,
+        channels: u32,
+        rate: u32,
+    ) -> io::Result<Measurement> {
+        let mut ebu = EbuR128::new(channels, rate, Mode::I | Mode::LRA | Mode::TRUE_PEAK)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+        let frame_bytes = format.bytes_per_sample() * channels as usize;
+        let mut buffer = vec![0u8; READ_FRAMES * frame_bytes];
+        // Number of bytes at the start of the buffer that were read but not analyzed yet
+        let mut filled = 0;
+
+        loop {
+            let read = match reader.read(&mut buffer[filled..]) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            filled += read;
+
+            // Keep a trailing partial frame for the next read
+            let complete = filled - filled % frame_bytes;
+            add_bytes(&mut ebu, format, &buffer[..complete])
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            buffer.copy_within(complete..filled, 0);
+            filled -= complete;
+        }
+
+        if filled > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "stream ended with a partial frame",
+            ));
+        }
+
+        Ok(Measurement::new(&ebu))
+    }
+
+    /// Analyze raw PCM data from the file descriptor `fd` until the end of the stream, e.g. from
+    /// standard input for `ffmpeg -i input -f f32le - | loudness-tool`.
+    ///
+    /// See [`EbuR128::analyze_reader`](struct.EbuR128.html#method.analyze_reader) for what is
+    /// measured and the returned errors. The file descriptor is not closed.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open file descriptor that is not closed while it is read.
+    #[cfg(unix)]
+    pub unsafe fn analyze_fd(
+        fd: std::os::unix::io::RawFd,
+        format: SampleFormat,
+        channels: u32,
+        rate: u32,
+    ) -> io::Result<Measurement> {
+        use std::os::unix::io::FromRawFd;
+
+        // The file descriptor is only borrowed and must not be closed when the file is dropped
+        let file = std::mem::ManuallyDrop::new(std::fs::File::from_raw_fd(fd));
+
+        Self::analyze_reader(&*file, format, channels, rate)
+    }
+}
+
+impl EbuR128 {
+    /// Add interleaved frames of little-endian 32 bit floating point samples to be processed.
+    ///
+    /// The bytes don't need to be aligned in any way. Returns `Error::BufferSizeMismatch` if
+    /// `frames` doesn't contain a whole number of samples, otherwise this behaves exactly like
+    /// [`EbuR128::add_frames_f32`](struct.EbuR128.html#method.add_frames_f32) with the decoded
+    /// samples.
+    pub fn add_frames_f32_le(&mut self, frames: &[u8]) -> Result<(), crate::Error> {
+        add_decoded_bytes(self, frames, f32::from_le_bytes)
+    }
+
+    /// Add interleaved frames of big-endian 32 bit floating point samples to be processed.
+    ///
+    /// See [`EbuR128::add_frames_f32_le`](struct.EbuR128.html#method.add_frames_f32_le).
+    pub fn add_frames_f32_be(&mut self, frames: &[u8]) -> Result<(), crate::Error> {
+        add_decoded_bytes(self, frames, f32::from_be_bytes)
+    }
+
+    /// Add interleaved frames of little-endian 64 bit floating point samples to be processed.
+    ///
+    /// See [`EbuR128::add_frames_f32_le`](struct.EbuR128.html#method.add_frames_f32_le).
+    pub fn add_frames_f64_le(&mut self, frames: &[u8]) -> Result<(), crate::Error> {
+        add_decoded_bytes(self, frames, f64::from_le_bytes)
+    }
+
+    /// Add interleaved frames of big-endian 64 bit floating point samples to be processed.
+    ///
+    /// See [`EbuR128::add_frames_f32_le`](struct.EbuR128.html#method.add_frames_f32_le).
+    pub fn add_frames_f64_be(&mut self, frames: &[u8]) -> Result<(), crate::Error> {
+        add_decoded_bytes(self, frames, f64::from_be_bytes)
+    }
+}
+
+/// Add the interleaved samples of `N` bytes each in `bytes` to `ebu`, decoding them in chunks.
+fn add_decoded_bytes<T: Sample, const N: usize>(
+    ebu: &mut EbuR128,
+    bytes: &[u8],
+    from_bytes: fn([u8; N]) -> T,
+) -> Result<(), crate::Error> {
+    if bytes.len() % N != 0 {
+        return Err(crate::Error::BufferSizeMismatch);
+    }
+
+    ebu.add_decoded_frames(bytes.len() / N, |offset, samples| {
+        for (sample, b) in Iterator::zip(samples.iter_mut(), bytes[offset * N..].chunks_exact(N)) {
+            let mut buf = [0; N];
+            buf.copy_from_slice(b);
+            *sample = from_bytes(buf);
+        }
+    })
+}
+
+/// Add the whole frames of native-endian samples in `bytes` to `ebu`.
+fn add_bytes(ebu: &mut EbuR128, format: SampleFormat, bytes: &[u8]) -> Result<(), crate::Error> {
+    match format {
+        SampleFormat::S16 => add_decoded_bytes(ebu, bytes, i16::from_ne_bytes),
+        SampleFormat::S32 => add_decoded_bytes(ebu, bytes, i32::from_ne_bytes),
+        SampleFormat::F32 => add_decoded_bytes(ebu, bytes, f32::from_ne_bytes),
+        SampleFormat::F64 => add_decoded_bytes(ebu, bytes, f64::from_ne_bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reader that returns at most `chunk` bytes per read and is interrupted every other read.
+    struct ShortReader<'a> {
+        data: &'a [u8],
+        chunk: usize,
+        interrupt: bool,
+    }
+
+    impl<'a> io::Read for ShortReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::Error::from(io::ErrorKind::Interrupted));
+            }
+
+            let len = usize::min(usize::min(self.chunk, buf.len()), self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    fn sine_s16() -> Vec<i16> {
+        (0..48_000 * 3)
+            .flat_map(|i| {
+                let v = f32::sin(2.0 * std::f32::consts::PI * 440.0 * i as f32 / 48_000.0);
+                let v = (v * 16_384.0) as i16;
+                std::iter::once(v).chain(std::iter::once(v / 2))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn analyze_reader() {
+        let samples = sine_s16();
+        let bytes = samples
+            .iter()
+            .flat_map(|s| s.to_ne_bytes().to_vec())
+            .collect::<Vec<_>>();
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::LRA | Mode::TRUE_PEAK).unwrap();
+        ebu.add_frames_i16(&samples).unwrap();
+        let expected = Measurement::new(&ebu);
+
+        // Reads that split frames and samples
+        let reader = ShortReader {
+            data: &bytes,
+            chunk: 1_001,
+            interrupt: false,
+        };
+        let measurement = EbuR128::analyze_reader(reader, SampleFormat::S16, 2, 48_000).unwrap();
+        assert_eq!(measurement, expected);
+
+        let err = EbuR128::analyze_reader(&bytes[..bytes.len() - 2], SampleFormat::S16, 2, 48_000)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let err = EbuR128::analyze_reader(&bytes[..], SampleFormat::S16, 0, 48_000).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            err.get_ref()
+                .and_then(|err| err.downcast_ref::<crate::Error>()),
+            Some(&crate::Error::NoMem)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn analyze_fd() {
+        use std::io::{Seek, Write};
+        use std::os::unix::io::AsRawFd;
+
+        let samples = sine_s16()
+            .iter()
+            .map(|s| *s as f32 / 32_768.0)
+            .collect::<Vec<_>>();
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::LRA | Mode::TRUE_PEAK).unwrap();
+        ebu.add_frames_f32(&samples).unwrap();
+        let expected = Measurement::new(&ebu);
+
+        let path = std::env::temp_dir().join(format!("ebur128-analyze-fd-{}", std::process::id()));
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for sample in &samples {
+            file.write_all(&sample.to_ne_bytes()).unwrap();
+        }
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+
+        let measurement =
+            unsafe { EbuR128::analyze_fd(file.as_raw_fd(), SampleFormat::F32, 2, 48_000) }.unwrap();
+        assert_eq!(measurement, expected);
+
+        // The file descriptor is still open
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+    }
+
+    #[test]
+    fn add_frames_bytes() {
+        let samples = sine_s16()
+            .iter()
+            .map(|s| *s as f64 / 32_768.0)
+            .collect::<Vec<_>>();
+        let samples_f32 = samples.iter().map(|s| *s as f32).collect::<Vec<_>>();
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        ebu.add_frames_f32(&samples_f32).unwrap();
+        let expected_f32 = Measurement::new(&ebu);
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        ebu.add_frames_f64(&samples).unwrap();
+        let expected_f64 = Measurement::new(&ebu);
+
+        // Start one byte into the buffer so that the samples are misaligned
+        let mut bytes = vec![0];
+        let f32_le = samples_f32.iter().flat_map(|s| s.to_le_bytes().to_vec());
+        bytes.extend(f32_le);
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        ebu.add_frames_f32_le(&bytes[1..]).unwrap();
+        assert_eq!(Measurement::new(&ebu), expected_f32);
+
+        // Samples that split frames with partial frames allowed
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        ebu.set_allow_partial_frames(true);
+        for chunk in bytes[1..].chunks(4 * 5_001) {
+            ebu.add_frames_f32_le(chunk).unwrap();
+        }
+        assert_eq!(Measurement::new(&ebu), expected_f32);
+
+        let bytes = samples_f32
+            .iter()
+            .flat_map(|s| s.to_be_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        ebu.add_frames_f32_be(&bytes).unwrap();
+        assert_eq!(Measurement::new(&ebu), expected_f32);
+
+        let mut bytes = vec![0];
+        bytes.extend(samples.iter().flat_map(|s| s.to_le_bytes().to_vec()));
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        ebu.add_frames_f64_le(&bytes[1..]).unwrap();
+        assert_eq!(Measurement::new(&ebu), expected_f64);
+
+        let bytes = samples
+            .iter()
+            .flat_map(|s| s.to_be_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        ebu.add_frames_f64_be(&bytes).unwrap();
+        assert_eq!(Measurement::new(&ebu), expected_f64);
+
+        // Incomplete samples and incomplete frames
+        assert_eq!(
+            ebu.add_frames_f64_be(&bytes[..12]),
+            Err(crate::Error::BufferSizeMismatch)
+        );
+        assert_eq!(
+            ebu.add_frames_f64_be(&bytes[..8]),
+            Err(crate::Error::BufferSizeMismatch)
+        );
+    }
+}
@@ -0,0 +1,302 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use crate::utils::Sample;
+
+/// Cutoff frequency of the anti-aliasing low-pass filter relative to the output rate.
+const LOWPASS_CUTOFF: f64 = 0.4;
+
+/// Q factors of the biquads of an 8th order Butterworth filter, i.e. `1 / (2 cos(θ))` for the pole
+/// angles `θ = (2k - 1) π / 16`.
+const LOWPASS_Q: [f64; 4] = [
+    0.509_795_579_104_159_2,
+    0.601_344_886_935_045_3,
+    0.899_976_223_136_415_6,
+    2.562_915_447_741_505,
+];
+
+/// 8th order Butterworth low-pass filter in front of the interpolation when downsampling.
+#[derive(Debug)]
+struct LowPass {
+    /// Normalized coefficients `b0, b1, b2, a1, a2` of each biquad.
+    coeffs: [[f64; 5]; 4],
+    /// State of each biquad per channel in transposed direct form II.
+    state: Box<[[[f64; 2]; 4]]>,
+}
+
+impl LowPass {
+    fn new(channels: usize, cutoff: f64, rate: f64) -> Self {
+        let w0 = 2.0 * std::f64::consts::PI * cutoff / rate;
+        let (sin_w0, cos_w0) = w0.sin_cos();
+
+        let mut coeffs = [[0.0; 5]; 4];
+        for (coeffs, q) in Iterator::zip(coeffs.iter_mut(), LOWPASS_Q.iter()) {
+            // Low-pass biquad from the Audio EQ Cookbook
+            let alpha = sin_w0 / (2.0 * q);
+            let a0 = 1.0 + alpha;
+            *coeffs = [
+                (1.0 - cos_w0) / 2.0 / a0,
+                (1.0 - cos_w0) / a0,
+                (1.0 - cos_w0) / 2.0 / a0,
+                -2.0 * cos_w0 / a0,
+                (1.0 - alpha) / a0,
+            ];
+        }
+
+        LowPass {
+            coeffs,
+            state: vec![[[0.0; 2]; 4]; channels].into_boxed_slice(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state.fill([[0.0; 2]; 4]);
+    }
+
+    fn process(coeffs: &[[f64; 5]; 4], state: &mut [[f64; 2]; 4], mut sample: f64) -> f64 {
+        for ([b0, b1, b2, a1, a2], [s1, s2]) in Iterator::zip(coeffs.iter(), state.iter_mut()) {
+            let out = b0 * sample + *s1;
+            *s1 = b1 * sample - a1 * out + *s2;
+            *s2 = b2 * sample - a2 * out;
+            sample = out;
+        }
+        sample
+    }
+}
+
+/// Resampler that converts frames to a different sample rate by linear interpolation.
+///
+/// Linear interpolation attenuates high frequencies: a frequency `f` is attenuated by a factor of
+/// `sinc²(f / input_rate)`, e.g. by about 0.37 dB at 5 kHz and 1.5 dB at 10 kHz for 44.1 kHz
+/// input. When downsampling, the input is first filtered by an 8th order Butterworth low-pass at
+/// 40% of the output rate, e.g. 19.2 kHz for 48 kHz output, so that frequencies above half the
+/// output rate are attenuated by at least 15 dB instead of being aliased.
+#[derive(Debug)]
+pub struct Resampler {
+    input_rate: u32,
+    output_rate: u32,
+    /// Position of the next output sample after the previous input sample in units of
+    /// `1 / output_rate` input samples.
+    position: u64,
+    /// Previous input sample per channel.
+    prev: Box<[f64]>,
+    /// Anti-aliasing filter if downsampling.
+    lowpass: Option<LowPass>,
+    /// Interleaved output of the last call to [`Resampler::process`].
+    output: Vec<f64>,
+}
+
+impl Resampler {
+    pub fn new(channels: u32, input_rate: u32, output_rate: u32) -> Self {
+        assert!(channels > 0 && input_rate > 0 && output_rate > 0);
+
+        let lowpass = if output_rate < input_rate {
+            Some(LowPass::new(
+                channels as usize,
+                LOWPASS_CUTOFF * output_rate as f64,
+                input_rate as f64,
+            ))
+        } else {
+            None
+        };
+
+        Resampler {
+            input_rate,
+            output_rate,
+            // The first output sample is the first input sample
+            position: output_rate as u64,
+            prev: vec![0.0; channels as usize].into_boxed_slice(),
+            lowpass,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn input_rate(&self) -> u32 {
+        self.input_rate
+    }
+
+    pub fn output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    pub fn reset(&mut self) {
+        self.position = self.output_rate as u64;
+        self.prev.fill(0.0);
+        if let Some(ref mut lowpass) = self.lowpass {
+            lowpass.reset();
+        }
+    }
+
+    /// Number of output frames that the next `input_frames` input frames are resampled to, and the
+    /// position after them.
+    fn output_frames_and_position(&self, input_frames: usize) -> (usize, u64) {
+        let input_rate = self.input_rate as u64;
+        let output_rate = self.output_rate as u64;
+
+        // Output samples are produced while the position is between the previous and the current
+        // input sample, i.e. at most at the current input sample.
+        let mut frames = 0;
+        let mut end_position = self.position;
+        for _ in 0..input_frames {
+            if end_position <= output_rate {
+                let n = (output_rate - end_position) / input_rate + 1;
+                frames += n as usize;
+                end_position += n * input_rate;
+            }
+            end_position -= output_rate;
+        }
+
+        (frames, end_position)
+    }
+
+    /// Number of output frames that the next `input_frames` input frames are resampled to.
+    pub fn output_frames(&self, input_frames: usize) -> usize {
+        self.output_frames_and_position(input_frames).0
+    }
+
+    /// Resample `src` and return the resampled interleaved frames.
+    ///
+    /// The returned frames are kept in a buffer that is reused by the next call, so this only
+    /// allocates if more frames are returned than by any call before.
+    pub fn process<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(&mut self, src: &S) -> &[f64] {
+        let channels = self.prev.len();
+        assert!(src.channels() == channels);
+
+        let input_rate = self.input_rate as u64;
+        let output_rate = self.output_rate as u64;
+
+        let (frames, end_position) = self.output_frames_and_position(src.frames());
+
+        self.output.clear();
+        self.output.resize(frames * channels, 0.0);
+
+        for (c, prev) in self.prev.iter_mut().enumerate() {
+            let mut position = self.position;
+            let mut index = c;
+            let output = &mut self.output;
+            let mut lowpass = self
+                .lowpass
+                .as_mut()
+                .map(|lowpass| (&lowpass.coeffs, &mut lowpass.state[c]));
+
+            src.foreach_sample(c, |sample| {
+                let mut cur = sample.to_sample::<f64>();
+                if let Some((coeffs, ref mut state)) = lowpass {
+                    cur = LowPass::process(coeffs, state, cur);
+                }
+                while position <= output_rate {
+                    let frac = position as f64 / output_rate as f64;
+                    output[index] = *prev + (cur - *prev) * frac;
+                    index += channels;
+                    position += input_rate;
+                }
+                position -= output_rate;
+                *prev = cur;
+            });
+
+            debug_assert!(position == end_position);
+        }
+
+        self.position = end_position;
+
+        &self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_rate() {
+        let mut resampler = Resampler::new(2, 48_000, 48_000);
+        let data = [0.1f32, 0.2, 0.3, 0.4, 0.5, 0.6];
+        let output = resampler.process(&crate::Interleaved::new(&data, 2).unwrap());
+        let expected = data.iter().map(|v| *v as f64).collect::<Vec<_>>();
+        assert_eq!(output, &expected[..]);
+    }
+
+    #[test]
+    fn downsample_lowpass() {
+        fn rms(data: &[f64]) -> f64 {
+            f64::sqrt(data.iter().map(|v| v * v).sum::<f64>() / data.len() as f64)
+        }
+
+        for (freq, min_rms, max_rms) in [
+            // Passed
+            (1000.0, 0.7, 0.71),
+            // Would be aliased to 18 kHz
+            (30_000.0, 0.0, 0.01),
+        ] {
+            let data = (0..96_000)
+                .map(|i| f64::sin(2.0 * std::f64::consts::PI * freq * i as f64 / 96_000.0))
+                .collect::<Vec<_>>();
+            let mut resampler = Resampler::new(1, 96_000, 48_000);
+            let output = resampler.process(&crate::Interleaved::new(&data, 1).unwrap());
+            assert_eq!(output.len(), 48_000);
+
+            // Skip the settling of the filter
+            let rms = rms(&output[1000..]);
+            assert!(
+                (min_rms..max_rms).contains(&rms),
+                "{} Hz: rms {}",
+                freq,
+                rms
+            );
+        }
+    }
+
+    #[test]
+    fn upsample() {
+        let mut resampler = Resampler::new(1, 24_000, 48_000);
+        let output = resampler
+            .process(&crate::Interleaved::new(&[1.0f64, 3.0], 1).unwrap())
+            .to_vec();
+        assert_eq!(output, [1.0, 2.0, 3.0]);
+        let output = resampler
+            .process(&crate::Interleaved::new(&[5.0f64, 7.0], 1).unwrap())
+            .to_vec();
+        assert_eq!(output, [4.0, 5.0, 6.0, 7.0]);
+    }
+
+    #[test]
+    fn frame_count() {
+        let data = vec![0.0f32; 44_100];
+
+        // Chunk sizes don't affect the number of frames
+        let mut resampler = Resampler::new(1, 44_100, 48_000);
+        let frames = data
+            .chunks(1000)
+            .map(|chunk| {
+                resampler
+                    .process(&crate::Interleaved::new(chunk, 1).unwrap())
+                    .len()
+            })
+            .sum::<usize>();
+
+        // Only the output frames up to the last input frame are produced, i.e. up to 44099/44100s.
+        // With the input frame at 1s the output frames up to 1s follow.
+        assert_eq!(frames, 47_999);
+        assert_eq!(resampler.output_frames(1), 2);
+        let output = resampler.process(&crate::Interleaved::new(&data[..1], 1).unwrap());
+        assert_eq!(output.len(), 2);
+    }
+}
@@ -0,0 +1,158 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use crate::{EbuR128, Error, Mode};
+
+/// Group of [`EbuR128`](struct.EbuR128.html) instances with the same configuration, e.g. for
+/// analyzing the stems of a mix.
+///
+/// All analyzers of a group have the same number of channels, sample rate and mode, so that their
+/// measurements can always be combined.
+#[derive(Debug)]
+pub struct StemGroup {
+    channels: u32,
+    rate: u32,
+    mode: Mode,
+    analyzers: Vec<EbuR128>,
+}
+
+impl StemGroup {
+    /// Create a new, empty group with the given configuration.
+    ///
+    /// Returns the same errors as [`EbuR128::new`](struct.EbuR128.html#method.new) for invalid
+    /// configurations.
+    pub fn new(channels: u32, rate: u32, mode: Mode) -> Result<Self, Error> {
+        // Check the configuration right away
+        EbuR128::new(channels, rate, mode)?;
+
+        Ok(StemGroup {
+            channels,
+            rate,
+            mode,
+            analyzers: Vec::new(),
+        })
+    }
+
+    /// Get the configured number of channels.
+    pub fn channels(&self) -> u32 {
+        self.channels
+    }
+
+    /// Get the configured sample rate.
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// Get the configured mode.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Create a new analyzer with the configuration of the group and add it to the group.
+    pub fn new_analyzer(&mut self) -> Result<&mut EbuR128, Error> {
+        let ebu = EbuR128::new(self.channels, self.rate, self.mode)?;
+        self.analyzers.push(ebu);
+
+        Ok(self.analyzers.last_mut().unwrap())
+    }
+
+    /// Get all analyzers of the group in the order they were created.
+    pub fn analyzers(&self) -> &[EbuR128] {
+        &self.analyzers
+    }
+
+    /// Get all analyzers of the group mutably in the order they were created.
+    pub fn analyzers_mut(&mut self) -> &mut [EbuR128] {
+        &mut self.analyzers
+    }
+
+    /// Get the global integrated loudness in LUFS across all analyzers.
+    ///
+    /// This is calculated with
+    /// [`EbuR128::loudness_global_multiple`](struct.EbuR128.html#method.loudness_global_multiple)
+    /// from the gating blocks of all analyzers. It is thus the loudness of all stems played one
+    /// after another and not the loudness of their mix, for which the mix itself has to be
+    /// measured.
+    pub fn combined_loudness(&self) -> Result<f64, Error> {
+        EbuR128::loudness_global_multiple(self.analyzers.iter())
+    }
+
+    /// Get the loudness range in LU across all analyzers.
+    ///
+    /// This is calculated with
+    /// [`EbuR128::loudness_range_multiple`](struct.EbuR128.html#method.loudness_range_multiple),
+    /// see [`StemGroup::combined_loudness`] for what this means.
+    pub fn combined_loudness_range(&self) -> Result<f64, Error> {
+        EbuR128::loudness_range_multiple(self.analyzers.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    #[test]
+    fn combined_loudness() {
+        assert_eq!(
+            StemGroup::new(2, 48_000, Mode::empty()).unwrap_err(),
+            Error::InvalidMode
+        );
+
+        let mut group = StemGroup::new(2, 48_000, Mode::I | Mode::LRA).unwrap();
+        assert!(group.analyzers().is_empty());
+        assert_eq!(group.combined_loudness(), Ok(-f64::INFINITY));
+
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let val = f32::sin(2.0 * std::f32::consts::PI * 440.0 * i as f32 / 48_000.0);
+            out[0] = val;
+            out[1] = val;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::LRA).unwrap();
+        for gain in [1.0, 0.5] {
+            let data = data.iter().map(|v| v * gain).collect::<Vec<_>>();
+
+            let stem = group.new_analyzer().unwrap();
+            assert_eq!(stem.channels(), 2);
+            assert_eq!(stem.rate(), 48_000);
+            assert_eq!(stem.mode(), Mode::I | Mode::LRA);
+            stem.add_frames_f32(&data).unwrap();
+
+            // A single analyzer that gets all stems one after another
+            ebu.add_frames_f32(&data).unwrap();
+        }
+
+        assert_eq!(group.analyzers().len(), 2);
+        assert_float_eq!(
+            group.combined_loudness().unwrap(),
+            ebu.loudness_global().unwrap(),
+            abs <= 0.1
+        );
+        assert!(group.combined_loudness_range().unwrap() > 0.0);
+
+        for stem in group.analyzers_mut() {
+            stem.reset();
+        }
+        assert_eq!(group.combined_loudness(), Ok(-f64::INFINITY));
+    }
+}
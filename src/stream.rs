@@ -0,0 +1,344 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use crate::{EbuR128, Error, Measurement};
+
+use futures_core::Stream;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread::JoinHandle;
+
+/// Number of chunks that are queued for the analysis thread before the stream is not polled
+/// anymore.
+const QUEUED_CHUNKS: usize = 4;
+
+/// State shared between [`AnalyzeStream`] and its analysis thread.
+#[derive(Default)]
+struct Shared {
+    /// Waker of the task that waits for space in the queue or the result.
+    waker: Option<Waker>,
+    /// Result of the analysis once all chunks are analyzed.
+    result: Option<Result<Measurement, Error>>,
+    /// Whether the analysis thread has ended, with or without a result.
+    exited: bool,
+}
+
+/// Lock the shared state, also if the analysis thread panicked while holding the lock.
+fn lock(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    shared.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Marks the analysis thread as ended and wakes the future when dropped, also when the thread
+/// panics.
+struct ExitGuard(Arc<Mutex<Shared>>);
+
+impl Drop for ExitGuard {
+    fn drop(&mut self) {
+        let mut shared = lock(&self.0);
+        shared.exited = true;
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Analyze the interleaved `f32` chunks of `stream` without blocking the async executor.
+///
+/// The chunks are analyzed by `ebu` on a separate thread, so that the CPU-bound analysis does not
+/// block the executor in the same way as e.g. `tokio::task::spawn_blocking()` would, but without
+/// depending on a specific executor. At most a few chunks are queued for the analysis thread, and
+/// the stream is only polled again once the analysis caught up.
+///
+/// `ebu` can be configured as needed before, and the returned future resolves to its
+/// [`Measurement`](struct.Measurement.html) once the stream has ended and all chunks are analyzed.
+/// If adding a chunk fails, e.g. because it does not contain complete frames, the stream is not
+/// polled anymore and the future resolves to the error. If the analysis thread panics, e.g.
+/// because `AsRef::as_ref()` of a chunk panics, the future resolves to
+/// `Error::AnalysisPanicked`, and if the thread can't be spawned to `Error::NoMem`.
+///
+/// Only available with the `async` feature.
+pub fn analyze_stream<St>(ebu: EbuR128, stream: St) -> AnalyzeStream<St>
+where
+    St: Stream + Unpin,
+    St::Item: AsRef<[f32]> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::sync_channel::<St::Item>(QUEUED_CHUNKS);
+    let shared = Arc::new(Mutex::new(Shared::default()));
+
+    let guard = ExitGuard(shared.clone());
+    let thread = std::thread::Builder::new()
+        .name(String::from("ebur128-analysis"))
+        .spawn(move || {
+            let mut ebu = ebu;
+            let mut result = Ok(());
+
+            while let Ok(chunk) = receiver.recv() {
+                // Let the future queue the next chunk
+                if let Some(waker) = lock(&guard.0).waker.take() {
+                    waker.wake();
+                }
+
+                result = ebu.add_frames_f32(chunk.as_ref());
+                if result.is_err() {
+                    break;
+                }
+            }
+            drop(receiver);
+
+            lock(&guard.0).result = Some(result.map(|_| Measurement::new(&ebu)));
+        });
+
+    let thread = match thread {
+        Ok(thread) => Some(thread),
+        Err(_) => {
+            // The closure and with it the guard was dropped already
+            lock(&shared).result = Some(Err(Error::NoMem));
+            None
+        }
+    };
+
+    AnalyzeStream {
+        stream: Some(stream),
+        sender: Some(sender),
+        pending: None,
+        shared,
+        thread,
+    }
+}
+
+/// Future returned by [`analyze_stream`](fn.analyze_stream.html).
+///
+/// Only available with the `async` feature.
+pub struct AnalyzeStream<St: Stream> {
+    /// The stream, until it has ended.
+    stream: Option<St>,
+    /// Sender of the chunks to the analysis thread, until the stream has ended.
+    sender: Option<SyncSender<St::Item>>,
+    /// Chunk that did not fit into the queue anymore.
+    pending: Option<St::Item>,
+    shared: Arc<Mutex<Shared>>,
+    /// The analysis thread, until it has ended.
+    thread: Option<JoinHandle<()>>,
+}
+
+// The stream is the only field that is polled, and it is `Unpin`
+impl<St: Stream + Unpin> Unpin for AnalyzeStream<St> {}
+
+impl<St> Future for AnalyzeStream<St>
+where
+    St: Stream + Unpin,
+    St::Item: AsRef<[f32]> + Send + 'static,
+{
+    type Output = Result<Measurement, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        while this.sender.is_some() {
+            let chunk = match this.pending.take() {
+                Some(chunk) => chunk,
+                None => match Pin::new(this.stream.as_mut().unwrap()).poll_next(cx) {
+                    Poll::Ready(Some(chunk)) => chunk,
+                    Poll::Ready(None) => {
+                        this.stream = None;
+                        this.sender = None;
+                        break;
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+            };
+
+            let sender = this.sender.as_ref().unwrap();
+            let res = match sender.try_send(chunk) {
+                Err(TrySendError::Full(chunk)) => {
+                    lock(&this.shared).waker = Some(cx.waker().clone());
+                    // The queue might have been emptied before the waker was set
+                    sender.try_send(chunk)
+                }
+                res => res,
+            };
+
+            match res {
+                Ok(()) => (),
+                Err(TrySendError::Full(chunk)) => {
+                    this.pending = Some(chunk);
+                    return Poll::Pending;
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    // The analysis failed or the thread panicked
+                    this.stream = None;
+                    this.sender = None;
+                }
+            }
+        }
+
+        let mut shared = lock(&this.shared);
+        if !shared.exited {
+            shared.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        // The thread sets the result right before it ends, so it panicked if there is none
+        let result = shared.result.take();
+        drop(shared);
+        let panicked = match this.thread.take() {
+            Some(thread) => thread.join().is_err(),
+            None => false,
+        };
+
+        match result {
+            Some(result) if !panicked => Poll::Ready(result),
+            _ => Poll::Ready(Err(Error::AnalysisPanicked)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mode;
+
+    use std::collections::VecDeque;
+    use std::sync::Condvar;
+    use std::task::Wake;
+
+    /// Stream of chunks that is only ready on every second poll.
+    struct Chunks {
+        chunks: VecDeque<Vec<f32>>,
+        ready: bool,
+    }
+
+    impl Stream for Chunks {
+        type Item = Vec<f32>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Vec<f32>>> {
+            self.ready = !self.ready;
+            if self.ready {
+                Poll::Ready(self.chunks.pop_front())
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct Notify {
+        woken: Mutex<bool>,
+        condvar: Condvar,
+    }
+
+    impl Wake for Notify {
+        fn wake(self: Arc<Self>) {
+            *self.woken.lock().unwrap() = true;
+            self.condvar.notify_one();
+        }
+    }
+
+    fn block_on<F: Future + Unpin>(mut future: F) -> F::Output {
+        let notify = Arc::new(Notify::default());
+        let waker = Waker::from(notify.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            if let Poll::Ready(output) = Pin::new(&mut future).poll(&mut cx) {
+                return output;
+            }
+
+            let mut woken = notify.woken.lock().unwrap();
+            while !*woken {
+                woken = notify.condvar.wait(woken).unwrap();
+            }
+            *woken = false;
+        }
+    }
+
+    fn chunks(data: &[f32], chunk_samples: usize) -> Chunks {
+        Chunks {
+            chunks: data.chunks(chunk_samples).map(Vec::from).collect(),
+            ready: false,
+        }
+    }
+
+    #[test]
+    fn analyze_stream() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        for (i, frame) in data.chunks_exact_mut(2).enumerate() {
+            frame.fill(f32::sin(
+                2.0 * std::f32::consts::PI * 440.0 * i as f32 / 48_000.0,
+            ));
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::SAMPLE_PEAK).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        let expected = Measurement::new(&ebu);
+
+        let ebu = EbuR128::new(2, 48_000, Mode::I | Mode::SAMPLE_PEAK).unwrap();
+        let measurement = block_on(super::analyze_stream(ebu, chunks(&data, 2 * 480))).unwrap();
+        assert_eq!(measurement, expected);
+
+        // Incomplete frames
+        let ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(
+            block_on(super::analyze_stream(ebu, chunks(&data, 1001))),
+            Err(Error::BufferSizeMismatch)
+        );
+    }
+
+    /// Chunk whose samples can't be accessed.
+    struct PanickingChunk;
+
+    impl AsRef<[f32]> for PanickingChunk {
+        fn as_ref(&self) -> &[f32] {
+            panic!("chunk is not accessible");
+        }
+    }
+
+    struct PanickingChunks(usize);
+
+    impl Stream for PanickingChunks {
+        type Item = PanickingChunk;
+
+        fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            if self.0 == 0 {
+                return Poll::Ready(None);
+            }
+            self.0 -= 1;
+            Poll::Ready(Some(PanickingChunk))
+        }
+    }
+
+    #[test]
+    fn analyze_stream_panic() {
+        // Whether the thread panics before or after the stream ended
+        for chunks in [1, 10] {
+            let ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+            assert_eq!(
+                block_on(super::analyze_stream(ebu, PanickingChunks(chunks))),
+                Err(Error::AnalysisPanicked)
+            );
+        }
+    }
+}
@@ -49,10 +49,13 @@ impl UpsamplingScanner {
             Two,
         }
         let interp_factor = if rate < 96_000 {
+            trace!("True peak: 4x oversampling at {} Hz", rate);
             Factor::Four
         } else if rate < 192_000 {
+            trace!("True peak: 2x oversampling at {} Hz", rate);
             Factor::Two
         } else {
+            trace!("True peak: no oversampling at {} Hz", rate);
             return None;
         };
 
@@ -72,10 +75,15 @@ impl UpsamplingScanner {
         })
     }
 
+    /// Update the true peaks and, if given, the sample peaks with the samples of `src`.
+    ///
+    /// Calculating the sample peaks here allows to only iterate once over the samples if both are
+    /// requested.
     pub fn check_true_peak<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
         &mut self,
         src: S,
         peaks: &mut [f64],
+        sample_peaks: Option<&mut [f64]>,
     ) {
         macro_rules! tp_specialized_impl {
             ( $channels:expr, $interpolator:expr ) => {{
@@ -83,13 +91,165 @@ impl UpsamplingScanner {
                 assert!(src.channels() == CHANNELS && peaks.len() == CHANNELS);
                 let mut tmp_peaks = <[f32; CHANNELS]>::from_fn(|i| peaks[i] as f32);
 
+                macro_rules! interpolate_frame {
+                    ( $frame:expr ) => {{
+                        let frame_f32: [f32; CHANNELS] =
+                            Frame::map($frame, |s| s.to_sample::<f32>());
+                        for new_frame in &$interpolator.interpolate(frame_f32) {
+                            tmp_peaks.retain_max_samples(&Frame::map(*new_frame, |s| s.abs()));
+                        }
+                    }};
+                }
+
+                if let Some(sample_peaks) = sample_peaks {
+                    assert!(sample_peaks.len() == CHANNELS);
+                    let mut tmp_sample_peaks = [0.0f64; CHANNELS];
+
+                    src.foreach_frame(|frame: [T; CHANNELS]| {
+                        for (max, sample) in Iterator::zip(tmp_sample_peaks.iter_mut(), &frame) {
+                            let v = sample.as_f64_raw().abs();
+                            if v > *max {
+                                *max = v;
+                            }
+                        }
+
+                        interpolate_frame!(frame);
+                    });
+
+                    for (dst, max) in Iterator::zip(sample_peaks.iter_mut(), &tmp_sample_peaks) {
+                        let max = *max / T::MAX_AMPLITUDE;
+                        if max > *dst {
+                            *dst = max;
+                        }
+                    }
+                } else {
+                    src.foreach_frame(|frame: [T; CHANNELS]| interpolate_frame!(frame));
+                }
+
+                for (dst, src) in Iterator::zip(peaks.into_iter(), &tmp_peaks) {
+                    *dst = *src as f64;
+                }
+            }};
+        }
+
+        macro_rules! tp_generic_impl {
+            ( $interpolators:expr ) => {{
+                assert!(src.channels() == $interpolators.len() && src.channels() == peaks.len());
+                let mut sample_peaks = sample_peaks;
+                if let Some(ref sample_peaks) = sample_peaks {
+                    assert!(sample_peaks.len() == src.channels());
+                }
+
+                for (c, (interpolator, channel_peak)) in
+                    Iterator::zip($interpolators.iter_mut(), peaks.iter_mut()).enumerate()
+                {
+                    macro_rules! interpolate_sample {
+                        ( $s:expr ) => {{
+                            for [new_sample] in &interpolator.interpolate([$s.to_sample::<f32>()]) {
+                                let new_sample = new_sample.abs() as f64;
+                                if new_sample > *channel_peak {
+                                    *channel_peak = new_sample;
+                                }
+                            }
+                        }};
+                    }
+
+                    if let Some(ref mut sample_peaks) = sample_peaks {
+                        let mut max = 0.0;
+
+                        src.foreach_sample(c, |s| {
+                            let v = s.as_f64_raw().abs();
+                            if v > max {
+                                max = v;
+                            }
+
+                            interpolate_sample!(s);
+                        });
+
+                        let max = max / T::MAX_AMPLITUDE;
+                        if max > sample_peaks[c] {
+                            sample_peaks[c] = max;
+                        }
+                    } else {
+                        src.foreach_sample(c, |s| interpolate_sample!(s));
+                    }
+                }
+            }};
+        }
+
+        match self {
+            Mono2F(interpolator) => tp_specialized_impl!(1, interpolator),
+            Stereo2F(interpolator) => tp_specialized_impl!(2, interpolator),
+            Quad2F(interpolator) => tp_specialized_impl!(4, interpolator),
+            Surround2F(interpolator) => tp_specialized_impl!(6, interpolator),
+            OctoSurround2F(interpolator) => tp_specialized_impl!(8, interpolator),
+            Mono4F(interpolator) => tp_specialized_impl!(1, interpolator),
+            Stereo4F(interpolator) => tp_specialized_impl!(2, interpolator),
+            Quad4F(interpolator) => tp_specialized_impl!(4, interpolator),
+            Surround4F(interpolator) => tp_specialized_impl!(6, interpolator),
+            OctoSurround4F(interpolator) => tp_specialized_impl!(8, interpolator),
+            Generic2F(interpolators) => tp_generic_impl!(interpolators),
+            Generic4F(interpolators) => tp_generic_impl!(interpolators),
+        }
+    }
+
+    /// Update the true peaks, the consumer of the peak of each frame, e.g. the window of the most
+    /// recent peaks, and, if given, the sample peaks with the samples of `src`.
+    ///
+    /// This is separate from [`UpsamplingScanner::check_true_peak`] to keep the common case
+    /// without a window as fast as possible.
+    pub fn check_true_peak_windowed<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        peaks: &mut [f64],
+        sample_peaks: Option<&mut [f64]>,
+        window: &mut impl FramePeaks,
+    ) {
+        let frames = src.frames();
+        assert!(src.channels() == peaks.len());
+        let mut sample_peaks = sample_peaks;
+        if let Some(ref sample_peaks) = sample_peaks {
+            assert!(sample_peaks.len() == src.channels());
+        }
+
+        macro_rules! tp_specialized_impl {
+            ( $channels:expr, $interpolator:expr ) => {{
+                const CHANNELS: usize = $channels;
+                let mut tmp_peaks = <[f32; CHANNELS]>::from_fn(|i| peaks[i] as f32);
+                let mut tmp_sample_peaks = [0.0f64; CHANNELS];
+                let mut frame_index = 0;
+
                 src.foreach_frame(|frame: [T; CHANNELS]| {
+                    for (max, sample) in Iterator::zip(tmp_sample_peaks.iter_mut(), &frame) {
+                        let v = sample.as_f64_raw().abs();
+                        if v > *max {
+                            *max = v;
+                        }
+                    }
+
                     let frame_f32: [f32; CHANNELS] = Frame::map(frame, |s| s.to_sample::<f32>());
+                    let mut frame_peaks = [0.0f32; CHANNELS];
                     for new_frame in &$interpolator.interpolate(frame_f32) {
-                        tmp_peaks.retain_max_samples(&Frame::map(*new_frame, |s| s.abs()));
+                        frame_peaks.retain_max_samples(&Frame::map(*new_frame, |s| s.abs()));
+                    }
+                    tmp_peaks.retain_max_samples(&frame_peaks);
+
+                    for (c, peak) in frame_peaks.iter().enumerate() {
+                        window.set(c, frame_index, *peak);
                     }
+                    frame_index += 1;
                 });
-                for (dst, src) in Iterator::zip(peaks.into_iter(), &tmp_peaks) {
+
+                if let Some(ref mut sample_peaks) = sample_peaks {
+                    for (dst, max) in Iterator::zip(sample_peaks.iter_mut(), &tmp_sample_peaks) {
+                        let max = *max / T::MAX_AMPLITUDE;
+                        if max > *dst {
+                            *dst = max;
+                        }
+                    }
+                }
+
+                for (dst, src) in Iterator::zip(peaks.iter_mut(), &tmp_peaks) {
                     *dst = *src as f64;
                 }
             }};
@@ -97,18 +257,36 @@ impl UpsamplingScanner {
 
         macro_rules! tp_generic_impl {
             ( $interpolators:expr ) => {{
-                assert!(src.channels() == $interpolators.len() && src.channels() == peaks.len());
                 for (c, (interpolator, channel_peak)) in
                     Iterator::zip($interpolators.iter_mut(), peaks.iter_mut()).enumerate()
                 {
-                    src.foreach_sample(c, move |s| {
+                    let mut max = 0.0;
+                    let mut frame_index = 0;
+
+                    src.foreach_sample(c, |s| {
+                        let v = s.as_f64_raw().abs();
+                        if v > max {
+                            max = v;
+                        }
+
+                        let mut frame_peak = 0.0f32;
                         for [new_sample] in &interpolator.interpolate([s.to_sample::<f32>()]) {
-                            let new_sample = new_sample.abs() as f64;
-                            if new_sample > *channel_peak {
-                                *channel_peak = new_sample;
-                            }
+                            frame_peak = f32::max(frame_peak, new_sample.abs());
+                        }
+                        if frame_peak as f64 > *channel_peak {
+                            *channel_peak = frame_peak as f64;
                         }
+
+                        window.set(c, frame_index, frame_peak);
+                        frame_index += 1;
                     });
+
+                    if let Some(ref mut sample_peaks) = sample_peaks {
+                        let max = max / T::MAX_AMPLITUDE;
+                        if max > sample_peaks[c] {
+                            sample_peaks[c] = max;
+                        }
+                    }
                 }
             }};
         }
@@ -127,6 +305,25 @@ impl UpsamplingScanner {
             Generic2F(interpolators) => tp_generic_impl!(interpolators),
             Generic4F(interpolators) => tp_generic_impl!(interpolators),
         }
+
+        window.advance(frames);
+    }
+
+    fn latency_samples(&self) -> f64 {
+        match self {
+            Mono2F(interpolator) => interpolator.latency_samples(),
+            Stereo2F(interpolator) => interpolator.latency_samples(),
+            Quad2F(interpolator) => interpolator.latency_samples(),
+            Surround2F(interpolator) => interpolator.latency_samples(),
+            OctoSurround2F(interpolator) => interpolator.latency_samples(),
+            Mono4F(interpolator) => interpolator.latency_samples(),
+            Stereo4F(interpolator) => interpolator.latency_samples(),
+            Quad4F(interpolator) => interpolator.latency_samples(),
+            Surround4F(interpolator) => interpolator.latency_samples(),
+            OctoSurround4F(interpolator) => interpolator.latency_samples(),
+            Generic2F(interpolators) => interpolators[0].latency_samples(),
+            Generic4F(interpolators) => interpolators[0].latency_samples(),
+        }
     }
 
     fn reset(&mut self) {
@@ -159,24 +356,226 @@ impl TruePeak {
         UpsamplingScanner::new(rate, channels).map(|interp| Self { interp })
     }
 
+    /// Heap memory in bytes that [`TruePeak::new`] allocates for the given configuration.
+    pub fn heap_size(rate: u32, channels: u32) -> usize {
+        let channels = channels as usize;
+        match channels {
+            1 | 2 | 4 | 6 | 8 => 0,
+            _ if rate >= 192_000 => 0,
+            _ if rate >= 96_000 => channels * std::mem::size_of::<InterpF<24, 2, [f32; 1]>>(),
+            _ => channels * std::mem::size_of::<InterpF<12, 4, [f32; 1]>>(),
+        }
+    }
+
     pub fn reset(&mut self) {
         self.interp.reset();
     }
 
+    /// Latency of the interpolator in input samples.
+    pub fn latency_samples(&self) -> f64 {
+        self.interp.latency_samples()
+    }
+
     pub fn check_true_peak<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
         &mut self,
         src: S,
         peaks: &mut [f64],
     ) {
-        self.interp.check_true_peak(src, peaks)
+        self.interp.check_true_peak(src, peaks, None)
+    }
+
+    /// Like [`TruePeak::check_true_peak`] but also updates the sample peaks in the same pass over
+    /// the samples.
+    pub fn check_true_and_sample_peak<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        peaks: &mut [f64],
+        sample_peaks: &mut [f64],
+    ) {
+        self.interp.check_true_peak(src, peaks, Some(sample_peaks))
+    }
+
+    /// Like [`TruePeak::check_true_peak`] but also updates the optional sample peaks and the
+    /// consumer of the peak of each frame.
+    pub fn check_true_peak_windowed<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        peaks: &mut [f64],
+        sample_peaks: Option<&mut [f64]>,
+        window: &mut impl FramePeaks,
+    ) {
+        self.interp
+            .check_true_peak_windowed(src, peaks, sample_peaks, window)
     }
 
     pub fn seed<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(&mut self, src: S) {
         let mut true_peaks: SmallVec<[f64; 16]> = smallvec![0.0; src.channels()];
-        self.interp.check_true_peak(src, &mut true_peaks)
+        self.interp.check_true_peak(src, &mut true_peaks, None)
+    }
+}
+
+/// Consumer of the true peak of each frame, per channel.
+pub trait FramePeaks {
+    /// Set the peak of the `offset`-th frame after the current position.
+    fn set(&mut self, channel: usize, offset: usize, peak: f32);
+
+    /// Move the current position by `frames` after setting their peaks.
+    fn advance(&mut self, frames: usize);
+}
+
+impl<P: FramePeaks> FramePeaks for Option<P> {
+    #[inline]
+    fn set(&mut self, channel: usize, offset: usize, peak: f32) {
+        if let Some(peaks) = self {
+            peaks.set(channel, offset, peak);
+        }
+    }
+
+    fn advance(&mut self, frames: usize) {
+        if let Some(peaks) = self {
+            peaks.advance(frames);
+        }
+    }
+}
+
+impl<P: FramePeaks + ?Sized> FramePeaks for &mut P {
+    #[inline]
+    fn set(&mut self, channel: usize, offset: usize, peak: f32) {
+        (**self).set(channel, offset, peak);
+    }
+
+    fn advance(&mut self, frames: usize) {
+        (**self).advance(frames);
+    }
+}
+
+impl<P: FramePeaks, Q: FramePeaks> FramePeaks for (P, Q) {
+    #[inline]
+    fn set(&mut self, channel: usize, offset: usize, peak: f32) {
+        self.0.set(channel, offset, peak);
+        self.1.set(channel, offset, peak);
+    }
+
+    fn advance(&mut self, frames: usize) {
+        self.0.advance(frames);
+        self.1.advance(frames);
     }
 }
 
+/// Ring buffer with the peak of each of the most recent frames, per channel.
+#[derive(Debug)]
+pub struct PeakWindow {
+    /// Peaks, `frames` consecutive values per channel.
+    peaks: Box<[f32]>,
+    /// Number of frames that are kept per channel.
+    frames: usize,
+    /// Position of the oldest frame, which is overwritten next.
+    index: usize,
+}
+
+impl PeakWindow {
+    pub fn new(channels: u32, frames: usize) -> Self {
+        assert!(channels > 0);
+        assert!(frames > 0);
+
+        PeakWindow {
+            peaks: vec![0.0; channels as usize * frames].into_boxed_slice(),
+            frames,
+            index: 0,
+        }
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn reset(&mut self) {
+        self.peaks.fill(0.0);
+        self.index = 0;
+    }
+
+    /// Maximum peak of the most recent `frames` frames of the given channel.
+    pub fn max(&self, channel: usize, frames: usize) -> f64 {
+        let frames = usize::min(frames, self.frames);
+        let peaks = &self.peaks[channel * self.frames..][..self.frames];
+
+        let start = (self.index + self.frames - frames) % self.frames;
+        let max = if start + frames <= self.frames {
+            peaks[start..][..frames]
+                .iter()
+                .fold(0.0f32, |a, b| f32::max(a, *b))
+        } else {
+            Iterator::chain(peaks[start..].iter(), peaks[..self.index].iter())
+                .fold(0.0f32, |a, b| f32::max(a, *b))
+        };
+
+        max as f64
+    }
+}
+
+impl FramePeaks for PeakWindow {
+    #[inline]
+    fn set(&mut self, channel: usize, offset: usize, peak: f32) {
+        let pos = (self.index + offset) % self.frames;
+        self.peaks[channel * self.frames + pos] = peak;
+    }
+
+    fn advance(&mut self, frames: usize) {
+        self.index = (self.index + frames) % self.frames;
+    }
+}
+
+/// Counter of the events where the true peak exceeds a ceiling, per channel.
+///
+/// Each run of consecutive frames above the ceiling counts as a single event.
+#[derive(Debug)]
+pub struct OverCounter {
+    /// Ceiling as linear amplitude.
+    ceiling: f32,
+    /// Whether the last frame was above the ceiling.
+    over: Box<[bool]>,
+    /// Events since the counts were last reset.
+    counts: Box<[u64]>,
+}
+
+impl OverCounter {
+    pub fn new(channels: u32, ceiling: f64) -> Self {
+        OverCounter {
+            ceiling: ceiling as f32,
+            over: vec![false; channels as usize].into_boxed_slice(),
+            counts: vec![0; channels as usize].into_boxed_slice(),
+        }
+    }
+
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Reset the counts but keep whether the last frame was above the ceiling, so that an event
+    /// that continues is not counted again.
+    pub fn reset_counts(&mut self) {
+        self.counts.fill(0);
+    }
+
+    pub fn reset(&mut self) {
+        self.over.fill(false);
+        self.counts.fill(0);
+    }
+}
+
+impl FramePeaks for OverCounter {
+    #[inline]
+    fn set(&mut self, channel: usize, _offset: usize, peak: f32) {
+        let over = peak > self.ceiling;
+        if over && !self.over[channel] {
+            self.counts[channel] += 1;
+        }
+        self.over[channel] = over;
+    }
+
+    fn advance(&mut self, _frames: usize) {}
+}
+
 #[cfg(feature = "c-tests")]
 use std::os::raw::c_void;
 
@@ -81,7 +81,7 @@ impl<'a, S> Interleaved<'a, S> {
         }
 
         if data.len() % channels != 0 {
-            return Err(crate::Error::NoMem);
+            return Err(crate::Error::BufferSizeMismatch);
         }
 
         Ok(Interleaved { data, channels })
@@ -242,6 +242,23 @@ pub trait Sample:
     const MAX_AMPLITUDE: f64;
 
     fn as_f64_raw(self) -> f64;
+
+    /// Maximum absolute value of the samples of the given channel, scaled by `MAX_AMPLITUDE`.
+    #[inline(always)]
+    fn sample_peak<'a, S: Samples<'a, Self>>(src: &S, channel: usize) -> f64
+    where
+        Self: 'a,
+    {
+        let mut max = 0.0;
+        src.foreach_sample(channel, |sample| {
+            let v = sample.as_f64_raw().abs();
+            if v > max {
+                max = v;
+            }
+        });
+
+        max / Self::MAX_AMPLITUDE
+    }
 }
 
 impl Sample for f32 {
@@ -267,6 +284,21 @@ impl Sample for i16 {
     fn as_f64_raw(self) -> f64 {
         self as f64
     }
+
+    /// Finds the maximum on the integers, which gives exactly the same result as the conversion
+    /// of each sample to floating point.
+    #[inline(always)]
+    fn sample_peak<'a, S: Samples<'a, Self>>(src: &S, channel: usize) -> f64
+    where
+        Self: 'a,
+    {
+        let mut max = 0;
+        src.foreach_sample(channel, |sample| {
+            max = u16::max(max, sample.unsigned_abs());
+        });
+
+        max as f64 / Self::MAX_AMPLITUDE
+    }
 }
 impl Sample for i32 {
     const MAX_AMPLITUDE: f64 = -(Self::MIN as f64);
@@ -275,6 +307,21 @@ impl Sample for i32 {
     fn as_f64_raw(self) -> f64 {
         self as f64
     }
+
+    /// Finds the maximum on the integers, which gives exactly the same result as the conversion
+    /// of each sample to floating point.
+    #[inline(always)]
+    fn sample_peak<'a, S: Samples<'a, Self>>(src: &S, channel: usize) -> f64
+    where
+        Self: 'a,
+    {
+        let mut max = 0;
+        src.foreach_sample(channel, |sample| {
+            max = u32::max(max, sample.unsigned_abs());
+        });
+
+        max as f64 / Self::MAX_AMPLITUDE
+    }
 }
 
 /// An extension-trait to accumulate samples into a frame
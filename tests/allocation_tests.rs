@@ -0,0 +1,276 @@
+// Checks the allocation behaviour of `EbuR128`, e.g. that adding frames doesn't allocate.
+//
+// This uses a counting global allocator, which is why it is a separate test binary. Allocations
+// are counted per thread so that tests running in parallel don't influence each other.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use ebur128::{EbuR128, Error, Mode, SampleFormat};
+
+struct CountingAllocator;
+
+thread_local! {
+    // (number of allocations, allocated bytes)
+    static ALLOCATED: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+    // Currently allocated bytes, which is negative if memory of other threads is freed
+    static LIVE: Cell<isize> = const { Cell::new(0) };
+}
+
+fn count_allocation(size: usize) {
+    // Ignore allocations while the thread-locals are destroyed
+    let _ = ALLOCATED.try_with(|allocated| {
+        let (count, bytes) = allocated.get();
+        allocated.set((count + 1, bytes + size));
+    });
+    let _ = LIVE.try_with(|live| live.set(live.get() + size as isize));
+}
+
+fn count_deallocation(size: usize) {
+    let _ = LIVE.try_with(|live| live.set(live.get() - size as isize));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_deallocation(layout.size());
+        count_allocation(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count_deallocation(layout.size());
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Number of allocations and allocated bytes of the current thread while running `f`.
+fn allocated_by<T>(f: impl FnOnce() -> T) -> ((usize, usize), T) {
+    let (count_before, bytes_before) = ALLOCATED.with(Cell::get);
+    let res = f();
+    let (count, bytes) = ALLOCATED.with(Cell::get);
+    ((count - count_before, bytes - bytes_before), res)
+}
+
+/// Bytes of memory of the current thread that are allocated by `f` and still in use afterwards.
+fn retained_by<T>(f: impl FnOnce() -> T) -> (isize, T) {
+    let before = LIVE.with(Cell::get);
+    let res = f();
+    (LIVE.with(Cell::get) - before, res)
+}
+
+fn sine(frames: usize, channels: usize) -> Vec<f32> {
+    let mut data = vec![0.0; frames * channels];
+    for (i, frame) in data.chunks_exact_mut(channels).enumerate() {
+        let s = f32::sin(2.0 * std::f32::consts::PI * 440.0 * i as f32 / 48_000.0) * 0.5;
+        frame.fill(s);
+    }
+    data
+}
+
+#[test]
+fn peak_only_modes() {
+    // A single gating block history already allocates 40kB, and the audio data of a 400ms
+    // window another 300kB.
+    for mode in [
+        Mode::SAMPLE_PEAK,
+        Mode::TRUE_PEAK,
+        Mode::TRUE_PEAK | Mode::HISTOGRAM,
+    ] {
+        let ((_, allocated), ebu) = allocated_by(|| EbuR128::new(2, 48_000, mode).unwrap());
+        assert!(
+            allocated < 16 * 1024,
+            "{} allocated {} bytes",
+            mode,
+            allocated
+        );
+
+        assert_eq!(ebu.loudness_global(), Err(Error::InvalidMode));
+        assert_eq!(ebu.loudness_momentary(), Err(Error::InvalidMode));
+    }
+
+    // Sanity check of the counting
+    let ((_, allocated), _ebu) = allocated_by(|| EbuR128::new(2, 48_000, Mode::I).unwrap());
+    assert!(allocated > 300 * 1024);
+}
+
+#[test]
+fn add_frames_steady_state() {
+    let data = sine(48_000, 2);
+    let data_i16 = data
+        .iter()
+        .map(|s| (s * i16::MAX as f32) as i16)
+        .collect::<Vec<_>>();
+    let data_f64_be = data
+        .iter()
+        .flat_map(|s| (*s as f64).to_be_bytes())
+        .collect::<Vec<_>>();
+    let (left, right): (Vec<f64>, Vec<f64>) = data
+        .chunks_exact(2)
+        .map(|frame| (frame[0] as f64, frame[1] as f64))
+        .unzip();
+
+    for mode in [
+        Mode::M,
+        Mode::S,
+        Mode::I | Mode::TRUE_PEAK,
+        Mode::LRA | Mode::SAMPLE_PEAK,
+        Mode::I | Mode::LRA | Mode::TRUE_PEAK | Mode::HISTOGRAM,
+        Mode::TRUE_PEAK,
+    ] {
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu.reserve_duration(60.0).unwrap();
+
+        let ((count, _), ()) = allocated_by(|| {
+            for _ in 0..10 {
+                ebu.add_frames_f32(&data).unwrap();
+                ebu.add_frames_i16(&data_i16).unwrap();
+                ebu.add_frames_planar_f64(&[&left, &right]).unwrap();
+                ebu.add_frames_circular_f32(&data[..1001], &data[1001..])
+                    .unwrap();
+                ebu.add_frames_f64_be(&data_f64_be).unwrap();
+                ebu.add_frames_from(&data[..]).unwrap();
+            }
+        });
+        assert_eq!(count, 0, "{} allocated while adding frames", mode);
+    }
+
+    // The measurement pregain scales the samples without a temporary allocation
+    let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+    ebu.reserve_duration(60.0).unwrap();
+    ebu.set_measurement_pregain(-6.0).unwrap();
+    let ((count, _), ()) = allocated_by(|| {
+        for _ in 0..10 {
+            ebu.add_frames_f32(&data).unwrap();
+            ebu.add_frames_planar_f64(&[&left, &right]).unwrap();
+            ebu.seed_frames_i16(&data_i16).unwrap();
+        }
+    });
+    assert_eq!(count, 0, "allocated while adding frames with a pregain");
+
+    // Buffering a partial frame for the first time allocates, but completing it and buffering
+    // the next one doesn't
+    let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+    ebu.reserve_duration(60.0).unwrap();
+    ebu.set_allow_partial_frames(true);
+    let ((count, _), ()) = allocated_by(|| ebu.add_frames_f32(&data[..1001]).unwrap());
+    assert!(count > 0);
+    let ((count, _), ()) = allocated_by(|| {
+        for _ in 0..10 {
+            ebu.add_frames_f32(&data[1001..]).unwrap();
+            ebu.add_frames_f32(&data[..1001]).unwrap();
+        }
+    });
+    assert_eq!(count, 0, "allocated while adding partial frames");
+}
+
+#[cfg(feature = "resampling")]
+#[test]
+fn add_frames_resampling_steady_state() {
+    let data = sine(44_100, 2);
+    let (left, right): (Vec<f64>, Vec<f64>) = data
+        .chunks_exact(2)
+        .map(|frame| (frame[0] as f64, frame[1] as f64))
+        .unzip();
+
+    for mode in [Mode::I | Mode::TRUE_PEAK, Mode::LRA | Mode::SAMPLE_PEAK] {
+        let mut ebu = EbuR128::with_resampling(2, 44_100, mode, 48_000).unwrap();
+        ebu.reserve_duration(60.0).unwrap();
+
+        let add_frames = |ebu: &mut EbuR128| {
+            ebu.add_frames_f32(&data).unwrap();
+            ebu.add_frames_planar_f64(&[&left, &right]).unwrap();
+            ebu.add_frames_from(&data[..]).unwrap();
+        };
+
+        // The buffer of the resampled frames grows to the largest number of frames per call
+        add_frames(&mut ebu);
+        add_frames(&mut ebu);
+
+        let ((count, _), ()) = allocated_by(|| {
+            for _ in 0..10 {
+                add_frames(&mut ebu);
+            }
+        });
+        assert_eq!(count, 0, "{} allocated while resampling frames", mode);
+    }
+}
+
+#[test]
+fn analyze_reader() {
+    // Only the analyzer and the read buffer are allocated, independent of the stream length
+    let bytes = sine(48_000 * 10, 2)
+        .iter()
+        .flat_map(|s| s.to_ne_bytes())
+        .collect::<Vec<_>>();
+
+    let ((count_short, _), _) = allocated_by(|| {
+        EbuR128::analyze_reader(&bytes[..bytes.len() / 10], SampleFormat::F32, 2, 48_000).unwrap()
+    });
+    let ((count_long, _), _) =
+        allocated_by(|| EbuR128::analyze_reader(&bytes[..], SampleFormat::F32, 2, 48_000).unwrap());
+    assert_eq!(count_short, count_long);
+}
+
+#[test]
+fn estimated_history_bytes() {
+    let data = sine(48_000, 5);
+
+    for (channels, rate, mode) in [
+        (2, 48_000, Mode::M),
+        (2, 48_000, Mode::I | Mode::TRUE_PEAK),
+        (5, 44_100, Mode::all()),
+        (12, 96_000, Mode::LRA | Mode::TRUE_PEAK),
+        (1, 192_000, Mode::TRUE_PEAK),
+    ] {
+        for use_histogram in [false, true] {
+            for max_history_s in [60, 3600] {
+                let estimate = EbuR128::estimated_history_bytes(
+                    channels,
+                    rate,
+                    mode,
+                    use_histogram,
+                    max_history_s as f64,
+                );
+
+                let (retained, ebu) = retained_by(|| {
+                    let mut ebu = Box::new(
+                        EbuR128::with_histogram(channels, rate, mode, use_histogram).unwrap(),
+                    );
+                    ebu.set_max_history(max_history_s * 1000).unwrap();
+                    ebu.reserve_duration(max_history_s as f64).unwrap();
+                    ebu
+                });
+                assert_eq!(
+                    estimate, retained as usize,
+                    "{} {} {} {} {}",
+                    channels, rate, mode, use_histogram, max_history_s
+                );
+                assert_eq!(ebu.mode().contains(Mode::HISTOGRAM), use_histogram);
+            }
+        }
+    }
+
+    // Measuring up to the maximum history doesn't allocate any further
+    let mut ebu = EbuR128::new(5, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+    ebu.set_max_history(60_000).unwrap();
+    ebu.reserve_duration(60.0).unwrap();
+    let ((count, _), ()) = allocated_by(|| {
+        for _ in 0..70 {
+            ebu.add_frames_f32(&data).unwrap();
+        }
+    });
+    assert_eq!(count, 0);
+}